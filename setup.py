@@ -14,6 +14,7 @@ try:
             target="warden.warden_core_rust",
             path="src/warden_rust/Cargo.toml",
             binding=Binding.PyO3,
+            features=["extension-module"],
             debug=False,
         )
     ]
@@ -0,0 +1,257 @@
+use crate::scanner::split_lines;
+use crate::{CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use regex::Regex;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::OnceLock;
+
+/// A URL, IP address or email address found in a file.
+///
+/// `column`/`end_column` are 1-based byte offsets into the line, end
+/// exclusive.
+#[pyclass]
+#[derive(Clone)]
+pub struct AddressHit {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line_number: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub end_column: usize,
+    // "url", "ipv4", "ipv6" or "email"
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub value: String,
+    // The URL's host, the address itself, or the email's domain
+    #[pyo3(get)]
+    pub host: String,
+    // Lower-cased, for URLs only
+    #[pyo3(get)]
+    pub scheme: Option<String>,
+    // Where `host` points: "localhost" (loopback included), "private"
+    // (RFC 1918, carrier-grade NAT, unique local and `.local`-style names),
+    // "link_local", "reserved" (unspecified, broadcast, multicast and
+    // documentation ranges) or "public"
+    #[pyo3(get)]
+    pub classification: String,
+    // A URL whose scheme sends data in the clear: http, ws, ftp, telnet
+    #[pyo3(get)]
+    pub insecure: bool,
+    #[pyo3(get)]
+    pub snippet: String,
+}
+
+#[pymethods]
+impl AddressHit {
+    fn __repr__(&self) -> String {
+        format!("AddressHit({}:{}:{} {} {} {})", self.file_path, self.line_number, self.column, self.kind, self.value, self.classification)
+    }
+}
+
+fn url_regex() -> &'static Regex {
+    static URL: OnceLock<Regex> = OnceLock::new();
+    URL.get_or_init(|| Regex::new(r#"\b[A-Za-z][A-Za-z0-9+.\-]*://[^\s"'<>`\\]+"#).unwrap())
+}
+
+fn email_regex() -> &'static Regex {
+    static EMAIL: OnceLock<Regex> = OnceLock::new();
+    EMAIL.get_or_init(|| Regex::new(r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9\-]+(?:\.[A-Za-z0-9\-]+)*\.[A-Za-z]{2,}\b").unwrap())
+}
+
+// Candidates only: each is parsed before it counts
+fn ip_regex() -> &'static Regex {
+    static IP: OnceLock<Regex> = OnceLock::new();
+    IP.get_or_init(|| Regex::new(r"[0-9A-Fa-f:.]*[0-9][0-9A-Fa-f:.]*").unwrap())
+}
+
+fn classify_ipv4(ip: Ipv4Addr) -> &'static str {
+    let [a, b, ..] = ip.octets();
+    if ip.is_loopback() {
+        "localhost"
+    } else if ip.is_private() || (a == 100 && (64..128).contains(&b)) {
+        "private"
+    } else if ip.is_link_local() {
+        "link_local"
+    } else if ip.is_unspecified() || ip.is_broadcast() || ip.is_multicast() || ip.is_documentation() {
+        "reserved"
+    } else {
+        "public"
+    }
+}
+
+fn classify_ipv6(ip: Ipv6Addr) -> &'static str {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return classify_ipv4(ip);
+    }
+    let first = ip.segments()[0];
+    if ip.is_loopback() {
+        "localhost"
+    } else if first & 0xfe00 == 0xfc00 {
+        "private"
+    } else if first & 0xffc0 == 0xfe80 {
+        "link_local"
+    } else if ip.is_unspecified() || ip.is_multicast() || first == 0x2001 && ip.segments()[1] == 0x0db8 {
+        "reserved"
+    } else {
+        "public"
+    }
+}
+
+fn classify_host(host: &str) -> &'static str {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        return classify_ipv4(ip);
+    }
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<Ipv6Addr>() {
+        return classify_ipv6(ip);
+    }
+    if host == "localhost" || host.ends_with(".localhost") {
+        "localhost"
+    } else if [".local", ".internal", ".lan", ".home.arpa"].iter().any(|suffix| host.ends_with(suffix)) {
+        "private"
+    } else {
+        "public"
+    }
+}
+
+// Trailing punctuation that ends the surrounding sentence or code rather
+// than the URL; a closing bracket stays when the URL opened it
+fn trim_url(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let Some(last) = url.chars().last() else { return url };
+        let opening = match last {
+            ')' => '(',
+            ']' => '[',
+            '}' => '{',
+            '.' | ',' | ';' | ':' | '!' | '?' => {
+                url = &url[..url.len() - 1];
+                continue;
+            }
+            _ => return url,
+        };
+        if url.matches(opening).count() >= url.matches(last).count() {
+            return url;
+        }
+        url = &url[..url.len() - 1];
+    }
+}
+
+// The host of `url`: the authority without user info and port
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    if host.starts_with('[') {
+        return host.split_once(']').map_or(host, |(address, _)| &host[..address.len() + 1]);
+    }
+    host.split(':').next().unwrap_or_default()
+}
+
+// An address located in a line, before it is classified
+struct Found {
+    start: usize,
+    end: usize,
+    kind: &'static str,
+    host: String,
+    scheme: Option<String>,
+}
+
+fn line_addresses(file_path: &str, line_number: usize, line: &str, out: &mut Vec<AddressHit>) {
+    let mut found: Vec<Found> = Vec::new();
+    for m in url_regex().find_iter(line) {
+        let url = trim_url(m.as_str());
+        let host = url_host(url);
+        if host.is_empty() {
+            continue;
+        }
+        let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        found.push(Found { start: m.start(), end: m.start() + url.len(), kind: "url", host: host.to_string(), scheme });
+    }
+    // Addresses inside a URL, or inside an email for IPs, are reported as
+    // that only
+    let overlaps = |start: usize, end: usize, found: &[Found]| found.iter().any(|other| start < other.end && end > other.start);
+    for m in email_regex().find_iter(line) {
+        if !overlaps(m.start(), m.end(), &found) {
+            let domain = m.as_str().rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default();
+            found.push(Found { start: m.start(), end: m.end(), kind: "email", host: domain.to_string(), scheme: None });
+        }
+    }
+    let bytes = line.as_bytes();
+    for m in ip_regex().find_iter(line) {
+        let start = m.start();
+        let candidate = m.as_str().trim_end_matches('.');
+        let end = start + candidate.len();
+        // Part of a longer word, such as an identifier or a hex string
+        let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        if (start > 0 && is_word(bytes[start - 1])) || bytes.get(end).copied().is_some_and(is_word) {
+            continue;
+        }
+        if overlaps(start, end, &found) {
+            continue;
+        }
+        // Dotted quads only, so version numbers like 1.2.3 are left alone
+        let kind = if candidate.parse::<Ipv4Addr>().is_ok() && candidate.split('.').count() == 4 {
+            "ipv4"
+        } else if candidate.contains("::") || candidate.matches(':').count() == 7 {
+            // Two groups with digits, so `a::b2` in a path like Rust's is
+            // not taken for one
+            let numeric = candidate.split(':').filter(|group| group.bytes().any(|b| b.is_ascii_digit())).count();
+            match candidate.parse::<Ipv6Addr>() {
+                Ok(ip) if numeric >= 2 || ip.is_loopback() => "ipv6",
+                _ => continue,
+            }
+        } else {
+            continue;
+        };
+        found.push(Found { start, end, kind, host: candidate.to_string(), scheme: None });
+    }
+    found.sort_by_key(|found| found.start);
+    for Found { start, end, kind, host, scheme } in found {
+        let insecure = scheme.as_deref().is_some_and(|scheme| matches!(scheme, "http" | "ws" | "ftp" | "telnet"));
+        out.push(AddressHit {
+            file_path: file_path.to_string(),
+            line_number,
+            column: start + 1,
+            end_column: end + 1,
+            kind: kind.to_string(),
+            value: line[start..end].to_string(),
+            classification: classify_host(&host).to_string(),
+            host,
+            scheme,
+            insecure,
+            snippet: line.trim().to_string(),
+        });
+    }
+}
+
+fn file_addresses(file_path: &str) -> Vec<AddressHit> {
+    let Ok(bytes) = std::fs::read(file_path) else { return Vec::new() };
+    let mut out = Vec::new();
+    for (ln, line) in split_lines(&bytes).into_iter().enumerate() {
+        line_addresses(file_path, ln + 1, &String::from_utf8_lossy(line), &mut out);
+    }
+    out
+}
+
+/// URLs (any `scheme://`), IPv4 and IPv6 addresses and email addresses in
+/// each file, in line and then column order, each classified by where its
+/// host points. An address within a URL is reported as part of the URL
+/// only, and trailing punctuation such as a sentence's full stop is left
+/// out of a URL.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn extract_addresses(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<AddressHit>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_addresses(path))
+}
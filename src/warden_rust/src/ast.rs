@@ -0,0 +1,1298 @@
+use crate::encoding;
+use crate::taint::{assignment_parts, is_member_kind, is_name_kind};
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::rc::Rc;
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor, QueryError, QueryErrorKind, Tree};
+
+#[pyclass]
+#[derive(Clone)]
+pub struct AstNodeInfo {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub line_number: usize,
+    #[pyo3(get)]
+    pub code_snippet: String,
+    // `name` prefixed with the enclosing classes, functions and namespaces
+    // of the same file, joined by dots (`Foo.save`); imports keep `name`
+    #[pyo3(get)]
+    pub qualified_name: String,
+    // Python docstring, or the comment block right above the definition
+    // (JSDoc, Go doc comments, `///`...) without its comment markers; None
+    // for imports and undocumented symbols
+    #[pyo3(get)]
+    pub docstring: Option<String>,
+    // Decorators, annotations and attributes attached to the definition, as
+    // written (`@app.route("/")`, `@Override`, `#[test]`, `[HttpGet]`)
+    #[pyo3(get)]
+    pub decorators: Vec<String>,
+    // Function signature where the grammar spells it out; empty and None
+    // for classes and imports
+    #[pyo3(get)]
+    pub parameters: Vec<AstParameter>,
+    #[pyo3(get)]
+    pub return_type: Option<String>,
+    // `async def`, `async function`, C# `async`, Kotlin `suspend`...; a
+    // generator yields in its own body (or is a JS `function*`)
+    #[pyo3(get)]
+    pub is_async: bool,
+    #[pyo3(get)]
+    pub is_generator: bool,
+    // Span of the whole definition (body included), unlike `line_number`
+    // which is where the name is: 1-based lines and byte columns, byte
+    // offsets into the UTF-8 content, ends exclusive
+    #[pyo3(get)]
+    pub start_line: usize,
+    #[pyo3(get)]
+    pub start_column: usize,
+    #[pyo3(get)]
+    pub end_line: usize,
+    #[pyo3(get)]
+    pub end_column: usize,
+    #[pyo3(get)]
+    pub start_byte: usize,
+    #[pyo3(get)]
+    pub end_byte: usize,
+}
+
+/// One parameter of a function definition. `name` is the parameter as
+/// written when it is not a plain name (`*args`, `&self`, `{a, b}`).
+#[pyclass]
+#[derive(Clone)]
+pub struct AstParameter {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub type_annotation: Option<String>,
+    #[pyo3(get)]
+    pub default: Option<String>,
+}
+
+#[pymethods]
+impl AstParameter {
+    fn __repr__(&self) -> String {
+        let annotation = self.type_annotation.as_ref().map_or(String::new(), |t| format!(": {}", t));
+        let default = self.default.as_ref().map_or(String::new(), |d| format!(" = {}", d));
+        format!("AstParameter({}{}{})", self.name, annotation, default)
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct AstMetadata {
+    #[pyo3(get)]
+    pub functions: Vec<AstNodeInfo>,
+    #[pyo3(get)]
+    pub classes: Vec<AstNodeInfo>,
+    #[pyo3(get)]
+    pub imports: Vec<AstNodeInfo>,
+    // Every identifier in the file, definitions and repeats included
+    #[pyo3(get)]
+    pub references: Vec<String>,
+    // Only with `reference_details=True`: `references` in order as `(name,
+    // line, column, is_definition)`, 1-based with byte columns;
+    // `is_definition` marks where a function, class, import binding,
+    // parameter or variable is defined
+    #[pyo3(get)]
+    pub located_references: Vec<(String, usize, usize, bool)>,
+    // Only with `reference_details=True`: how often each name is used,
+    // leaving out where it is defined and the uses of a parameter or local
+    // variable that shadows it
+    #[pyo3(get)]
+    pub reference_counts: HashMap<String, usize>,
+    // `(caller, callee, line)` for every call: the caller's qualified name
+    // ("<module>" outside any function), the name the callee is called by
+    // (the method name for `obj.save()`) and the 1-based line of that name
+    #[pyo3(get)]
+    pub calls: Vec<(String, String, usize)>,
+    // `(line, column, message)` for every syntax error the parser recovered
+    // from, 1-based with byte columns. The rest of the metadata is still
+    // filled in from the parts that did parse
+    #[pyo3(get)]
+    pub parse_errors: Vec<(usize, usize, String)>,
+    #[pyo3(get)]
+    pub has_errors: bool,
+    // Size of the syntax tree: every node, punctuation included; levels
+    // from the root, which alone is 1; and leaf tokens with any text
+    #[pyo3(get)]
+    pub node_count: usize,
+    #[pyo3(get)]
+    pub max_depth: usize,
+    #[pyo3(get)]
+    pub token_count: usize,
+}
+
+pub(crate) fn get_language_parser(lang: &str) -> Option<Language> {
+    match lang {
+        "python" => Some(tree_sitter_python::language()),
+        "typescript" => Some(tree_sitter_typescript::language_typescript()),
+        "javascript" => Some(tree_sitter_javascript::language()),
+        "go" => Some(tree_sitter_go::language()),
+        "java" => Some(tree_sitter_java::language()),
+        "rust" => Some(tree_sitter_rust::language()),
+        "c" => Some(tree_sitter_c::language()),
+        "cpp" => Some(tree_sitter_cpp::language()),
+        "csharp" => Some(tree_sitter_c_sharp::language()),
+        "ruby" => Some(tree_sitter_ruby::language()),
+        "php" => Some(tree_sitter_php::language()),
+        "kotlin" => Some(tree_sitter_kotlin::language()),
+        "swift" => Some(tree_sitter_swift::language()),
+        _ => None,
+    }
+}
+
+// Queries for definitions and references. Definition patterns capture the
+// name as @name and the whole definition as @definition; any other capture
+// only serves a predicate
+fn get_queries(lang: &str) -> (&'static str, &'static str, &'static str, &'static str) {
+    match lang {
+        "python" => (
+            "(function_definition name: (identifier) @name) @definition",
+            "(class_definition name: (identifier) @name) @definition",
+            "(import_from_statement (dotted_name (identifier) @name)) @definition (import_statement (dotted_name (identifier) @name)) @definition",
+            "(identifier) @name" // Capture ALL identifiers as references
+        ),
+        "java" => (
+            "(method_declaration name: (identifier) @name) @definition (constructor_declaration name: (identifier) @name) @definition",
+            "(class_declaration name: (identifier) @name) @definition (interface_declaration name: (identifier) @name) @definition (enum_declaration name: (identifier) @name) @definition",
+            "(import_declaration (scoped_identifier) @name) @definition",
+            "(identifier) @name"
+        ),
+        "typescript" | "javascript" => (
+            "(function_declaration name: (identifier) @name) @definition (generator_function_declaration name: (identifier) @name) @definition (method_definition name: (property_identifier) @name) @definition",
+            "(class_declaration name: (type_identifier) @name) @definition",
+            "(import_statement (import_clause (named_imports (import_specifier name: (identifier) @name)))) @definition",
+            "(identifier) @name"
+        ),
+         "go" => (
+            "(function_declaration name: (identifier) @name) @definition (method_declaration name: (field_identifier) @name) @definition",
+            "(type_declaration (type_spec name: (type_identifier) @name)) @definition",
+            "(import_spec path: (interpreted_string_literal) @name) @definition",
+            "(identifier) @name"
+        ),
+        "rust" => (
+            "(function_item name: (identifier) @name) @definition",
+            "(struct_item name: (type_identifier) @name) @definition (enum_item name: (type_identifier) @name) @definition (trait_item name: (type_identifier) @name) @definition",
+            "(use_declaration argument: (_) @name) @definition",
+            "(identifier) @name"
+        ),
+        "c" => (
+            "(function_definition declarator: (function_declarator declarator: (identifier) @name)) @definition (function_definition declarator: (pointer_declarator declarator: (function_declarator declarator: (identifier) @name))) @definition",
+            "(struct_specifier name: (type_identifier) @name body: (_)) @definition (enum_specifier name: (type_identifier) @name body: (_)) @definition",
+            "(preproc_include path: (_) @name) @definition",
+            "(identifier) @name"
+        ),
+        "cpp" => (
+            "(function_definition declarator: (function_declarator declarator: [(identifier) (field_identifier) (qualified_identifier) (destructor_name)] @name)) @definition (function_definition declarator: (pointer_declarator declarator: (function_declarator declarator: [(identifier) (field_identifier) (qualified_identifier)] @name))) @definition",
+            "(class_specifier name: (type_identifier) @name body: (_)) @definition (struct_specifier name: (type_identifier) @name body: (_)) @definition",
+            "(preproc_include path: (_) @name) @definition",
+            "(identifier) @name"
+        ),
+        "csharp" => (
+            "(method_declaration name: (identifier) @name) @definition (constructor_declaration name: (identifier) @name) @definition (local_function_statement name: (identifier) @name) @definition",
+            "(class_declaration name: (identifier) @name) @definition (interface_declaration name: (identifier) @name) @definition (struct_declaration name: (identifier) @name) @definition (enum_declaration name: (identifier) @name) @definition",
+            "(using_directive [(identifier) (qualified_name)] @name) @definition",
+            "(identifier) @name"
+        ),
+        "ruby" => (
+            "(method name: (_) @name) @definition (singleton_method name: (_) @name) @definition",
+            "(class name: (_) @name) @definition (module name: (_) @name) @definition",
+            "(call method: (identifier) @method arguments: (argument_list (string (string_content) @name)) (#match? @method \"^require(_relative)?$\")) @definition",
+            "(identifier) @name"
+        ),
+        "php" => (
+            "(function_definition name: (name) @name) @definition (method_declaration name: (name) @name) @definition",
+            "(class_declaration name: (name) @name) @definition (interface_declaration name: (name) @name) @definition (trait_declaration name: (name) @name) @definition",
+            "(namespace_use_clause [(name) (qualified_name)] @name) @definition",
+            "(name) @name"
+        ),
+        "kotlin" => (
+            "(function_declaration (simple_identifier) @name) @definition",
+            "(class_declaration (type_identifier) @name) @definition (object_declaration (type_identifier) @name) @definition",
+            "(import_header (identifier) @name) @definition",
+            "(simple_identifier) @name"
+        ),
+        "swift" => (
+            "(function_declaration name: (simple_identifier) @name) @definition",
+            "(class_declaration name: (_) @name) @definition (protocol_declaration name: (type_identifier) @name) @definition",
+            "(import_declaration (identifier) @name) @definition",
+            "(simple_identifier) @name"
+        ),
+        _ => ("", "", "", "") 
+    }
+}
+
+// Query capturing as @name the called name of every call expression
+fn call_query(lang: &str) -> &'static str {
+    match lang {
+        "python" => "(call function: [(identifier) @name (attribute attribute: (identifier) @name)])",
+        "typescript" | "javascript" => "(call_expression function: [(identifier) @name (member_expression property: (property_identifier) @name)]) (new_expression constructor: (identifier) @name)",
+        "go" => "(call_expression function: [(identifier) @name (selector_expression field: (field_identifier) @name)])",
+        "rust" => "(call_expression function: [(identifier) @name (field_expression field: (field_identifier) @name) (scoped_identifier name: (identifier) @name)])",
+        "java" => "(method_invocation name: (identifier) @name) (object_creation_expression type: (type_identifier) @name)",
+        "c" => "(call_expression function: (identifier) @name)",
+        "cpp" => "(call_expression function: [(identifier) @name (field_expression field: (field_identifier) @name) (qualified_identifier name: (identifier) @name)])",
+        "csharp" => "(invocation_expression function: [(identifier) @name (member_access_expression name: (identifier) @name)]) (object_creation_expression type: (identifier) @name)",
+        "ruby" => "(call method: (identifier) @name)",
+        "php" => "(function_call_expression function: (name) @name) (member_call_expression name: (name) @name) (scoped_call_expression name: (name) @name)",
+        "kotlin" => "(call_expression (simple_identifier) @name) (call_expression (navigation_expression (navigation_suffix (simple_identifier) @name)))",
+        "swift" => "(call_expression (simple_identifier) @name) (call_expression (navigation_expression suffix: (navigation_suffix suffix: (simple_identifier) @name)))",
+        _ => "",
+    }
+}
+
+// The module each import names, as @name, in its statement, as @definition
+fn module_query(lang: &str) -> &'static str {
+    match lang {
+        "python" => "(import_statement name: [(dotted_name) @name (aliased_import name: (dotted_name) @name)]) @definition (import_from_statement module_name: (_) @name) @definition",
+        "javascript" => "(import_statement source: (string) @name) @definition (export_statement source: (string) @name) @definition (call_expression function: [(identifier) @f (import)] arguments: (arguments . (string) @name) (#match? @f \"^(require|import)$\")) @definition",
+        "typescript" => "(import_statement source: (string) @name) @definition (export_statement source: (string) @name) @definition (call_expression function: [(identifier) @f (import)] arguments: (arguments . (string) @name) (#match? @f \"^(require|import)$\")) @definition (import_require_clause source: (string) @name) @definition",
+        "go" => "(import_spec path: (_) @name) @definition",
+        "rust" => "(use_declaration argument: (_) @name) @definition (mod_item name: (identifier) @name !body) @definition",
+        "java" => "(import_declaration [(scoped_identifier) (identifier)] @name) @definition",
+        "c" | "cpp" => "(preproc_include path: (_) @name) @definition",
+        "csharp" => "(using_directive [(identifier) (qualified_name)] @name) @definition",
+        "ruby" => "(call method: (identifier) @f arguments: (argument_list . (string (string_content) @name)) (#match? @f \"^(require|require_relative|load)$\")) @definition",
+        "php" => "(namespace_use_clause [(name) (qualified_name)] @name) @definition (namespace_use_declaration (namespace_name) @name) @definition [(include_expression [(string) @name (parenthesized_expression (string) @name) (binary_expression right: (string) @name)]) (include_once_expression [(string) @name (parenthesized_expression (string) @name) (binary_expression right: (string) @name)]) (require_expression [(string) @name (parenthesized_expression (string) @name) (binary_expression right: (string) @name)]) (require_once_expression [(string) @name (parenthesized_expression (string) @name) (binary_expression right: (string) @name)])] @definition",
+        "kotlin" => "(import_header (identifier) @name) @definition",
+        "swift" => "(import_declaration (identifier) @name) @definition",
+        _ => "",
+    }
+}
+
+// The names imports bind in the file, as @name, in their statement, as
+// @definition. Where the statement does not spell the name out (an
+// unaliased Go, Java, Kotlin or PHP import), @name is the imported path
+fn binding_query(lang: &str) -> &'static str {
+    match lang {
+        "python" => "(import_statement name: (dotted_name . (identifier) @name)) @definition (import_statement name: (aliased_import alias: (identifier) @name)) @definition (import_from_statement name: (dotted_name (identifier) @name .)) @definition (import_from_statement name: (aliased_import alias: (identifier) @name)) @definition",
+        "javascript" | "typescript" => "(import_statement (import_clause (identifier) @name)) @definition (import_statement (import_clause (namespace_import (identifier) @name))) @definition (import_statement (import_clause (named_imports (import_specifier !alias name: (identifier) @name)))) @definition (import_statement (import_clause (named_imports (import_specifier alias: (identifier) @name)))) @definition",
+        "go" | "java" | "kotlin" => module_query(lang),
+        "php" => "(namespace_use_clause [(name) (qualified_name)] @name) @definition",
+        _ => "",
+    }
+}
+
+// Node kinds that put their name in front of the definitions nested in them
+fn scope_kinds(lang: &str) -> &'static [&'static str] {
+    match lang {
+        "python" => &["class_definition", "function_definition"],
+        "typescript" | "javascript" => &[
+            "class_declaration", "abstract_class_declaration", "interface_declaration", "internal_module",
+            "function_declaration", "method_definition",
+        ],
+        "java" => &[
+            "class_declaration", "interface_declaration", "enum_declaration", "method_declaration",
+            "constructor_declaration",
+        ],
+        "rust" => &["mod_item", "impl_item", "trait_item", "function_item"],
+        "cpp" => &["namespace_definition", "class_specifier", "struct_specifier"],
+        "csharp" => &[
+            "namespace_declaration", "class_declaration", "interface_declaration", "struct_declaration",
+            "record_declaration", "method_declaration", "constructor_declaration", "local_function_statement",
+        ],
+        "ruby" => &["module", "class", "method", "singleton_method"],
+        "php" => &[
+            "namespace_definition", "class_declaration", "interface_declaration", "trait_declaration",
+            "function_definition", "method_declaration",
+        ],
+        "kotlin" => &["class_declaration", "object_declaration", "function_declaration"],
+        "swift" => &["class_declaration", "protocol_declaration", "function_declaration"],
+        _ => &[],
+    }
+}
+
+// The name a scope node gives its members: the `name` field, the implemented
+// type of a Rust impl block, or the first identifier child where the grammar
+// has no field (Kotlin)
+fn scope_name<'a>(node: Node, language: &str, source: &'a [u8]) -> Option<&'a str> {
+    let name = if language == "rust" && node.kind() == "impl_item" {
+        node.child_by_field_name("type")
+    } else {
+        node.child_by_field_name("name").or_else(|| {
+            let mut cursor = node.walk();
+            let found = node.named_children(&mut cursor)
+                .find(|c| matches!(c.kind(), "identifier" | "type_identifier" | "simple_identifier"));
+            found
+        })
+    }?;
+    let text = name.utf8_text(source).ok()?;
+    // `impl<T> Foo<T>` is about Foo
+    Some(text.split('<').next().unwrap_or(text).trim())
+}
+
+// The receiver type of a Go method, `T` in `func (r *T) m()`
+fn go_receiver<'a>(node: Node, source: &'a [u8]) -> Option<&'a str> {
+    let receiver = node.child_by_field_name("receiver")?;
+    let mut stack = vec![receiver];
+    while let Some(node) = stack.pop() {
+        if node.kind() == "type_identifier" {
+            return node.utf8_text(source).ok();
+        }
+        let mut cursor = node.walk();
+        let children: Vec<_> = node.named_children(&mut cursor).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    None
+}
+
+// `name` prefixed with the names of the scopes enclosing `definition`
+pub(crate) fn qualified_name(definition: Node, name: &str, language: &str, source: &[u8]) -> String {
+    let kinds = scope_kinds(language);
+    let mut parts = vec![name];
+    if language == "go" && definition.kind() == "method_declaration" {
+        parts.extend(go_receiver(definition, source));
+    }
+    let mut node = definition.parent();
+    while let Some(current) = node {
+        if kinds.contains(&current.kind()) {
+            parts.extend(scope_name(current, language, source));
+        }
+        node = current.parent();
+    }
+    parts.reverse();
+    parts.join(".")
+}
+
+// Like Python's inspect.cleandoc: drops the common indentation of every line
+// after the first, and blank lines at both ends
+fn clean_doc(lines: &[&str]) -> Option<String> {
+    let indent = lines.iter().skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = lines.iter().enumerate()
+        .map(|(i, line)| if i == 0 { line.trim() } else { line.get(indent..).unwrap_or(line.trim_start()).trim_end() })
+        .collect();
+    let start = lines.iter().position(|line| !line.is_empty())?;
+    let end = lines.iter().rposition(|line| !line.is_empty())?;
+    Some(lines[start..=end].join("\n"))
+}
+
+// The string literal opening a Python function or class body
+fn python_docstring(definition: Node, source: &[u8]) -> Option<String> {
+    let body = definition.child_by_field_name("body")?;
+    let first = body.named_child(0)?;
+    let string = first.named_child(0).filter(|s| first.kind() == "expression_statement" && s.kind() == "string")?;
+    let text = string.utf8_text(source).ok()?;
+    let text = text.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let quote = ["\"\"\"", "'''", "\"", "'"].into_iter().find(|q| text.starts_with(q))?;
+    let inner = text.strip_prefix(quote)?.strip_suffix(quote)?;
+    clean_doc(&inner.lines().collect::<Vec<_>>())
+}
+
+// A comment's text without `//`, `///`, `#`, `/**`, ` * ` and `*/`
+fn comment_text(comment: &str) -> Vec<&str> {
+    let block = comment.starts_with("/*");
+    let comment = comment.trim_start_matches("/*").trim_start_matches(['*', '!']);
+    let comment = if block { comment.trim_end().trim_end_matches("*/") } else { comment };
+    comment.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if block {
+                trimmed.strip_prefix('*').map_or(line, |rest| rest.strip_prefix(' ').unwrap_or(rest))
+            } else {
+                let rest = trimmed.trim_start_matches('/').trim_start_matches(['!', '#']);
+                rest.strip_prefix(' ').unwrap_or(rest)
+            }
+        })
+        .collect()
+}
+
+// The comments directly above `definition` (or above the export statement
+// or decorators wrapping it), with no blank line in between; attributes
+// between the comments and the definition are skipped
+fn leading_comments(definition: Node, source: &[u8]) -> Option<String> {
+    let mut node = definition;
+    while let Some(parent) = node.parent().filter(|p| matches!(p.kind(), "export_statement" | "decorated_definition")) {
+        node = parent;
+    }
+    let mut comments = Vec::new();
+    let mut next_row = node.start_position().row;
+    let mut sibling = node.prev_named_sibling();
+    while let Some(current) = sibling {
+        if current.end_position().row + 1 < next_row {
+            break;
+        }
+        if current.kind().contains("comment") {
+            comments.push(current.utf8_text(source).ok()?);
+        } else if !matches!(current.kind(), "attribute_item" | "attribute" | "decorator" | "annotation") {
+            break;
+        }
+        next_row = current.start_position().row;
+        sibling = current.prev_named_sibling();
+    }
+    comments.reverse();
+    let lines: Vec<&str> = comments.into_iter().flat_map(comment_text).collect();
+    clean_doc(&lines)
+}
+
+const DECORATOR_KINDS: &[&str] =
+    &["decorator", "annotation", "marker_annotation", "attribute_item", "attribute_list", "attribute"];
+
+// Decorators of `definition` in source order: its own decorator children and
+// those of its modifier list, the decorators of a wrapping decorated or
+// export statement, and decorators right before it among its siblings (Rust
+// attributes, TypeScript method decorators)
+pub(crate) fn decorators(definition: Node, source: &[u8]) -> Vec<String> {
+    fn collect<'t>(parent: Node<'t>, modifiers: bool, found: &mut Vec<Node<'t>>) {
+        let mut cursor = parent.walk();
+        for child in parent.named_children(&mut cursor) {
+            if DECORATOR_KINDS.contains(&child.kind()) {
+                found.push(child);
+            } else if modifiers && child.kind() == "modifiers" {
+                collect(child, false, found);
+            }
+        }
+    }
+    let mut found = Vec::new();
+    collect(definition, true, &mut found);
+    let mut node = definition;
+    while let Some(parent) = node.parent().filter(|p| matches!(p.kind(), "export_statement" | "decorated_definition")) {
+        collect(parent, false, &mut found);
+        node = parent;
+    }
+    let mut sibling = node.prev_named_sibling();
+    while let Some(current) = sibling.filter(|s| DECORATOR_KINDS.contains(&s.kind())) {
+        found.push(current);
+        sibling = current.prev_named_sibling();
+    }
+    found.sort_by_key(|n| n.start_byte());
+    found.dedup();
+    found.iter().filter_map(|n| n.utf8_text(source).ok()).map(|text| text.trim().to_string()).collect()
+}
+
+// Text of the first of `fields` that `node` has, without the `:`, `->` or
+// `=` some grammars keep in the node
+fn field_text(node: Node, fields: &[&str], source: &[u8]) -> Option<String> {
+    let child = fields.iter().find_map(|field| node.child_by_field_name(field))?;
+    let text = child.utf8_text(source).ok()?;
+    Some(text.trim_start_matches([':', '-', '>', '=']).trim().to_string())
+}
+
+// The node holding a function's parameters
+pub(crate) fn parameter_list<'t>(definition: Node<'t>, language: &str) -> Option<Node<'t>> {
+    match language {
+        // The parameters belong to the (possibly pointer-wrapped) declarator
+        "c" | "cpp" => {
+            let mut declarator = definition.child_by_field_name("declarator");
+            while let Some(node) = declarator {
+                if node.kind() == "function_declarator" {
+                    return node.child_by_field_name("parameters");
+                }
+                declarator = node.child_by_field_name("declarator");
+            }
+            None
+        }
+        "kotlin" => {
+            let mut cursor = definition.walk();
+            let list = definition.named_children(&mut cursor).find(|c| c.kind() == "function_value_parameters");
+            list
+        }
+        // Swift parameters are children of the declaration itself
+        "swift" => Some(definition),
+        _ => definition.child_by_field_name("parameters"),
+    }
+}
+
+// The name a C declarator declares, under any pointers and references
+fn declarator_name<'a>(mut node: Node, source: &'a [u8]) -> Option<&'a str> {
+    while let Some(inner) = node.child_by_field_name("declarator") {
+        node = inner;
+    }
+    let mut cursor = node.walk();
+    let name = node.named_children(&mut cursor).last().filter(|_| node.kind().ends_with("declarator")).unwrap_or(node);
+    name.utf8_text(source).ok()
+}
+
+fn parameter(node: Node, source: &[u8]) -> AstParameter {
+    let text = || node.utf8_text(source).unwrap_or_default().to_string();
+    // Variadic, destructured and self parameters are kept as written
+    let as_written = ["splat", "variadic", "spread", "rest", "self", "block", "object_pattern", "array_pattern"];
+    let name = if node.named_child_count() == 0 || as_written.iter().any(|k| node.kind().contains(k)) {
+        text()
+    } else if let Some(declarator) = node.child_by_field_name("declarator") {
+        declarator_name(declarator, source).map_or_else(text, str::to_string)
+    } else {
+        ["name", "pattern", "left"].iter()
+            .find_map(|field| node.child_by_field_name(field))
+            .or_else(|| node.named_child(0))
+            .and_then(|n| n.utf8_text(source).ok())
+            .map_or_else(text, str::to_string)
+    };
+    let mut default = field_text(node, &["value", "default_value", "right"], source);
+    if default.is_none() {
+        let mut cursor = node.walk();
+        let clause = node.named_children(&mut cursor).find(|c| c.kind() == "equals_value_clause");
+        default = clause.and_then(|c| c.utf8_text(source).ok()).map(|t| t.trim_start_matches('=').trim().to_string());
+    }
+    // Kotlin types have no field name
+    let type_annotation = field_text(node, &["type"], source).or_else(|| {
+        let mut cursor = node.walk();
+        let found = node.named_children(&mut cursor).skip(1).find(|c| c.kind().ends_with("_type"));
+        found.and_then(|c| c.utf8_text(source).ok()).map(str::to_string)
+    });
+    AstParameter { name, type_annotation, default }
+}
+
+pub(crate) fn parameters(definition: Node, language: &str, source: &[u8]) -> Vec<AstParameter> {
+    let Some(list) = parameter_list(definition, language) else { return vec![] };
+    let mut parameters: Vec<AstParameter> = Vec::new();
+    // The type of a C# `params` array, which the grammar leaves loose in the list
+    let mut params_type = None;
+    let mut cursor = list.walk();
+    for child in list.named_children(&mut cursor) {
+        match child.kind() {
+            kind if kind.contains("comment") => {}
+            "keyword_separator" | "positional_separator" | "parameter_modifiers" => {}
+            // Go lets several names share one type: `a, b int`
+            "parameter_declaration" | "variadic_parameter_declaration" if language == "go" => {
+                let variadic = if child.kind() == "variadic_parameter_declaration" { "..." } else { "" };
+                let type_annotation = field_text(child, &["type"], source).map(|t| format!("{}{}", variadic, t));
+                let mut names = child.walk();
+                let names: Vec<_> = child.children_by_field_name("name", &mut names).collect();
+                if names.is_empty() {
+                    parameters.push(AstParameter { name: String::new(), type_annotation: type_annotation.clone(), default: None });
+                }
+                for name in names {
+                    let name = name.utf8_text(source).unwrap_or_default().to_string();
+                    parameters.push(AstParameter { name, type_annotation: type_annotation.clone(), default: None });
+                }
+            }
+            "parameter" => parameters.push(parameter(child, source)),
+            // Kotlin and Swift default values follow their parameter
+            _ if matches!(language, "kotlin" | "swift") => {
+                if let Some(last) = parameters.last_mut().filter(|_| child.prev_sibling().is_some_and(|p| p.kind() == "=")) {
+                    last.default = child.utf8_text(source).ok().map(str::to_string);
+                }
+            }
+            kind if language == "csharp" && kind.ends_with("_type") => {
+                params_type = child.utf8_text(source).ok().map(|t| format!("params {}", t));
+            }
+            "identifier" if language == "csharp" => {
+                let name = child.utf8_text(source).unwrap_or_default().to_string();
+                parameters.push(AstParameter { name, type_annotation: params_type.take(), default: None });
+            }
+            _ => parameters.push(parameter(child, source)),
+        }
+    }
+    parameters
+}
+
+// The declared return type of a function
+fn return_type(definition: Node, language: &str, source: &[u8]) -> Option<String> {
+    match language {
+        // Kotlin puts the type after the parameters without a field name
+        "kotlin" => {
+            let mut cursor = definition.walk();
+            let children: Vec<_> = definition.named_children(&mut cursor).collect();
+            let parameters = children.iter().position(|c| c.kind() == "function_value_parameters")?;
+            children.get(parameters + 1)
+                .filter(|c| c.kind().ends_with("_type"))
+                .and_then(|c| c.utf8_text(source).ok())
+                .map(str::to_string)
+        }
+        _ => field_text(definition, &["return_type", "result", "returns", "type"], source),
+    }
+}
+
+// Whether an `async` (or Kotlin `suspend`) keyword sits among the
+// function's own tokens or modifiers
+fn is_async(definition: Node, source: &[u8]) -> bool {
+    let mut cursor = definition.walk();
+    let found = definition.children(&mut cursor).any(|child| match child.kind() {
+        "async" | "suspend" => true,
+        "modifier" | "modifiers" | "function_modifiers" => child.utf8_text(source)
+            .is_ok_and(|text| text.split_whitespace().any(|word| matches!(word, "async" | "suspend"))),
+        _ => false,
+    });
+    found
+}
+
+// Whether the function yields, not counting functions, lambdas and classes
+// nested in it. Ruby's `yield` calls a block, so it does not count
+fn is_generator(definition: Node, language: &str) -> bool {
+    if language == "ruby" {
+        return false;
+    }
+    let mut cursor = definition.walk();
+    let mut stack: Vec<Node> = definition.children(&mut cursor).collect();
+    // `function* f()` and `*m()` in JavaScript
+    if definition.kind() == "generator_function_declaration" || stack.iter().any(|c| c.kind() == "*") {
+        return true;
+    }
+    while let Some(node) = stack.pop() {
+        let kind = node.kind();
+        if matches!(kind, "yield" | "yield_expression" | "yield_statement") {
+            return true;
+        }
+        if ["function", "lambda", "method", "class", "closure"].iter().any(|k| kind.contains(k)) {
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+    false
+}
+
+fn docstring(definition: Node, language: &str, source: &[u8]) -> Option<String> {
+    match language {
+        "python" => python_docstring(definition, source).or_else(|| leading_comments(definition, source)),
+        _ => leading_comments(definition, source),
+    }
+}
+
+/// `reference_details=True` also fills in `located_references` and
+/// `reference_counts`, which are left empty otherwise to save the memory.
+#[pyfunction]
+#[pyo3(signature = (content, language, reference_details=false))]
+pub fn get_ast_metadata(content: String, language: String, reference_details: bool) -> PyResult<AstMetadata> {
+    Ok(ast_metadata(&content, &language, reference_details))
+}
+
+// Parsers and compiled queries are expensive to set up, so each thread keeps
+// one parser per language and every query it compiled, keyed by language and
+// query source
+type QueryCache = HashMap<(String, &'static str), Option<Rc<Query>>>;
+
+thread_local! {
+    static PARSERS: RefCell<HashMap<String, Parser>> = RefCell::new(HashMap::new());
+    static QUERIES: RefCell<QueryCache> = RefCell::new(HashMap::new());
+}
+
+// Parses `content` with this thread's parser for `language`, reusing the
+// unchanged parts of `old` when given; `old` must already reflect the edits
+// made since it was parsed
+pub(crate) fn parse(content: &str, language: &str, grammar: Language, old: Option<&Tree>) -> Option<Tree> {
+    PARSERS.with(|parsers| {
+        let mut parsers = parsers.borrow_mut();
+        let parser = match parsers.entry(language.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let mut parser = Parser::new();
+                if parser.set_language(grammar).is_err() {
+                    eprintln!("[RUST ERROR] Failed to set language parser for: {}", language);
+                    return None;
+                }
+                entry.insert(parser)
+            }
+        };
+        let tree = parser.parse(content, old);
+        if tree.is_none() {
+            eprintln!("[RUST ERROR] Failed to parse content for language: {}", language);
+            // A parse that gave up can leave state behind for the next one
+            parser.reset();
+        }
+        tree
+    })
+}
+
+// This thread's compiled copy of `source`; None if it does not compile
+fn query(language: &str, grammar: Language, source: &'static str) -> Option<Rc<Query>> {
+    QUERIES.with(|queries| {
+        queries.borrow_mut()
+            .entry((language.to_string(), source))
+            .or_insert_with(|| Query::new(grammar, source).ok().map(Rc::new))
+            .clone()
+    })
+}
+
+impl AstMetadata {
+    pub(crate) fn empty() -> Self {
+        AstMetadata {
+            functions: vec![],
+            classes: vec![],
+            imports: vec![],
+            references: vec![],
+            located_references: vec![],
+            reference_counts: HashMap::new(),
+            calls: vec![],
+            parse_errors: vec![],
+            has_errors: false,
+            node_count: 0,
+            max_depth: 0,
+            token_count: 0,
+        }
+    }
+
+    // For content the parser gave up on altogether
+    pub(crate) fn unparsed() -> Self {
+        AstMetadata { parse_errors: vec![(1, 1, "Failed to parse".to_string())], has_errors: true, ..AstMetadata::empty() }
+    }
+}
+
+fn ast_metadata(content: &str, language: &str, reference_details: bool) -> AstMetadata {
+    let Some(grammar) = get_language_parser(language) else { return AstMetadata::empty() };
+    let Some(tree) = parse(content, language, grammar, None) else {
+        return AstMetadata::unparsed();
+    };
+    let mut metadata = tree_metadata(&tree, content, language, grammar);
+    if reference_details {
+        (metadata.located_references, metadata.reference_counts) = located_references(&tree, content, language, grammar);
+    }
+    metadata
+}
+
+// The (name, definition) node pairs a definition query finds; the
+// definition is the name itself when the pattern has no @definition
+fn definitions<'t>(root: Node<'t>, content: &str, language: &str, grammar: Language, source: &'static str) -> Vec<(Node<'t>, Node<'t>)> {
+    let mut found = Vec::new();
+    if source.is_empty() {
+        return found;
+    }
+    let Some(query) = query(language, grammar, source) else { return found };
+    let name_index = query.capture_index_for_name("name");
+    let definition_index = query.capture_index_for_name("definition");
+    let mut cursor = QueryCursor::new();
+    for m in cursor.matches(&query, root, content.as_bytes()) {
+        let definition = m.captures.iter().find(|c| Some(c.index) == definition_index).map(|c| c.node);
+        for capture in m.captures.iter().filter(|c| Some(c.index) == name_index) {
+            found.push((capture.node, definition.unwrap_or(capture.node)));
+        }
+    }
+    found
+}
+
+// The functions defined in `tree`, as (name, definition) node pairs
+pub(crate) fn function_definitions<'t>(tree: &'t Tree, content: &str, language: &str, grammar: Language) -> Vec<(Node<'t>, Node<'t>)> {
+    definitions(tree.root_node(), content, language, grammar, get_queries(language).0)
+}
+
+// The classes (and other type definitions) in `tree`, as (name, definition)
+// node pairs
+pub(crate) fn class_definitions<'t>(tree: &'t Tree, content: &str, language: &str, grammar: Language) -> Vec<(Node<'t>, Node<'t>)> {
+    definitions(tree.root_node(), content, language, grammar, get_queries(language).1)
+}
+
+// The modules `tree` imports, as (module, import statement) node pairs
+pub(crate) fn imported_modules<'t>(tree: &'t Tree, content: &str, language: &str, grammar: Language) -> Vec<(Node<'t>, Node<'t>)> {
+    definitions(tree.root_node(), content, language, grammar, module_query(language))
+}
+
+// The names `tree`'s imports bind, as in `binding_query`
+pub(crate) fn imported_bindings<'t>(tree: &'t Tree, content: &str, language: &str, grammar: Language) -> Vec<(Node<'t>, Node<'t>)> {
+    definitions(tree.root_node(), content, language, grammar, binding_query(language))
+}
+
+// Every identifier token in `tree`, in source order: the named leaves whose
+// kind is one (`identifier`, `property_identifier`, `type_identifier`,
+// Ruby's `constant`, PHP's `name`, ...), where `references` has only the
+// plain `identifier`s
+pub(crate) fn identifiers(tree: &Tree) -> Vec<Node<'_>> {
+    let mut found = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.child_count() == 0 {
+            if node.is_named() && (node.kind().ends_with("identifier") || matches!(node.kind(), "constant" | "name")) {
+                found.push(node);
+            }
+            continue;
+        }
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    found
+}
+
+// (node count, max depth, token count) of `tree`, as in `AstMetadata`
+fn tree_size(tree: &Tree) -> (usize, usize, usize) {
+    let (mut nodes, mut deepest, mut tokens) = (0, 0, 0);
+    let mut cursor = tree.walk();
+    let mut depth = 1;
+    loop {
+        let node = cursor.node();
+        nodes += 1;
+        deepest = deepest.max(depth);
+        if node.child_count() == 0 && node.end_byte() > node.start_byte() {
+            tokens += 1;
+        }
+        if cursor.goto_first_child() {
+            depth += 1;
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return (nodes, deepest, tokens);
+            }
+            depth -= 1;
+        }
+    }
+}
+
+// Where the parser had to skip text (ERROR nodes) or make up a token that
+// was not there (MISSING nodes)
+fn parse_errors(tree: &Tree, source: &[u8]) -> Vec<(usize, usize, String)> {
+    let mut errors = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let position = node.start_position();
+        if node.is_error() {
+            let text = node.utf8_text(source).unwrap_or_default();
+            let text = text.lines().next().unwrap_or_default().trim();
+            let message = match text.chars().count() {
+                0 => "Syntax error".to_string(),
+                1..=40 => format!("Unexpected '{}'", text),
+                _ => format!("Unexpected '{}...'", text.chars().take(40).collect::<String>()),
+            };
+            errors.push((position.row + 1, position.column + 1, message));
+            continue;
+        }
+        if node.is_missing() {
+            errors.push((position.row + 1, position.column + 1, format!("Missing '{}'", node.kind())));
+            continue;
+        }
+        if node.has_error() {
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    errors
+}
+
+// The definitions and references found in an already parsed `tree`
+pub(crate) fn tree_metadata(tree: &Tree, content: &str, language: &str, grammar: Language) -> AstMetadata {
+    let root_node = tree.root_node();
+
+    let (func_q, class_q, imp_q, ref_q) = get_queries(language);
+
+    let process_query = |query_str: &'static str, symbols: bool, functions: bool| -> Vec<AstNodeInfo> {
+        let mut results = Vec::new();
+        for (name, span) in definitions(root_node, content, language, grammar, query_str) {
+            if let Ok(text) = name.utf8_text(content.as_bytes()) {
+                let start_line = name.start_position().row + 1;
+                // Use parent for snippet context
+                let snippet = name.parent()
+                    .and_then(|p| p.utf8_text(content.as_bytes()).ok())
+                    .unwrap_or(text)
+                    .lines().next().unwrap_or(text).to_string(); // First line only
+
+                // Cut on a character boundary, as a byte slice may split one
+                let snippet = match snippet.char_indices().nth(200) {
+                    Some((end, _)) => format!("{}...", &snippet[..end]),
+                    None => snippet,
+                };
+
+                let (start, end) = (span.start_position(), span.end_position());
+                let source = content.as_bytes();
+                let (qualified_name, docstring, decorators) = if symbols {
+                    (qualified_name(span, text, language, source), docstring(span, language, source), decorators(span, source))
+                } else {
+                    (text.to_string(), None, vec![])
+                };
+                let (parameters, return_type) = if functions {
+                    (parameters(span, language, source), return_type(span, language, source))
+                } else {
+                    (vec![], None)
+                };
+                let is_async = functions && is_async(span, source);
+                let is_generator = functions && is_generator(span, language);
+                results.push(AstNodeInfo {
+                    name: text.to_string(),
+                    line_number: start_line,
+                    code_snippet: snippet,
+                    qualified_name,
+                    docstring,
+                    decorators,
+                    parameters,
+                    return_type,
+                    is_async,
+                    is_generator,
+                    start_line: start.row + 1,
+                    start_column: start.column + 1,
+                    end_line: end.row + 1,
+                    end_column: end.column + 1,
+                    start_byte: span.start_byte(),
+                    end_byte: span.end_byte(),
+                });
+            }
+        }
+        results
+    };
+
+    let function_definitions = definitions(root_node, content, language, grammar, func_q);
+
+    // Process references separately (simple string list)
+    let mut references = Vec::new();
+    if !ref_q.is_empty() {
+        if let Some(query) = query(language, grammar, ref_q) {
+             let mut cursor = QueryCursor::new();
+             for m in cursor.matches(&query, root_node, content.as_bytes()) {
+                for capture in m.captures {
+                    if let Ok(text) = capture.node.utf8_text(content.as_bytes()) {
+                        references.push(text.to_string());
+                    }
+                }
+             }
+        }
+    }
+    // Return all references (not deduped) to allow counting
+    // references.sort();
+    // references.dedup();
+
+    let parse_errors = parse_errors(tree, content.as_bytes());
+    let (node_count, max_depth, token_count) = tree_size(tree);
+
+    // Each call belongs to the innermost function around it
+    let callers: HashMap<usize, String> = function_definitions
+        .into_iter()
+        .filter_map(|(name, definition)| {
+            let name = name.utf8_text(content.as_bytes()).ok()?;
+            Some((definition.id(), qualified_name(definition, name, language, content.as_bytes())))
+        })
+        .collect();
+    let mut calls = Vec::new();
+    for (callee, _) in definitions(root_node, content, language, grammar, call_query(language)) {
+        let Ok(text) = callee.utf8_text(content.as_bytes()) else { continue };
+        let mut node = callee.parent();
+        let caller = loop {
+            match node {
+                Some(current) => match callers.get(&current.id()) {
+                    Some(caller) => break caller.clone(),
+                    None => node = current.parent(),
+                },
+                None => break "<module>".to_string(),
+            }
+        };
+        calls.push((caller, text.to_string(), callee.start_position().row + 1));
+    }
+
+    AstMetadata {
+        functions: process_query(func_q, true, true),
+        classes: process_query(class_q, true, false),
+        imports: process_query(imp_q, false, false),
+        references,
+        located_references: vec![],
+        reference_counts: HashMap::new(),
+        calls,
+        has_errors: !parse_errors.is_empty(),
+        parse_errors,
+        node_count,
+        max_depth,
+        token_count,
+    }
+}
+
+// The functions of a file as scopes, each with the names it binds itself:
+// its parameters and the targets of its assignments
+struct Scopes {
+    // (enclosing scope, bound names) by index; 0 is the top level, whose
+    // names never shadow, as they are what the other scopes refer to
+    scopes: Vec<(usize, HashSet<String>)>,
+    // The innermost scope around each name, by node id
+    scope_of: HashMap<usize, usize>,
+    // The names that bind a parameter or a local, by node id
+    binding: HashSet<usize>,
+}
+
+impl Scopes {
+    fn new(root: Node, functions: &[(Node, Node)], language: &str, source: &[u8]) -> Self {
+        let definitions: HashSet<usize> = functions.iter().map(|(_, definition)| definition.id()).collect();
+        let mut scopes = Scopes { scopes: vec![(0, HashSet::new())], scope_of: HashMap::new(), binding: HashSet::new() };
+        let mut stack = vec![(root, 0)];
+        while let Some((node, mut scope)) = stack.pop() {
+            if definitions.contains(&node.id()) {
+                scopes.scopes.push((scope, HashSet::new()));
+                scope = scopes.scopes.len() - 1;
+                // Variadic and `mut` parameters are named as written
+                let names: HashSet<String> = parameters(node, language, source).into_iter()
+                    .filter_map(|parameter| Some(parameter.name.trim_start_matches(['*', '&', '.']).rsplit(' ').next()?.to_string()))
+                    .collect();
+                if let Some(list) = parameter_list(node, language) {
+                    scopes.bind(scope, list, source, |name| names.contains(name));
+                }
+            } else if let Some((target, _, _)) = assignment_parts(node, source) {
+                if !is_member_kind(target.kind()) {
+                    scopes.bind(scope, target, source, |_| true);
+                }
+            }
+            if is_name_kind(node.kind()) {
+                scopes.scope_of.insert(node.id(), scope);
+            }
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.named_children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev().map(|child| (child, scope)));
+        }
+        scopes
+    }
+
+    // Binds the names under `node` that `wanted` picks in `scope`
+    fn bind(&mut self, scope: usize, node: Node, source: &[u8], wanted: impl Fn(&str) -> bool) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if is_name_kind(node.kind()) {
+                if let Ok(name) = node.utf8_text(source) {
+                    if wanted(name) {
+                        self.binding.insert(node.id());
+                        self.scopes[scope].1.insert(name.to_string());
+                    }
+                }
+                continue;
+            }
+            let mut cursor = node.walk();
+            stack.extend(node.named_children(&mut cursor));
+        }
+    }
+
+    // Whether `name` at `node` is a parameter or local of a scope around it
+    fn shadows(&self, node: Node, name: &str) -> bool {
+        let mut scope = self.scope_of.get(&node.id()).copied().unwrap_or(0);
+        while scope > 0 {
+            let (outer, names) = &self.scopes[scope];
+            if names.contains(name) {
+                return true;
+            }
+            scope = *outer;
+        }
+        false
+    }
+}
+
+// `(name, line, column, is_definition)`, as in `AstMetadata`
+type LocatedReference = (String, usize, usize, bool);
+
+// `AstMetadata.located_references` and `reference_counts`
+fn located_references(tree: &Tree, content: &str, language: &str, grammar: Language) -> (Vec<LocatedReference>, HashMap<String, usize>) {
+    let (func_q, class_q, _, ref_q) = get_queries(language);
+    let root_node = tree.root_node();
+    let source = content.as_bytes();
+    let mut located = Vec::new();
+    let mut counts = HashMap::new();
+    let Some(query) = query(language, grammar, ref_q).filter(|_| !ref_q.is_empty()) else { return (located, counts) };
+    let functions = definitions(root_node, content, language, grammar, func_q);
+    let scopes = Scopes::new(root_node, &functions, language, source);
+    // Names that define a function, class or import binding
+    let defining: HashSet<usize> = [class_q, binding_query(language)].into_iter()
+        .flat_map(|query| definitions(root_node, content, language, grammar, query))
+        .chain(functions.iter().copied())
+        .map(|(name, _)| name.id())
+        .collect();
+    let mut cursor = QueryCursor::new();
+    for m in cursor.matches(&query, root_node, source) {
+        for capture in m.captures {
+            let Ok(text) = capture.node.utf8_text(source) else { continue };
+            let is_definition = defining.contains(&capture.node.id()) || scopes.binding.contains(&capture.node.id());
+            if !is_definition && !scopes.shadows(capture.node, text) {
+                *counts.entry(text.to_string()).or_insert(0) += 1;
+            }
+            let position = capture.node.start_position();
+            located.push((text.to_string(), position.row + 1, position.column + 1, is_definition));
+        }
+    }
+    (located, counts)
+}
+
+/// A file for `get_ast_metadata_batch`: a path to read from disk, or a
+/// `(path, content)` pair such as an unsaved editor buffer.
+#[derive(FromPyObject)]
+pub enum AstSource {
+    Path(String),
+    Content(String, String),
+}
+
+/// `get_ast_metadata` over many files at once, parsed in parallel with the
+/// GIL released. Each file's language comes from its extension, as in
+/// `discover_files`. Returns a dict of path to `AstMetadata`; files that
+/// cannot be read as UTF-8 text are left out.
+#[pyfunction]
+#[pyo3(signature = (sources, progress=None, progress_every=100, cancel_token=None, threads=None, reference_details=false))]
+pub fn get_ast_metadata_batch(
+    py: Python<'_>,
+    sources: Vec<AstSource>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    reference_details: bool,
+) -> PyResult<HashMap<String, AstMetadata>> {
+    let items: Vec<(String, Option<String>)> = sources.into_iter()
+        .map(|source| match source {
+            AstSource::Path(path) => (path, None),
+            AstSource::Content(path, content) => (path, Some(content)),
+        })
+        .collect();
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    let parsed = control.run(py, &items, |(path, content)| {
+        let read;
+        let content = match content {
+            Some(content) => content,
+            None => {
+                read = encoding::read_to_string(path).ok()?;
+                &read
+            }
+        };
+        let language = detect_language(Path::new(path), content.as_bytes());
+        Some((path.clone(), ast_metadata(content, &language, reference_details)))
+    })?;
+    Ok(parsed.into_iter().collect())
+}
+
+/// One capture of a `run_ast_query` match. Lines and columns are 1-based,
+/// columns and byte offsets count bytes, and the end is exclusive.
+#[pyclass]
+#[derive(Clone)]
+pub struct AstCapture {
+    // The capture's name in the query, without the `@`
+    #[pyo3(get)]
+    pub name: String,
+    // The captured node's grammar type, e.g. "call_expression"
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub start_line: usize,
+    #[pyo3(get)]
+    pub start_column: usize,
+    #[pyo3(get)]
+    pub end_line: usize,
+    #[pyo3(get)]
+    pub end_column: usize,
+    #[pyo3(get)]
+    pub start_byte: usize,
+    #[pyo3(get)]
+    pub end_byte: usize,
+    #[pyo3(get)]
+    pub text: String,
+    // Which of the query's patterns matched, and an ordinal shared by the
+    // captures of one match
+    #[pyo3(get)]
+    pub pattern_index: usize,
+    #[pyo3(get)]
+    pub match_index: usize,
+}
+
+#[pymethods]
+impl AstCapture {
+    fn __repr__(&self) -> String {
+        format!("AstCapture(@{} {} {}:{})", self.name, self.kind, self.start_line, self.start_column)
+    }
+}
+
+// Compiles a query a rule author wrote. tree-sitter 0.20 panics instead of
+// reporting an unknown node, field or capture name that ends the query, so
+// the name is ended with a newline, which changes nothing else. An error
+// past the end the author wrote, or any other panic, is placed at that end
+fn compile_query(grammar: Language, query_source: &str) -> Result<Query, QueryError> {
+    let source = format!("{}\n", query_source);
+    let mut error = match std::panic::catch_unwind(AssertUnwindSafe(|| Query::new(grammar, &source))) {
+        Ok(Ok(query)) => return Ok(query),
+        Ok(Err(error)) => error,
+        Err(_) => QueryError {
+            row: 0,
+            column: 0,
+            offset: query_source.len(),
+            message: String::new(),
+            kind: QueryErrorKind::Syntax,
+        },
+    };
+    if error.kind != QueryErrorKind::Language && error.offset >= query_source.len() {
+        let line_start = query_source.rfind('\n').map_or(0, |newline| newline + 1);
+        error.offset = query_source.len();
+        error.row = query_source.matches('\n').count();
+        error.column = query_source.len() - line_start;
+        error.message = "Unexpected end of query".to_string();
+    }
+    Err(error)
+}
+
+/// Runs a tree-sitter query written by a rule author over `content`, for
+/// structural checks the built-in metadata does not cover. Predicates such
+/// as `#eq?` and `#match?` are honoured. Returns every capture of every
+/// match in document order.
+///
+/// Raises ValueError for a language without a grammar or a query that does
+/// not compile, with the position of the error in `query_source`.
+#[pyfunction]
+pub fn run_ast_query(py: Python<'_>, content: String, language: String, query_source: String) -> PyResult<Vec<AstCapture>> {
+    let Some(grammar) = get_language_parser(&language) else {
+        return Err(PyValueError::new_err(format!("No AST grammar for language '{}'", language)));
+    };
+    let query = compile_query(grammar, &query_source).map_err(|e| {
+        PyValueError::new_err(format!(
+            "Invalid query at line {}, column {}: {:?} error {}", e.row + 1, e.column + 1, e.kind, e.message
+        ))
+    })?;
+    py.allow_threads(|| {
+        let Some(tree) = parse(&content, &language, grammar, None) else { return Ok(Vec::new()) };
+        let mut captures = Vec::new();
+        let mut cursor = QueryCursor::new();
+        let matches = cursor.matches(&query, tree.root_node(), content.as_bytes()).filter(|m| !m.captures.is_empty());
+        for (match_index, m) in matches.enumerate() {
+            for capture in m.captures {
+                let node = capture.node;
+                let (start, end) = (node.start_position(), node.end_position());
+                captures.push(AstCapture {
+                    name: query.capture_names()[capture.index as usize].clone(),
+                    kind: node.kind().to_string(),
+                    start_line: start.row + 1,
+                    start_column: start.column + 1,
+                    end_line: end.row + 1,
+                    end_column: end.column + 1,
+                    start_byte: node.start_byte(),
+                    end_byte: node.end_byte(),
+                    text: node.utf8_text(content.as_bytes()).unwrap_or_default().to_string(),
+                    pattern_index: m.pattern_index,
+                    match_index,
+                });
+            }
+        }
+        Ok(captures)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_snippets_are_cut_on_a_character_boundary() {
+        let content = format!("def f(x=\"{}\"):\n    pass\n", "é".repeat(300));
+        let metadata = ast_metadata(&content, "python", false);
+        let snippet = &metadata.functions[0].code_snippet;
+        assert!(snippet.ends_with("..."));
+        assert_eq!(snippet.chars().count(), 203);
+        assert!(content.starts_with(snippet.trim_end_matches("...")));
+    }
+
+    #[test]
+    fn short_snippets_are_the_whole_first_line() {
+        let metadata = ast_metadata("def f(x):\n    pass\n", "python", false);
+        assert_eq!(metadata.functions[0].code_snippet, "def f(x):");
+    }
+
+    fn query_error(query_source: &str) -> String {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let result = run_ast_query(py, "x = 1\n".to_string(), "python".to_string(), query_source.to_string());
+            let error = result.err().expect("the query should not compile");
+            assert!(error.is_instance_of::<PyValueError>(py));
+            error.value(py).to_string()
+        })
+    }
+
+    #[test]
+    fn malformed_queries_raise_instead_of_panicking() {
+        assert_eq!(query_error("(bogus"), "Invalid query at line 1, column 2: NodeType error bogus");
+        assert_eq!(query_error("(identifier) @x (bogus_name"), "Invalid query at line 1, column 18: NodeType error bogus_name");
+        assert_eq!(query_error("(identifier)\n(call"), "Invalid query at line 2, column 6: Syntax error Unexpected end of query");
+        assert!(query_error("((identifier) @x (#eq? @y \"a\"))").contains("Capture error"));
+    }
+
+    #[test]
+    fn queries_capture_in_document_order() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let captures = run_ast_query(py, "a = b\n".to_string(), "python".to_string(), "(identifier) @name".to_string()).unwrap();
+            let found: Vec<(&str, usize)> = captures.iter().map(|capture| (capture.text.as_str(), capture.start_column)).collect();
+            assert_eq!(found, vec![("a", 1), ("b", 5)]);
+        });
+    }
+}
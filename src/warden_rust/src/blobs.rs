@@ -0,0 +1,94 @@
+use crate::secrets::token_regex;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+
+// Shorter literals are mostly identifiers and digests; longer ones are not
+// decoded at all so one huge embedded asset cannot blow up a scan
+const MIN_BASE64_LEN: usize = 24;
+const MIN_HEX_LEN: usize = 32;
+const MAX_BLOB_LEN: usize = 64 * 1024;
+
+/// A base64 or hex literal in a line that decodes to text.
+pub(crate) struct DecodedBlob {
+    // Byte range of the literal in the line
+    pub start: usize,
+    pub end: usize,
+    // "base64" or "hex"
+    pub encoding: &'static str,
+    pub text: String,
+}
+
+fn decode_base64(token: &str) -> Option<Vec<u8>> {
+    let config = GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    let alphabet = if token.contains(['-', '_']) { &alphabet::URL_SAFE } else { &alphabet::STANDARD };
+    GeneralPurpose::new(alphabet, config).decode(token).ok()
+}
+
+fn decode_hex(token: &str) -> Option<Vec<u8>> {
+    if !token.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..token.len()).step_by(2).map(|i| u8::from_str_radix(&token[i..i + 2], 16).ok()).collect()
+}
+
+// Decoded bytes worth scanning: UTF-8 without control characters other than
+// whitespace, which rules out keys, digests and compressed data
+fn as_text(bytes: Vec<u8>) -> Option<String> {
+    let text = String::from_utf8(bytes).ok()?;
+    let is_text = text.chars().all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'));
+    is_text.then_some(text)
+}
+
+/// Every literal in `line` long enough to hide a credential that decodes to
+/// text. Hex is tried first, since every hex literal is also valid base64.
+pub(crate) fn decoded_blobs(line: &str) -> Vec<DecodedBlob> {
+    token_regex().find_iter(line)
+        .filter(|m| m.len() <= MAX_BLOB_LEN)
+        .filter_map(|m| {
+            let token = m.as_str();
+            let is_hex = token.bytes().all(|b| b.is_ascii_hexdigit());
+            let (encoding, bytes) = if is_hex && token.len() >= MIN_HEX_LEN {
+                ("hex", decode_hex(token)?)
+            } else if token.len() >= MIN_BASE64_LEN {
+                ("base64", decode_base64(token)?)
+            } else {
+                return None;
+            };
+            Some(DecodedBlob { start: m.start(), end: m.end(), encoding, text: as_text(bytes)? })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+
+    fn hex(text: &str) -> String {
+        text.bytes().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn decoded(line: &str) -> Vec<(&'static str, String)> {
+        decoded_blobs(line).into_iter().map(|blob| (blob.encoding, blob.text)).collect()
+    }
+
+    #[test]
+    fn base64_and_hex_literals_decode_to_their_text() {
+        let encoded = STANDARD.encode("password=correct horse battery");
+        let line = format!("a = \"{}\"; b = \"{}\"", encoded, hex("token=0123456789abcdef"));
+        assert_eq!(decoded(&line), vec![
+            ("base64", "password=correct horse battery".to_string()),
+            ("hex", "token=0123456789abcdef".to_string()),
+        ]);
+        let blob = &decoded_blobs(&line)[0];
+        assert_eq!(&line[blob.start..blob.end], encoded);
+    }
+
+    #[test]
+    fn short_literals_and_binary_payloads_are_left_alone() {
+        assert!(decoded(&STANDARD.encode("short one")).is_empty());
+        assert!(decoded(&STANDARD.encode([0u8, 159, 146, 150, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14])).is_empty());
+        assert!(decoded("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855").is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// The regex crate's own default; callers loading untrusted rules can lower it
+// so a runaway counted repetition fails to compile instead of crawling later
+pub(crate) const DEFAULT_REGEX_SIZE_LIMIT: usize = 10 * (1 << 20);
+pub(crate) const DEFAULT_LINE_BUDGET_MS: u64 = 1_000;
+pub(crate) const DEFAULT_FILE_BUDGET_MS: u64 = 10_000;
+
+/// A rule that was switched off part-way through a file because it ran past
+/// its time budget. Hits it found before that are kept.
+///
+/// `budget` is "line" or "file"; `line_number` is where a line budget ran
+/// out and None for `multiline` rules, which only have a file budget.
+#[pyclass]
+#[derive(Clone)]
+pub struct BudgetReport {
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line_number: Option<usize>,
+    #[pyo3(get)]
+    pub budget: String,
+    #[pyo3(get)]
+    pub elapsed_ms: f64,
+}
+
+#[pymethods]
+impl BudgetReport {
+    fn __repr__(&self) -> String {
+        format!("BudgetReport({} in {}: {} budget, {:.1}ms)", self.rule_id, self.file_path, self.budget, self.elapsed_ms)
+    }
+}
+
+/// Compile-time size limit and wall-clock budgets for regex rules.
+#[derive(Clone, Copy)]
+pub(crate) struct RegexLimits {
+    pub size_limit: usize,
+    pub line_budget: Option<Duration>,
+    pub file_budget: Option<Duration>,
+}
+
+impl RegexLimits {
+    pub(crate) fn new(size_limit: usize, line_budget_ms: Option<u64>, file_budget_ms: Option<u64>) -> Self {
+        RegexLimits {
+            size_limit,
+            line_budget: line_budget_ms.map(Duration::from_millis),
+            file_budget: file_budget_ms.map(Duration::from_millis),
+        }
+    }
+
+    fn is_timed(&self) -> bool {
+        self.line_budget.is_some() || self.file_budget.is_some()
+    }
+}
+
+/// Wall-clock time each rule has spent on the file being scanned.
+///
+/// A regex search cannot be interrupted, so budgets are checked between
+/// searches: the rule that overran is reported and the caller stops running
+/// it on this file.
+pub(crate) struct RuleClock<'a> {
+    limits: &'a RegexLimits,
+    pub file_path: &'a str,
+    spent: Vec<Duration>,
+    pub reports: Vec<BudgetReport>,
+}
+
+impl<'a> RuleClock<'a> {
+    pub(crate) fn new(limits: &'a RegexLimits, file_path: &'a str, rule_count: usize) -> Self {
+        RuleClock { limits, file_path, spent: vec![Duration::ZERO; rule_count], reports: Vec::new() }
+    }
+
+    /// Runs one `search` for the rule in `slot` and charges its time. The flag
+    /// is false once the rule is over budget. `line_number` is None for
+    /// searches over the whole file, which only count against the file budget.
+    pub(crate) fn time<T>(&mut self, slot: usize, rule_id: &str, line_number: Option<usize>, search: impl FnOnce() -> T) -> (T, bool) {
+        if !self.limits.is_timed() {
+            return (search(), true);
+        }
+        let started = Instant::now();
+        let found = search();
+        let elapsed = started.elapsed();
+        self.spent[slot] += elapsed;
+
+        let over_line = line_number.is_some() && self.limits.line_budget.is_some_and(|budget| elapsed > budget);
+        let over_file = self.limits.file_budget.is_some_and(|budget| self.spent[slot] > budget);
+        if !over_line && !over_file {
+            return (found, true);
+        }
+        let (budget, elapsed) = if over_line { ("line", elapsed) } else { ("file", self.spent[slot]) };
+        self.reports.push(BudgetReport {
+            rule_id: rule_id.to_string(),
+            file_path: self.file_path.to_string(),
+            line_number: if over_line { line_number } else { None },
+            budget: budget.to_string(),
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+        });
+        (found, false)
+    }
+}
+
+// One UserWarning naming every rule that overran, with how many files it did
+// so in; the full list stays available to `Scanner` callers
+pub(crate) fn warn_over_budget(py: Python<'_>, reports: &[BudgetReport]) -> PyResult<()> {
+    if reports.is_empty() {
+        return Ok(());
+    }
+    let mut files_per_rule: BTreeMap<&str, usize> = BTreeMap::new();
+    for report in reports {
+        *files_per_rule.entry(&report.rule_id).or_default() += 1;
+    }
+    let details: Vec<String> = files_per_rule.iter()
+        .map(|(rule_id, files)| format!("{} ({} file(s))", rule_id, files))
+        .collect();
+    let message = format!(
+        "{} rule(s) exceeded their regex time budget and were skipped for the rest of the file: {}",
+        files_per_rule.len(),
+        details.join(", ")
+    );
+    crate::warn_user(py, &message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slow_search() -> bool {
+        std::thread::sleep(Duration::from_millis(3));
+        true
+    }
+
+    #[test]
+    fn a_search_over_the_line_budget_is_reported_with_its_line() {
+        let limits = RegexLimits::new(DEFAULT_REGEX_SIZE_LIMIT, Some(1), None);
+        let mut clock = RuleClock::new(&limits, "a.py", 1);
+        assert_eq!(clock.time(0, "slow", Some(7), slow_search), (true, false));
+        let report = &clock.reports[0];
+        assert_eq!((report.rule_id.as_str(), report.line_number, report.budget.as_str()), ("slow", Some(7), "line"));
+        assert!(report.elapsed_ms >= 3.0);
+    }
+
+    #[test]
+    fn the_file_budget_adds_up_each_rules_searches() {
+        let limits = RegexLimits::new(DEFAULT_REGEX_SIZE_LIMIT, None, Some(5));
+        let mut clock = RuleClock::new(&limits, "a.py", 2);
+        assert!(clock.time(0, "slow", Some(1), slow_search).1);
+        assert!(clock.time(1, "other", Some(1), slow_search).1);
+        assert!(!clock.time(0, "slow", Some(2), slow_search).1);
+        let report = &clock.reports[0];
+        assert_eq!((report.rule_id.as_str(), report.line_number, report.budget.as_str()), ("slow", None, "file"));
+        assert_eq!(clock.reports.len(), 1);
+    }
+
+    #[test]
+    fn without_budgets_nothing_is_timed() {
+        let limits = RegexLimits::new(DEFAULT_REGEX_SIZE_LIMIT, None, None);
+        let mut clock = RuleClock::new(&limits, "a.py", 1);
+        assert!(clock.time(0, "slow", Some(1), slow_search).1);
+        assert!(clock.reports.is_empty());
+    }
+}
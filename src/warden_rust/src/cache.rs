@@ -0,0 +1,284 @@
+use crate::scanner::{MatchHit, Metadata};
+use crate::warn_user;
+use pyo3::exceptions::PyOSError;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Bumped whenever the on-disk layout changes; older caches start empty
+const CACHE_VERSION: u32 = 1;
+
+/// A hit as stored in the cache: everything but what the path and the rule
+/// supply when it is read back.
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedHit {
+    line_number: usize,
+    column: usize,
+    end_line_number: usize,
+    match_end_column: usize,
+    rule_id: String,
+    snippet: String,
+    matched_text: String,
+    groups: HashMap<String, String>,
+    before: Vec<String>,
+    after: Vec<String>,
+    had_encoding_errors: bool,
+    severity: Option<String>,
+    category: Option<String>,
+    validator: Option<String>,
+    validated: bool,
+    redacted: bool,
+    decoded_from: Option<String>,
+}
+
+impl CachedHit {
+    fn new(hit: &MatchHit) -> Self {
+        CachedHit {
+            line_number: hit.line_number,
+            column: hit.column,
+            end_line_number: hit.end_line_number,
+            match_end_column: hit.match_end_column,
+            rule_id: hit.rule_id.clone(),
+            snippet: hit.snippet.clone(),
+            matched_text: hit.matched_text.clone(),
+            groups: hit.groups.clone(),
+            before: hit.before.clone(),
+            after: hit.after.clone(),
+            had_encoding_errors: hit.had_encoding_errors,
+            severity: hit.severity.clone(),
+            category: hit.category.clone(),
+            validator: hit.validator.clone(),
+            validated: hit.validated,
+            redacted: hit.redacted,
+            decoded_from: hit.decoded_from.clone(),
+        }
+    }
+
+    fn to_hit(&self, file_path: &str, metadata: Metadata) -> MatchHit {
+        let hit = self.clone();
+        MatchHit {
+            file_path: file_path.to_string(),
+            line_number: hit.line_number,
+            column: hit.column,
+            rule_id: hit.rule_id,
+            snippet: hit.snippet,
+            matched_text: hit.matched_text,
+            end_line_number: hit.end_line_number,
+            match_end_column: hit.match_end_column,
+            groups: hit.groups,
+            before: hit.before,
+            after: hit.after,
+            truncated: false,
+            had_encoding_errors: hit.had_encoding_errors,
+            severity: hit.severity,
+            category: hit.category,
+            metadata,
+            validator: hit.validator,
+            validated: hit.validated,
+            redacted: hit.redacted,
+            decoded_from: hit.decoded_from,
+        }
+    }
+}
+
+/// Identifies the scan a cached result came from: the rule set, the options
+/// that change what a hit looks like, and the file. Hit limits and
+/// `changed_ranges` only filter hits and are applied after the cache.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct CacheKey {
+    pub ruleset: String,
+    pub context_lines: usize,
+    pub decode_blobs: bool,
+    pub path: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    content_hash: String,
+    line_count: usize,
+    hits: Vec<CachedHit>,
+    // Seconds since the Unix epoch
+    last_used: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<CacheKey, CacheEntry>,
+}
+
+/// What a file's earlier scan found, as handed back by `ScanCache::lookup`.
+pub(crate) struct CachedScan {
+    pub line_count: usize,
+    pub hits: Vec<MatchHit>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Pattern results of earlier scans, keyed by file content hash and rule
+/// set, so files that did not change since are not matched again.
+///
+/// Pass it as `cache=` to `Scanner.scan` or `Scanner.match_files`; persist it
+/// with `save()`. One entry is kept per file and rule set, replaced when the
+/// file's content changes. Results of a file that overran a time budget are
+/// never cached.
+#[pyclass]
+pub struct ScanCache {
+    path: Option<String>,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl ScanCache {
+    pub(crate) fn lookup(&self, key: &CacheKey, content_hash: &str, metadata: impl Fn(&str) -> Metadata) -> Option<CachedScan> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(key).filter(|e| e.content_hash == content_hash) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        entry.last_used = now_secs();
+        let hits = entry.hits.iter().map(|hit| hit.to_hit(&key.path, metadata(&hit.rule_id))).collect();
+        Some(CachedScan { line_count: entry.line_count, hits })
+    }
+
+    pub(crate) fn store(&self, key: CacheKey, content_hash: String, line_count: usize, hits: &[MatchHit]) {
+        let entry = CacheEntry {
+            content_hash,
+            line_count,
+            hits: hits.iter().map(CachedHit::new).collect(),
+            last_used: now_secs(),
+        };
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+}
+
+fn load(path: &str) -> Result<HashMap<CacheKey, CacheEntry>, String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let file: CacheFile = bincode::deserialize(&bytes).map_err(|e| e.to_string())?;
+    Ok(if file.version == CACHE_VERSION { file.entries } else { HashMap::new() })
+}
+
+#[pymethods]
+impl ScanCache {
+    /// Loads the cache saved at `path`, if any. A cache that cannot be read
+    /// starts out empty, with a UserWarning.
+    #[new]
+    #[pyo3(signature = (path=None))]
+    fn new(py: Python<'_>, path: Option<String>) -> PyResult<Self> {
+        let entries = match path.as_deref().map(load).unwrap_or_else(|| Ok(HashMap::new())) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn_user(py, &format!("Ignoring unreadable scan cache {}: {}", path.as_deref().unwrap_or_default(), e))?;
+                HashMap::new()
+            }
+        };
+        Ok(ScanCache { path, entries: Mutex::new(entries), hits: AtomicUsize::new(0), misses: AtomicUsize::new(0) })
+    }
+
+    /// Writes the cache to `path`, or to the one it was loaded from.
+    #[pyo3(signature = (path=None))]
+    fn save(&self, path: Option<String>) -> PyResult<()> {
+        let Some(path) = path.or_else(|| self.path.clone()) else {
+            return Err(PyOSError::new_err("ScanCache has no path to save to"));
+        };
+        let file = CacheFile { version: CACHE_VERSION, entries: self.entries.lock().unwrap().clone() };
+        let bytes = bincode::serialize(&file).map_err(|e| PyOSError::new_err(e.to_string()))?;
+        // Renamed over the target so an interrupted save keeps the old cache
+        let temp = format!("{}.tmp", path);
+        std::fs::write(&temp, bytes)
+            .and_then(|_| std::fs::rename(&temp, &path))
+            .map_err(|e| PyOSError::new_err(format!("Failed to write scan cache {}: {}", path, e)))
+    }
+
+    fn __len__(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    // Lookups answered from the cache, and the ones that had to scan, since
+    // this object was created
+    #[getter]
+    fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    #[getter]
+    fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// `(ruleset, path, content_hash, hit_count, last_used)` for every
+    /// entry, optionally only those of one `Scanner.ruleset_hash`.
+    #[pyo3(signature = (ruleset=None))]
+    fn entries(&self, ruleset: Option<String>) -> Vec<(String, String, String, usize, u64)> {
+        let entries = self.entries.lock().unwrap();
+        let mut listed: Vec<_> = entries.iter()
+            .filter(|(key, _)| ruleset.as_ref().is_none_or(|r| &key.ruleset == r))
+            .map(|(key, e)| (key.ruleset.clone(), key.path.clone(), e.content_hash.clone(), e.hits.len(), e.last_used))
+            .collect();
+        listed.sort();
+        listed
+    }
+
+    /// Drops entries unused for more than `max_age_days`, entries of files
+    /// that no longer exist (with `missing_files`), and entries of rule sets
+    /// other than `keep_rulesets`. Returns how many were dropped.
+    #[pyo3(signature = (max_age_days=None, missing_files=true, keep_rulesets=None))]
+    fn prune(&self, max_age_days: Option<f64>, missing_files: bool, keep_rulesets: Option<Vec<String>>) -> usize {
+        let cutoff = max_age_days.map(|days| now_secs().saturating_sub((days * 86400.0) as u64));
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|key, entry| {
+            cutoff.is_none_or(|cutoff| entry.last_used >= cutoff)
+                && (!missing_files || Path::new(&key.path).exists())
+                && keep_rulesets.as_ref().is_none_or(|keep| keep.contains(&key.ruleset))
+        });
+        before - entries.len()
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(version: u32) -> tempfile::NamedTempFile {
+        let key = CacheKey { ruleset: "rules".to_string(), context_lines: 0, decode_blobs: false, path: "a.py".to_string() };
+        let entry = CacheEntry { content_hash: "hash".to_string(), line_count: 1, hits: Vec::new(), last_used: 0 };
+        let file = CacheFile { version, entries: HashMap::from([(key, entry)]) };
+        let saved = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(saved.path(), bincode::serialize(&file).unwrap()).unwrap();
+        saved
+    }
+
+    #[test]
+    fn caches_of_another_version_load_empty() {
+        let current = saved(CACHE_VERSION);
+        assert_eq!(load(current.path().to_str().unwrap()).unwrap().len(), 1);
+        let other = saved(CACHE_VERSION + 1);
+        assert!(load(other.path().to_str().unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_missing_cache_is_empty_and_a_garbled_one_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load(dir.path().join("none").to_str().unwrap()).unwrap().is_empty());
+        let garbled = dir.path().join("garbled");
+        std::fs::write(&garbled, b"\xff\xff\xff\xff\xff\xff\xff\xff\xff").unwrap();
+        assert!(load(garbled.to_str().unwrap()).is_err());
+    }
+}
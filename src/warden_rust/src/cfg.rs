@@ -0,0 +1,562 @@
+use crate::ast::{function_definitions, get_language_parser, parse, qualified_name};
+use pyo3::prelude::*;
+use std::collections::{HashSet, VecDeque};
+use tree_sitter::Node;
+
+/// A basic block: statements that run one after the other, entered only at
+/// the top. Lines are 1-based and inclusive.
+#[pyclass]
+#[derive(Clone)]
+pub struct CfgBlock {
+    // Index into `FunctionCfg.blocks`
+    #[pyo3(get)]
+    pub id: usize,
+    // "entry" and "exit", which hold no statements and span the function's
+    // first and last line, or "block"
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub start_line: usize,
+    #[pyo3(get)]
+    pub end_line: usize,
+    // Whether any path from the entry leads here
+    #[pyo3(get)]
+    pub reachable: bool,
+}
+
+#[pymethods]
+impl CfgBlock {
+    fn __repr__(&self) -> String {
+        format!("CfgBlock({} {} {}-{})", self.id, self.kind, self.start_line, self.end_line)
+    }
+}
+
+/// Control passing from one block to another. `kind` is "fallthrough",
+/// "true" or "false" (the branches of a condition, a loop's body and its
+/// way out), "case", "loop" (back to the loop's head), "break",
+/// "continue", "return", "raise" (a throw) or "exception" (into a handler
+/// or `finally`).
+#[pyclass]
+#[derive(Clone)]
+pub struct CfgEdge {
+    #[pyo3(get)]
+    pub source: usize,
+    #[pyo3(get)]
+    pub target: usize,
+    #[pyo3(get)]
+    pub kind: String,
+}
+
+#[pymethods]
+impl CfgEdge {
+    fn __repr__(&self) -> String {
+        format!("CfgEdge({} -> {} {})", self.source, self.target, self.kind)
+    }
+}
+
+/// The control-flow graph of one function. Block 0 is the entry and block
+/// 1 the exit, which every return and throw leads to.
+#[pyclass]
+#[derive(Clone)]
+pub struct FunctionCfg {
+    #[pyo3(get)]
+    pub function: String,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub end_line: usize,
+    #[pyo3(get)]
+    pub blocks: Vec<CfgBlock>,
+    #[pyo3(get)]
+    pub edges: Vec<CfgEdge>,
+}
+
+#[pymethods]
+impl FunctionCfg {
+    fn __repr__(&self) -> String {
+        format!("FunctionCfg({} {} blocks, {} edges)", self.function, self.blocks.len(), self.edges.len())
+    }
+}
+
+fn is_block(kind: &str) -> bool {
+    matches!(kind, "block" | "statement_block" | "compound_statement" | "statements" | "function_body" | "body_statement" | "control_structure_body")
+}
+
+fn is_if(kind: &str) -> bool {
+    matches!(kind, "if_statement" | "if_expression" | "if" | "unless")
+}
+
+fn is_loop(kind: &str) -> bool {
+    kind.starts_with("while") || kind.starts_with("for") || kind.starts_with("do_") || matches!(kind, "loop_expression" | "until")
+}
+
+fn is_switch(kind: &str) -> bool {
+    kind.contains("switch") || matches!(kind, "match_statement" | "match_expression" | "when_expression" | "select_statement")
+}
+
+fn is_case(kind: &str) -> bool {
+    ["case", "default", "arm", "when_entry", "switch_block_statement_group", "switch_section", "switch_rule"].iter().any(|part| kind.contains(part))
+}
+
+// Statements that carry on into a body of their own, like `with`
+fn has_inline_body(kind: &str) -> bool {
+    kind.starts_with("with") || ["synchronized", "labeled", "using", "lock", "unsafe"].iter().any(|part| kind.contains(part))
+}
+
+// The statements of a block, or the node itself when it is a lone
+// statement
+fn statements(node: Node) -> Vec<Node> {
+    if !is_block(node.kind()) {
+        return vec![node];
+    }
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.named_children(&mut cursor).filter(|child| !child.is_extra()).collect();
+    children.into_iter().flat_map(statements).collect()
+}
+
+// The control-flow statement an expression statement consists of, if any
+fn unwrap(node: Node) -> Node {
+    if node.kind() == "expression_statement" && node.named_child_count() == 1 {
+        if let Some(child) = node.named_child(0) {
+            let kind = child.kind();
+            if is_if(kind) || is_loop(kind) || is_switch(kind) || kind.starts_with("return") || kind.starts_with("break") || kind.starts_with("continue") {
+                return child;
+            }
+        }
+    }
+    node
+}
+
+const ENTRY: usize = 0;
+const EXIT: usize = 1;
+
+struct Builder<'a> {
+    source: &'a [u8],
+    language: &'a str,
+    // Line range of each block, None while it holds no statement
+    blocks: Vec<Option<(usize, usize)>>,
+    edges: Vec<(usize, usize, &'static str)>,
+    // Where `break` and `continue` go, innermost last
+    jumps: Vec<(usize, Option<usize>)>,
+}
+
+impl Builder<'_> {
+    fn block(&mut self) -> usize {
+        self.blocks.push(None);
+        self.blocks.len() - 1
+    }
+
+    fn edge(&mut self, source: usize, target: usize, kind: &'static str) {
+        self.edges.push((source, target, kind));
+    }
+
+    fn add_lines(&mut self, block: usize, start: usize, end: usize) {
+        let lines = &mut self.blocks[block];
+        *lines = Some(match *lines {
+            Some((first, last)) => (first.min(start), last.max(end)),
+            None => (start, end),
+        });
+    }
+
+    fn add(&mut self, block: usize, node: Node) {
+        self.add_lines(block, node.start_position().row + 1, node.end_position().row + 1);
+    }
+
+    // The first line of `node` through the end of its `field`, such as an
+    // `if` up to its condition
+    fn add_header(&mut self, block: usize, node: Node, fields: &[&str]) {
+        let end = fields.iter().find_map(|field| node.child_by_field_name(field)).unwrap_or(node.child(0).unwrap_or(node));
+        self.add_lines(block, node.start_position().row + 1, end.end_position().row + 1);
+    }
+
+    // The block to carry on in: `current`, or a new one no path leads to
+    // once control has left
+    fn current(&mut self, current: Option<usize>) -> usize {
+        current.unwrap_or_else(|| self.block())
+    }
+
+    fn sequence(&mut self, nodes: Vec<Node>, mut current: Option<usize>) -> Option<usize> {
+        for node in nodes {
+            current = self.statement(node, current);
+        }
+        current
+    }
+
+    // Runs `body` in a new block entered from `from`, returning where it ends
+    fn branch(&mut self, from: usize, kind: &'static str, body: Node) -> Option<usize> {
+        let start = self.block();
+        self.edge(from, start, kind);
+        self.sequence(statements(body), Some(start))
+    }
+
+    fn join(&mut self, ends: &[Option<usize>]) -> Option<usize> {
+        let live: Vec<usize> = ends.iter().flatten().copied().collect();
+        if live.is_empty() {
+            return None;
+        }
+        let joined = self.block();
+        for end in live {
+            self.edge(end, joined, "fallthrough");
+        }
+        Some(joined)
+    }
+
+    // The `else` side of a condition tested in `from`: Python's `elif`s
+    // and `else`, or the statement (maybe another `if`) after `else`
+    fn alternatives(&mut self, from: usize, alternatives: &[Node]) -> Option<usize> {
+        let Some((&first, rest)) = alternatives.split_first() else {
+            let skipped = self.block();
+            self.edge(from, skipped, "false");
+            return Some(skipped);
+        };
+        match first.kind() {
+            "elif_clause" => {
+                let test = self.block();
+                self.edge(from, test, "false");
+                self.add_header(test, first, &["condition"]);
+                let then_end = match first.child_by_field_name("consequence") {
+                    Some(consequence) => self.branch(test, "true", consequence),
+                    None => Some(test),
+                };
+                let else_end = self.alternatives(test, rest);
+                self.join(&[then_end, else_end])
+            }
+            "else_clause" | "else" => {
+                let mut cursor = first.walk();
+                let body = first.child_by_field_name("body").or_else(|| first.named_children(&mut cursor).last());
+                match body {
+                    Some(body) => self.branch(from, "false", body),
+                    None => self.alternatives(from, &[]),
+                }
+            }
+            _ => self.branch(from, "false", first),
+        }
+    }
+
+    fn if_statement(&mut self, node: Node, current: Option<usize>) -> Option<usize> {
+        let test = self.current(current);
+        self.add_header(test, node, &["condition"]);
+        let then_end = match node.child_by_field_name("consequence") {
+            Some(consequence) => self.branch(test, "true", consequence),
+            None => Some(test),
+        };
+        let mut cursor = node.walk();
+        let alternatives: Vec<Node> = node.children_by_field_name("alternative", &mut cursor).collect();
+        let else_end = self.alternatives(test, &alternatives);
+        self.join(&[then_end, else_end])
+    }
+
+    fn loop_statement(&mut self, node: Node, current: Option<usize>) -> Option<usize> {
+        let body = node.child_by_field_name("body");
+        let before = self.current(current);
+        let head = self.block();
+        let after = self.block();
+        if node.kind() == "do_statement" {
+            // The body runs once before the condition is tested
+            let start = self.block();
+            self.edge(before, start, "fallthrough");
+            self.jumps.push((after, Some(head)));
+            let end = body.and_then(|body| self.sequence(statements(body), Some(start)));
+            self.jumps.pop();
+            if let Some(end) = end {
+                self.edge(end, head, "fallthrough");
+            }
+            match node.child_by_field_name("condition") {
+                Some(condition) => self.add(head, condition),
+                None => self.add_header(head, node, &[]),
+            }
+            self.edge(head, start, "loop");
+            self.edge(head, after, "false");
+            return Some(after);
+        }
+        self.edge(before, head, "fallthrough");
+        // The head is the loop's first line through its condition or
+        // iterable
+        let mut cursor = node.walk();
+        let header_end = node.named_children(&mut cursor)
+            .take_while(|child| Some(child.id()) != body.map(|body| body.id()))
+            .map(|child| child.end_position().row + 1)
+            .max();
+        let start_line = node.start_position().row + 1;
+        self.add_lines(head, start_line, header_end.unwrap_or(start_line));
+        let condition = node.child_by_field_name("condition").and_then(|condition| condition.utf8_text(self.source).ok());
+        let infinite = node.kind() == "loop_expression"
+            || header_end.is_none()
+            || matches!(condition.map(|c| c.trim_matches(['(', ')'])), Some("True" | "true" | "1"));
+        if !infinite {
+            self.edge(head, after, "false");
+        }
+        self.jumps.push((after, Some(head)));
+        let end = match body {
+            Some(body) => self.branch(head, "true", body),
+            None => Some(head),
+        };
+        self.jumps.pop();
+        if let Some(end) = end {
+            self.edge(end, head, "loop");
+        }
+        // Python runs a loop's `else` once it ends
+        match node.child_by_field_name("alternative") {
+            Some(alternative) => self.sequence(statements(alternative.child_by_field_name("body").unwrap_or(alternative)), Some(after)),
+            None => Some(after),
+        }
+    }
+
+    fn try_statement(&mut self, node: Node, current: Option<usize>) -> Option<usize> {
+        let before = self.current(current);
+        self.add_header(before, node, &[]);
+        let start = self.block();
+        self.edge(before, start, "fallthrough");
+        let body = node.child_by_field_name("body");
+        let mut ends = vec![body.and_then(|body| self.sequence(statements(body), Some(start)))];
+        let mut finally = None;
+        let mut cursor = node.walk();
+        let clauses: Vec<Node> = node.named_children(&mut cursor).collect();
+        for clause in clauses {
+            let kind = clause.kind();
+            let mut cursor = clause.walk();
+            let clause_body = clause.child_by_field_name("body")
+                .or_else(|| clause.named_children(&mut cursor).filter(|child| is_block(child.kind())).last());
+            if ["except", "catch", "rescue"].iter().any(|part| kind.contains(part)) {
+                let handler = self.block();
+                self.edge(start, handler, "exception");
+                self.add_header(handler, clause, &[]);
+                ends.push(match clause_body {
+                    Some(body) => self.sequence(statements(body), Some(handler)),
+                    None => Some(handler),
+                });
+            } else if kind == "else_clause" {
+                ends[0] = match clause_body {
+                    Some(body) => self.sequence(statements(body), ends[0]),
+                    None => ends[0],
+                };
+            } else if kind.contains("finally") || kind.contains("ensure") {
+                finally = clause_body.or(Some(clause));
+            }
+        }
+        let Some(finally) = finally else { return self.join(&ends) };
+        let live = ends.iter().any(Option::is_some);
+        let finally_start = match self.join(&ends) {
+            Some(joined) => joined,
+            None => {
+                let unwinding = self.block();
+                self.edge(start, unwinding, "exception");
+                unwinding
+            }
+        };
+        let end = self.sequence(statements(finally), Some(finally_start));
+        end.filter(|_| live)
+    }
+
+    fn switch_statement(&mut self, node: Node, current: Option<usize>) -> Option<usize> {
+        let test = self.current(current);
+        self.add_header(test, node, &["condition", "value", "subject"]);
+        let after = self.block();
+        // C-like cases run on into the next one, and `break` leaves them
+        let falls_through = matches!(self.language, "c" | "cpp" | "java" | "javascript" | "typescript" | "csharp" | "php");
+        let breaks = falls_through || self.language == "go";
+        if breaks {
+            self.jumps.push((after, None));
+        }
+        let container = node.child_by_field_name("body").unwrap_or(node);
+        let mut cursor = container.walk();
+        let cases: Vec<Node> = container.named_children(&mut cursor).filter(|child| is_case(child.kind())).collect();
+        let mut previous = None;
+        let mut has_default = false;
+        for case in cases {
+            let start = self.block();
+            self.edge(test, start, "case");
+            self.add_lines(start, case.start_position().row + 1, case.start_position().row + 1);
+            if let (true, Some(previous)) = (falls_through, previous) {
+                self.edge(previous, start, "fallthrough");
+            }
+            let end = self.sequence(case_statements(case), Some(start));
+            if !falls_through {
+                if let Some(end) = end {
+                    self.edge(end, after, "fallthrough");
+                }
+            }
+            previous = end;
+            has_default |= is_default(case, self.source);
+        }
+        if let (true, Some(previous)) = (falls_through, previous) {
+            self.edge(previous, after, "fallthrough");
+        }
+        if !has_default {
+            self.edge(test, after, "false");
+        }
+        if breaks {
+            self.jumps.pop();
+        }
+        Some(after)
+    }
+
+    fn statement(&mut self, node: Node, current: Option<usize>) -> Option<usize> {
+        let node = unwrap(node);
+        let kind = node.kind();
+        if node.is_extra() {
+            return current;
+        }
+        if is_if(kind) {
+            return self.if_statement(node, current);
+        }
+        if is_loop(kind) {
+            return self.loop_statement(node, current);
+        }
+        if is_switch(kind) {
+            return self.switch_statement(node, current);
+        }
+        // Rust's `try_expression` is the `?` operator
+        if kind.starts_with("try") && self.language != "rust" {
+            return self.try_statement(node, current);
+        }
+        if is_block(kind) {
+            return self.sequence(statements(node), current);
+        }
+        let block = self.current(current);
+        if kind.starts_with("return") || kind.starts_with("raise") || kind.starts_with("throw") {
+            self.add(block, node);
+            let edge = if kind.starts_with("return") { "return" } else { "raise" };
+            self.edge(block, EXIT, edge);
+            return None;
+        }
+        if kind.starts_with("break") {
+            self.add(block, node);
+            if let Some(&(target, _)) = self.jumps.last() {
+                self.edge(block, target, "break");
+            }
+            return None;
+        }
+        if kind.starts_with("continue") {
+            self.add(block, node);
+            if let Some(target) = self.jumps.iter().rev().find_map(|&(_, target)| target) {
+                self.edge(block, target, "continue");
+            }
+            return None;
+        }
+        if let (true, Some(body)) = (has_inline_body(kind), node.child_by_field_name("body")) {
+            self.add_lines(block, node.start_position().row + 1, body.start_position().row + 1);
+            return self.sequence(statements(body), Some(block));
+        }
+        self.add(block, node);
+        Some(block)
+    }
+}
+
+// What a case runs, leaving out the values or patterns it matches
+fn case_statements(case: Node) -> Vec<Node> {
+    if case.kind() == "match_arm" {
+        return case.child_by_field_name("value").map(statements).unwrap_or_default();
+    }
+    let mut body = Vec::new();
+    let mut cursor = case.walk();
+    let mut more = cursor.goto_first_child();
+    while more {
+        let child = cursor.node();
+        let field = cursor.field_name();
+        more = cursor.goto_next_sibling();
+        let kind = child.kind();
+        let is_test = matches!(field, Some("value" | "pattern" | "label" | "guard"))
+            || ["label", "pattern", "condition"].iter().any(|part| kind.contains(part));
+        if child.is_named() && !child.is_extra() && !is_test {
+            body.extend(statements(child));
+        }
+    }
+    body
+}
+
+fn is_default(case: Node, source: &[u8]) -> bool {
+    let text = case.utf8_text(source).unwrap_or_default().trim_start();
+    let first = case.named_child(0).and_then(|child| child.utf8_text(source).ok());
+    case.kind().contains("default") || text.starts_with("default") || text.starts_with("else") || first == Some("_")
+}
+
+// Drops the blocks left without statements, other than the entry and exit,
+// linking their predecessors straight to their successors, then numbers
+// the rest; an edge into a dropped block keeps its kind unless it was a
+// plain fallthrough
+fn finish(builder: Builder, function: String, line: usize, end_line: usize) -> FunctionCfg {
+    let Builder { blocks, mut edges, .. } = builder;
+    let is_kept = |block: usize| block == ENTRY || block == EXIT || blocks[block].is_some();
+    for block in (0..blocks.len()).filter(|&block| !is_kept(block)) {
+        let (touching, mut rest): (Vec<_>, Vec<_>) = edges.into_iter().partition(|&(source, target, _)| source == block || target == block);
+        for &(source, _, kind) in touching.iter().filter(|&&(source, target, _)| target == block && source != block) {
+            for &(_, target, next) in touching.iter().filter(|&&(source, target, _)| source == block && target != block) {
+                rest.push((source, target, if kind == "fallthrough" { next } else { kind }));
+            }
+        }
+        edges = rest;
+    }
+    let kept: Vec<usize> = (0..blocks.len()).filter(|&block| is_kept(block)).collect();
+    let mut ids = vec![usize::MAX; blocks.len()];
+    for (id, &block) in kept.iter().enumerate() {
+        ids[block] = id;
+    }
+    let mut seen = HashSet::new();
+    let edges: Vec<CfgEdge> = edges.into_iter()
+        .map(|(source, target, kind)| (ids[source], ids[target], kind))
+        .filter(|edge| seen.insert(*edge))
+        .map(|(source, target, kind)| CfgEdge { source, target, kind: kind.to_string() })
+        .collect();
+
+    let mut reachable = vec![false; kept.len()];
+    let mut queue = VecDeque::from([ENTRY]);
+    reachable[ENTRY] = true;
+    while let Some(block) = queue.pop_front() {
+        for edge in edges.iter().filter(|edge| edge.source == block) {
+            if !reachable[edge.target] {
+                reachable[edge.target] = true;
+                queue.push_back(edge.target);
+            }
+        }
+    }
+    let blocks = kept.iter().enumerate()
+        .map(|(id, &block)| {
+            let (kind, (start_line, end_line)) = match block {
+                ENTRY => ("entry", (line, line)),
+                EXIT => ("exit", (end_line, end_line)),
+                _ => ("block", blocks[block].unwrap_or_default()),
+            };
+            CfgBlock { id, kind: kind.to_string(), start_line, end_line, reachable: reachable[id] }
+        })
+        .collect();
+    FunctionCfg { function, line, end_line, blocks, edges }
+}
+
+fn function_cfg(definition: Node, function: String, language: &str, source: &[u8]) -> FunctionCfg {
+    let mut builder = Builder { source, language, blocks: vec![None, None], edges: Vec::new(), jumps: Vec::new() };
+    let start = builder.block();
+    builder.edge(ENTRY, start, "fallthrough");
+    let mut cursor = definition.walk();
+    let body = definition.child_by_field_name("body")
+        .or_else(|| definition.named_children(&mut cursor).filter(|child| is_block(child.kind())).last());
+    let end = match body {
+        Some(body) => builder.sequence(statements(body), Some(start)),
+        None => Some(start),
+    };
+    if let Some(end) = end {
+        builder.edge(end, EXIT, "fallthrough");
+    }
+    finish(builder, function, definition.start_position().row + 1, definition.end_position().row + 1)
+}
+
+/// The control-flow graph of every function in `content`, in source order,
+/// nested functions included as graphs of their own. Conditions,
+/// loops, `switch`/`match`, `try` with its handlers and `finally`, and
+/// `break`, `continue`, `return` and throws are followed; an exception can
+/// reach a handler from the start of the `try` body, and calls are
+/// assumed to return. Code after a return or a loop that never ends sits in
+/// blocks with `reachable` false. Empty when `language` has no grammar.
+#[pyfunction]
+pub fn build_cfg(content: String, language: String) -> Vec<FunctionCfg> {
+    let Some(grammar) = get_language_parser(&language) else { return vec![] };
+    let Some(tree) = parse(&content, &language, grammar, None) else { return vec![] };
+    let source = content.as_bytes();
+    function_definitions(&tree, &content, &language, grammar)
+        .into_iter()
+        .map(|(name, definition)| {
+            let name = name.utf8_text(source).unwrap_or_default();
+            function_cfg(definition, qualified_name(definition, name, &language, source), &language, source)
+        })
+        .collect()
+}
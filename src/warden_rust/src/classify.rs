@@ -0,0 +1,303 @@
+// Path and content heuristics that sort files by who wrote them and what
+// they are for, reported in `FileStats`, and that name the language of the
+// files `detect_language` cannot tell by extension
+
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// How much of the start of a file the content heuristics look at.
+pub(crate) const HEAD_BYTES: usize = 8 * 1024;
+
+// File names code generators give their output
+const GENERATED_SUFFIXES: &[&str] = &[
+    ".pb.go", ".pb.cc", ".pb.h", "_pb2.py", "_pb2_grpc.py", ".pb.swift", ".g.dart", ".freezed.dart", ".designer.cs", ".g.cs",
+];
+
+// Headers generators write, matched case-insensitively
+const GENERATED_MARKERS: &[&str] = &[
+    "@generated",
+    "do not edit",
+    "code generated by",
+    "generated by the protocol buffer compiler",
+    "auto-generated",
+    "autogenerated",
+    "this file was automatically generated",
+    "this file is automatically generated",
+    "openapi generator",
+    "swagger-codegen",
+];
+
+/// Whether the file at `path`, starting with `head`, was written by a code
+/// generator: protobuf and similar output by name, or a generator's header
+/// (`@generated`, `DO NOT EDIT`, `Code generated by ...`) in `head`.
+pub(crate) fn is_generated(path: &Path, head: &[u8]) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if GENERATED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) || name.contains(".generated.") {
+        return true;
+    }
+    let head = String::from_utf8_lossy(&head[..head.len().min(HEAD_BYTES)]).to_lowercase();
+    GENERATED_MARKERS.iter().any(|marker| head.contains(marker))
+}
+
+// Directories holding tests in the usual layouts
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "specs", "testing", "e2e"];
+
+// Imports of test frameworks, matched as written
+const TEST_IMPORTS: &[&str] = &[
+    "import pytest",
+    "from pytest",
+    "import unittest",
+    "from unittest",
+    "org.junit",
+    "org.testng",
+    // Go's, alone or in an import block
+    "import \"testing\"",
+    "\t\"testing\"",
+    "from 'vitest'",
+    "from \"vitest\"",
+    "@jest/globals",
+    "require('mocha')",
+    "PHPUnit\\Framework",
+    "require 'rspec'",
+    "require \"rspec\"",
+    "import XCTest",
+    "using NUnit",
+    "using Xunit",
+    "Microsoft.VisualStudio.TestTools",
+];
+
+fn is_test_name(name: &str) -> bool {
+    let (stem, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+    match extension {
+        "py" => stem.starts_with("test_") || stem.ends_with("_test") || stem == "conftest",
+        "go" => stem.ends_with("_test"),
+        "rb" => stem.ends_with("_spec") || stem.ends_with("_test"),
+        "java" | "kt" | "cs" | "php" | "swift" => stem.ends_with("Test") || stem.ends_with("Tests") || stem.ends_with("Spec"),
+        _ => [".test", ".spec", "_test", "_spec"].iter().any(|suffix| stem.ends_with(suffix)),
+    }
+}
+
+/// The part of `path` below `root`, which the directory heuristics look at
+/// so that the directories a checkout sits in (`~/tests/project/`) do not
+/// count; all of `path` when it is not under `root`.
+pub(crate) fn below_root<'p>(path: &'p Path, root: &Path) -> &'p Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+/// Whether the file at `path`, relative to the scan root and starting with
+/// `head`, is test code: under a test directory (`tests/`, `__tests__/`,
+/// `spec/`), named as tests are (`test_*.py`, `*_test.go`, `*.spec.ts`,
+/// `FooTest.java`), or importing a test framework (pytest, unittest, JUnit,
+/// Go's `testing`, and the like) in `head`.
+pub(crate) fn is_test(path: &Path, head: &[u8]) -> bool {
+    let in_test_dir = path.parent().is_some_and(|parent| {
+        parent.components().any(|component| TEST_DIRS.contains(&component.as_os_str().to_string_lossy().to_lowercase().as_str()))
+    });
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    if in_test_dir || is_test_name(&name) {
+        return true;
+    }
+    let head = String::from_utf8_lossy(&head[..head.len().min(HEAD_BYTES)]);
+    TEST_IMPORTS.iter().any(|import| head.contains(import))
+}
+
+// Directories package managers and vendoring tools copy upstream code into
+const VENDOR_DIRS: &[&str] = &["vendor", "vendors", "node_modules", "bower_components", "third_party", "third-party", "thirdparty", "Pods", "Carthage"];
+
+// Runtimes bundlers write into their output
+const BUNDLE_MARKERS: &[&str] = &["__webpack_require__", "webpackBootstrap", "parcelRequire", "System.register(", "__vite_ssr_import__"];
+
+// Lines this long are a minifier's, not anyone's to edit
+const MINIFIED_LINE_BYTES: usize = 500;
+
+// Whether the file named `name`, starting with `head`, is a minifier's or
+// bundler's output
+fn is_built(name: &str, head: &str) -> bool {
+    name.contains(".min.")
+        || name.contains(".bundle.")
+        || BUNDLE_MARKERS.iter().any(|marker| head.contains(marker))
+        || head.lines().any(|line| line.len() > MINIFIED_LINE_BYTES)
+}
+
+// A library's name and version, as in `jQuery v3.7.1` or `lodash 4.17.21`
+fn library_regex() -> &'static Regex {
+    static LIBRARY: OnceLock<Regex> = OnceLock::new();
+    LIBRARY.get_or_init(|| Regex::new(r"[A-Za-z][\w.@/\-]*\s+v?\d+\.\d+").unwrap())
+}
+
+// Whether `head` opens with the banner libraries keep through
+// minification, a `/*!` or `@license` comment naming them and their version
+fn has_library_banner(head: &str) -> bool {
+    let Some(comment) = head.trim_start().strip_prefix("/*") else { return false };
+    let comment = comment.split("*/").next().unwrap_or_default();
+    (comment.starts_with('!') || comment.contains("@license")) && library_regex().is_match(comment)
+}
+
+/// Whether the file at `path`, relative to the scan root and starting with
+/// `head`, is a copy of someone else's code: under a vendoring directory
+/// (`vendor/`, `node_modules/`, `third_party/`), or minified or bundled
+/// (`.min.js`, a webpack runtime, very long lines) and opening with the
+/// banner of an upstream library (`/*! jQuery v3.7.1 */`, or an `@license`
+/// comment that names one).
+pub(crate) fn is_vendored(path: &Path, head: &[u8]) -> bool {
+    let in_vendor_dir = path.parent().is_some_and(|parent| {
+        parent.components().any(|component| VENDOR_DIRS.contains(&component.as_os_str().to_string_lossy().as_ref()))
+    });
+    if in_vendor_dir {
+        return true;
+    }
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let head = String::from_utf8_lossy(&head[..head.len().min(HEAD_BYTES)]);
+    is_built(&name, &head) && has_library_banner(&head)
+}
+
+// A language as editors and interpreters name it, in `detect_language`
+// terms
+fn language_named(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    let name = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match name {
+        "python" | "pypy" => "python",
+        "node" | "nodejs" | "deno" | "bun" | "javascript" | "js" => "javascript",
+        "ts-node" | "tsx" | "typescript" | "ts" => "typescript",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "ash" | "shell" | "shell-script" => "shell",
+        "ruby" | "rb" => "ruby",
+        "php" => "php",
+        "c" => "c",
+        "cpp" | "c++" => "cpp",
+        "go" => "go",
+        "rust" => "rust",
+        "java" => "java",
+        "kotlin" => "kotlin",
+        "swift" => "swift",
+        "csharp" | "cs" => "csharp",
+        "yaml" => "yaml",
+        "json" => "json",
+        "sql" => "sql",
+        "markdown" | "md" => "markdown",
+        "make" | "makefile" => "makefile",
+        "dockerfile" => "dockerfile",
+        _ => return None,
+    })
+}
+
+/// The language of a file going by its name alone, for the files known
+/// without an extension: `Dockerfile`, `Makefile`, `Gemfile` and the like.
+pub(crate) fn language_from_name(name: &str) -> Option<&'static str> {
+    let lower = name.to_lowercase();
+    if lower == "dockerfile" || lower.starts_with("dockerfile.") || lower.ends_with(".dockerfile") || lower == "containerfile" {
+        Some("dockerfile")
+    } else if matches!(lower.as_str(), "makefile" | "gnumakefile") || lower.ends_with(".mk") {
+        Some("makefile")
+    } else if matches!(name, "Rakefile" | "Gemfile" | "Guardfile" | "Podfile" | "Vagrantfile") {
+        Some("ruby")
+    } else {
+        None
+    }
+}
+
+// The interpreter a `#!` line runs, through `env` if need be
+/// The command of a `#!` first line in `head`, trimmed, such as
+/// `/usr/bin/env python3`; None without one.
+pub(crate) fn shebang(head: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(head);
+    let command = head.lines().next()?.strip_prefix("#!")?.trim();
+    (!command.is_empty()).then(|| command.to_string())
+}
+
+/// The program a shebang command runs, by file name, looking through
+/// `env` and its options and variable settings: `python3` for both
+/// `/usr/bin/python3` and `/usr/bin/env -S python3 -u`.
+pub(crate) fn shebang_interpreter(command: &str) -> Option<&str> {
+    let mut words = command.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        words.find(|word| !word.starts_with('-') && !word.contains('='))
+    } else {
+        Some(program)
+    }
+}
+
+fn shebang_language(line: &str) -> Option<&'static str> {
+    language_named(shebang_interpreter(line.strip_prefix("#!")?)?)
+}
+
+// Emacs `-*- mode: python -*-` or `-*- python -*-`, Vim `vim: set ft=python:`
+fn modeline_language(line: &str) -> Option<&'static str> {
+    if let Some((_, rest)) = line.split_once("-*-") {
+        let settings = rest.split("-*-").next()?.trim();
+        let mode = settings.split(';').find_map(|setting| {
+            let (key, value) = setting.split_once(':')?;
+            key.trim().eq_ignore_ascii_case("mode").then(|| value.trim())
+        });
+        return language_named(mode.unwrap_or(settings));
+    }
+    let (_, settings) = ["vim:", "vi:", "ex:"].iter().find_map(|marker| line.split_once(marker))?;
+    settings.split([' ', ':', '\t']).find_map(|setting| {
+        let (key, value) = setting.split_once('=')?;
+        matches!(key, "ft" | "filetype" | "syntax").then(|| language_named(value)).flatten()
+    })
+}
+
+/// The language of a file with no telling extension from its first bytes:
+/// a shebang, an Emacs or Vim modeline in the first lines, else None.
+pub(crate) fn language_from_content(head: &[u8]) -> Option<&'static str> {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines().take(5);
+    let first = lines.next()?;
+    shebang_language(first).or_else(|| std::iter::once(first).chain(lines).find_map(modeline_language))
+}
+
+/// Whether a `.h` header starting with `head` is C++ rather than C: it
+/// includes a standard C++ header (`<iostream>`, `<vector>`, no `.h`) or
+/// declares a class, namespace or template.
+pub(crate) fn is_cpp_header(head: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(head);
+    head.lines().map(str::trim_start).any(|line| {
+        let cpp_include = line.strip_prefix("#include").map(str::trim_start).and_then(|rest| rest.strip_prefix('<'))
+            .and_then(|rest| rest.split_once('>'))
+            .is_some_and(|(header, _)| !header.contains('.') && !header.contains('/'));
+        cpp_include
+            || ["class ", "namespace ", "template<", "template <", "public:", "private:", "protected:"].iter().any(|start| line.starts_with(start))
+            || line.contains("std::")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_directories_below_the_root_make_a_test() {
+        let path = Path::new("/home/dev/tests/project/src/app.py");
+        let relative = below_root(path, Path::new("/home/dev/tests/project"));
+        assert_eq!(relative, Path::new("src/app.py"));
+        assert!(!is_test(relative, b""));
+        assert!(is_test(Path::new("tests/helpers.py"), b""));
+        assert!(is_test(Path::new("src/test_app.py"), b""));
+        // Paths outside the root are taken as they are
+        assert_eq!(below_root(path, Path::new("/elsewhere")), path);
+    }
+
+    #[test]
+    fn vendored_code_is_a_vendor_directory_or_a_built_library() {
+        let banner = b"/*! jQuery v3.7.1 | (c) OpenJS Foundation */\n!function(e){}";
+        assert!(is_vendored(Path::new("third_party/lib.py"), b""));
+        assert!(is_vendored(Path::new("static/jquery.min.js"), banner));
+        // Minified, but the project's own
+        assert!(!is_vendored(Path::new("static/app.min.js"), b"!function(e){}"));
+        // A banner, but not built
+        assert!(!is_vendored(Path::new("static/jquery.js"), banner));
+        // Only directories below the root count
+        assert!(!is_vendored(below_root(Path::new("/srv/vendor/app/main.go"), Path::new("/srv/vendor/app")), b""));
+    }
+
+    #[test]
+    fn a_library_banner_names_a_library_and_its_version() {
+        assert!(has_library_banner("/*!\n * lodash 4.17.21\n */"));
+        assert!(has_library_banner("/* @license React react.production.min.js v18.2.0 */"));
+        assert!(!has_library_banner("/*! built by our pipeline */"));
+        assert!(!has_library_banner("// jQuery v3.7.1"));
+    }
+}
@@ -0,0 +1,261 @@
+use crate::ast::{function_definitions, get_language_parser, parse, qualified_name};
+use pyo3::prelude::*;
+use std::collections::HashSet;
+use tree_sitter::{Language, Node, Tree};
+
+/// Complexity of one function, for threshold findings. Lines are 1-based
+/// and cover the whole definition.
+#[pyclass]
+#[derive(Clone)]
+pub struct FunctionMetrics {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub qualified_name: String,
+    #[pyo3(get)]
+    pub start_line: usize,
+    #[pyo3(get)]
+    pub end_line: usize,
+    // McCabe cyclomatic complexity: 1 + the decision points in the body
+    #[pyo3(get)]
+    pub complexity: usize,
+    // Sonar cognitive complexity: breaks in linear flow, weighted by how
+    // deeply they are nested
+    #[pyo3(get)]
+    pub cognitive_complexity: usize,
+}
+
+#[pymethods]
+impl FunctionMetrics {
+    fn __repr__(&self) -> String {
+        format!(
+            "FunctionMetrics({} lines {}-{}, complexity={}, cognitive={})",
+            self.qualified_name, self.start_line, self.end_line, self.complexity, self.cognitive_complexity
+        )
+    }
+}
+
+// Branches, loops, handlers and conditional expressions across grammars
+const DECISION_KINDS: &[&str] = &[
+    "if_statement", "elif_clause", "if_expression", "if_let_expression", "if", "elsif", "unless",
+    "if_modifier", "unless_modifier", "if_clause", "guard_statement",
+    "for_statement", "for_in_statement", "foreach_statement", "for_each_statement", "enhanced_for_statement",
+    "for_range_loop", "for_expression", "for_in_clause", "for", "while_statement", "while_expression",
+    "while_let_expression", "while", "until", "while_modifier", "until_modifier", "do_statement",
+    "do_while_statement", "repeat_while_statement",
+    "catch_clause", "except_clause", "rescue", "rescue_modifier", "catch_block",
+    "conditional_expression", "ternary_expression", "boolean_operator",
+    "conjunction_expression", "disjunction_expression",
+];
+
+// One per way into a switch or match, except the default one
+const CASE_KINDS: &[&str] = &[
+    "case_clause", "switch_case", "case_statement", "expression_case", "type_case", "communication_case",
+    "switch_section", "switch_label", "match_arm", "when_entry", "when", "switch_entry",
+];
+
+// Whether `node` adds a path through the function
+fn is_decision(node: Node, source: &[u8]) -> bool {
+    let kind = node.kind();
+    if DECISION_KINDS.contains(&kind) {
+        return true;
+    }
+    if CASE_KINDS.contains(&kind) {
+        let text = node.utf8_text(source).unwrap_or_default().trim_start();
+        let pattern = node.child_by_field_name("pattern").or_else(|| node.named_child(0));
+        let wildcard = pattern.is_some_and(|p| p.utf8_text(source) == Ok("_"));
+        return !(text.starts_with("default") || text.starts_with("else") || wildcard);
+    }
+    // Short-circuit operators: `a && b` can skip `b`
+    if kind == "binary_expression" || kind == "binary" {
+        let mut cursor = node.walk();
+        let found = node.children(&mut cursor).any(|c| matches!(c.kind(), "&&" | "||" | "and" | "or"));
+        return found;
+    }
+    false
+}
+
+// The named nodes in the body of `definition`, leaving out functions nested
+// in it, which are measured on their own
+fn body_nodes<'t>(definition: Node<'t>, nested: &HashSet<usize>) -> Vec<Node<'t>> {
+    let mut nodes = Vec::new();
+    let mut cursor = definition.walk();
+    let mut stack: Vec<Node> = definition.named_children(&mut cursor).collect();
+    while let Some(node) = stack.pop() {
+        if nested.contains(&node.id()) {
+            continue;
+        }
+        nodes.push(node);
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+    nodes
+}
+
+fn cyclomatic(definition: Node, nested: &HashSet<usize>, source: &[u8]) -> usize {
+    1 + body_nodes(definition, nested).into_iter().filter(|&node| is_decision(node, source)).count()
+}
+
+fn is_if(kind: &str) -> bool {
+    matches!(kind, "if_statement" | "if_expression" | "if_let_expression" | "if" | "unless" | "if_modifier" | "unless_modifier")
+}
+
+const ELSE_KINDS: &[&str] = &["else_clause", "else"];
+
+// `elif` and `elsif`: one more branch of the enclosing `if`
+const ELSE_IF_KINDS: &[&str] = &["elif_clause", "elsif"];
+
+// Structures that cost 1 plus their nesting level and nest what they contain
+const NESTING_KINDS: &[&str] = &[
+    "switch_statement", "switch_expression", "match_statement", "match_expression", "expression_switch_statement",
+    "type_switch_statement", "select_statement", "case", "when_expression",
+    "for_statement", "for_in_statement", "foreach_statement", "for_each_statement", "enhanced_for_statement",
+    "for_range_loop", "for_expression", "for", "while_statement", "while_expression", "while_let_expression",
+    "while", "until", "while_modifier", "until_modifier", "do_statement", "do_while_statement",
+    "repeat_while_statement", "loop_expression",
+    "catch_clause", "except_clause", "rescue", "catch_block",
+    "conditional_expression", "ternary_expression",
+];
+
+// Anonymous functions nest their bodies without costing anything themselves
+const LAMBDA_KINDS: &[&str] = &[
+    "lambda", "lambda_expression", "arrow_function", "closure_expression", "function_expression", "func_literal",
+    "anonymous_function", "lambda_literal",
+];
+
+// An `if` that is the `else` branch of another one
+fn is_else_if(node: Node) -> bool {
+    let Some(parent) = node.parent() else { return false };
+    if ELSE_KINDS.contains(&parent.kind()) {
+        return parent.named_child_count() == 1;
+    }
+    is_if(parent.kind()) && parent.child_by_field_name("alternative") == Some(node)
+}
+
+// The short-circuit operator `node` applies, if any
+fn logical_operator(node: Node) -> Option<&'static str> {
+    match node.kind() {
+        "conjunction_expression" => Some("&&"),
+        "disjunction_expression" => Some("||"),
+        "binary_expression" | "binary" | "boolean_operator" => {
+            let mut cursor = node.walk();
+            let operator = node.children(&mut cursor).find_map(|c| match c.kind() {
+                "&&" | "and" => Some("&&"),
+                "||" | "or" => Some("||"),
+                _ => None,
+            });
+            operator
+        }
+        _ => None,
+    }
+}
+
+// Adds up the cognitive cost of the children of `node`, `nesting` deep
+fn cognitive(node: Node, nesting: usize, nested: &HashSet<usize>, total: &mut usize) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if nested.contains(&child.id()) {
+            continue;
+        }
+        let kind = child.kind();
+        let mut inner = nesting;
+        if is_if(kind) {
+            if is_else_if(child) {
+                *total += 1;
+            } else {
+                *total += 1 + nesting;
+                inner = nesting + 1;
+            }
+            // An `else` given directly as the alternative (C, Java, Go)
+            let alternative = child.child_by_field_name("alternative").map(|a| a.kind());
+            if alternative.is_some_and(|a| !is_if(a) && !ELSE_KINDS.contains(&a) && !ELSE_IF_KINDS.contains(&a)) {
+                *total += 1;
+            }
+        } else if ELSE_KINDS.contains(&kind) {
+            let wraps_if = child.named_child_count() == 1 && child.named_child(0).is_some_and(|c| is_if(c.kind()));
+            if !wraps_if {
+                *total += 1;
+            }
+        } else if ELSE_IF_KINDS.contains(&kind) || kind == "goto_statement" {
+            *total += 1;
+        } else if matches!(kind, "break_statement" | "continue_statement") {
+            // Only a jump to a label breaks the flow
+            if child.named_child_count() > 0 {
+                *total += 1;
+            }
+        } else if NESTING_KINDS.contains(&kind) {
+            *total += 1 + nesting;
+            inner = nesting + 1;
+        } else if LAMBDA_KINDS.contains(&kind) {
+            inner = nesting + 1;
+        } else if let Some(operator) = logical_operator(child) {
+            // Each run of the same operator costs 1: `a && b && c || d` is 2
+            if logical_operator(node) != Some(operator) {
+                *total += 1;
+            }
+        }
+        cognitive(child, inner, nested, total);
+    }
+}
+
+fn cognitive_complexity(definition: Node, nested: &HashSet<usize>) -> usize {
+    let mut total = 0;
+    cognitive(definition, 0, nested, &mut total);
+    total
+}
+
+// How deep control structures nest in `tree` at most, and the 1-based line
+// of the innermost one at that depth; an `else if` is one level with its
+// `if`. (0, 0) when nothing nests
+pub(crate) fn max_nesting_depth(tree: &Tree) -> (usize, usize) {
+    let mut deepest = (0, 0);
+    let mut stack = vec![(tree.root_node(), 0)];
+    while let Some((node, depth)) = stack.pop() {
+        let kind = node.kind();
+        let structure = (is_if(kind) && !is_else_if(node))
+            || (NESTING_KINDS.contains(&kind) && !matches!(kind, "conditional_expression" | "ternary_expression"));
+        let depth = if structure { depth + 1 } else { depth };
+        // Ties go to the first in the file
+        if depth > deepest.0 || (depth == deepest.0 && structure && node.start_position().row + 1 < deepest.1) {
+            deepest = (depth, node.start_position().row + 1);
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor).map(|child| (child, depth)));
+    }
+    deepest
+}
+
+// The metrics of every function in an already parsed `tree`
+pub(crate) fn function_metrics(tree: &Tree, content: &str, language: &str, grammar: Language) -> Vec<FunctionMetrics> {
+    let source = content.as_bytes();
+    let functions = function_definitions(tree, content, language, grammar);
+    let nested: HashSet<usize> = functions.iter().map(|(_, definition)| definition.id()).collect();
+    functions.iter()
+        .filter_map(|&(name, definition)| {
+            let name = name.utf8_text(source).ok()?;
+            Some(FunctionMetrics {
+                name: name.to_string(),
+                qualified_name: qualified_name(definition, name, language, source),
+                start_line: definition.start_position().row + 1,
+                end_line: definition.end_position().row + 1,
+                complexity: cyclomatic(definition, &nested, source),
+                cognitive_complexity: cognitive_complexity(definition, &nested),
+            })
+        })
+        .collect()
+}
+
+/// Cyclomatic and cognitive complexity of every function in `content`.
+/// Cyclomatic complexity counts the branches, loops, cases, exception
+/// handlers, conditional expressions and short-circuit operators of each
+/// body; cognitive complexity charges control structures more the deeper
+/// they are nested, and a whole `switch` once. Functions nested in another
+/// are reported on their own and do not add to the outer one.
+#[pyfunction]
+pub fn compute_complexity(py: Python<'_>, content: String, language: String) -> Vec<FunctionMetrics> {
+    py.allow_threads(|| {
+        let Some(grammar) = get_language_parser(&language) else { return vec![] };
+        let Some(tree) = parse(&content, &language, grammar, None) else { return vec![] };
+        function_metrics(&tree, &content, &language, grammar)
+    })
+}
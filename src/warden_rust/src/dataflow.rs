@@ -0,0 +1,253 @@
+use crate::ast::{function_definitions, get_language_parser, parameter_list, parameters, parse, qualified_name};
+use crate::taint::{assignment_parts, code_text, is_member_kind, is_name_kind, target_names};
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+use tree_sitter::Node;
+
+/// One definition of a local variable and the lines that use it before it
+/// is next redefined.
+#[pyclass]
+#[derive(Clone)]
+pub struct DefUseChain {
+    // Qualified name of the function, `<module>` for top-level code
+    #[pyo3(get)]
+    pub function: String,
+    #[pyo3(get)]
+    pub variable: String,
+    // "parameter", "assignment" or "augmented" (`+=` and the like, which
+    // also uses the previous definition)
+    #[pyo3(get)]
+    pub kind: String,
+    // 1-based line of the definition
+    #[pyo3(get)]
+    pub line: usize,
+    // 1-based, sorted, each line once
+    #[pyo3(get)]
+    pub uses: Vec<usize>,
+}
+
+#[pymethods]
+impl DefUseChain {
+    fn __repr__(&self) -> String {
+        format!("DefUseChain({} {}@{} -> {:?})", self.function, self.variable, self.line, self.uses)
+    }
+}
+
+// Names that are never a variable use: the member of a member access, a
+// keyword argument's name, and types
+fn is_use(node: Node) -> bool {
+    if matches!(node.kind(), "property_identifier" | "field_identifier" | "type_identifier") {
+        return false;
+    }
+    let Some(parent) = node.parent() else { return true };
+    let is_field = |field| parent.child_by_field_name(field).is_some_and(|child| child.id() == node.id());
+    !(is_field("attribute") || is_field("property") || is_field("field") || (parent.kind() == "keyword_argument" && is_field("name")))
+}
+
+// The definitions and uses of one function, in source order
+struct Chains<'a> {
+    source: &'a [u8],
+    // Nested functions, each its own scope
+    skip: &'a HashSet<usize>,
+    function: String,
+    // Each variable's chain currently in effect, by index into `chains`
+    current: HashMap<String, usize>,
+    chains: Vec<DefUseChain>,
+}
+
+// The steps of `Chains::visit`, run from a stack rather than by recursion,
+// since expressions nest deeper than the thread's stack
+enum Step<'a> {
+    Visit(Node<'a>),
+    Define(Node<'a>),
+}
+
+impl<'a> Chains<'a> {
+    fn define(&mut self, variable: String, kind: &str, line: usize) {
+        self.current.insert(variable.clone(), self.chains.len());
+        self.chains.push(DefUseChain { function: self.function.clone(), variable, kind: kind.to_string(), line, uses: Vec::new() });
+    }
+
+    fn record_use(&mut self, variable: &str, line: usize) {
+        if let Some(&index) = self.current.get(variable) {
+            let uses = &mut self.chains[index].uses;
+            if uses.last() != Some(&line) {
+                uses.push(line);
+            }
+        }
+    }
+
+    // Takes the code under `node` in source order: an assignment's value
+    // before its target is defined, and the rest, such as a loop's body,
+    // after
+    fn visit(&mut self, node: Node<'a>) {
+        let mut stack = vec![Step::Visit(node)];
+        while let Some(step) = stack.pop() {
+            let node = match step {
+                Step::Visit(node) => node,
+                Step::Define(node) => {
+                    self.define_target(node);
+                    continue;
+                }
+            };
+            if self.skip.contains(&node.id()) {
+                continue;
+            }
+            let mut cursor = node.walk();
+            let children: Vec<Node<'a>> = node.children(&mut cursor).collect();
+            if let Some((target, value, _)) = assignment_parts(node, self.source) {
+                // A loop's body and the like
+                let rest = children.into_iter().filter(|child| child.id() != target.id() && child.id() != value.id());
+                stack.extend(rest.rev().map(Step::Visit));
+                stack.push(Step::Define(node));
+                if is_member_kind(target.kind()) {
+                    stack.push(Step::Visit(target));
+                }
+                stack.push(Step::Visit(value));
+                continue;
+            }
+            if is_name_kind(node.kind()) {
+                if is_use(node) {
+                    self.record_use(&code_text(node, self.source), node.start_position().row + 1);
+                }
+                continue;
+            }
+            stack.extend(children.into_iter().rev().map(Step::Visit));
+        }
+    }
+
+    fn define_target(&mut self, node: Node) {
+        let Some((target, _, compound)) = assignment_parts(node, self.source) else { return };
+        let line = target.start_position().row + 1;
+        let names = if is_member_kind(target.kind()) {
+            Vec::new()
+        } else if is_name_kind(target.kind()) {
+            vec![code_text(target, self.source)]
+        } else {
+            target_names(target, self.source)
+        };
+        for name in names.into_iter().filter(|name| name != "_") {
+            if compound {
+                self.record_use(&name, line);
+            }
+            self.define(name, if compound { "augmented" } else { "assignment" }, line);
+        }
+    }
+}
+
+// The parameters of `definition` with the line of each name
+fn parameter_definitions(definition: Node, language: &str, source: &[u8]) -> Vec<(String, usize)> {
+    let Some(list) = parameter_list(definition, language) else { return vec![] };
+    let mut lines = HashMap::new();
+    let mut stack = vec![list];
+    while let Some(node) = stack.pop() {
+        if is_name_kind(node.kind()) {
+            lines.entry(code_text(node, source)).or_insert(node.start_position().row + 1);
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+    parameters(definition, language, source)
+        .into_iter()
+        .filter_map(|parameter| {
+            // Variadic and `mut` parameters are named as written
+            let name = parameter.name.trim_start_matches(['*', '&', '.']).rsplit(' ').next()?.to_string();
+            let line = *lines.get(&name)?;
+            Some((name, line))
+        })
+        .collect()
+}
+
+fn def_use_chains(content: &str, language: &str) -> Vec<DefUseChain> {
+    let Some(grammar) = get_language_parser(language) else { return vec![] };
+    let Some(tree) = parse(content, language, grammar, None) else { return vec![] };
+    let source = content.as_bytes();
+    let functions = function_definitions(&tree, content, language, grammar);
+    let skip: HashSet<usize> = functions.iter().map(|(_, definition)| definition.id()).collect();
+    let mut chains = Vec::new();
+    let scopes = functions.iter().map(|&(name, definition)| Some((name, definition))).chain(std::iter::once(None));
+    for scope in scopes {
+        let (function, root) = match scope {
+            Some((name, definition)) => {
+                let name = name.utf8_text(source).unwrap_or_default();
+                (qualified_name(definition, name, language, source), definition)
+            }
+            None => ("<module>".to_string(), tree.root_node()),
+        };
+        let mut scope = Chains { source, skip: &skip, function, current: HashMap::new(), chains: Vec::new() };
+        if root.id() != tree.root_node().id() {
+            for (name, line) in parameter_definitions(root, language, source) {
+                scope.define(name, "parameter", line);
+            }
+        }
+        // The parameters were defined above; the body is everything else
+        let list = parameter_list(root, language).map(|list| list.id());
+        let mut cursor = root.walk();
+        let children: Vec<Node> = root.children(&mut cursor).filter(|child| Some(child.id()) != list).collect();
+        for child in children {
+            scope.visit(child);
+        }
+        chains.extend(scope.chains);
+    }
+    chains
+}
+
+/// Def-use chains of every function in `content`, and of the top-level
+/// code as `<module>`: for each parameter and each assignment to a plain
+/// name (loop variables and each name of a destructuring pattern
+/// included), the lines using that value. Code is taken in source order
+/// within a function, so a use is charged to the last definition above it
+/// and nested functions, which are chains of their own, are not looked
+/// into. Names never defined in the function (globals, imports, builtins)
+/// have no chains. Empty when `language` has no grammar.
+#[pyfunction]
+pub fn get_def_use_chains(content: String, language: String) -> Vec<DefUseChain> {
+    def_use_chains(&content, &language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chains(content: &str) -> Vec<(String, String, usize, Vec<usize>)> {
+        def_use_chains(content, "python")
+            .into_iter()
+            .map(|chain| (chain.variable, chain.kind, chain.line, chain.uses))
+            .collect()
+    }
+
+    fn chain(variable: &str, kind: &str, line: usize, uses: &[usize]) -> (String, String, usize, Vec<usize>) {
+        (variable.to_string(), kind.to_string(), line, uses.to_vec())
+    }
+
+    #[test]
+    fn charges_each_use_to_the_last_definition_above_it() {
+        let content = "def f(a):\n    b = a + 1\n    b += a\n    print(b, b)\n    return b\n";
+        assert_eq!(chains(content), vec![
+            chain("a", "parameter", 1, &[2, 3]),
+            chain("b", "assignment", 2, &[3]),
+            chain("b", "augmented", 3, &[4, 5]),
+        ]);
+    }
+
+    #[test]
+    fn members_keywords_and_nested_functions_are_not_uses() {
+        let content = "def f(a):\n    a.a = 1\n    g(a=2)\n    def inner():\n        return a\n";
+        let found = def_use_chains(content, "python");
+        let outer: Vec<_> = found.iter().filter(|chain| chain.function == "f").collect();
+        assert_eq!(outer.len(), 1);
+        assert_eq!(outer[0].uses, vec![2]);
+    }
+
+    #[test]
+    fn deeply_nested_expressions_do_not_overflow_the_stack() {
+        let terms = vec!["x"; 10_000].join(" + ");
+        let content = format!("x = 1\ny = {}\n", terms);
+        assert_eq!(chains(&content), vec![chain("x", "assignment", 1, &[2]), chain("y", "assignment", 2, &[])]);
+    }
+
+    #[test]
+    fn languages_without_a_grammar_have_no_chains() {
+        assert!(def_use_chains("x = 1\n", "cobol").is_empty());
+    }
+}
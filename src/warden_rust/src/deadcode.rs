@@ -0,0 +1,226 @@
+use crate::ast::{class_definitions, decorators, function_definitions, get_language_parser, parse, qualified_name};
+use crate::encoding;
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+use tree_sitter::Node;
+
+/// An exported function or class nothing outside its own file refers to.
+///
+/// References are matched by name across every parsed file, so a symbol
+/// only counts as dead when no file mentions its name at all outside the
+/// one defining it; `local_references` counts the mentions inside it.
+/// `confidence` is "high" for a top-level symbol with no references
+/// anywhere, "medium" for one used only in its own file (which could be
+/// made private) or an unreferenced method, and "low" for a method used
+/// only in its own file or a decorated symbol, which frameworks and
+/// overrides reach without naming it.
+#[pyclass]
+#[derive(Clone)]
+pub struct DeadSymbol {
+    #[pyo3(get)]
+    pub path: String,
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub qualified_name: String,
+    // "function" or "class"
+    #[pyo3(get)]
+    pub kind: String,
+    // 1-based line of the name
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub local_references: usize,
+    #[pyo3(get)]
+    pub confidence: String,
+}
+
+#[pymethods]
+impl DeadSymbol {
+    fn __repr__(&self) -> String {
+        format!("DeadSymbol({}:{} {} {} {})", self.path, self.line, self.kind, self.qualified_name, self.confidence)
+    }
+}
+
+// An exported definition, before the references of the other files are in
+struct Candidate {
+    name: String,
+    qualified_name: String,
+    kind: &'static str,
+    line: usize,
+    method: bool,
+    decorated: bool,
+}
+
+// What one file defines and how often it mentions each name, leaving out
+// the names of its own definitions
+struct FileSymbols {
+    path: String,
+    candidates: Vec<Candidate>,
+    mentions: HashMap<String, usize>,
+}
+
+// Names a runtime, framework or test runner calls without the code naming
+// them
+fn is_entry_point(name: &str) -> bool {
+    let dunder = name.len() > 4 && name.starts_with("__") && name.ends_with("__");
+    dunder
+        || matches!(name, "main" | "init" | "setUp" | "tearDown" | "setUpClass" | "tearDownClass" | "setup" | "teardown")
+        || ["test", "Test", "Benchmark", "Example", "Fuzz"].iter().any(|prefix| name.starts_with(prefix))
+}
+
+fn is_name_kind(kind: &str) -> bool {
+    kind.ends_with("identifier") || matches!(kind, "name" | "constant")
+}
+
+// Whether code outside the file can reach `definition`, going by the
+// language's own visibility rules
+fn is_exported(definition: Node, name: Node, language: &str, source: &[u8], has_exports: bool) -> bool {
+    let text = name.utf8_text(source).unwrap_or_default();
+    let mut cursor = definition.walk();
+    let mut children = definition.children(&mut cursor);
+    match language {
+        "python" => !text.starts_with('_'),
+        "go" => text.starts_with(|c: char| c.is_uppercase()),
+        "rust" => children.any(|child| child.kind() == "visibility_modifier"),
+        // CommonJS modules export by assignment, so without any ESM export
+        // every top-level symbol may be
+        "javascript" | "typescript" => {
+            if definition.kind() == "method_definition" {
+                return !text.starts_with('#')
+                    && !children.any(|child| child.kind() == "accessibility_modifier" && child.utf8_text(source) != Ok("public"));
+            }
+            definition.parent().is_some_and(|parent| parent.kind() == "export_statement") || !has_exports
+        }
+        "c" | "cpp" => !children.any(|child| child.kind() == "storage_class_specifier" && child.utf8_text(source) == Ok("static")),
+        "ruby" => true,
+        // Modifiers come before the name
+        _ => {
+            let head = &source[definition.start_byte()..name.start_byte()];
+            !String::from_utf8_lossy(head).split(|c: char| !c.is_alphanumeric()).any(|word| matches!(word, "private" | "fileprivate"))
+        }
+    }
+}
+
+fn file_symbols(path: &str) -> Option<FileSymbols> {
+    let content = encoding::read_to_string(path).ok()?;
+    let language = detect_language(std::path::Path::new(path), content.as_bytes());
+    let grammar = get_language_parser(&language)?;
+    let tree = parse(&content, &language, grammar, None)?;
+    let source = content.as_bytes();
+    let functions = function_definitions(&tree, &content, &language, grammar);
+    let classes = class_definitions(&tree, &content, &language, grammar);
+    let function_ids: HashSet<usize> = functions.iter().map(|(_, definition)| definition.id()).collect();
+    let class_ids: HashSet<usize> = classes.iter().map(|(_, definition)| definition.id()).collect();
+    let mut cursor = tree.root_node().walk();
+    let has_exports = tree.root_node().children(&mut cursor).any(|child| child.kind() == "export_statement");
+
+    let mut candidates = Vec::new();
+    let mut defined_names = HashSet::new();
+    let kinds = functions.iter().map(|&pair| (pair, "function")).chain(classes.iter().map(|&pair| (pair, "class")));
+    for ((name, definition), kind) in kinds {
+        defined_names.insert(name.id());
+        let Ok(text) = name.utf8_text(source) else { continue };
+        // `Foo::bar` out of class in C++ is mentioned as `bar`
+        let short = text.rsplit("::").next().unwrap_or(text);
+        if is_entry_point(short) || definition.kind() == "constructor_declaration" {
+            continue;
+        }
+        // Functions nested in functions are local to them; methods are
+        // reached through their class
+        let mut method = definition.kind() == "method_declaration" && language == "go";
+        let mut ancestor = definition.parent();
+        let mut local = false;
+        while let Some(node) = ancestor {
+            if function_ids.contains(&node.id()) {
+                local = true;
+                break;
+            }
+            method |= class_ids.contains(&node.id()) || matches!(node.kind(), "impl_item" | "trait_item" | "object" | "class_body");
+            ancestor = node.parent();
+        }
+        if local || !is_exported(definition, name, &language, source, has_exports) {
+            continue;
+        }
+        candidates.push(Candidate {
+            name: short.to_string(),
+            qualified_name: qualified_name(definition, text, &language, source),
+            kind,
+            line: name.start_position().row + 1,
+            method,
+            decorated: !decorators(definition, source).is_empty(),
+        });
+    }
+
+    let mut mentions = HashMap::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.child_count() == 0 {
+            if is_name_kind(node.kind()) && !defined_names.contains(&node.id()) {
+                if let Ok(text) = node.utf8_text(source) {
+                    *mentions.entry(text.to_string()).or_insert(0) += 1;
+                }
+            }
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    Some(FileSymbols { path: path.to_string(), candidates, mentions })
+}
+
+/// Exported functions and classes in `files` that no other file in `files`
+/// refers to, sorted by path and line. Files are parsed in parallel with
+/// the GIL released; files that cannot be read as UTF-8 text, or whose
+/// language has no grammar, neither define nor reference anything.
+///
+/// Entry points called by name from outside the code are never reported:
+/// `main`, dunder methods, constructors, Go `init`, and test functions and
+/// fixtures (`test_*`, `Test*`, `setUp`...).
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_dead_symbols(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<DeadSymbol>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    let parsed = control.run(py, &files, |path| file_symbols(path))?;
+    Ok(py.allow_threads(|| {
+        let mut total: HashMap<&str, usize> = HashMap::new();
+        for file in &parsed {
+            for (name, count) in &file.mentions {
+                *total.entry(name.as_str()).or_insert(0) += count;
+            }
+        }
+        let mut dead = Vec::new();
+        for file in &parsed {
+            for candidate in &file.candidates {
+                let local = file.mentions.get(&candidate.name).copied().unwrap_or(0);
+                if total.get(candidate.name.as_str()).copied().unwrap_or(0) > local {
+                    continue;
+                }
+                let confidence = match (candidate.decorated, candidate.method, local) {
+                    (true, _, _) | (_, true, 1..) => "low",
+                    (_, true, 0) | (_, false, 1..) => "medium",
+                    _ => "high",
+                };
+                dead.push(DeadSymbol {
+                    path: file.path.clone(),
+                    name: candidate.name.clone(),
+                    qualified_name: candidate.qualified_name.clone(),
+                    kind: candidate.kind.to_string(),
+                    line: candidate.line,
+                    local_references: local,
+                    confidence: confidence.to_string(),
+                });
+            }
+        }
+        dead.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+        dead
+    }))
+}
@@ -0,0 +1,126 @@
+// Text in encodings other than UTF-8, told apart by a byte order mark or,
+// for UTF-16 without one, by where its zero bytes fall, and transcoded to
+// UTF-8 before lines are split, matched or parsed
+
+use content_inspector::{inspect, ContentType};
+use std::borrow::Cow;
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+// How much of the content the UTF-16 zero byte count looks at
+const SNIFF_BYTES: usize = 1024;
+
+// "utf-16-le" or "utf-16-be" when `bytes` open with a UTF-16 byte order
+// mark, or hold mostly ASCII text with a zero byte beside each character
+fn utf16_order(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\xFF\xFE") && !bytes.starts_with(b"\xFF\xFE\x00\x00") {
+        return Some("utf-16-le");
+    }
+    if bytes.starts_with(b"\xFE\xFF") {
+        return Some("utf-16-be");
+    }
+    let pairs = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    let pairs = pairs.chunks_exact(2);
+    let count = pairs.len();
+    let (mut even, mut odd) = (0, 0);
+    for pair in pairs {
+        even += usize::from(pair[0] == 0);
+        odd += usize::from(pair[1] == 0);
+    }
+    // Binaries have zeros in both places, text in the one only
+    match (even, odd) {
+        _ if count < 2 => None,
+        (0, odd) if odd * 10 >= count * 9 => Some("utf-16-le"),
+        (even, 0) if even * 10 >= count * 9 => Some("utf-16-be"),
+        _ => None,
+    }
+}
+
+/// The encoding of `bytes`, which may be just the head of a file, by
+/// Python's codec names: "utf-8", "utf-8-sig" with a byte order mark,
+/// "utf-16-le", "utf-16-be", or "latin-1" for text that is not UTF-8. Text
+/// that is UTF-8 apart from a few invalid bytes stays "utf-8".
+pub(crate) fn detect(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(UTF8_BOM) {
+        return "utf-8-sig";
+    }
+    if let Some(order) = utf16_order(bytes) {
+        return order;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => "utf-8",
+        // Only cut off mid-character, at the end of a head
+        Err(e) if e.error_len().is_none() => "utf-8",
+        Err(_) if bytes.utf8_chunks().any(|chunk| !chunk.valid().is_ascii()) => "utf-8",
+        Err(_) => "latin-1",
+    }
+}
+
+/// `bytes` as UTF-8, with the encoding `detect` found. UTF-8 comes back as
+/// it is, invalid bytes and all, only a byte order mark dropped.
+pub(crate) fn decode(bytes: &[u8]) -> (Cow<'_, [u8]>, &'static str) {
+    let encoding = detect(bytes);
+    let text = match encoding {
+        "utf-8-sig" => Cow::Borrowed(&bytes[UTF8_BOM.len()..]),
+        "utf-16-le" | "utf-16-be" => {
+            let body = bytes.strip_prefix(b"\xFF\xFE").or_else(|| bytes.strip_prefix(b"\xFE\xFF")).unwrap_or(bytes);
+            let units = body.chunks_exact(2).map(|pair| match encoding {
+                "utf-16-le" => u16::from_le_bytes([pair[0], pair[1]]),
+                _ => u16::from_be_bytes([pair[0], pair[1]]),
+            });
+            let text: String = char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect();
+            Cow::Owned(text.into_bytes())
+        }
+        "latin-1" => Cow::Owned(bytes.iter().map(|&byte| char::from(byte)).collect::<String>().into_bytes()),
+        _ => Cow::Borrowed(bytes),
+    };
+    (text, encoding)
+}
+
+/// Whether the head of a file is binary to `content_inspector`, except for
+/// UTF-16 text without a byte order mark, which it takes for binary.
+pub(crate) fn is_binary(head: &[u8]) -> bool {
+    inspect(head) == ContentType::BINARY && utf16_order(head).is_none()
+}
+
+/// `std::fs::read_to_string`, transcoding from the file's encoding; still
+/// an error for content that is not valid in the encoding found.
+pub(crate) fn read_to_string(path: impl AsRef<Path>) -> std::io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    let (text, _) = decode(&bytes);
+    String::from_utf8(text.into_owned()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings_are_told_apart() {
+        assert_eq!(detect(b"plain ascii\n"), "utf-8");
+        assert_eq!(detect("caf\u{e9}\n".as_bytes()), "utf-8");
+        assert_eq!(detect(b"\xEF\xBB\xBFx = 1\n"), "utf-8-sig");
+        assert_eq!(detect(b"\xFF\xFEx\x00"), "utf-16-le");
+        assert_eq!(detect(b"x\x00 \x00=\x00 \x001\x00"), "utf-16-le");
+        assert_eq!(detect(b"\x00x\x00 \x00=\x00 \x001"), "utf-16-be");
+        assert_eq!(detect(b"caf\xe9\n"), "latin-1");
+        // A stray bad byte in UTF-8 text, or a character cut off by the head
+        assert_eq!(detect(&["\u{e9}t\u{e9} ".as_bytes(), b"\xff"].concat()), "utf-8");
+        assert_eq!(detect(&"caf\u{e9}".as_bytes()[..4]), "utf-8");
+    }
+
+    #[test]
+    fn text_is_transcoded_to_utf8() {
+        assert_eq!(&*decode(b"\xEF\xBB\xBFx\n").0, b"x\n");
+        assert_eq!(&*decode(b"\xFF\xFEc\x00\xe9\x00").0, "c\u{e9}".as_bytes());
+        assert_eq!(decode(b"caf\xe9\n"), (Cow::Owned("caf\u{e9}\n".as_bytes().to_vec()), "latin-1"));
+        assert!(matches!(decode(b"x = 1\n").0, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn utf16_text_is_not_binary() {
+        assert!(!is_binary(b"x\x00 \x00=\x00 \x001\x00\n\x00"));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00"));
+    }
+}
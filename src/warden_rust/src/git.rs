@@ -0,0 +1,454 @@
+use crate::scanner::{split_lines, MatchHit};
+use crate::{stop_here, CancellationToken, ScanControl, ScanItem};
+use content_inspector::{inspect, ContentType};
+use gix::diff::blob::{Algorithm, Diff, InternedInput};
+use gix::object::tree::diff::{Action, Change};
+use gix::{ObjectId, ThreadSafeRepository};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::sync_channel;
+use std::sync::Arc;
+use std::thread;
+
+// Changes the history walk may run ahead of the scan by
+const HISTORY_CHANNEL_CAPACITY: usize = 1024;
+
+pub(crate) fn git_error(repo: &str, e: impl std::fmt::Display) -> PyErr {
+    PyRuntimeError::new_err(format!("Git error in {}: {}", repo, e))
+}
+
+/// Who committed what, for findings traced back to a commit.
+#[pyclass]
+#[derive(Clone)]
+pub struct CommitInfo {
+    #[pyo3(get)]
+    pub commit: String,
+    #[pyo3(get)]
+    pub author_name: String,
+    #[pyo3(get)]
+    pub author_email: String,
+    // Author time, seconds since the Unix epoch
+    #[pyo3(get)]
+    pub timestamp: i64,
+}
+
+impl CommitInfo {
+    fn new(commit: &gix::Commit<'_>) -> Result<Self, gix::Error> {
+        let author = commit.author()?;
+        Ok(CommitInfo {
+            commit: commit.id.to_string(),
+            author_name: author.name.to_string(),
+            author_email: author.email.to_string(),
+            timestamp: author.seconds(),
+        })
+    }
+}
+
+/// A rule match on a line that some commit added. The line may have been
+/// changed or deleted since; `hit.line_number` is its line in that commit.
+#[pyclass]
+#[derive(Clone)]
+pub struct GitHistoryHit {
+    #[pyo3(get)]
+    pub commit: CommitInfo,
+    #[pyo3(get)]
+    pub hit: MatchHit,
+}
+
+#[pymethods]
+impl GitHistoryHit {
+    fn __repr__(&self) -> String {
+        let short = &self.commit.commit[..self.commit.commit.len().min(12)];
+        format!("GitHistoryHit({} {}:{} {})", short, self.hit.file_path, self.hit.line_number, self.hit.rule_id)
+    }
+}
+
+/// One file a commit added or modified.
+pub(crate) struct BlobChange {
+    pub commit: Arc<CommitInfo>,
+    // Repository-relative, with `/` separators
+    pub path: String,
+    // None when the commit added the file
+    pub previous: Option<ObjectId>,
+    pub id: ObjectId,
+}
+
+impl ScanItem for BlobChange {
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// The commits reachable from HEAD, newest first, as the file changes they
+/// introduced. Merge commits are skipped: the lines they bring in were added
+/// by the commits being merged, which the walk visits as well.
+pub(crate) struct GitHistory {
+    repo: ThreadSafeRepository,
+}
+
+impl GitHistory {
+    pub(crate) fn open(path: &str) -> PyResult<Self> {
+        let repo = gix::discover(path).map_err(|e| git_error(path, e))?;
+        Ok(GitHistory { repo: repo.into_sync() })
+    }
+
+    /// Walks the newest `max_commits` commits (all by default) on a
+    /// background thread and runs `scan_one` over each change as soon as the
+    /// walk reaches it, so memory does not grow with the length of history.
+    /// Setting `control`'s cancellation token stops the walk as well.
+    pub(crate) fn scan_changes<T, I, F>(&self, py: Python<'_>, path: &str, max_commits: Option<usize>, control: ScanControl, scan_one: F) -> PyResult<Vec<T>>
+    where
+        T: Send,
+        I: IntoIterator<Item = T>,
+        F: Fn(&BlobChange) -> I + Sync + Send,
+    {
+        let (sender, receiver) = sync_channel(HISTORY_CHANNEL_CAPACITY);
+        let cancel_token = control.cancel_token.clone();
+        thread::scope(|scope| {
+            let walker = scope.spawn(move || {
+                let repo = self.repo.to_thread_local();
+                // A send only fails once the scan has stopped listening
+                history_changes(&repo, max_commits, &cancel_token, |change| sender.send(change).is_ok())
+            });
+            let results = control.stream(py, receiver.into_iter(), scan_one);
+            let walked = walker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            walked.map_err(|e| git_error(path, e))?;
+            results
+        })
+    }
+
+    /// The content `change` left the file with, None if unreadable.
+    pub(crate) fn blob(&self, change: &BlobChange) -> Option<Vec<u8>> {
+        self.read(change.id)
+    }
+
+    fn read(&self, id: ObjectId) -> Option<Vec<u8>> {
+        let repo = self.repo.to_thread_local();
+        repo.find_object(id).ok().map(|object| object.detach().data)
+    }
+
+    /// `(line_number, bytes)` of every line `change` added, in file order.
+    /// Binary and unreadable blobs yield nothing.
+    pub(crate) fn added_lines(&self, change: &BlobChange) -> Vec<(usize, Vec<u8>)> {
+        let Some(after) = self.read(change.id) else { return Vec::new() };
+        let before = match change.previous {
+            Some(previous) => match self.read(previous) {
+                Some(before) => before,
+                None => return Vec::new(),
+            },
+            None => Vec::new(),
+        };
+        if inspect(&after) == ContentType::BINARY {
+            return Vec::new();
+        }
+        added_line_numbers(&before, &after)
+            .into_iter()
+            .zip(split_lines(&after).into_iter().enumerate())
+            .filter(|&(added, _)| added)
+            .map(|(_, (idx, line))| (idx + 1, line.to_vec()))
+            .collect()
+    }
+}
+
+// For every line of `after`, whether it is new relative to `before`
+fn added_line_numbers(before: &[u8], after: &[u8]) -> Vec<bool> {
+    let input = InternedInput::new(before, after);
+    let diff = Diff::compute(Algorithm::Histogram, &input);
+    (0..input.after.len() as u32).map(|idx| diff.is_added(idx)).collect()
+}
+
+// The line ranges of `after` (1-based, inclusive) that differ from `before`
+fn changed_line_ranges(before: &[u8], after: &[u8]) -> Vec<(usize, usize)> {
+    let input = InternedInput::new(before, after);
+    let diff = Diff::compute(Algorithm::Histogram, &input);
+    diff.hunks()
+        .filter(|hunk| !hunk.after.is_empty())
+        .map(|hunk| (hunk.after.start as usize + 1, hunk.after.end as usize))
+        .collect()
+}
+
+// Hands `send` the changes of each commit in turn, stopping early when it
+// returns false or `cancel_token` is set
+fn history_changes(
+    repo: &gix::Repository,
+    max_commits: Option<usize>,
+    cancel_token: &Option<CancellationToken>,
+    mut send: impl FnMut(BlobChange) -> bool,
+) -> Result<(), gix::Error> {
+    let head = repo.head_id()?;
+    let mut cache = repo.diff_resource_cache(gix::diff::blob::pipeline::Mode::ToGit, Default::default())?;
+    let walk = repo.rev_walk([head.detach()]).all()?;
+    for info in walk.take(max_commits.unwrap_or(usize::MAX)) {
+        if stop_here(cancel_token) {
+            break;
+        }
+        let info = info?;
+        let parents: Vec<ObjectId> = info.parent_ids().map(|id| id.detach()).collect();
+        if parents.len() > 1 {
+            continue;
+        }
+        let commit = info.object()?;
+        let tree = commit.tree()?;
+        let parent_tree = match parents.first() {
+            Some(&parent) => repo.find_commit(parent)?.tree()?,
+            None => repo.empty_tree(),
+        };
+        let info = Arc::new(CommitInfo::new(&commit)?);
+        let mut changes = Vec::new();
+        parent_tree
+            .changes()?
+            .options(|options| {
+                options.track_path().track_rewrites(None);
+            })
+            .for_each_to_obtain_tree_with_cache(&tree, &mut cache, |change| {
+                let (previous, id, location, mode) = match change {
+                    Change::Addition { location, entry_mode, id, .. } => (None, id, location, entry_mode),
+                    Change::Modification { location, previous_id, id, entry_mode, .. } => {
+                        (Some(previous_id.detach()), id, location, entry_mode)
+                    }
+                    Change::Rewrite { location, source_id, id, entry_mode, .. } => {
+                        (Some(source_id.detach()), id, location, entry_mode)
+                    }
+                    Change::Deletion { .. } => return Ok(Action::Continue(())),
+                };
+                if mode.is_blob() {
+                    changes.push(BlobChange {
+                        commit: Arc::clone(&info),
+                        path: location.to_string(),
+                        previous,
+                        id: id.detach(),
+                    });
+                }
+                Ok(Action::Continue(()))
+            })?;
+        cache.clear_resource_cache();
+        if !changes.into_iter().all(&mut send) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// `path` as an index key: relative to the work tree, `/`-separated
+fn index_key(workdir: Option<&Path>, path: &str) -> String {
+    let as_given = Path::new(path);
+    let relative = match workdir {
+        Some(root) if as_given.is_absolute() => {
+            let canonical = as_given.canonicalize().unwrap_or_else(|_| as_given.to_path_buf());
+            as_given.strip_prefix(root)
+                .or_else(|_| canonical.strip_prefix(root))
+                .map(Path::to_path_buf)
+                .unwrap_or(canonical)
+        }
+        _ => as_given.to_path_buf(),
+    };
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// The staged content of each of `paths`, as it would be committed. Paths
+/// are relative to the work tree or absolute inside it, and are returned as
+/// given; the ones not in the index (untracked, deleted, submodules) are
+/// left out.
+pub(crate) fn staged_blobs(path: &str, paths: &[String]) -> PyResult<Vec<(String, Vec<u8>)>> {
+    let repo = gix::discover(path).map_err(|e| git_error(path, e))?;
+    let index = repo.index_or_empty().map_err(|e| git_error(path, e))?;
+    let workdir = repo.workdir().and_then(|root| root.canonicalize().ok());
+    let mut blobs = Vec::new();
+    for staged_path in paths {
+        let key = index_key(workdir.as_deref(), staged_path);
+        let Some(entry) = index.entry_by_path(key.as_str().into()) else { continue };
+        if !entry.mode.to_tree_entry_mode().is_some_and(|mode| mode.is_blob()) {
+            continue;
+        }
+        let object = repo.find_object(entry.id).map_err(|e| git_error(path, e))?;
+        blobs.push((staged_path.clone(), object.detach().data));
+    }
+    Ok(blobs)
+}
+
+// The lines of one file to blame, with where each came from in the request
+struct BlameRequest {
+    path: String,
+    lines: Vec<(usize, usize)>,
+}
+
+impl ScanItem for BlameRequest {
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+// `(item index, commit)` for every requested line of one working-tree file;
+// uncommitted lines and files git cannot blame get None
+fn blame_request(repo: &ThreadSafeRepository, request: &BlameRequest) -> Vec<(usize, Option<CommitInfo>)> {
+    let unattributed = || request.lines.iter().map(|&(idx, _)| (idx, None)).collect();
+    let repo = repo.to_thread_local();
+    let Some(workdir) = repo.workdir().and_then(|root| root.canonicalize().ok()) else { return unattributed() };
+    let key = index_key(Some(&workdir), &request.path);
+    let Ok(contents) = std::fs::read(workdir.join(&key)) else { return unattributed() };
+    let Ok(head) = repo.head_id() else { return unattributed() };
+    let ranges = request.lines.iter().map(|&(_, line)| line as u32..=line as u32).collect();
+    let Ok(ranges) = gix::blame::BlameRanges::from_one_based_inclusive_ranges(ranges) else { return unattributed() };
+    let (Ok(graph), Ok(mut cache), Ok(diff_algorithm)) =
+        (repo.commit_graph_if_enabled(), repo.diff_resource_cache_for_tree_diff(), repo.diff_algorithm())
+    else {
+        return unattributed();
+    };
+    let options = gix::blame::Options { diff_algorithm, ranges, since: None, rewrites: None, debug_track_path: false };
+    let start = gix::blame::Start::Contents { first_suspect: head.detach(), contents: contents.into() };
+    let Ok(outcome) = gix::blame::file(&repo.objects, start, graph, &mut cache, key.as_str().into(), options) else {
+        return unattributed();
+    };
+
+    let mut commits: HashMap<ObjectId, Option<CommitInfo>> = HashMap::new();
+    request.lines.iter()
+        .map(|&(idx, line)| {
+            let line = line.saturating_sub(1) as u32;
+            let entry = outcome.entries.iter()
+                .find(|e| e.start_in_blamed_file <= line && line < e.start_in_blamed_file + e.len.get());
+            let commit = entry.filter(|e| !e.commit_id.is_null()).and_then(|e| {
+                commits.entry(e.commit_id)
+                    .or_insert_with(|| repo.find_commit(e.commit_id).ok().and_then(|c| CommitInfo::new(&c).ok()))
+                    .clone()
+            });
+            (idx, commit)
+        })
+        .collect()
+}
+
+/// The commit that last changed each `(path, line_number)` location, as `git
+/// blame` reports it for the working-tree file; one entry per item, in order.
+/// Lines not committed yet, and files git does not track, get None. Each file
+/// is blamed once however many of its lines are asked for.
+#[pyfunction]
+#[pyo3(signature = (repo, items, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn blame_lines(
+    py: Python<'_>,
+    repo: String,
+    items: Vec<(String, usize)>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<Option<CommitInfo>>> {
+    let handle = gix::discover(&repo).map_err(|e| git_error(&repo, e))?.into_sync();
+    let mut by_path: HashMap<&str, usize> = HashMap::new();
+    let mut requests: Vec<BlameRequest> = Vec::new();
+    for (idx, (path, line)) in items.iter().enumerate() {
+        let slot = *by_path.entry(path).or_insert_with(|| {
+            requests.push(BlameRequest { path: path.clone(), lines: Vec::new() });
+            requests.len() - 1
+        });
+        requests[slot].lines.push((idx, *line));
+    }
+
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    let blamed = control.run(py, &requests, |request| blame_request(&handle, request))?;
+    let mut commits = vec![None; items.len()];
+    for (idx, commit) in blamed {
+        commits[idx] = commit;
+    }
+    Ok(commits)
+}
+
+/// A file that differs between two refs.
+///
+/// `change_type` is "added", "modified", "deleted", "renamed" or "copied";
+/// `old_path` is set for the last two. `ranges` are the 1-based, inclusive
+/// line ranges of the new version that were added or changed, in the form
+/// `changed_ranges` takes. Deleted and binary files have none.
+#[pyclass]
+#[derive(Clone)]
+pub struct ChangedFile {
+    #[pyo3(get)]
+    pub path: String,
+    #[pyo3(get)]
+    pub old_path: Option<String>,
+    #[pyo3(get)]
+    pub change_type: String,
+    #[pyo3(get)]
+    pub ranges: Vec<(usize, usize)>,
+}
+
+#[pymethods]
+impl ChangedFile {
+    fn __repr__(&self) -> String {
+        format!("ChangedFile({} {} {} ranges)", self.change_type, self.path, self.ranges.len())
+    }
+}
+
+fn blob_ranges(repo: &gix::Repository, previous: Option<ObjectId>, id: ObjectId) -> Result<Vec<(usize, usize)>, gix::Error> {
+    let after = repo.find_object(id)?.detach().data;
+    if inspect(&after) == ContentType::BINARY {
+        return Ok(Vec::new());
+    }
+    let before = match previous {
+        Some(previous) => repo.find_object(previous)?.detach().data,
+        None => Vec::new(),
+    };
+    Ok(changed_line_ranges(&before, &after))
+}
+
+// None when the two share no history, so head never forked off base
+fn changed_files(repo: &gix::Repository, base_ref: &str, head_ref: &str) -> Result<Option<Vec<ChangedFile>>, gix::Error> {
+    let base = repo.rev_parse_single(base_ref)?.object()?.peel_to_commit()?.id;
+    let head = repo.rev_parse_single(head_ref)?.object()?.peel_to_commit()?;
+    // Like `git diff base...head`: what head changed since it forked off base
+    let Some(fork_point) = repo.merge_base(base, head.id)?.map(|id| id.detach()) else { return Ok(None) };
+    let base_tree = repo.find_commit(fork_point)?.tree()?;
+    let head_tree = head.tree()?;
+
+    let mut cache = repo.diff_resource_cache_for_tree_diff()?;
+    let mut changes = Vec::new();
+    base_tree
+        .changes()?
+        .options(|options| {
+            options.track_path().track_rewrites(Some(Default::default()));
+        })
+        .for_each_to_obtain_tree_with_cache(&head_tree, &mut cache, |change| {
+            if !change.entry_mode().is_blob() {
+                return Ok(Action::Continue(()));
+            }
+            let path = change.location().to_string();
+            let (change_type, old_path, previous, id) = match change {
+                Change::Addition { id, .. } => ("added", None, None, Some(id.detach())),
+                Change::Deletion { .. } => ("deleted", None, None, None),
+                Change::Modification { previous_id, id, .. } => {
+                    ("modified", None, Some(previous_id.detach()), Some(id.detach()))
+                }
+                Change::Rewrite { source_location, source_id, id, copy, .. } => {
+                    let change_type = if copy { "copied" } else { "renamed" };
+                    (change_type, Some(source_location.to_string()), Some(source_id.detach()), Some(id.detach()))
+                }
+            };
+            changes.push((ChangedFile { path, old_path, change_type: change_type.to_string(), ranges: Vec::new() }, previous, id));
+            Ok(Action::Continue(()))
+        })?;
+
+    let mut files = Vec::with_capacity(changes.len());
+    for (mut file, previous, id) in changes {
+        if let Some(id) = id {
+            file.ranges = blob_ranges(repo, previous, id)?;
+        }
+        files.push(file);
+    }
+    Ok(Some(files))
+}
+
+/// The files `head_ref` changed relative to where it forked off `base_ref`
+/// (their merge base, like `git diff base...head`), with renames detected
+/// and the changed line ranges of each, for feeding `changed_ranges` when
+/// reviewing a pull request. Paths are relative to the repository root.
+/// ValueError when the two share no history and so have no merge base.
+#[pyfunction]
+#[pyo3(signature = (repo, base_ref, head_ref="HEAD"))]
+pub fn git_changed_files(py: Python<'_>, repo: &str, base_ref: &str, head_ref: &str) -> PyResult<Vec<ChangedFile>> {
+    py.allow_threads(|| {
+        let handle = gix::discover(repo).map_err(|e| git_error(repo, e))?;
+        changed_files(&handle, base_ref, head_ref).map_err(|e| git_error(repo, e))?.ok_or_else(|| {
+            PyValueError::new_err(format!("{} and {} share no history in {}: there is no merge base to diff against", base_ref, head_ref, repo))
+        })
+    })
+}
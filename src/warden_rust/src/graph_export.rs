@@ -0,0 +1,230 @@
+use crate::ast::{get_language_parser, parse, tree_metadata};
+use crate::encoding;
+use crate::imports::{import_edges, Graph};
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// The functions of one file and the calls made from it, as
+// (caller qualified name, callee name)
+struct FileCalls {
+    path: String,
+    functions: Vec<String>,
+    calls: Vec<(String, String)>,
+}
+
+fn file_calls(path: &str) -> Option<FileCalls> {
+    let content = encoding::read_to_string(path).ok()?;
+    let language = detect_language(std::path::Path::new(path), content.as_bytes());
+    let grammar = get_language_parser(&language)?;
+    let tree = parse(&content, &language, grammar, None)?;
+    let metadata = tree_metadata(&tree, &content, &language, grammar);
+    Some(FileCalls {
+        path: path.to_string(),
+        functions: metadata.functions.into_iter().map(|function| function.qualified_name).collect(),
+        calls: metadata.calls.into_iter().map(|(caller, callee, _)| (caller, callee)).collect(),
+    })
+}
+
+// Calls between the functions of `files`, nodes named `path:qualified_name`
+// and module-level code `path:<module>`. A callee is looked for by name in
+// the calling file first, then among the functions of all files, where it
+// has to be the only one of that name; calls that resolve to nothing, or to
+// too many, are left out
+fn call_graph(files: &[FileCalls]) -> Graph {
+    let short = |qualified: &str| qualified.rsplit('.').next().unwrap_or(qualified).to_string();
+    let mut project: HashMap<String, Vec<String>> = HashMap::new();
+    for file in files {
+        for function in &file.functions {
+            project.entry(short(function)).or_default().push(format!("{}:{}", file.path, function));
+        }
+    }
+    let mut pairs = Vec::new();
+    for file in files {
+        let mut local: HashMap<String, Vec<String>> = HashMap::new();
+        for function in &file.functions {
+            local.entry(short(function)).or_default().push(format!("{}:{}", file.path, function));
+        }
+        for (caller, callee) in &file.calls {
+            let targets = match (local.get(callee), project.get(callee)) {
+                (Some(targets), _) => targets.as_slice(),
+                (None, Some(targets)) if targets.len() == 1 => targets.as_slice(),
+                _ => continue,
+            };
+            let caller = format!("{}:{}", file.path, caller);
+            pairs.extend(targets.iter().filter(|&target| *target != caller).map(|target| (caller.clone(), target.clone())));
+        }
+    }
+    Graph::from_pairs(pairs)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn to_dot(graph: &Graph, name: &str) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut out = format!("digraph {} {{\n", name);
+    for (source, targets) in graph.successors.iter().enumerate() {
+        for &target in targets {
+            let _ = writeln!(out, "  {} -> {};", quote(&graph.names[source]), quote(&graph.names[target]));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn to_graphml(graph: &Graph, name: &str) -> String {
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+    ));
+    let _ = writeln!(out, "  <graph id=\"{}\" edgedefault=\"directed\">", name);
+    for (i, node) in graph.names.iter().enumerate() {
+        let _ = writeln!(out, "    <node id=\"n{}\"><data key=\"label\">{}</data></node>", i, escape_xml(node));
+    }
+    for (source, targets) in graph.successors.iter().enumerate() {
+        for &target in targets {
+            let _ = writeln!(out, "    <edge source=\"n{}\" target=\"n{}\"/>", source, target);
+        }
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+// `{"nodes": [name, ...], "edges": [[source, target], ...]}`, edges by node
+// index
+fn to_json(graph: &Graph) -> String {
+    let edges: Vec<[usize; 2]> = graph.successors.iter().enumerate()
+        .flat_map(|(source, targets)| targets.iter().map(move |&target| [source, target]))
+        .collect();
+    serde_json::json!({ "nodes": graph.names, "edges": edges }).to_string()
+}
+
+/// Serialises the import graph or the call graph of `files` without
+/// building a Python object per edge. `graph` is "imports", the resolved
+/// part of `build_import_graph`, or "calls", between the functions of
+/// `files` named `path:qualified_name` (module-level code is
+/// `path:<module>`). A call resolves by name to a function of the calling
+/// file, or else to the only function of that name in `files`; others are
+/// left out.
+///
+/// `format` is "dot", "graphml" or "json", the last a compact
+/// `{"nodes": [...], "edges": [[source, target], ...]}` with edges as node
+/// indexes. Nodes are sorted by name and repeated edges folded together.
+/// Returns the text, or writes it to `output` and returns None.
+///
+/// Raises ValueError for an unknown `graph` or `format`, and OSError when
+/// `output` cannot be written.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (files, graph="imports", format="json", output=None, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn export_graph(
+    py: Python<'_>,
+    files: Vec<String>,
+    graph: &str,
+    format: &str,
+    output: Option<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Option<String>> {
+    if !matches!(format, "dot" | "graphml" | "json") {
+        return Err(PyValueError::new_err(format!("Unknown graph format '{}': expected dot, graphml or json", format)));
+    }
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    let built = match graph {
+        "imports" => {
+            let edges = import_edges(py, &files, control)?;
+            py.allow_threads(|| Graph::new(&edges))
+        }
+        "calls" => {
+            let parsed = control.run(py, &files, |path| file_calls(path))?;
+            py.allow_threads(|| call_graph(&parsed))
+        }
+        _ => return Err(PyValueError::new_err(format!("Unknown graph '{}': expected imports or calls", graph))),
+    };
+    let text = py.allow_threads(|| match format {
+        "dot" => to_dot(&built, graph),
+        "graphml" => to_graphml(&built, graph),
+        _ => to_json(&built),
+    });
+    match output {
+        Some(path) => {
+            std::fs::write(&path, text)
+                .map_err(|e| PyOSError::new_err(format!("Failed to write graph {}: {}", path, e)))?;
+            Ok(None)
+        }
+        None => Ok(Some(text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calls(path: &str, functions: &[&str], calls: &[(&str, &str)]) -> FileCalls {
+        FileCalls {
+            path: path.to_string(),
+            functions: functions.iter().map(|f| f.to_string()).collect(),
+            calls: calls.iter().map(|&(caller, callee)| (caller.to_string(), callee.to_string())).collect(),
+        }
+    }
+
+    fn edges(graph: &Graph) -> Vec<(&str, &str)> {
+        graph.successors.iter().enumerate()
+            .flat_map(|(source, targets)| targets.iter().map(move |&target| (graph.names[source].as_str(), graph.names[target].as_str())))
+            .collect()
+    }
+
+    #[test]
+    fn calls_resolve_locally_first_then_to_a_unique_project_function() {
+        let files = [
+            calls("a.py", &["helper", "Job.run"], &[("Job.run", "helper"), ("Job.run", "shared"), ("<module>", "run"), ("Job.run", "missing"), ("Job.run", "twice")]),
+            calls("b.py", &["helper", "shared", "twice"], &[]),
+            calls("c.py", &["twice"], &[]),
+        ];
+        assert_eq!(edges(&call_graph(&files)), vec![
+            ("a.py:<module>", "a.py:Job.run"),
+            ("a.py:Job.run", "a.py:helper"),
+            ("a.py:Job.run", "b.py:shared"),
+        ]);
+    }
+
+    #[test]
+    fn a_python_file_exports_its_call_graph() {
+        let file = tempfile::Builder::new().suffix(".py").tempfile().unwrap();
+        std::fs::write(file.path(), "def helper():\n    pass\n\ndef run():\n    helper()\n\nrun()\n").unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        let graph = call_graph(&[file_calls(&path).unwrap()]);
+        let node = |name: &str| format!("{}:{}", path, name);
+        let found: Vec<(String, String)> = edges(&graph).into_iter().map(|(s, t)| (s.to_string(), t.to_string())).collect();
+        assert_eq!(found, vec![(node("<module>"), node("run")), (node("run"), node("helper"))]);
+    }
+
+    #[test]
+    fn each_format_lists_every_edge_once() {
+        let pairs = vec![("a\"<x>".to_string(), "b".to_string()), ("a\"<x>".to_string(), "b".to_string())];
+        let graph = Graph::from_pairs(pairs);
+        assert_eq!(to_json(&graph), r#"{"edges":[[0,1]],"nodes":["a\"<x>","b"]}"#);
+        assert_eq!(to_dot(&graph, "calls"), "digraph calls {\n  \"a\\\"<x>\" -> \"b\";\n}\n");
+        let graphml = to_graphml(&graph, "calls");
+        assert!(graphml.contains("<node id=\"n0\"><data key=\"label\">a&quot;&lt;x&gt;</data></node>"));
+        assert_eq!(graphml.matches("<edge ").count(), 1);
+    }
+
+    #[test]
+    fn unknown_graphs_and_formats_are_rejected() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let export = |graph: &str, format: &str| export_graph(py, Vec::new(), graph, format, None, None, 100, None, None);
+            assert!(export("imports", "svg").is_err_and(|e| e.is_instance_of::<PyValueError>(py)));
+            assert!(export("types", "json").is_err_and(|e| e.is_instance_of::<PyValueError>(py)));
+            assert_eq!(export("calls", "json").unwrap().as_deref(), Some(r#"{"edges":[],"nodes":[]}"#));
+        });
+    }
+}
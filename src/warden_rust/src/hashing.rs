@@ -0,0 +1,70 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+
+/// Which digest fills `FileStats.hash`. SHA-256 is the default; BLAKE3 and
+/// XXH3 (128-bit) are much faster and enough for change detection, but XXH3
+/// is not collision resistant against crafted input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum HashAlgo {
+    Sha256,
+    Blake3,
+    Xxh3,
+}
+
+impl HashAlgo {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(HashAlgo::Sha256),
+            "blake3" => Ok(HashAlgo::Blake3),
+            "xxh3" => Ok(HashAlgo::Xxh3),
+            _ => Err(PyValueError::new_err(format!(
+                "Unknown hash_algo '{}', expected \"sha256\", \"blake3\" or \"xxh3\"", name
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Blake3 => "blake3",
+            HashAlgo::Xxh3 => "xxh3",
+        }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            HashAlgo::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgo::Blake3 => Hasher::Blake3(Box::default()),
+            HashAlgo::Xxh3 => Hasher::Xxh3(Box::default()),
+        }
+    }
+}
+
+// The incremental hashers behind `HashAlgo`; `finish` gives lowercase hex
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(bytes),
+            Hasher::Blake3(h) => {
+                h.update(bytes);
+            }
+            Hasher::Xxh3(h) => h.update(bytes),
+        }
+    }
+
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(h) => format!("{:x}", h.finalize()),
+            Hasher::Blake3(h) => h.finalize().to_hex().to_string(),
+            Hasher::Xxh3(h) => format!("{:032x}", h.digest128()),
+        }
+    }
+}
@@ -0,0 +1,797 @@
+use crate::ast::{get_language_parser, imported_bindings, imported_modules, parse};
+use crate::encoding;
+use crate::scanner::ValidationResult;
+use crate::{detect_language, detect_language_rs, language_by_name, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Component, Path, PathBuf};
+use tree_sitter::Node;
+
+/// One import in the project: `source` imports `module` on `line`.
+///
+/// `target` is the project file the module resolves to, or its directory
+/// when the import names a package rather than a file: always in Go, C#
+/// and Swift, and for Java and Kotlin wildcard imports. None for
+/// third-party modules and anything else outside `files`. Paths are
+/// written as they were given.
+#[pyclass]
+#[derive(Clone)]
+pub struct ImportEdge {
+    #[pyo3(get)]
+    pub source: String,
+    // As the import names it, extended by the imported name when that is a
+    // module itself: `from . import x` gives ".x"
+    #[pyo3(get)]
+    pub module: String,
+    #[pyo3(get)]
+    pub target: Option<String>,
+    #[pyo3(get)]
+    pub line: usize,
+}
+
+#[pymethods]
+impl ImportEdge {
+    fn __repr__(&self) -> String {
+        format!(
+            "ImportEdge({}:{} -> {})",
+            self.source, self.line, self.target.as_deref().unwrap_or(&self.module)
+        )
+    }
+}
+
+/// How one module of the import graph couples to the rest of the project.
+///
+/// `instability` is efferent / (afferent + efferent): 0.0 for a module
+/// nothing it depends on can break, 1.0 for one nothing depends on, and 0.0
+/// for an isolated one.
+#[pyclass]
+#[derive(Clone)]
+pub struct ModuleCoupling {
+    // A file, or a package directory for Go, C# and Swift
+    #[pyo3(get)]
+    pub module: String,
+    // Fan-in: the project modules that import this one
+    #[pyo3(get)]
+    pub afferent: usize,
+    // Fan-out: the project modules this one imports
+    #[pyo3(get)]
+    pub efferent: usize,
+    #[pyo3(get)]
+    pub instability: f64,
+}
+
+#[pymethods]
+impl ModuleCoupling {
+    fn __repr__(&self) -> String {
+        format!(
+            "ModuleCoupling({} in={} out={} instability={:.2})",
+            self.module, self.afferent, self.efferent, self.instability
+        )
+    }
+}
+
+// What a relative JavaScript or TypeScript specifier can leave off
+const SCRIPT_SUFFIXES: &[&str] = &[
+    "", ".ts", ".tsx", ".d.ts", ".js", ".jsx", ".mjs", ".cjs", "/index.ts", "/index.tsx", "/index.js", "/index.jsx",
+];
+
+// `path` with `.` and `..` folded away, without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normal.components().next_back(), Some(Component::Normal(_))) => {
+                normal.pop();
+            }
+            other => normal.push(other),
+        }
+    }
+    normal
+}
+
+// Every trailing run of the components of `path`, joined with `/`:
+// "a/b/c.py", "b/c.py" and "c.py"
+fn suffixes(path: &Path) -> Vec<String> {
+    let parts: Vec<&str> = path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    (0..parts.len()).map(|i| parts[i..].join("/")).collect()
+}
+
+// Looks `segments` up with `find`, then without their leading segments
+// while at least `min` of them are left
+fn trailing(segments: &[&str], min: usize, find: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let min = min.clamp(1, segments.len().max(1));
+    (0..=segments.len().saturating_sub(min)).find_map(|skip| find(&segments[skip..].join("/")))
+}
+
+// The paths a Rust `use` tree names: `a::{b, c::{self, d}}` gives `a::b`,
+// `a::c` and `a::c::d`; globs and renames name the module they come from
+fn use_paths(tree: &str) -> Vec<String> {
+    let tree = tree.trim();
+    let Some(open) = tree.find('{') else {
+        let path = tree.split(" as ").next().unwrap_or(tree).trim();
+        return vec![path.trim_end_matches('*').trim_end_matches("::").to_string()];
+    };
+    let prefix = tree[..open].trim_end_matches("::");
+    let inner = &tree[open + 1..tree.rfind('}').unwrap_or(tree.len())];
+    let (mut items, mut depth, mut start) = (Vec::new(), 0, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&inner[start..]);
+    items.into_iter()
+        .filter(|item| !item.trim().is_empty())
+        .flat_map(use_paths)
+        .map(|path| match path.as_str() {
+            "" | "self" => prefix.to_string(),
+            _ if prefix.is_empty() => path,
+            _ => format!("{}::{}", prefix, path),
+        })
+        .collect()
+}
+
+// How `path`'s directory is written as an edge target: "." for the current
+// one
+fn package_dir(path: &str) -> String {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    }
+}
+
+// The files being graphed, indexed every way an import can name them
+#[derive(Default)]
+struct Project {
+    // By normalised path, and the language of each, told once
+    files: HashMap<PathBuf, String>,
+    languages: HashMap<PathBuf, String>,
+    // By each trailing run of path components, shortest path first
+    by_suffix: HashMap<String, Vec<String>>,
+    // Directories holding files of a language, by (language, trailing run)
+    dirs: HashMap<(String, String), Vec<String>>,
+    // Python files by the dotted name they are imported as, and back
+    python: HashMap<String, String>,
+    python_modules: HashMap<PathBuf, String>,
+}
+
+impl Project {
+    fn new(paths: &[String]) -> Self {
+        let mut project = Project::default();
+        let mut seen_dirs = HashSet::new();
+        let normals: Vec<PathBuf> = paths.iter().map(|path| normalize(Path::new(path))).collect();
+        for (path, normal) in paths.iter().zip(&normals) {
+            for suffix in suffixes(normal) {
+                project.by_suffix.entry(suffix).or_default().push(path.clone());
+            }
+            let language = detect_language_rs(normal);
+            project.languages.insert(normal.clone(), language.clone());
+            if let Some(normal_dir) = normal.parent() {
+                let dir = package_dir(path);
+                if seen_dirs.insert((language.clone(), dir.clone())) {
+                    for suffix in suffixes(normal_dir) {
+                        project.dirs.entry((language.clone(), suffix)).or_default().push(dir.clone());
+                    }
+                }
+            }
+            project.files.insert(normal.clone(), path.clone());
+        }
+        let mut packages = HashMap::new();
+        for (path, normal) in paths.iter().zip(&normals) {
+            if project.languages[normal] != "python" {
+                continue;
+            }
+            if let Some(module) = project.python_module(normal, &mut packages) {
+                project.python.entry(module.clone()).or_insert_with(|| path.clone());
+                project.python_modules.insert(normal.clone(), module);
+            }
+        }
+        for candidates in project.by_suffix.values_mut().chain(project.dirs.values_mut()) {
+            candidates.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+            candidates.dedup();
+        }
+        project
+    }
+
+    fn file(&self, path: &Path) -> Option<String> {
+        self.files.get(&normalize(path)).cloned()
+    }
+
+    fn suffix(&self, key: &str) -> Option<String> {
+        self.by_suffix.get(key).map(|candidates| candidates[0].clone())
+    }
+
+    fn dir(&self, language: &str, key: &str) -> Option<String> {
+        self.dirs.get(&(language.to_string(), key.to_string())).map(|candidates| candidates[0].clone())
+    }
+
+    // The dotted name Python imports `file` by: its path from the outermost
+    // package directory above it, or just its stem outside any package
+    fn python_module(&self, file: &Path, packages: &mut HashMap<PathBuf, bool>) -> Option<String> {
+        let stem = file.file_stem()?.to_str()?;
+        let mut parts = if stem == "__init__" { vec![] } else { vec![stem.to_string()] };
+        let mut dir = file.parent();
+        while let Some(current) = dir {
+            let init = current.join("__init__.py");
+            let is_package = *packages.entry(current.to_path_buf())
+                .or_insert_with(|| self.files.contains_key(&init) || init.is_file());
+            let Some(name) = current.file_name().and_then(|n| n.to_str()).filter(|_| is_package) else { break };
+            parts.push(name.to_string());
+            dir = current.parent();
+        }
+        parts.reverse();
+        (!parts.is_empty()).then(|| parts.join("."))
+    }
+
+    // `module` or, failing that, the closest package above it
+    fn python_find(&self, module: &str) -> Option<String> {
+        let mut module = module;
+        loop {
+            if let Some(path) = self.python.get(module) {
+                return Some(path.clone());
+            }
+            module = module.rsplit_once('.')?.0;
+        }
+    }
+
+    fn python_import(&self, file: &Path, text: &str, statement: Node, source: &[u8]) -> Vec<(String, Option<String>)> {
+        if statement.kind() == "import_statement" {
+            return vec![(text.to_string(), self.python_find(text))];
+        }
+        let dots = text.len() - text.trim_start_matches('.').len();
+        let base = if dots == 0 {
+            Some(text.to_string())
+        } else {
+            // Relative to the package `file` is in, one level up per extra dot
+            self.python_modules.get(file)
+                .and_then(|module| match file.file_stem().is_some_and(|s| s == "__init__") {
+                    true => Some(module.as_str()),
+                    false => module.rsplit_once('.').map(|(package, _)| package),
+                })
+                .and_then(|package| {
+                    let mut parts: Vec<&str> = package.split('.').collect();
+                    parts.truncate(parts.len().checked_sub(dots - 1)?);
+                    parts.extend(Some(&text[dots..]).filter(|rest| !rest.is_empty()));
+                    (!parts.is_empty()).then(|| parts.join("."))
+                })
+        };
+        let Some(base) = base else { return vec![(text.to_string(), None)] };
+        // `from package import module` imports the modules themselves
+        let mut cursor = statement.walk();
+        let found: Vec<(String, Option<String>)> = statement.children_by_field_name("name", &mut cursor)
+            .filter_map(|name| {
+                let name = name.child_by_field_name("name").unwrap_or(name).utf8_text(source).ok()?;
+                let target = self.python.get(&format!("{}.{}", base, name))?;
+                let separator = if text.ends_with('.') { "" } else { "." };
+                Some((format!("{}{}{}", text, separator, name), Some(target.clone())))
+            })
+            .collect();
+        if found.is_empty() {
+            return vec![(text.to_string(), self.python_find(&base))];
+        }
+        found
+    }
+
+    // The directory above `dir` holding the crate's lib.rs or main.rs
+    fn crate_root(&self, dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .find(|d| ["lib.rs", "main.rs"].iter().any(|root| {
+                let root = d.join(root);
+                self.files.contains_key(&root) || root.is_file()
+            }))
+            .map(Path::to_path_buf)
+    }
+
+    // The file of the longest run of `segments`, at least `min` long, that
+    // is a module under `base`; no segments at all is `base`'s own module
+    fn rust_file(&self, base: &Path, segments: &[&str], min: usize) -> Option<String> {
+        (min..=segments.len()).rev().find_map(|n| {
+            let path = segments[..n].join("/");
+            let candidates = match n {
+                0 => vec![base.join("mod.rs"), base.join("lib.rs"), base.join("main.rs"), base.with_extension("rs")],
+                _ => vec![base.join(format!("{}.rs", path)), base.join(&path).join("mod.rs")],
+            };
+            candidates.iter().find_map(|candidate| self.file(candidate))
+        })
+    }
+
+    fn rust_module(&self, file: &Path, path: &str, declared: bool) -> Option<String> {
+        let dir = file.parent()?;
+        let stem = file.file_stem()?.to_str()?;
+        // Where the submodules of `file`'s module live
+        let children = match stem {
+            "mod" | "lib" | "main" => dir.to_path_buf(),
+            _ => dir.join(stem),
+        };
+        let segments: Vec<&str> = path.trim_start_matches("::").split("::").collect();
+        if declared {
+            return self.rust_file(&children, &segments, 1);
+        }
+        match segments[0] {
+            "crate" => self.rust_file(&self.crate_root(dir)?, &segments[1..], 0),
+            "self" => self.rust_file(&children, &segments[1..], 0),
+            "super" => {
+                let mut base = children.as_path();
+                let mut rest = &segments[..];
+                while rest.first() == Some(&"super") {
+                    base = base.parent()?;
+                    rest = &rest[1..];
+                }
+                self.rust_file(base, rest, 0)
+            }
+            // A submodule, or a path from the crate root as in Rust 2015
+            _ => self.rust_file(&children, &segments, 1)
+                .or_else(|| self.rust_file(&self.crate_root(dir)?, &segments, 1)),
+        }
+    }
+
+    fn relative_script(&self, dir: &Path, name: &str) -> Option<String> {
+        if !name.starts_with('.') {
+            return None;
+        }
+        let base = dir.join(name).to_string_lossy().into_owned();
+        // TypeScript ESM imports name the compiled `.js` of a `.ts` file
+        let stem = [".js", ".jsx", ".mjs"].iter().find_map(|ext| base.strip_suffix(ext));
+        std::iter::once(base.as_str()).chain(stem)
+            .find_map(|base| SCRIPT_SUFFIXES.iter().find_map(|suffix| self.file(Path::new(&format!("{}{}", base, suffix)))))
+    }
+
+    // The (module, target) pairs one import comes to: several for Rust use
+    // lists and Python imports of submodules
+    fn resolve(&self, language: &str, file: &Path, module: Node, statement: Node, source: &[u8]) -> Vec<(String, Option<String>)> {
+        let text = module.utf8_text(source).unwrap_or_default();
+        let dir = file.parent().unwrap_or(Path::new(""));
+        match language {
+            "python" => return self.python_import(file, text, statement, source),
+            "rust" => {
+                let declared = statement.kind() == "mod_item";
+                return use_paths(text).into_iter()
+                    .filter(|path| !path.is_empty())
+                    .map(|path| {
+                        let target = self.rust_module(file, &path, declared);
+                        (path, target)
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+        let name = text.trim_matches(|c| matches!(c, '"' | '\'' | '`' | '<' | '>'));
+        let target = match language {
+            "javascript" | "typescript" => self.relative_script(dir, name),
+            // Module paths end in the package's directory
+            "go" => {
+                let segments: Vec<&str> = name.split('/').collect();
+                match segments.len() {
+                    0 | 1 => None,
+                    _ => trailing(&segments, 2, |key| self.dir("go", key)),
+                }
+            }
+            // A class, a class member (static imports) or a package
+            "java" | "kotlin" => {
+                let extension = if language == "java" { "java" } else { "kt" };
+                let segments: Vec<&str> = name.split('.').collect();
+                let (full, parent) = (segments.join("/"), segments[..segments.len() - 1].join("/"));
+                let parent = Some(parent).filter(|_| segments.len() > 2);
+                self.suffix(&format!("{}.{}", full, extension))
+                    .or_else(|| self.suffix(&format!("{}.{}", parent.as_deref()?, extension)))
+                    .or_else(|| self.dir(language, &full))
+                    .or_else(|| self.dir(language, parent.as_deref()?))
+            }
+            // User headers are looked for next to the file first
+            "c" | "cpp" => text.starts_with('"').then(|| self.file(&dir.join(name))).flatten()
+                .or_else(|| self.suffix(name)),
+            "csharp" | "swift" => {
+                let segments: Vec<&str> = name.split('.').collect();
+                trailing(&segments, 2, |key| self.dir(language, key))
+            }
+            "ruby" => {
+                let name = if name.ends_with(".rb") { name.to_string() } else { format!("{}.rb", name) };
+                let method = statement.child_by_field_name("method").and_then(|m| m.utf8_text(source).ok());
+                match method {
+                    Some("require_relative") => self.file(&dir.join(&name)),
+                    _ => self.suffix(&name),
+                }
+            }
+            "php" if module.kind() == "string" => {
+                let name = name.trim_start_matches('/');
+                self.file(&dir.join(name)).or_else(|| self.suffix(name))
+            }
+            // Namespaces follow directories, under a PSR-4 prefix that may
+            // not be one
+            "php" => {
+                let segments: Vec<&str> = name.split('\\').filter(|s| !s.is_empty()).collect();
+                trailing(&segments, 2, |key| self.suffix(&format!("{}.php", key)))
+            }
+            _ => None,
+        };
+        vec![(name.to_string(), target)]
+    }
+}
+
+fn file_imports(project: &Project, path: &str) -> Vec<ImportEdge> {
+    let Ok(content) = encoding::read_to_string(path) else { return vec![] };
+    let file = normalize(Path::new(path));
+    let language = project.languages.get(&file).cloned().unwrap_or_else(|| detect_language(&file, content.as_bytes()));
+    let Some(grammar) = get_language_parser(&language) else { return vec![] };
+    let Some(tree) = parse(&content, &language, grammar, None) else { return vec![] };
+    let source = content.as_bytes();
+    let mut seen = HashSet::new();
+    let mut edges = Vec::new();
+    for (module, statement) in imported_modules(&tree, &content, &language, grammar) {
+        let line = statement.start_position().row + 1;
+        for (module, target) in project.resolve(&language, &file, module, statement, source) {
+            let target = target.filter(|target| target != path);
+            if seen.insert((module.clone(), target.clone())) {
+                edges.push(ImportEdge { source: path.to_string(), module, target, line });
+            }
+        }
+    }
+    edges
+}
+
+pub(crate) fn import_edges(py: Python<'_>, files: &[String], control: ScanControl) -> PyResult<Vec<ImportEdge>> {
+    let project = py.allow_threads(|| Project::new(files));
+    control.run(py, files, |path| file_imports(&project, path))
+}
+
+// The graph node a file belongs to: the file itself, or its package
+// directory in the languages whose imports name directories, which their
+// extensions tell
+fn graph_node(path: &str) -> String {
+    match language_by_name(Path::new(path)) {
+        Some("go" | "csharp" | "swift") => package_dir(path),
+        _ => path.to_string(),
+    }
+}
+
+// The resolved part of an import graph, between files, or packages where
+// imports name directories; nodes are numbered in name order
+pub(crate) struct Graph {
+    pub names: Vec<String>,
+    pub successors: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    pub(crate) fn new(edges: &[ImportEdge]) -> Self {
+        Graph::from_pairs(edges.iter()
+            .filter_map(|edge| {
+                let target = edge.target.clone()?;
+                Some((graph_node(&edge.source), target)).filter(|(source, target)| source != target)
+            })
+            .collect())
+    }
+
+    // From (source, target) name pairs, repeats folded together
+    pub(crate) fn from_pairs(pairs: Vec<(String, String)>) -> Self {
+        let mut names: Vec<String> = pairs.iter().flat_map(|(source, target)| [source.clone(), target.clone()]).collect();
+        names.sort();
+        names.dedup();
+        let index: HashMap<&str, usize> = names.iter().enumerate().map(|(i, name)| (name.as_str(), i)).collect();
+        let mut successors = vec![Vec::new(); names.len()];
+        for (source, target) in &pairs {
+            successors[index[source.as_str()]].push(index[target.as_str()]);
+        }
+        for targets in &mut successors {
+            targets.sort_unstable();
+            targets.dedup();
+        }
+        Graph { names, successors }
+    }
+
+    // The strongly connected component of each node, by Kosaraju's
+    // algorithm; iterative, as import chains can run deeper than the stack
+    fn components(&self) -> Vec<usize> {
+        let count = self.names.len();
+        let mut finished = Vec::with_capacity(count);
+        let mut visited = vec![false; count];
+        for start in 0..count {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut stack = vec![(start, 0)];
+            while let Some((node, next)) = stack.pop() {
+                match self.successors[node].get(next) {
+                    Some(&child) => {
+                        stack.push((node, next + 1));
+                        if !visited[child] {
+                            visited[child] = true;
+                            stack.push((child, 0));
+                        }
+                    }
+                    None => finished.push(node),
+                }
+            }
+        }
+        let mut predecessors = vec![Vec::new(); count];
+        for (node, targets) in self.successors.iter().enumerate() {
+            for &target in targets {
+                predecessors[target].push(node);
+            }
+        }
+        let mut component = vec![usize::MAX; count];
+        for (id, &root) in finished.iter().rev().enumerate() {
+            if component[root] != usize::MAX {
+                continue;
+            }
+            component[root] = id;
+            let mut stack = vec![root];
+            while let Some(node) = stack.pop() {
+                for &previous in &predecessors[node] {
+                    if component[previous] == usize::MAX {
+                        component[previous] = id;
+                        stack.push(previous);
+                    }
+                }
+            }
+        }
+        component
+    }
+
+    // The shortest cycle through `start` inside its component, by breadth
+    // first search, as the nodes from `start` on
+    fn shortest_cycle(&self, start: usize, component: &[usize]) -> Option<Vec<usize>> {
+        let mut parent = HashMap::from([(start, start)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            for &child in &self.successors[node] {
+                if component[child] != component[start] {
+                    continue;
+                }
+                if child == start {
+                    let mut cycle = vec![node];
+                    let mut current = node;
+                    while current != start {
+                        current = parent[&current];
+                        cycle.push(current);
+                    }
+                    cycle.reverse();
+                    return Some(cycle);
+                }
+                if let Entry::Vacant(entry) = parent.entry(child) {
+                    entry.insert(node);
+                    queue.push_back(child);
+                }
+            }
+        }
+        None
+    }
+
+    // One shortest cycle through each node that is on any, each rotated to
+    // start at its first node by name and reported once
+    fn cycles(&self) -> Vec<Vec<String>> {
+        let component = self.components();
+        let mut seen = HashSet::new();
+        let mut cycles = Vec::new();
+        for start in 0..self.names.len() {
+            let Some(mut cycle) = self.shortest_cycle(start, &component) else { continue };
+            let first = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap_or(0);
+            cycle.rotate_left(first);
+            if seen.insert(cycle.clone()) {
+                cycles.push(cycle);
+            }
+        }
+        cycles.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+        cycles.into_iter()
+            .map(|cycle| cycle.into_iter().map(|node| self.names[node].clone()).collect())
+            .collect()
+    }
+}
+
+/// The import graph of `files`, as one edge per module each file imports,
+/// in file and then line order. Files are parsed in parallel with the GIL
+/// released; those that cannot be read as UTF-8 text, or whose language has
+/// no grammar, have no edges.
+///
+/// Imports resolve against `files` only: Python by package-qualified module
+/// name (relative imports included), JavaScript and TypeScript relative
+/// specifiers by path with the extensions and index files they may leave
+/// off, Rust `mod` and `use` by file layout, and the other languages by
+/// the trailing path their module or namespace names.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn build_import_graph(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<ImportEdge>> {
+    import_edges(py, &files, ScanControl::new(progress, progress_every, cancel_token, threads))
+}
+
+/// The circular imports among `files`, each as the files (or, for Go, C#
+/// and Swift, package directories) around the loop: every one imports the
+/// next and the last imports the first. Each cycle starts at its first path
+/// in sort order, and cycles come shortest first.
+///
+/// A tangle of interlocking imports has more cycles than can be listed, so
+/// this reports the shortest cycle through each file caught in one; every
+/// such file shows up at least once. The graph is the resolved part of
+/// `build_import_graph`.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_import_cycles(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<Vec<String>>> {
+    let edges = import_edges(py, &files, ScanControl::new(progress, progress_every, cancel_token, threads))?;
+    Ok(py.allow_threads(|| Graph::new(&edges).cycles()))
+}
+
+/// Afferent and efferent coupling and instability of every module among
+/// `files`, sorted by module, counting the distinct project modules on each
+/// side; third-party imports do not count. Files without any resolved
+/// imports either way are listed with zeros. The graph is the resolved part
+/// of `build_import_graph`.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn compute_coupling(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<ModuleCoupling>> {
+    let edges = import_edges(py, &files, ScanControl::new(progress, progress_every, cancel_token, threads))?;
+    Ok(py.allow_threads(|| {
+        let graph = Graph::new(&edges);
+        let mut afferent = vec![0; graph.names.len()];
+        for &target in graph.successors.iter().flatten() {
+            afferent[target] += 1;
+        }
+        let mut coupling: HashMap<String, ModuleCoupling> = graph.names.iter().enumerate()
+            .map(|(i, name)| {
+                let (afferent, efferent) = (afferent[i], graph.successors[i].len());
+                let instability = match afferent + efferent {
+                    0 => 0.0,
+                    total => efferent as f64 / total as f64,
+                };
+                (name.clone(), ModuleCoupling { module: name.clone(), afferent, efferent, instability })
+            })
+            .collect();
+        for file in &files {
+            let module = graph_node(file);
+            coupling.entry(module.clone())
+                .or_insert(ModuleCoupling { module, afferent: 0, efferent: 0, instability: 0.0 });
+        }
+        let mut coupling: Vec<ModuleCoupling> = coupling.into_values().collect();
+        coupling.sort_by(|a, b| a.module.cmp(&b.module));
+        coupling
+    }))
+}
+
+// The name an import binds, from a `binding_query` match; None for those
+// that bind nothing to check: wildcards, Go blank and dot imports,
+// `from __future__`, and Go packages not named after their path
+fn bound_name(language: &str, name: Node, statement: Node, source: &[u8]) -> Option<String> {
+    let text = name.utf8_text(source).ok()?;
+    let mut cursor = statement.walk();
+    let children: Vec<Node> = statement.children(&mut cursor).collect();
+    let last_segment = |separator: char| text.rsplit(separator).next().map(str::to_string);
+    match language {
+        "python" => {
+            let module = statement.child_by_field_name("module_name").and_then(|m| m.utf8_text(source).ok());
+            (module != Some("__future__")).then(|| text.to_string())
+        }
+        "go" => match statement.child_by_field_name("name") {
+            Some(alias) => Some(alias.utf8_text(source).ok()?).filter(|a| !matches!(*a, "_" | ".")).map(str::to_string),
+            // Named after the last segment, less a major version suffix
+            None => {
+                let mut segments = text.trim_matches('"').rsplit('/');
+                let mut package = segments.next()?;
+                if package.len() > 1 && package.starts_with('v') && package[1..].chars().all(|c| c.is_ascii_digit()) {
+                    package = segments.next()?;
+                }
+                package.chars().all(|c| c.is_alphanumeric() || c == '_').then(|| package.to_string())
+            }
+        },
+        "java" | "kotlin" | "php" => {
+            if statement.utf8_text(source).ok()?.trim_end_matches(';').trim_end().ends_with('*') {
+                return None;
+            }
+            match children.iter().find(|child| matches!(child.kind(), "import_alias" | "namespace_aliasing_clause")) {
+                Some(alias) => alias.named_child(alias.named_child_count().checked_sub(1)?)?.utf8_text(source).ok().map(str::to_string),
+                None if language == "php" => last_segment('\\'),
+                None => last_segment('.'),
+            }
+        }
+        _ => Some(text.to_string()),
+    }
+}
+
+fn file_unused_imports(path: &str) -> Vec<ValidationResult> {
+    let Ok(content) = encoding::read_to_string(path) else { return vec![] };
+    let file = Path::new(path);
+    let language = detect_language(file, content.as_bytes());
+    // A package's __init__.py imports to re-export
+    if language == "python" && file.file_name().is_some_and(|name| name == "__init__.py") {
+        return vec![];
+    }
+    let Some(grammar) = get_language_parser(&language) else { return vec![] };
+    let Some(tree) = parse(&content, &language, grammar, None) else { return vec![] };
+    let source = content.as_bytes();
+    let bindings = imported_bindings(&tree, &content, &language, grammar);
+    if bindings.is_empty() {
+        return vec![];
+    }
+    let statements: HashSet<usize> = bindings.iter().map(|(_, statement)| statement.id()).collect();
+    let mut used = HashSet::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if statements.contains(&node.id()) {
+            continue;
+        }
+        let kind = node.kind();
+        // `__all__` entries and quoted annotations name imports in strings
+        if language == "python" && kind == "string" {
+            let text = node.utf8_text(source).unwrap_or_default();
+            let text = text.trim_start_matches(|c: char| c.is_ascii_alphabetic()).trim_matches(|c| c == '"' || c == '\'');
+            if !text.contains(char::is_whitespace) {
+                used.extend(text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')).flat_map(|word| word.split('.')));
+            }
+        }
+        if node.child_count() == 0 && (kind.ends_with("identifier") || kind == "name") {
+            used.extend(node.utf8_text(source).ok());
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    bindings.into_iter()
+        .filter_map(|(name, statement)| {
+            let bound = bound_name(&language, name, statement, source)?;
+            if used.contains(bound.as_str()) {
+                return None;
+            }
+            let line = name.start_position().row;
+            let snippet = content.lines().nth(line).unwrap_or_default().trim().to_string();
+            let message = format!("'{}' is imported but never used", bound);
+            Some(ValidationResult::finding("unused_import", path, message, line + 1, snippet))
+        })
+        .collect()
+}
+
+/// Imports in `files` whose bound name the rest of the file never mentions,
+/// as "unused_import" results on the line of the name, in file and then
+/// line order. Aliases count by the alias (`from x import y as z` binds
+/// `z`), and Python names listed in strings, as in `__all__` and quoted
+/// annotations, count as used; a package's `__init__.py` is skipped since
+/// its imports re-export.
+///
+/// Covers Python, JavaScript, TypeScript, Go, Java, Kotlin and PHP. C#
+/// `using` and the include-style imports of other languages bring in a
+/// whole namespace or file with no name to look for, and Rust imports
+/// traits whose use never names them, so those are left to the compiler.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_unused_imports(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<ValidationResult>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_unused_imports(path))
+}
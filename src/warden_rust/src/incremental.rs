@@ -0,0 +1,273 @@
+use crate::{build_walker, discovered_file, is_truncated, size_limit_bytes, stop_here, warn_user, CancellationToken, ScanControl};
+use pyo3::exceptions::{PyOSError, PyRuntimeError};
+use pyo3::prelude::*;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+// Bumped whenever the snapshot layout changes; older snapshots are ignored
+const SNAPSHOT_VERSION: u64 = 1;
+
+/// What changed under a root since the snapshot `discover_changed` last
+/// committed.
+///
+/// `added` and `changed` hold `(path, size, language)` tuples like
+/// `discover_files`; `deleted` holds the paths that are gone, and
+/// `unreadable` the ones found but not read, which are neither.
+#[pyclass]
+#[derive(Clone)]
+pub struct DiscoveredChanges {
+    #[pyo3(get)]
+    pub added: Vec<(String, u64, String)>,
+    #[pyo3(get)]
+    pub changed: Vec<(String, u64, String)>,
+    #[pyo3(get)]
+    pub deleted: Vec<String>,
+    #[pyo3(get)]
+    pub unreadable: Vec<String>,
+    // Files found unchanged
+    #[pyo3(get)]
+    pub unchanged: usize,
+    cache_path: String,
+    // The snapshot `commit` writes, None when cancellation cut the discovery short
+    snapshot: Option<HashMap<String, Fingerprint>>,
+}
+
+#[pymethods]
+impl DiscoveredChanges {
+    /// Saves what was discovered as the snapshot the next `discover_changed`
+    /// compares against; call it once the changed files were scanned, so a
+    /// failed scan finds them changed again. Unreadable files keep their
+    /// previous entry. Raises RuntimeError when the discovery was
+    /// cancelled, and OSError when the snapshot cannot be written.
+    fn commit(&self) -> PyResult<()> {
+        let snapshot = self.snapshot.as_ref()
+            .ok_or_else(|| PyRuntimeError::new_err("discover_changed was cancelled; there is no snapshot to commit"))?;
+        save_snapshot(&self.cache_path, snapshot)
+            .map_err(|e| PyOSError::new_err(format!("Failed to write scan snapshot {}: {}", self.cache_path, e)))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "DiscoveredChanges(added={}, changed={}, deleted={}, unreadable={}, unchanged={})",
+            self.added.len(), self.changed.len(), self.deleted.len(), self.unreadable.len(), self.unchanged
+        )
+    }
+}
+
+// What the snapshot remembers about one file
+#[derive(Clone)]
+struct Fingerprint {
+    mtime_ns: u64,
+    size: u64,
+    hash: String,
+}
+
+fn load_snapshot(cache_path: &str) -> Result<HashMap<String, Fingerprint>, String> {
+    let bytes = match std::fs::read(cache_path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let snapshot: Value = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    if snapshot["version"].as_u64() != Some(SNAPSHOT_VERSION) {
+        return Ok(HashMap::new());
+    }
+    let files = snapshot["files"].as_object().ok_or("no \"files\" object")?;
+    Ok(files.iter()
+        .filter_map(|(path, entry)| {
+            let fingerprint = Fingerprint {
+                mtime_ns: entry[0].as_u64()?,
+                size: entry[1].as_u64()?,
+                hash: entry[2].as_str()?.to_string(),
+            };
+            Some((path.clone(), fingerprint))
+        })
+        .collect())
+}
+
+// Written next to the target and renamed over it, so an interrupted run
+// never leaves a truncated snapshot behind
+fn save_snapshot(cache_path: &str, files: &HashMap<String, Fingerprint>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = files.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let files: Map<String, Value> = entries.into_iter()
+        .map(|(path, f)| (path.clone(), json!([f.mtime_ns, f.size, f.hash])))
+        .collect();
+    let snapshot = json!({ "version": SNAPSHOT_VERSION, "files": files });
+    let temp = format!("{}.tmp", cache_path);
+    std::fs::write(&temp, serde_json::to_vec(&snapshot)?)?;
+    std::fs::rename(&temp, cache_path)
+}
+
+fn mtime_ns(path: &Path) -> u64 {
+    path.metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+fn content_hash(path: &str) -> Option<String> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+/// Discovers the files under `root_path` like `discover_files` and compares
+/// them against the snapshot at `cache_path`, so repeat scans only look at
+/// what changed. A file whose mtime and size match the snapshot is taken as
+/// unchanged without being read; otherwise its SHA-256 decides. Nothing is
+/// saved until `DiscoveredChanges.commit()`, so changes stay pending across
+/// runs until a scan of them succeeds.
+///
+/// A missing snapshot reports every file as added; an unreadable one does
+/// the same with a UserWarning.
+#[pyfunction]
+#[pyo3(signature = (root_path, cache_path, use_gitignore=true, max_size_mb=None, progress=None, progress_every=100, cancel_token=None, threads=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn discover_changed(
+    py: Python<'_>,
+    root_path: String,
+    cache_path: String,
+    use_gitignore: bool,
+    max_size_mb: Option<u64>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<DiscoveredChanges> {
+    let previous = match load_snapshot(&cache_path) {
+        Ok(previous) => previous,
+        Err(e) => {
+            warn_user(py, &format!("Ignoring unreadable scan snapshot {}: {}", cache_path, e))?;
+            HashMap::new()
+        }
+    };
+
+    let size_limit = size_limit_bytes(max_size_mb);
+    let files: Vec<(String, (u64, String))> = py.allow_threads(|| {
+        build_walker(&root_path, use_gitignore)
+            .take_while(|_| !stop_here(&cancel_token))
+            .flatten()
+            .filter_map(|entry| discovered_file(&entry, size_limit))
+            .map(|(path, size, language)| (path, (size, language)))
+            .collect()
+    });
+
+    let control = ScanControl::new(progress, progress_every, cancel_token.clone(), threads);
+    let current = control.run(py, &files, |(path, (size, _))| {
+        let mtime_ns = mtime_ns(Path::new(path));
+        let known = previous.get(path).filter(|f| f.size == *size);
+        let hash = match known {
+            Some(f) if f.mtime_ns == mtime_ns => Some(f.hash.clone()),
+            _ => content_hash(path),
+        };
+        Some((path.clone(), hash.map(|hash| Fingerprint { mtime_ns, size: *size, hash })))
+    })?;
+    // Cancelled files are not in `current` at all, unreadable ones are None
+    let mut current: HashMap<String, Option<Fingerprint>> = current.into_iter().collect();
+
+    let mut changes = DiscoveredChanges {
+        added: Vec::new(),
+        changed: Vec::new(),
+        deleted: Vec::new(),
+        unreadable: Vec::new(),
+        unchanged: 0,
+        cache_path,
+        snapshot: None,
+    };
+    for (path, (size, language)) in files {
+        let Some(now) = current.get(&path) else { continue };
+        match (now, previous.get(&path)) {
+            (None, _) => changes.unreadable.push(path),
+            (Some(_), None) => changes.added.push((path, size, language)),
+            (Some(now), Some(before)) if before.hash != now.hash => changes.changed.push((path, size, language)),
+            (Some(_), Some(_)) => changes.unchanged += 1,
+        }
+    }
+    if is_truncated(&cancel_token) {
+        return Ok(changes);
+    }
+    changes.deleted = previous.keys().filter(|path| !current.contains_key(*path)).cloned().collect();
+    changes.deleted.sort();
+
+    for path in &changes.unreadable {
+        current.insert(path.clone(), previous.get(path).cloned());
+    }
+    changes.snapshot = Some(current.into_iter().filter_map(|(path, fingerprint)| Some((path, fingerprint?))).collect());
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(files: &[(String, u64, String)]) -> Vec<String> {
+        let mut names: Vec<String> = files.iter().map(|(path, _, _)| file_name(path)).collect();
+        names.sort();
+        names
+    }
+
+    fn file_name(path: &str) -> String {
+        Path::new(path).file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn changes_are_reported_against_the_committed_snapshot() {
+        let root = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let cache_path = state.path().join("snapshot.json").to_str().unwrap().to_string();
+        let write = |name: &str, content: &str| std::fs::write(root.path().join(name), content).unwrap();
+        write("kept.py", "x = 1\n");
+        write("edited.py", "y = 1\n");
+        write("removed.py", "z = 1\n");
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let discover = || {
+                let root = root.path().to_str().unwrap().to_string();
+                discover_changed(py, root, cache_path.clone(), true, None, None, 100, None, None).unwrap()
+            };
+            let first = discover();
+            assert_eq!(names(&first.added), vec!["edited.py", "kept.py", "removed.py"]);
+            // Nothing is remembered until the changes are committed
+            assert_eq!(discover().added.len(), 3);
+            first.commit().unwrap();
+
+            write("edited.py", "y = 2\n");
+            write("kept.py", "x = 1\n");
+            write("new.py", "w = 1\n");
+            std::fs::remove_file(root.path().join("removed.py")).unwrap();
+            let second = discover();
+            assert_eq!(names(&second.added), vec!["new.py"]);
+            assert_eq!(names(&second.changed), vec!["edited.py"]);
+            assert_eq!(second.deleted.iter().map(|path| file_name(path)).collect::<Vec<_>>(), vec!["removed.py"]);
+            assert_eq!(second.unchanged, 1);
+            second.commit().unwrap();
+
+            let third = discover();
+            assert!(third.added.is_empty() && third.changed.is_empty() && third.deleted.is_empty());
+            assert_eq!(third.unchanged, 3);
+        });
+    }
+
+    #[test]
+    fn a_cancelled_discovery_has_nothing_to_commit() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("a.py"), "x = 1\n").unwrap();
+        let cache_path = root.path().join("snapshot.json").to_str().unwrap().to_string();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let token = CancellationToken::default();
+            token.cancel();
+            let root = root.path().to_str().unwrap().to_string();
+            let changes = discover_changed(py, root, cache_path.clone(), true, None, None, 100, Some(token.clone()), None).unwrap();
+            assert!(changes.added.is_empty() && changes.deleted.is_empty());
+            assert!(token.truncated());
+            assert!(changes.commit().is_err_and(|e| e.is_instance_of::<PyRuntimeError>(py)));
+            assert!(!Path::new(&cache_path).exists());
+        });
+    }
+}
@@ -1,29 +1,91 @@
 use pyo3::prelude::*;
+use pyo3::exceptions::{PyRuntimeError, PyUserWarning, PyValueError};
+use ignore::overrides::{Override, OverrideBuilder};
 use ignore::WalkBuilder;
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::fs::File;
-use regex::Regex;
 use rayon::prelude::*;
-use std::io::{BufRead, BufReader, Read};
-use sha2::{Sha256, Digest};
-use content_inspector::{inspect, ContentType};
+use std::io::Read;
+use memmap2::Mmap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
 
-#[pyclass]
-#[derive(Clone)]
-pub struct RustRule {
-    #[pyo3(get, set)]
-    pub id: String,
-    #[pyo3(get, set)]
-    pub pattern: String,
-}
+mod addresses;
+mod ast;
+mod blobs;
+mod budget;
+mod cache;
+mod cfg;
+mod classify;
+mod complexity;
+mod dataflow;
+mod deadcode;
+mod encoding;
+mod envfile;
+mod git;
+mod graph_export;
+mod hashing;
+mod imports;
+mod incremental;
+mod licenses;
+mod literals;
+mod loc;
+mod markers;
+mod patterns;
+mod permissions;
+mod prefilter;
+mod profile;
+mod renames;
+mod scanner;
+mod secrets;
+mod session;
+mod strings;
+mod suppressions;
+mod taint;
+mod treehash;
+mod unicode;
+mod validators;
 
-#[pymethods]
-impl RustRule {
-    #[new]
-    fn new(id: String, pattern: String) -> Self {
-        RustRule { id, pattern }
-    }
-}
+use addresses::{extract_addresses, AddressHit};
+use ast::{get_ast_metadata, get_ast_metadata_batch, run_ast_query, AstCapture, AstMetadata, AstNodeInfo, AstParameter};
+use budget::BudgetReport;
+use cache::ScanCache;
+use cfg::{build_cfg, CfgBlock, CfgEdge, FunctionCfg};
+use complexity::{compute_complexity, FunctionMetrics};
+use dataflow::{get_def_use_chains, DefUseChain};
+use deadcode::{find_dead_symbols, DeadSymbol};
+use scanner::{
+    check_rules, match_patterns, match_patterns_content, scan_all, scan_binary_strings, scan_git_history, split_lines,
+    validate_files, validate_staged, MatchHit, MetricRule, RuleCompileReport, RustRule, ScanAllResult, Scanner,
+    ValidationResult,
+};
+use envfile::{parse_env_files, EnvEntry};
+use hashing::HashAlgo;
+use imports::{build_import_graph, compute_coupling, find_import_cycles, find_unused_imports, ImportEdge, ModuleCoupling};
+use loc::{LineCounter, LineEndings, Whitespace};
+use markers::{extract_markers, Marker};
+use patterns::{match_ast_patterns, AstPattern, AstPatternHit};
+use permissions::check_file_permissions;
+use profile::{detect_project_profile, ProjectProfile};
+use graph_export::export_graph;
+use git::{blame_lines, git_changed_files, ChangedFile, CommitInfo, GitHistoryHit};
+use incremental::{discover_changed, DiscoveredChanges};
+use licenses::check_license_headers;
+use literals::{extract_strings, find_absolute_paths, AbsolutePath, StringLiteral};
+use renames::{detect_renames, Rename};
+use session::ParseSession;
+use treehash::{hash_tree, TreeHash};
+use secrets::{detect_secrets, SecretCandidate};
+use strings::BinaryStringHit;
+use suppressions::{parse_suppressions, Suppression};
+use taint::{find_taint_flows, TaintFlow, TaintRule};
+use unicode::{find_bidi_controls, find_non_ascii_identifiers, find_suspicious_identifiers, NonAsciiIdentifier, UnicodeHit};
+
+// How many discovered files DiscoverIter buffers ahead of the Python consumer
+const DISCOVER_CHANNEL_CAPACITY: usize = 1024;
 
 #[pyclass]
 #[derive(Clone)]
@@ -34,21 +96,159 @@ pub struct FileStats {
     pub size: u64,
     #[pyo3(get)]
     pub line_count: usize,
+    // `line_count` split by what is on each line, using the comment syntax
+    // of `language`: any code makes a code line, only comments a comment
+    // line, only whitespace a blank one
+    #[pyo3(get)]
+    pub code_lines: usize,
+    #[pyo3(get)]
+    pub comment_lines: usize,
+    #[pyo3(get)]
+    pub blank_lines: usize,
     #[pyo3(get)]
     pub is_binary: bool,
     #[pyo3(get)]
     pub hash: String,
+    // Which digest `hash` is, as passed in `hash_algo`
+    #[pyo3(get)]
+    pub hash_algo: String,
     #[pyo3(get)]
     pub language: String,
+    // Written by a code generator, going by the file name or a header such
+    // as `@generated` or `DO NOT EDIT` near the top; never set for binaries
+    #[pyo3(get)]
+    pub is_generated: bool,
+    // Test code, going by the directory, the file name or a test
+    // framework imported near the top; never set for binaries
+    #[pyo3(get)]
+    pub is_test: bool,
+    // Third-party code copied into the tree, going by the directory or the
+    // banner of a minified or bundled library; counted like any other file
+    #[pyo3(get)]
+    pub is_vendored: bool,
+    // What the text was transcoded from, by Python's codec name: "utf-8",
+    // "utf-8-sig", "utf-16-le", "utf-16-be" or "latin-1"; None for binaries
+    #[pyo3(get)]
+    pub encoding: Option<String>,
+    // "lf", "crlf" or "mixed"; None for binaries and text without a line
+    // ending
+    #[pyo3(get)]
+    pub eol_type: Option<String>,
+    // Lines ending in a bare `\n`, and in `\r\n`
+    #[pyo3(get)]
+    pub lf_count: usize,
+    #[pyo3(get)]
+    pub crlf_count: usize,
+    // Lines ending in spaces or tabs, blank ones included
+    #[pyo3(get)]
+    pub trailing_whitespace_lines: usize,
+    // Non-blank lines indented with a tab first, and with a space first
+    #[pyo3(get)]
+    pub tab_indented_lines: usize,
+    #[pyo3(get)]
+    pub space_indented_lines: usize,
+    // "tabs", "spaces" or "mixed"; None when no line is indented
+    #[pyo3(get)]
+    pub indent_style: Option<String>,
+    // Whether the last line ends in a newline, as it does in an empty file;
+    // never set for binaries
+    #[pyo3(get)]
+    pub has_trailing_newline: bool,
+    // NUL characters and invalid UTF-8 sequences anywhere in a text file,
+    // past the 1 KiB the binary check looks at included, with the
+    // `(line, column)` of the first of each, 1-based with byte columns
+    #[pyo3(get)]
+    pub nul_count: usize,
+    #[pyo3(get)]
+    pub first_nul: Option<(usize, usize)>,
+    #[pyo3(get)]
+    pub invalid_sequence_count: usize,
+    #[pyo3(get)]
+    pub first_invalid_sequence: Option<(usize, usize)>,
+    // POSIX permission bits such as 0o755, setuid, setgid and sticky
+    // included; None off Unix and for staged content
+    #[pyo3(get)]
+    pub mode: Option<u32>,
+    // The command of a `#!` first line, such as "/usr/bin/env python3",
+    // and the program it runs, such as "python3"; None without one
+    #[pyo3(get)]
+    pub shebang: Option<String>,
+    #[pyo3(get)]
+    pub interpreter: Option<String>,
 }
 
-fn detect_language_rs(path: &Path) -> String {
+impl FileStats {
+    // `shebang` and `interpreter` from the decoded head of a text file
+    fn set_shebang(&mut self, head: &[u8]) {
+        self.shebang = classify::shebang(head);
+        self.interpreter = self.shebang.as_deref().and_then(classify::shebang_interpreter).map(str::to_string);
+    }
+
+    // Line counts, line endings, whitespace, anomalies, hash and encoding of
+    // text content, transcoded to UTF-8 first. Undecodable lines are skipped,
+    // as `BufRead::lines` does; unless `exact_hash`, the others are hashed
+    // each followed by `\n`
+    fn set_text_stats(&mut self, bytes: &[u8], algo: HashAlgo, exact_hash: bool) {
+        let mut hasher = algo.hasher();
+        let mut counter = LineCounter::new(&self.language);
+        let mut whitespace = Whitespace::default();
+        let (text, encoding) = encoding::decode(bytes);
+        for (ln, line) in split_lines(&text).into_iter().enumerate() {
+            for at in line.iter().enumerate().filter(|&(_, &b)| b == 0).map(|(at, _)| at) {
+                self.nul_count += 1;
+                self.first_nul.get_or_insert((ln + 1, at + 1));
+            }
+            let Ok(line) = std::str::from_utf8(line) else {
+                let mut at = 0;
+                for chunk in line.utf8_chunks() {
+                    at += chunk.valid().len();
+                    if !chunk.invalid().is_empty() {
+                        self.invalid_sequence_count += 1;
+                        self.first_invalid_sequence.get_or_insert((ln + 1, at + 1));
+                    }
+                    at += chunk.invalid().len();
+                }
+                continue;
+            };
+            counter.count(line);
+            whitespace.count(line);
+            if !exact_hash {
+                hasher.update(line.as_bytes());
+                hasher.update(b"\n");
+            }
+        }
+        if exact_hash {
+            hasher.update(bytes);
+        }
+        let counts = counter.counts;
+        self.line_count = counts.lines;
+        self.code_lines = counts.code;
+        self.comment_lines = counts.comment;
+        self.blank_lines = counts.blank;
+        self.hash = hasher.finish();
+        self.encoding = Some(encoding.to_string());
+        let endings = LineEndings::count(&text);
+        self.eol_type = endings.kind().map(str::to_string);
+        self.lf_count = endings.lf;
+        self.crlf_count = endings.crlf;
+        self.trailing_whitespace_lines = whitespace.trailing;
+        self.tab_indented_lines = whitespace.tabs;
+        self.space_indented_lines = whitespace.spaces;
+        self.indent_style = whitespace.indent_style().map(str::to_string);
+        self.has_trailing_newline = text.is_empty() || text.ends_with(b"\n");
+    }
+}
+
+// The language of `path` as far as its name tells, None for the names
+// that take a look at the content: `.h` headers, C or C++, and files
+// without an extension, by shebang or modeline
+fn language_by_name(path: &Path) -> Option<&'static str> {
     let ext = path.extension()
         .and_then(|s| s.to_str())
         .map(|s| s.to_lowercase())
         .unwrap_or_default();
     
-    match ext.as_str() {
+    Some(match ext.as_str() {
         "py" | "pyw" => "python",
         "js" | "jsx" | "mjs" | "cjs" => "javascript",
         "ts" => "typescript",
@@ -60,7 +260,7 @@ fn detect_language_rs(path: &Path) -> String {
         "swift" => "swift",
         "kt" | "kts" => "kotlin",
         "c" => "c",
-        "h" => "c", // Default to C, heuristic later
+        "h" => return None,
         "cpp" | "cc" | "cxx" | "hpp" | "hh" => "cpp",
         "cs" => "csharp",
         "rb" => "ruby",
@@ -70,493 +270,1252 @@ fn detect_language_rs(path: &Path) -> String {
         "json" => "json",
         "sql" => "sql",
         "sh" => "shell",
-        _ => "unknown",
-    }.to_string()
+        _ => match classify::language_from_name(&path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()) {
+            Some(language) => language,
+            None if ext.is_empty() => return None,
+            None => "unknown",
+        },
+    })
 }
 
-#[pyclass]
-#[derive(Clone)]
-pub struct MatchHit {
-    #[pyo3(get)]
-    pub file_path: String,
-    #[pyo3(get)]
-    pub line_number: usize,
-    #[pyo3(get)]
-    pub column: usize,
-    #[pyo3(get)]
-    pub rule_id: String,
-    #[pyo3(get)]
-    pub snippet: String,
+// The language of the file at `path` whose content starts with `head`;
+// nothing is read, so callers holding the content pass it in
+fn detect_language(path: &Path, head: &[u8]) -> String {
+    let head = &head[..head.len().min(SNIFF_BYTES)];
+    let language = language_by_name(path).or_else(|| match path.extension() {
+        Some(_) => Some(if classify::is_cpp_header(head) { "cpp" } else { "c" }),
+        None => classify::language_from_content(head),
+    });
+    language.unwrap_or("unknown").to_string()
 }
 
-#[pyfunction]
-#[pyo3(signature = (root_path, use_gitignore=true, max_size_mb=None))]
-fn discover_files(root_path: String, use_gitignore: bool, max_size_mb: Option<u64>) -> PyResult<Vec<(String, u64, String)>> {
-    let mut files = Vec::new();
-    let mut builder = WalkBuilder::new(&root_path);
-    
-    builder.standard_filters(use_gitignore)
-           .hidden(false); 
+// `detect_language` for a file known only by its path, reading its head
+// when the name does not tell
+fn detect_language_rs(path: &Path) -> String {
+    match language_by_name(path) {
+        Some(language) => language.to_string(),
+        None => detect_language(path, &file_head(path)),
+    }
+}
+
+// How much of a file's start tells its language
+const SNIFF_BYTES: usize = 1024;
 
-    let warden_ignore = Path::new(&root_path).join(".wardenignore");
-    if warden_ignore.exists() {
-        builder.add_ignore(warden_ignore);
+// The first `SNIFF_BYTES` of the file at `path`, empty when it cannot be read
+fn file_head(path: &Path) -> Vec<u8> {
+    let mut head = Vec::new();
+    if let Ok(file) = std::fs::File::open(path) {
+        let _ = file.take(SNIFF_BYTES as u64).read_to_end(&mut head);
     }
+    head
+}
 
-    let walker = builder.build();
+fn build_walker(root_path: &str, use_gitignore: bool) -> ignore::Walk {
+    WalkOptions { use_gitignore, ..WalkOptions::default() }.builder(root_path, &SkipLog::default()).build()
+}
 
-    // Default hard limit: 100MB if not specified, to prevent system freeze
-    let size_limit_bytes = max_size_mb.unwrap_or(100) * 1024 * 1024;
+// The order of two paths as strings, with a directory taken as its path
+// and a `/`: walking each directory's entries in this order yields every
+// path in plain string order, `a.py` before the files of `a/`. Only a name
+// that begins another needs a stat to tell a directory
+fn path_order(a: &Path, b: &Path, follow_symlinks: bool) -> std::cmp::Ordering {
+    let (x, y) = (a.as_os_str().as_encoded_bytes(), b.as_os_str().as_encoded_bytes());
+    if !x.starts_with(y) && !y.starts_with(x) {
+        return x.cmp(y);
+    }
+    let is_dir = |path: &Path| {
+        let metadata = if follow_symlinks { std::fs::metadata(path) } else { std::fs::symlink_metadata(path) };
+        metadata.is_ok_and(|metadata| metadata.is_dir())
+    };
+    let key = |bytes: &[u8], path: &Path| [bytes, if is_dir(path) { b"/" } else { b"" }].concat();
+    key(x, a).cmp(&key(y, b))
+}
 
-    for result in walker {
-        if let Ok(entry) = result {
-            if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                let path = entry.path();
-                
-                // 1. Early Size Check (Fast metadata check)
-                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
-                if size > size_limit_bytes {
-                    continue; // Skip huge files immediately
-                }
+// Entries a walk left out on its own account, as (path, reason)
+type SkipLog = Arc<Mutex<Vec<(String, String)>>>;
 
-                // 2. Early Binary Check (Read first 1024 bytes)
-                if let Ok(mut file) = File::open(path) {
-                    let mut buffer = [0; 1024];
-                    let bytes_read = file.read(&mut buffer).unwrap_or(0);
-                    if inspect(&buffer[..bytes_read]) == ContentType::BINARY {
-                        continue; 
-                    }
-                }
+// How `discover_files` walks a tree; `build_walker` takes the defaults
+// apart from gitignore handling
+#[derive(Clone, Default)]
+struct WalkOptions {
+    use_gitignore: bool,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    // Globs as in a `.gitignore`, relative to the root: files matching none
+    // of `include` (when given) and files or directories matching one of
+    // `exclude` are left out
+    include: Vec<String>,
+    exclude: Vec<String>,
+    hidden: Hidden,
+    // Entries in path order (see `path_order`), so walks repeat
+    sort: bool,
+    // Git's global excludes file, `.git/info/exclude` and the ignore files
+    // above the root, each on or off apart from `use_gitignore` when set
+    git_global: Option<bool>,
+    git_exclude: Option<bool>,
+    parents: Option<bool>,
+}
+
+// Which hidden files and directories, those named with a leading dot, a
+// walk takes in: all, none, or only the ones with these names
+#[derive(Clone, Default)]
+enum Hidden {
+    #[default]
+    Include,
+    Exclude,
+    Allow(Vec<String>),
+}
+
+impl Hidden {
+    // Whether an entry called `name` is left out
+    fn hides(&self, name: &str) -> bool {
+        name.starts_with('.')
+            && match self {
+                Hidden::Include => false,
+                Hidden::Exclude => true,
+                Hidden::Allow(names) => !names.iter().any(|allow| allow == name),
+            }
+    }
+}
 
-                let path_str = path.to_string_lossy().to_string();
-                let lang = detect_language_rs(path);
-                files.push((path_str, size, lang));
+/// `hidden_policy` for discovery: "include" or "exclude", or a list of the
+/// hidden names to walk into, such as `[".github", ".gitlab-ci.yml"]`.
+#[derive(FromPyObject)]
+pub enum HiddenPolicy {
+    Named(String),
+    Allow(Vec<String>),
+}
+
+impl HiddenPolicy {
+    // None is "include", what discovery has always done
+    fn resolve(policy: Option<HiddenPolicy>) -> PyResult<Hidden> {
+        match policy {
+            None => Ok(Hidden::Include),
+            Some(HiddenPolicy::Named(name)) => match name.as_str() {
+                "include" => Ok(Hidden::Include),
+                "exclude" => Ok(Hidden::Exclude),
+                _ => Err(PyValueError::new_err(format!(
+                    "hidden_policy must be \"include\", \"exclude\" or a list of names, not '{}'",
+                    name
+                ))),
+            },
+            Some(HiddenPolicy::Allow(names)) => {
+                Ok(Hidden::Allow(names.into_iter().map(|name| name.trim_end_matches('/').to_string()).collect()))
             }
         }
     }
-    Ok(files)
 }
 
-#[pyfunction]
-fn get_file_stats(paths: Vec<String>) -> PyResult<Vec<FileStats>> {
-    let stats: Vec<FileStats> = paths.par_iter().map(|path_str| {
-        let path = Path::new(path_str);
-        let mut stats = FileStats {
-            path: path_str.clone(),
-            size: 0,
-            line_count: 0,
-            is_binary: false,
-            hash: String::new(),
-            language: detect_language_rs(path),
-        };
+impl WalkOptions {
+    // Everything but the globs, which can be invalid
+    fn builder(&self, root_path: &str, skipped: &SkipLog) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(root_path);
+
+        builder.standard_filters(self.use_gitignore)
+               .hidden(matches!(self.hidden, Hidden::Exclude))
+               .follow_links(self.follow_symlinks)
+               .max_depth(self.max_depth);
+        if let Some(git_global) = self.git_global {
+            builder.git_global(git_global);
+        }
+        if let Some(git_exclude) = self.git_exclude {
+            builder.git_exclude(git_exclude);
+        }
+        if let Some(parents) = self.parents {
+            builder.parents(parents);
+        }
 
-        if let Ok(metadata) = path.metadata() {
-            stats.size = metadata.len();
+        if self.sort {
+            let follow_symlinks = self.follow_symlinks;
+            builder.sort_by_file_path(move |a, b| path_order(a, b, follow_symlinks));
         }
 
-        if let Ok(mut file) = File::open(path) {
-            // Read first 1024 bytes for binary check
-            let mut buffer = [0; 1024];
-            // FIX ID 34: Avoid .unwrap(), use unwrap_or with error logging
-            let bytes_read = match file.read(&mut buffer) {
-                Ok(n) => n,
-                Err(e) => {
-                    eprintln!("[RUST ERROR] Failed to read file {}: {}", path.display(), e);
-                    0
+        // In every directory, like a `.gitignore` but ahead of all the
+        // other ignore files and whatever `use_gitignore` says
+        builder.add_custom_ignore_filename(".wardenignore");
+
+        let allowed = matches!(self.hidden, Hidden::Allow(_)).then(|| self.hidden.clone());
+        if self.follow_symlinks || allowed.is_some() {
+            let follow_symlinks = self.follow_symlinks;
+            let root = Path::new(root_path).canonicalize().unwrap_or_else(|_| root_path.into());
+            let skipped = Arc::clone(skipped);
+            builder.filter_entry(move |entry| {
+                if allowed.as_ref().is_some_and(|allowed| allowed.hides(&entry.file_name().to_string_lossy())) {
+                    return false;
                 }
-            };
-            stats.is_binary = inspect(&buffer[..bytes_read]) == ContentType::BINARY;
-
-            if !stats.is_binary {
-                // Return to start for hash and line count
-                if let Ok(file_reopen) = File::open(path) {
-                    let reader = BufReader::new(file_reopen);
-                    let mut line_count = 0;
-                    let mut hasher = Sha256::new();
-                    
-                    for line_result in reader.lines() {
-                        if let Ok(line) = line_result {
-                            line_count += 1;
-                            hasher.update(line.as_bytes());
-                            hasher.update(b"\n");
-                        }
-                    }
-                    stats.line_count = line_count;
-                    stats.hash = format!("{:x}", hasher.finalize());
+                if !(follow_symlinks && escapes_root(entry, &root)) {
+                    return true;
                 }
-            } else {
-                // For binary, just do a fast whole-file hash if small
-                if stats.size < 50_000_000 { // 50MB limit for full hash
-                    if let Ok(mut file_reopen) = File::open(path) {
-                        let mut hasher = Sha256::new();
-                        let mut buffer = Vec::new();
-                        if file_reopen.read_to_end(&mut buffer).is_ok() {
-                            hasher.update(&buffer);
-                            stats.hash = format!("{:x}", hasher.finalize());
-                        }
-                    }
+                if let Ok(mut skipped) = skipped.lock() {
+                    skipped.push((entry.path().to_string_lossy().to_string(), "symlink_outside_root".to_string()));
                 }
+                false
+            });
+        }
+
+        builder
+    }
+
+    fn overrides(root_path: &str, include: &[String], exclude: &[String]) -> PyResult<Override> {
+        let mut overrides = OverrideBuilder::new(root_path);
+        let include = include.iter().map(|glob| (glob, glob.clone()));
+        let exclude = exclude.iter().map(|glob| (glob, format!("!{}", glob)));
+        for (glob, line) in include.chain(exclude) {
+            overrides.add(&line).map_err(|e| PyValueError::new_err(format!("Invalid discovery glob '{}': {}", glob, e)))?;
+        }
+        overrides.build().map_err(|e| PyValueError::new_err(format!("Invalid discovery globs: {}", e)))
+    }
+
+    // The walk's entries, its errors dropped, the symlink ones logged
+    fn entries(&self, root_path: &str, skipped: &SkipLog) -> PyResult<impl Iterator<Item = ignore::DirEntry>> {
+        let mut builder = self.builder(root_path, skipped);
+        if !self.include.is_empty() || !self.exclude.is_empty() {
+            builder.overrides(WalkOptions::overrides(root_path, &self.include, &self.exclude)?);
+        }
+        let skipped = Arc::clone(skipped);
+        Ok(builder.build().filter_map(move |entry| {
+            let err = match entry {
+                Ok(entry) => return Some(entry),
+                Err(err) => err,
+            };
+            if let (Some((path, reason)), Ok(mut skipped)) = (symlink_error(&err), skipped.lock()) {
+                skipped.push((path, reason.to_string()));
             }
+            None
+        }))
+    }
+
+    // `entries` of each root in turn, those inside another one left out;
+    // with `sort`, the roots too go in path order
+    fn entries_under(&self, roots: &[String], skipped: &SkipLog) -> PyResult<impl Iterator<Item = ignore::DirEntry>> {
+        let mut roots = distinct_roots(roots);
+        if self.sort {
+            roots.sort_by(|a, b| path_order(Path::new(a), Path::new(b), self.follow_symlinks));
         }
-        stats
-    }).collect();
+        let walks = roots
+            .into_iter()
+            .map(|root| self.entries(root, skipped))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(walks.into_iter().flatten())
+    }
 
-    Ok(stats)
+    // What the walk of `entries` left out under `root_path` without
+    // yielding it, found by walking again with every filter off; anything
+    // neither `seen` nor `reported` already is reported once, a directory
+    // standing for all inside it. The reason is "excluded" or
+    // "not_included" for the globs, "hidden", or "ignored" for an ignore file
+    fn left_out(&self, root_path: &str, seen: &Arc<HashSet<PathBuf>>, reported: &Arc<HashSet<PathBuf>>) -> PyResult<Vec<(String, String)>> {
+        let include = WalkOptions::overrides(root_path, &self.include, &[])?;
+        let exclude = WalkOptions::overrides(root_path, &[], &self.exclude)?;
+        let hidden = self.hidden.clone();
+        let seen = Arc::clone(seen);
+        let reported = Arc::clone(reported);
+        let left_out = SkipLog::default();
+        let log = Arc::clone(&left_out);
+        let mut builder = WalkBuilder::new(root_path);
+        builder.standard_filters(false)
+               .follow_links(self.follow_symlinks)
+               .max_depth(self.max_depth)
+               .filter_entry(move |entry| {
+                   if seen.contains(entry.path()) {
+                       return true;
+                   }
+                   if reported.contains(entry.path()) {
+                       return false;
+                   }
+                   let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+                   let reason = if exclude.matched(entry.path(), is_dir).is_ignore() {
+                       "excluded"
+                   } else if !is_dir && !include.is_empty() && !include.matched(entry.path(), false).is_whitelist() {
+                       "not_included"
+                   } else if hidden.hides(&entry.file_name().to_string_lossy()) {
+                       "hidden"
+                   } else {
+                       "ignored"
+                   };
+                   if let Ok(mut log) = log.lock() {
+                       log.push((entry.path().to_string_lossy().to_string(), reason.to_string()));
+                   }
+                   false
+               });
+        builder.build().for_each(drop);
+        let left_out = left_out.lock().map(|mut left_out| std::mem::take(&mut *left_out)).unwrap_or_default();
+        Ok(left_out)
+    }
 }
 
+// `roots` without the ones the walk of another covers: a directory inside
+// another root, or the same directory given again under another name
+fn distinct_roots(roots: &[String]) -> Vec<&String> {
+    let canonical: Vec<PathBuf> = roots
+        .iter()
+        .map(|root| Path::new(root).canonicalize().unwrap_or_else(|_| root.into()))
+        .collect();
+    let covered = |i: usize| {
+        canonical.iter().enumerate().any(|(j, other)| j != i && canonical[i].starts_with(other) && (canonical[i] != *other || j < i))
+    };
+    roots.iter().enumerate().filter(|&(i, _)| !covered(i)).map(|(_, root)| root).collect()
+}
 
-#[pyclass]
-#[derive(Clone)]
-pub struct AstNodeInfo {
-    #[pyo3(get)]
-    pub name: String,
-    #[pyo3(get)]
-    pub line_number: usize,
-    #[pyo3(get)]
-    pub code_snippet: String,
+/// One root to walk, or several.
+#[derive(FromPyObject)]
+pub enum DiscoverRoots {
+    One(String),
+    Many(Vec<String>),
 }
 
-#[pyclass]
-#[derive(Clone)]
-pub struct AstMetadata {
-    #[pyo3(get)]
-    pub functions: Vec<AstNodeInfo>,
-    #[pyo3(get)]
-    pub classes: Vec<AstNodeInfo>,
-    #[pyo3(get)]
-    pub imports: Vec<AstNodeInfo>,
-    #[pyo3(get)]
-    pub references: Vec<String>,
+impl DiscoverRoots {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            DiscoverRoots::One(root) => vec![root],
+            DiscoverRoots::Many(roots) => roots,
+        }
+    }
 }
 
-fn get_language_parser(lang: &str) -> Option<tree_sitter::Language> {
-    match lang {
-        "python" => Some(tree_sitter_python::language()),
-        "typescript" => Some(tree_sitter_typescript::language_typescript()),
-        "javascript" => Some(tree_sitter_javascript::language()),
-        "go" => Some(tree_sitter_go::language()),
-        "java" => Some(tree_sitter_java::language()),
+// Whether `entry` is a symlink whose target lies outside `root`, such as
+// one to `/`, which following would scan the whole machine through
+fn escapes_root(entry: &ignore::DirEntry, root: &Path) -> bool {
+    entry.path_is_symlink() && entry.path().canonicalize().is_ok_and(|target| !target.starts_with(root))
+}
+
+// The symlink behind a walk error, with why it cannot be followed: it leads
+// back to a directory holding it ("symlink_loop"), which the walker breaks
+// off, or to nothing ("broken_symlink")
+fn symlink_error(err: &ignore::Error) -> Option<(String, &'static str)> {
+    match err {
+        ignore::Error::Loop { child, .. } => Some((child.to_string_lossy().to_string(), "symlink_loop")),
+        ignore::Error::WithPath { path, err } => match &**err {
+            ignore::Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound && path.is_symlink() => {
+                Some((path.to_string_lossy().to_string(), "broken_symlink"))
+            }
+            err => symlink_error(err),
+        },
+        ignore::Error::WithDepth { err, .. } => symlink_error(err),
         _ => None,
     }
 }
 
-// Queries for definitions and references
-fn get_queries(lang: &str) -> (&str, &str, &str, &str) {
-    match lang {
-        "python" => (
-            "(function_definition name: (identifier) @name)",
-            "(class_definition name: (identifier) @name)",
-            "(import_from_statement (dotted_name (identifier) @name)) (import_statement (dotted_name (identifier) @name))",
-            "(identifier) @name" // Capture ALL identifiers as references
-        ),
-        "typescript" | "javascript" => (
-            "(function_declaration name: (identifier) @name) (method_definition name: (property_identifier) @name)",
-            "(class_declaration name: (type_identifier) @name)",
-            "(import_statement (import_clause (named_imports (import_specifier name: (identifier) @name))))",
-            "(identifier) @name"
-        ),
-         "go" => (
-            "(function_declaration name: (identifier) @name) (method_declaration name: (field_identifier) @name)",
-            "(type_declaration (type_spec name: (type_identifier) @name))",
-            "(import_spec path: (interpreted_string_literal) @name)",
-            "(identifier) @name"
-        ),
-        _ => ("", "", "", "") 
+// Warns about each symlink a walk declined to follow
+fn warn_skipped_symlinks(py: Python<'_>, skipped: &SkipLog) -> PyResult<()> {
+    let skipped = skipped.lock().map(|mut skipped| std::mem::take(&mut *skipped)).unwrap_or_default();
+    for (path, reason) in skipped {
+        warn_user(py, &format!("Not following symlink {} ({})", path, reason))?;
     }
+    Ok(())
 }
 
-#[pyfunction]
-fn get_ast_metadata(content: String, language: String) -> PyResult<AstMetadata> {
-    let mut parser = tree_sitter::Parser::new();
-    let lang_parser = get_language_parser(&language);
-    
-    if lang_parser.is_none() {
-        return Ok(AstMetadata {
-            functions: vec![],
-            classes: vec![],
-            imports: vec![],
-            references: vec![],
-        });
+/// Cooperative cancellation flag shared between Python and the scan workers.
+///
+/// Call `cancel()` from any thread; long-running functions that were given the
+/// token stop picking up new files and return whatever they collected so far.
+/// `truncated` tells the caller whether those results are partial: it is set
+/// once a function actually left files out, and stays false when the token was
+/// cancelled after the work was done. `reset()` clears both flags for reuse.
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    flag: Arc<AtomicBool>,
+    truncated: Arc<AtomicBool>,
+}
+
+#[pymethods]
+impl CancellationToken {
+    #[new]
+    fn new() -> Self {
+        CancellationToken::default()
     }
 
-    // FIX ID 34: Replace .unwrap() with proper error handling
-    if let Err(_) = parser.set_language(lang_parser.unwrap()) {
-        eprintln!("[RUST ERROR] Failed to set language parser for: {}", language);
-        return Ok(AstMetadata {
-            functions: vec![],
-            classes: vec![],
-            imports: vec![],
-            references: vec![],
-        });
+    fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
     }
 
-    let tree = match parser.parse(&content, None) {
-        Some(t) => t,
-        None => {
-            eprintln!("[RUST ERROR] Failed to parse content for language: {}", language);
-            return Ok(AstMetadata {
-                functions: vec![],
-                classes: vec![],
-                imports: vec![],
-                references: vec![],
-            });
+    fn reset(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+        self.truncated.store(false, Ordering::Relaxed);
+    }
+
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.is_set()
+    }
+
+    #[getter]
+    fn truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+}
+
+impl CancellationToken {
+    fn is_set(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+// Whether a function given `token` left work out since its last reset
+fn is_truncated(token: &Option<CancellationToken>) -> bool {
+    token.as_ref().is_some_and(|t| t.truncated())
+}
+
+// Checks for cancellation before taking on one more piece of work, and notes
+// on the token that the results are partial when it is set
+fn stop_here(token: &Option<CancellationToken>) -> bool {
+    let Some(token) = token.as_ref().filter(|t| t.is_set()) else { return false };
+    token.truncated.store(true, Ordering::Relaxed);
+    true
+}
+
+// Applies the discovery filters to one walker entry; None means "skip it"
+fn discovered_file(entry: &ignore::DirEntry, size_limit_bytes: u64) -> Option<(String, u64, String)> {
+    discovery(entry, size_limit_bytes).ok()
+}
+
+// `discovered_file` with the reason for a skip: "symlink" (not followed),
+// "too_large" or "binary"; None for directories and other entries that are
+// no files to begin with
+fn discovery(entry: &ignore::DirEntry, size_limit_bytes: u64) -> Result<(String, u64, String), Option<&'static str>> {
+    if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+        return Err(entry.file_type().is_some_and(|ft| ft.is_symlink()).then_some("symlink"));
+    }
+    let path = entry.path();
+
+    // 1. Early Size Check (Fast metadata check)
+    let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+    if size > size_limit_bytes {
+        return Err(Some("too_large")); // Skip huge files immediately
+    }
+
+    // 2. Early Binary Check (Read first 1024 bytes, which also tell the
+    // language where the name does not)
+    let mut buffer = [0; SNIFF_BYTES];
+    let mut bytes_read = 0;
+    if let Ok(mut file) = File::open(path) {
+        bytes_read = file.read(&mut buffer).unwrap_or(0);
+        if encoding::is_binary(&buffer[..bytes_read]) {
+            return Err(Some("binary"));
         }
-    };
-    let root_node = tree.root_node();
-    
-    let (func_q, class_q, imp_q, ref_q) = get_queries(&language);
-    
-    let process_query = |query_str: &str| -> Vec<AstNodeInfo> {
-        let mut results = Vec::new();
-        if query_str.is_empty() { return results; }
-
-        // FIX ID 35: Safe unwrap with early return on error
-        let lang = match lang_parser {
-            Some(l) => l,
-            None => return results,
-        };
+    }
 
-        if let Ok(query) = tree_sitter::Query::new(lang, query_str) {
-            let mut cursor = tree_sitter::QueryCursor::new();
-            for m in cursor.matches(&query, root_node, content.as_bytes()) {
-                for capture in m.captures {
-                    if let Ok(text) = capture.node.utf8_text(content.as_bytes()) {
-                        let start_line = capture.node.start_position().row + 1;
-                        // Use parent for snippet context
-                        let snippet = capture.node.parent()
-                            .and_then(|p| p.utf8_text(content.as_bytes()).ok())
-                            .unwrap_or(text)
-                            .lines().next().unwrap_or(text).to_string(); // First line only
-
-                        let snippet = if snippet.len() > 200 { snippet[..200].to_string() + "..." } else { snippet.to_string() };
-
-                        results.push(AstNodeInfo {
-                            name: text.to_string(),
-                            line_number: start_line,
-                            code_snippet: snippet,
-                        });
-                    }
-                }
-            }
+    let path_str = path.to_string_lossy().to_string();
+    let lang = detect_language(path, &buffer[..bytes_read]);
+    Ok((path_str, size, lang))
+}
+
+// Surfaces a non-fatal problem to the caller as a Python UserWarning
+fn warn_user(py: Python<'_>, message: &str) -> PyResult<()> {
+    let message = std::ffi::CString::new(message.replace('\0', " ")).unwrap_or_default();
+    PyErr::warn(py, &py.get_type::<PyUserWarning>(), &message, 1)
+}
+
+// Default hard limit: 100MB if not specified, to prevent system freeze
+fn size_limit_bytes(max_size_mb: Option<u64>) -> u64 {
+    max_size_mb.unwrap_or(100) * 1024 * 1024
+}
+
+// Files at least this large are memory-mapped rather than read into a buffer
+const MMAP_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+// A whole file's bytes, mapped or read depending on its size
+enum FileBytes {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl std::ops::Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileBytes::Mapped(map) => map,
+            FileBytes::Read(bytes) => bytes,
         }
-        results
+    }
+}
+
+fn is_large_file(path: &Path) -> bool {
+    path.metadata().is_ok_and(|m| m.len() >= MMAP_THRESHOLD)
+}
+
+// `std::fs::read`, except that large files are mapped, which saves copying
+// them and lets the regexes run over the mapped pages directly
+fn read_file(path: impl AsRef<Path>) -> std::io::Result<FileBytes> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    if file.metadata()?.len() < MMAP_THRESHOLD {
+        let mut bytes = Vec::new();
+        (&file).read_to_end(&mut bytes)?;
+        return Ok(FileBytes::Read(bytes));
+    }
+    // SAFETY: the map is read-only and dropped once the file is scanned. A
+    // file truncated by another process meanwhile can still fault, the same
+    // trade-off every mmap-based scanner makes.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(FileBytes::Mapped(map))
+}
+
+/// The `(path, size, language)` of every text file under `root_path` no
+/// larger than `max_size_mb` (100 by default), honouring `.gitignore` and
+/// friends unless `use_gitignore=False`, and `.wardenignore` files always.
+/// A `.wardenignore` works like a `.gitignore` in whichever directory it is
+/// in and wins over every other ignore file.
+///
+/// `root_path` may also be a list of roots, walked one after the other
+/// with the same options into one list. A root inside another, or the same
+/// directory given twice, is walked only once, as part of the outer one.
+///
+/// Symlinks are skipped unless `follow_symlinks=True`. Then those pointing
+/// inside the root are followed, while broken ones, ones leading out of the
+/// root and ones back to a directory holding them are not, each with a
+/// UserWarning.
+///
+/// `max_depth` stops the walk that many levels down: 1 lists the files
+/// directly in the root, 2 those in its subdirectories too, and so on.
+///
+/// `include` and `exclude` take globs in `.gitignore` syntax, relative to
+/// the root (`*.py`, `src/**/*.ts`, `tests/`): only files matching an
+/// `include` glob are kept when there are any, and files and whole
+/// directories matching an `exclude` glob are skipped. A file matching
+/// `include` is kept even where an ignore file names it, though not inside
+/// an ignored directory. ValueError names an invalid glob.
+///
+/// `hidden_policy` decides on files and directories whose name starts with
+/// a dot: "include" walks them all, as by default, "exclude" none, and a
+/// list of names such as `[".github", ".env"]` just those (a hidden entry
+/// inside one must be listed too).
+///
+/// The walk's order can change from run to run; `sort=True` returns the
+/// files sorted by path instead, the same on every run and across roots.
+///
+/// `debug=True` tells why files are missing: it returns `(files, skipped)`,
+/// with a `(path, reason)` in `skipped` for everything left out. The
+/// reason is "ignored" (by a `.gitignore`, `.ignore`, `.wardenignore` or
+/// git's exclude files), "hidden", "excluded" or "not_included" (by the
+/// globs), "too_large", "binary", "symlink" (not followed), or one of the
+/// symlink warnings' reasons. A directory left out stands for everything
+/// in it. Finding the ignored entries walks each root a second time.
+///
+/// `git_global`, `git_exclude` and `parents` turn single parts of the
+/// ignore handling on or off: git's global excludes file
+/// (`core.excludesFile`), the repository's `.git/info/exclude`, and the
+/// ignore files of the directories above the root, through which a scan of
+/// a subdirectory honours the `.gitignore` at the repository root. Left at
+/// None, each follows `use_gitignore`.
+#[pyfunction]
+#[pyo3(signature = (root_path, use_gitignore=true, max_size_mb=None, cancel_token=None, follow_symlinks=false, max_depth=None, include=vec![], exclude=vec![], hidden_policy=None, sort=false, debug=false, git_global=None, git_exclude=None, parents=None))]
+#[allow(clippy::too_many_arguments)]
+fn discover_files(
+    py: Python<'_>,
+    root_path: DiscoverRoots,
+    use_gitignore: bool,
+    max_size_mb: Option<u64>,
+    cancel_token: Option<CancellationToken>,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    hidden_policy: Option<HiddenPolicy>,
+    sort: bool,
+    debug: bool,
+    git_global: Option<bool>,
+    git_exclude: Option<bool>,
+    parents: Option<bool>,
+) -> PyResult<Discovery> {
+    let hidden = HiddenPolicy::resolve(hidden_policy)?;
+    let options = WalkOptions {
+        use_gitignore, follow_symlinks, max_depth, include, exclude, hidden, sort, git_global, git_exclude, parents,
     };
+    let roots = root_path.into_vec();
+    let skipped = SkipLog::default();
+    let entries = options.entries_under(&roots, &skipped)?;
+    // Walking is pure IO, let other Python threads run meanwhile
+    let (files, left_out) = py.allow_threads(|| {
+        let size_limit = size_limit_bytes(max_size_mb);
+        let mut files = Vec::new();
+        let mut left_out = Vec::new();
+        let mut seen = HashSet::new();
+        for entry in entries.take_while(|_| !stop_here(&cancel_token)) {
+            match discovery(&entry, size_limit) {
+                Ok(file) => files.push(file),
+                Err(Some(reason)) if debug => left_out.push((entry.path().to_string_lossy().to_string(), reason.to_string())),
+                Err(_) => {}
+            }
+            if debug {
+                seen.insert(entry.into_path());
+            }
+        }
+        if debug && !is_truncated(&cancel_token) {
+            left_out.extend(skipped.lock().map(|skipped| skipped.clone()).unwrap_or_default());
+            let reported = Arc::new(left_out.iter().map(|(path, _)| PathBuf::from(path)).collect());
+            let seen = Arc::new(seen);
+            for root in distinct_roots(&roots) {
+                left_out.extend(options.left_out(root, &seen, &reported)?);
+            }
+        }
+        // The files came sorted from the walk; these from walks of their own
+        if sort {
+            left_out.sort();
+        }
+        Ok::<_, PyErr>((files, left_out))
+    })?;
+    warn_skipped_symlinks(py, &skipped)?;
+    Ok(if debug { Discovery::Debug(files, left_out) } else { Discovery::Files(files) })
+}
 
-    // Process references separately (simple string list)
-    let mut references = Vec::new();
-    if !ref_q.is_empty() {
-        // FIX ID 35: Safe unwrap with early return on error
-        let lang = match lang_parser {
-            Some(l) => l,
-            None => return Ok(AstMetadata {
-                functions: vec![],
-                classes: vec![],
-                imports: vec![],
-                references: vec![],
-            }),
+/// What `discover_files` returns: the files, or in debug mode the files
+/// and what was skipped.
+#[derive(IntoPyObject)]
+pub enum Discovery {
+    Files(Vec<(String, u64, String)>),
+    Debug(Vec<(String, u64, String)>, Vec<(String, String)>),
+}
+
+/// Streaming counterpart of `discover_files`.
+///
+/// The walk runs on a background thread and feeds a bounded channel, so the
+/// first `(path, size, language)` tuples are available immediately and memory
+/// stays flat regardless of repository size. Dropping the iterator early stops
+/// the walk. With `sort=True` the files come sorted by path, in the order
+/// `discover_files` returns them.
+#[pyclass]
+pub struct DiscoverIter {
+    receiver: Mutex<Receiver<(String, u64, String)>>,
+    // Symlinks not followed, warned about once the walk is done
+    skipped: SkipLog,
+}
+
+#[pymethods]
+impl DiscoverIter {
+    #[new]
+    #[pyo3(signature = (root_path, use_gitignore=true, max_size_mb=None, cancel_token=None, follow_symlinks=false, max_depth=None, include=vec![], exclude=vec![], hidden_policy=None, sort=false, git_global=None, git_exclude=None, parents=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        root_path: DiscoverRoots,
+        use_gitignore: bool,
+        max_size_mb: Option<u64>,
+        cancel_token: Option<CancellationToken>,
+        follow_symlinks: bool,
+        max_depth: Option<usize>,
+        include: Vec<String>,
+        exclude: Vec<String>,
+        hidden_policy: Option<HiddenPolicy>,
+        sort: bool,
+        git_global: Option<bool>,
+        git_exclude: Option<bool>,
+        parents: Option<bool>,
+    ) -> PyResult<Self> {
+        let (sender, receiver) = sync_channel(DISCOVER_CHANNEL_CAPACITY);
+        let size_limit = size_limit_bytes(max_size_mb);
+        let hidden = HiddenPolicy::resolve(hidden_policy)?;
+        let options = WalkOptions {
+            use_gitignore, follow_symlinks, max_depth, include, exclude, hidden, sort, git_global, git_exclude, parents,
         };
+        let skipped = SkipLog::default();
+        let entries = options.entries_under(&root_path.into_vec(), &skipped)?;
 
-        if let Ok(query) = tree_sitter::Query::new(lang, ref_q) {
-             let mut cursor = tree_sitter::QueryCursor::new();
-             for m in cursor.matches(&query, root_node, content.as_bytes()) {
-                for capture in m.captures {
-                    if let Ok(text) = capture.node.utf8_text(content.as_bytes()) {
-                        references.push(text.to_string());
+        thread::spawn(move || {
+            for entry in entries {
+                if stop_here(&cancel_token) {
+                    break;
+                }
+                if let Some(file) = discovered_file(&entry, size_limit) {
+                    if sender.send(file).is_err() {
+                        break; // Iterator was dropped on the Python side
                     }
                 }
-             }
-        }
+            }
+        });
+
+        Ok(DiscoverIter { receiver: Mutex::new(receiver), skipped })
     }
-    // Return all references (not deduped) to allow counting
-    // references.sort();
-    // references.dedup();
 
-    Ok(AstMetadata {
-        functions: process_query(func_q),
-        classes: process_query(class_q),
-        imports: process_query(imp_q),
-        references
-    })
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<(String, u64, String)>> {
+        // Block on the walker without holding the GIL
+        let next = py.allow_threads(|| {
+            self.receiver.lock().ok()?.recv().ok()
+        });
+        if next.is_none() {
+            warn_skipped_symlinks(py, &self.skipped)?;
+        }
+        Ok(next)
+    }
 }
 
+// Module-wide worker count set through `set_thread_count`; 0 means rayon's default
+static DEFAULT_THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
 
+/// Caps the number of worker threads used by subsequent scans (0 restores the
+/// default of one thread per logical CPU). A per-call `threads=` argument wins.
 #[pyfunction]
-fn match_patterns(files: Vec<String>, rules: Vec<RustRule>) -> PyResult<Vec<MatchHit>> {
-    // Compile regexes once
-    let compiled_rules: Vec<(String, Regex)> = rules.into_iter()
-        .filter_map(|r| {
-            Regex::new(&r.pattern).ok().map(|re| (r.id, re))
-        })
-        .collect();
+fn set_thread_count(n: usize) {
+    DEFAULT_THREAD_COUNT.store(n, Ordering::Relaxed);
+}
 
-    if compiled_rules.is_empty() {
-        return Ok(Vec::new());
-    }
-
-    // Process files in parallel
-    let hits: Vec<MatchHit> = files.par_iter()
-        .flat_map(|file_path| {
-            let mut file_hits = Vec::new();
-            if let Ok(file) = File::open(file_path) {
-                let reader = BufReader::new(file);
-                for (ln, line_result) in reader.lines().enumerate() {
-                    if let Ok(line) = line_result {
-                        for (id, re) in &compiled_rules {
-                            if let Some(m) = re.find(&line) {
-                                file_hits.push(MatchHit {
-                                    file_path: file_path.clone(),
-                                    line_number: ln + 1,
-                                    column: m.start() + 1,
-                                    rule_id: id.clone(),
-                                    snippet: line.trim().to_string(),
-                                });
-                                // We found a match for this rule on this line, stop checking this rule for this line
-                                // (Actually, we might want multiple rules for the same line, but maybe one hit per rule per line is enough)
-                            }
-                        }
-                    }
+// Runs `op` on a dedicated pool of the requested size, or on the global rayon
+// pool when neither the call nor `set_thread_count` asked for a limit. The
+// global pool sizes itself from the host CPU count, which ignores cgroup quotas.
+fn run_in_pool<R, F>(threads: Option<usize>, op: F) -> PyResult<R>
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    let count = threads.unwrap_or_else(|| DEFAULT_THREAD_COUNT.load(Ordering::Relaxed));
+    if count == 0 {
+        return Ok(op());
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(count)
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(format!("Failed to build thread pool: {}", e)))?;
+    Ok(pool.install(op))
+}
+
+// Calls an optional Python `progress(files_done, files_total, current_path)`
+// callback every `every` files from inside the rayon workers. The GIL is only
+// re-acquired when the callback actually fires; the first exception it raises
+// is kept and re-raised once the scan returns.
+struct ProgressReporter {
+    callback: Option<PyObject>,
+    every: usize,
+    total: usize,
+    done: AtomicUsize,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl ProgressReporter {
+    fn new(callback: Option<PyObject>, every: usize, total: usize) -> Self {
+        ProgressReporter {
+            callback,
+            every: every.max(1),
+            total,
+            done: AtomicUsize::new(0),
+            error: Mutex::new(None),
+        }
+    }
+
+    fn tick(&self, current_path: &str) {
+        let Some(callback) = &self.callback else { return };
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if !done.is_multiple_of(self.every) && done != self.total {
+            return;
+        }
+        Python::with_gil(|py| {
+            if let Err(e) = callback.call1(py, (done, self.total, current_path)) {
+                if let Ok(mut slot) = self.error.lock() {
+                    slot.get_or_insert(e);
                 }
             }
-            file_hits
-        })
-        .collect();
+        });
+    }
 
-    Ok(hits)
+    fn finish(&self) -> PyResult<()> {
+        match self.error.lock().ok().and_then(|mut slot| slot.take()) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
 }
 
-#[pyclass]
-#[derive(Clone)]
-pub struct MetricRule {
-    #[pyo3(get, set)]
-    pub id: String,
-    #[pyo3(get, set)]
-    pub metric_type: String, // "line_count", "size_bytes"
-    #[pyo3(get, set)]
-    pub threshold: u64,
+// The progress / cancellation / thread-count knobs every per-file scan accepts
+struct ScanControl {
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
 }
 
-#[pymethods]
-impl MetricRule {
-    #[new]
-    fn new(id: String, metric_type: String, threshold: u64) -> Self {
-        MetricRule { id, metric_type, threshold }
+// What `ScanControl::run` fans out over: a path, or a path with its content
+trait ScanItem: Sync {
+    fn path(&self) -> &str;
+}
+
+impl ScanItem for String {
+    fn path(&self) -> &str {
+        self
     }
 }
 
-#[pyclass]
-#[derive(Clone)]
-pub struct ValidationResult {
-    #[pyo3(get)]
-    pub rule_id: String,
-    #[pyo3(get)]
-    pub file_path: String,
-    #[pyo3(get)]
-    pub message: String,
-    #[pyo3(get)]
-    pub line: usize,
-    #[pyo3(get)]
-    pub snippet: String,
+impl<T: Sync> ScanItem for (String, T) {
+    fn path(&self) -> &str {
+        &self.0
+    }
 }
 
-#[pyfunction]
-fn validate_files(
-    files: Vec<String>, 
-    regex_rules: Vec<RustRule>, 
-    metric_rules: Vec<MetricRule>
-) -> PyResult<Vec<ValidationResult>> {
-    
-    // Compile regex rules
-    let compiled_regexes: Vec<(String, Regex)> = regex_rules.into_iter()
-        .filter_map(|r| Regex::new(&r.pattern).ok().map(|re| (r.id, re)))
-        .collect();
+impl ScanControl {
+    fn new(
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+    ) -> Self {
+        ScanControl { progress, progress_every, cancel_token, threads }
+    }
 
-    let results: Vec<ValidationResult> = files.par_iter()
-        .flat_map(|path_str| {
-            let path = Path::new(path_str);
-            let mut file_results = Vec::new();
-
-            // 1. Check Metadata Metrics (Fastest)
-            if !metric_rules.is_empty() {
-                if let Ok(metadata) = path.metadata() {
-                    let size = metadata.len();
-                    
-                    // Size check
-                    for rule in &metric_rules {
-                        if rule.metric_type == "size_bytes" && size > rule.threshold {
-                            file_results.push(ValidationResult {
-                                rule_id: rule.id.clone(),
-                                file_path: path_str.clone(),
-                                message: format!("File size {} exceeds limit {}", size, rule.threshold),
-                                line: 0,
-                                snippet: String::new(),
-                            });
-                        }
-                    }
+    // Fans `scan_one` out over `files` in parallel with the GIL released,
+    // skipping the remaining files once the cancellation token is set
+    fn run<S, T, I, F>(self, py: Python<'_>, files: &[S], scan_one: F) -> PyResult<Vec<T>>
+    where
+        S: ScanItem,
+        T: Send,
+        I: IntoIterator<Item = T>,
+        F: Fn(&S) -> I + Sync + Send,
+    {
+        let reporter = ProgressReporter::new(self.progress, self.progress_every, files.len());
+        let cancel_token = self.cancel_token;
+        let results = py.allow_threads(|| run_in_pool(self.threads, || files.par_iter()
+            .flat_map_iter(|path| {
+                if stop_here(&cancel_token) {
+                    return Vec::new();
+                }
+                let items: Vec<T> = scan_one(path).into_iter().collect();
+                reporter.tick(path.path());
+                items
+            })
+            .collect()))?;
+        reporter.finish()?;
+        Ok(results)
+    }
 
-                    // Line count check (requires reading, but avoiding regex)
-                    let check_lines = metric_rules.iter().any(|r| r.metric_type == "line_count");
-                    if check_lines {
-                        if let Ok(file) = File::open(path) {
-                            let reader = BufReader::new(file);
-                            let line_count = reader.lines().count();
-                             for rule in &metric_rules {
-                                if rule.metric_type == "line_count" && line_count as u64 > rule.threshold {
-                                    file_results.push(ValidationResult {
-                                        rule_id: rule.id.clone(),
-                                        file_path: path_str.clone(),
-                                        message: format!("Line count {} exceeds limit {}", line_count, rule.threshold),
-                                        line: 0,
-                                        snippet: String::new(),
-                                    });
-                                }
-                            }
-                        }
-                    }
+    // `run` over items that keep arriving while the scan is under way, such
+    // as the changes of a history walk. How many there are is not known up
+    // front, so `progress` is told 0 files in total. Results keep the order
+    // the items came in.
+    fn stream<S, T, I, F>(self, py: Python<'_>, items: impl Iterator<Item = S> + Send, scan_one: F) -> PyResult<Vec<T>>
+    where
+        S: ScanItem + Send,
+        T: Send,
+        I: IntoIterator<Item = T>,
+        F: Fn(&S) -> I + Sync + Send,
+    {
+        let reporter = ProgressReporter::new(self.progress, self.progress_every, 0);
+        let cancel_token = self.cancel_token;
+        let mut results: Vec<(usize, Vec<T>)> = py.allow_threads(|| run_in_pool(self.threads, || items
+            .enumerate()
+            .par_bridge()
+            .filter_map(|(idx, item)| {
+                if stop_here(&cancel_token) {
+                    return None;
                 }
-            }
+                let found = scan_one(&item).into_iter().collect();
+                reporter.tick(item.path());
+                Some((idx, found))
+            })
+            .collect()))?;
+        reporter.finish()?;
+        results.sort_unstable_by_key(|&(idx, _)| idx);
+        Ok(results.into_iter().flat_map(|(_, found)| found).collect())
+    }
+}
 
-            // 2. Check Regex Patterns (Slower)
-            if !compiled_regexes.is_empty() {
-                if let Ok(file) = File::open(path) {
-                    let reader = BufReader::new(file);
-                    for (ln, line_result) in reader.lines().enumerate() {
-                        if let Ok(line) = line_result {
-                            for (id, re) in &compiled_regexes {
-                                if let Some(_m) = re.find(&line) {
-                                    file_results.push(ValidationResult {
-                                        rule_id: id.clone(),
-                                        file_path: path_str.clone(),
-                                        message: "Pattern match found".to_string(),
-                                        line: ln + 1,
-                                        snippet: line.trim().to_string(),
-                                    });
-                                }
-                            }
-                        }
-                    }
+/// Size, line count, binary, generated, test and vendored flags, language,
+/// encoding, line endings, whitespace style, final newline, NUL and invalid
+/// UTF-8 positions, permission bits, shebang and content hash of each path.
+/// `hash_algo` picks the digest: "sha256" (the default), or the much faster
+/// "blake3" or "xxh3" when the hash only serves change detection.
+///
+/// Text files are hashed line by line with every line ending normalised to
+/// `\n`, so CRLF/LF conversions and a missing final newline leave the hash
+/// alone. `exact_hash=True` hashes their raw bytes instead, like binaries.
+/// Text in UTF-16, with a UTF-8 byte order mark or in Latin-1 is counted
+/// and hashed as the UTF-8 it transcodes to.
+///
+/// Only the directories below `root`, the working directory by default,
+/// make a file test or vendored code, so a checkout under `~/tests/` is
+/// not all tests.
+#[pyfunction]
+#[pyo3(signature = (paths, progress=None, progress_every=100, cancel_token=None, threads=None, hash_algo="sha256", exact_hash=false, root=None))]
+#[allow(clippy::too_many_arguments)]
+fn get_file_stats(
+    py: Python<'_>,
+    paths: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    hash_algo: &str,
+    exact_hash: bool,
+    root: Option<String>,
+) -> PyResult<Vec<FileStats>> {
+    let algo = HashAlgo::parse(hash_algo)?;
+    let root = scan_root(root);
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &paths, |path_str| Some(file_stats(path_str, &root, algo, exact_hash)))
+}
+
+// The root paths are classified below: `root` as given, else the working
+// directory
+pub(crate) fn scan_root(root: Option<String>) -> PathBuf {
+    root.map(PathBuf::from).or_else(|| std::env::current_dir().ok()).unwrap_or_default()
+}
+
+fn file_stats(path_str: &str, root: &Path, algo: HashAlgo, exact_hash: bool) -> FileStats {
+    let path = Path::new(path_str);
+    let mut stats = FileStats {
+        path: path_str.to_string(),
+        size: 0,
+        line_count: 0,
+        code_lines: 0,
+        comment_lines: 0,
+        blank_lines: 0,
+        is_binary: false,
+        hash: String::new(),
+        hash_algo: algo.name().to_string(),
+        // Settled by the head below when the file can be read
+        language: detect_language(path, &[]),
+        is_generated: false,
+        is_test: false,
+        is_vendored: false,
+        encoding: None,
+        eol_type: None,
+        lf_count: 0,
+        crlf_count: 0,
+        trailing_whitespace_lines: 0,
+        tab_indented_lines: 0,
+        space_indented_lines: 0,
+        indent_style: None,
+        has_trailing_newline: false,
+        nul_count: 0,
+        first_nul: None,
+        invalid_sequence_count: 0,
+        first_invalid_sequence: None,
+        mode: None,
+        shebang: None,
+        interpreter: None,
+    };
+
+    if let Ok(metadata) = path.metadata() {
+        stats.size = metadata.len();
+        stats.mode = permissions::mode_bits(&metadata);
+    }
+
+    if let Ok(file) = File::open(path) {
+        // Read the head: the first 1024 bytes for binary check, more for
+        // generator headers
+        let mut buffer = Vec::with_capacity(classify::HEAD_BYTES);
+        // FIX ID 34: Avoid .unwrap(), use unwrap_or with error logging
+        if let Err(e) = file.take(classify::HEAD_BYTES as u64).read_to_end(&mut buffer) {
+            eprintln!("[RUST ERROR] Failed to read file {}: {}", path.display(), e);
+        }
+        stats.is_binary = encoding::is_binary(&buffer[..buffer.len().min(1024)]);
+        stats.language = detect_language(path, &buffer);
+        let (head, _) = encoding::decode(&buffer);
+        stats.is_generated = !stats.is_binary && classify::is_generated(path, &head);
+        stats.is_test = !stats.is_binary && classify::is_test(classify::below_root(path, root), &head);
+        stats.is_vendored = classify::is_vendored(classify::below_root(path, root), if stats.is_binary { &[] } else { &head });
+        if !stats.is_binary {
+            stats.set_shebang(&head);
+        }
+
+        if !stats.is_binary {
+            // One pass over the whole buffer, mapped if large, so the
+            // encoding is told from all of it
+            if let Ok(bytes) = read_file(path) {
+                stats.set_text_stats(&bytes, algo, exact_hash);
+            }
+        } else {
+            // For binary, just do a fast whole-file hash if small
+            if stats.size < 50_000_000 { // 50MB limit for full hash
+                if let Ok(bytes) = read_file(path) {
+                    let mut hasher = algo.hasher();
+                    hasher.update(&bytes);
+                    stats.hash = hasher.finish();
                 }
             }
+        }
+    }
+    stats
+}
 
-            file_results
-        })
-        .collect();
 
-    Ok(results)
+// `file_stats` over content already in memory, hashed the same way so a
+// staged file and an identical working-tree copy agree; `mode` is left for
+// callers whose content came from a file on disk
+fn content_stats(path_str: &str, root: &Path, bytes: &[u8], algo: HashAlgo, exact_hash: bool) -> FileStats {
+    let mut stats = FileStats {
+        path: path_str.to_string(),
+        size: bytes.len() as u64,
+        line_count: 0,
+        code_lines: 0,
+        comment_lines: 0,
+        blank_lines: 0,
+        is_binary: encoding::is_binary(&bytes[..bytes.len().min(1024)]),
+        hash: String::new(),
+        hash_algo: algo.name().to_string(),
+        language: detect_language(Path::new(path_str), bytes),
+        is_generated: false,
+        is_test: false,
+        is_vendored: false,
+        encoding: None,
+        eol_type: None,
+        lf_count: 0,
+        crlf_count: 0,
+        trailing_whitespace_lines: 0,
+        tab_indented_lines: 0,
+        space_indented_lines: 0,
+        indent_style: None,
+        has_trailing_newline: false,
+        nul_count: 0,
+        first_nul: None,
+        invalid_sequence_count: 0,
+        first_invalid_sequence: None,
+        mode: None,
+        shebang: None,
+        interpreter: None,
+    };
+    let (head, _) = encoding::decode(&bytes[..bytes.len().min(classify::HEAD_BYTES)]);
+    stats.is_generated = !stats.is_binary && classify::is_generated(Path::new(path_str), &head);
+    stats.is_test = !stats.is_binary && classify::is_test(classify::below_root(Path::new(path_str), root), &head);
+    stats.is_vendored = classify::is_vendored(classify::below_root(Path::new(path_str), root), if stats.is_binary { &[] } else { &head });
+    if !stats.is_binary {
+        stats.set_shebang(&head);
+        stats.set_text_stats(bytes, algo, exact_hash);
+    } else if stats.size < 50_000_000 {
+        let mut hasher = algo.hasher();
+        hasher.update(bytes);
+        stats.hash = hasher.finish();
+    }
+    stats
+}
+
+/// `get_file_stats` over the staged version of each path in the git
+/// repository at `repo`, exactly as it would be committed. See
+/// `Scanner.scan_staged` for how paths are resolved.
+#[pyfunction]
+#[pyo3(signature = (repo, paths, progress=None, progress_every=100, cancel_token=None, threads=None, hash_algo="sha256", exact_hash=false))]
+#[allow(clippy::too_many_arguments)]
+fn get_staged_file_stats(
+    py: Python<'_>,
+    repo: String,
+    paths: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    hash_algo: &str,
+    exact_hash: bool,
+) -> PyResult<Vec<FileStats>> {
+    let algo = HashAlgo::parse(hash_algo)?;
+    let blobs = py.allow_threads(|| git::staged_blobs(&repo, &paths))?;
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &blobs, |(path, bytes)| Some(content_stats(path, Path::new(&repo), bytes, algo, exact_hash)))
 }
 
 #[pymodule]
 fn warden_core_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<AstMetadata>()?;
     m.add_class::<AstNodeInfo>()?;
+    m.add_class::<AstParameter>()?;
+    m.add_class::<AstCapture>()?;
+    m.add_class::<ParseSession>()?;
+    m.add_class::<FunctionMetrics>()?;
+    m.add_class::<DefUseChain>()?;
+    m.add_class::<FunctionCfg>()?;
+    m.add_class::<CfgBlock>()?;
+    m.add_class::<CfgEdge>()?;
+    m.add_class::<ImportEdge>()?;
+    m.add_class::<ModuleCoupling>()?;
+    m.add_class::<DeadSymbol>()?;
+    m.add_class::<AstPattern>()?;
+    m.add_class::<AstPatternHit>()?;
+    m.add_class::<TaintRule>()?;
+    m.add_class::<TaintFlow>()?;
     m.add_class::<RustRule>()?;
     m.add_class::<MetricRule>()?;
     m.add_class::<MatchHit>()?;
     m.add_class::<FileStats>()?;
+    m.add_class::<ProjectProfile>()?;
     m.add_class::<ValidationResult>()?;
+    m.add_class::<DiscoverIter>()?;
+    m.add_class::<DiscoveredChanges>()?;
+    m.add_class::<CancellationToken>()?;
+    m.add_class::<Scanner>()?;
+    m.add_class::<ScanAllResult>()?;
+    m.add_class::<ScanCache>()?;
+    m.add_class::<RuleCompileReport>()?;
+    m.add_class::<BudgetReport>()?;
+    m.add_class::<BinaryStringHit>()?;
+    m.add_class::<StringLiteral>()?;
+    m.add_class::<AbsolutePath>()?;
+    m.add_class::<Marker>()?;
+    m.add_class::<Suppression>()?;
+    m.add_class::<UnicodeHit>()?;
+    m.add_class::<NonAsciiIdentifier>()?;
+    m.add_class::<SecretCandidate>()?;
+    m.add_class::<AddressHit>()?;
+    m.add_class::<EnvEntry>()?;
+    m.add_class::<CommitInfo>()?;
+    m.add_class::<GitHistoryHit>()?;
+    m.add_class::<ChangedFile>()?;
+    m.add_class::<Rename>()?;
+    m.add_class::<TreeHash>()?;
+    m.add_function(wrap_pyfunction!(set_thread_count, m)?)?;
     m.add_function(wrap_pyfunction!(discover_files, m)?)?;
+    m.add_function(wrap_pyfunction!(discover_changed, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_project_profile, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_tree, m)?)?;
     m.add_function(wrap_pyfunction!(get_file_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(get_staged_file_stats, m)?)?;
     m.add_function(wrap_pyfunction!(get_ast_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(get_ast_metadata_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(run_ast_query, m)?)?;
+    m.add_function(wrap_pyfunction!(get_def_use_chains, m)?)?;
+    m.add_function(wrap_pyfunction!(build_cfg, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_complexity, m)?)?;
+    m.add_function(wrap_pyfunction!(build_import_graph, m)?)?;
+    m.add_function(wrap_pyfunction!(find_import_cycles, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_coupling, m)?)?;
+    m.add_function(wrap_pyfunction!(find_dead_symbols, m)?)?;
+    m.add_function(wrap_pyfunction!(find_unused_imports, m)?)?;
+    m.add_function(wrap_pyfunction!(export_graph, m)?)?;
+    m.add_function(wrap_pyfunction!(match_ast_patterns, m)?)?;
+    m.add_function(wrap_pyfunction!(find_taint_flows, m)?)?;
     m.add_function(wrap_pyfunction!(match_patterns, m)?)?;
+    m.add_function(wrap_pyfunction!(match_patterns_content, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_binary_strings, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_strings, m)?)?;
+    m.add_function(wrap_pyfunction!(find_absolute_paths, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_markers, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_suppressions, m)?)?;
+    m.add_function(wrap_pyfunction!(check_license_headers, m)?)?;
+    m.add_function(wrap_pyfunction!(check_file_permissions, m)?)?;
+    m.add_function(wrap_pyfunction!(find_bidi_controls, m)?)?;
+    m.add_function(wrap_pyfunction!(find_suspicious_identifiers, m)?)?;
+    m.add_function(wrap_pyfunction!(find_non_ascii_identifiers, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_secrets, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_addresses, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_env_files, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_git_history, m)?)?;
+    m.add_function(wrap_pyfunction!(blame_lines, m)?)?;
+    m.add_function(wrap_pyfunction!(git_changed_files, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_renames, m)?)?;
     m.add_function(wrap_pyfunction!(validate_files, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_staged, m)?)?;
+    m.add_function(wrap_pyfunction!(scan_all, m)?)?;
+    m.add_function(wrap_pyfunction!(check_rules, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tree of small text files at `paths` under a fresh directory
+    fn tree(paths: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for path in paths {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "x = 1\n").unwrap();
+        }
+        dir
+    }
+
+    // The files `options` discovers under `roots`, relative to `base`
+    fn discovered(base: &Path, roots: &[&str], options: WalkOptions) -> Vec<String> {
+        let roots: Vec<String> = roots.iter().map(|root| base.join(root).to_string_lossy().to_string()).collect();
+        let entries = options.entries_under(&roots, &SkipLog::default()).unwrap();
+        entries
+            .filter_map(|entry| discovered_file(&entry, size_limit_bytes(None)))
+            .map(|(path, _, _)| Path::new(&path).strip_prefix(base).unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn a_cancelled_token_skips_the_remaining_files_and_marks_the_scan_truncated() {
+        let files: Vec<String> = (0..20).map(|idx| format!("file{}.py", idx)).collect();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let token = CancellationToken::default();
+            let control = ScanControl::new(None, 100, Some(token.clone()), Some(1));
+            let scanned = control.run(py, &files, |path| {
+                token.cancel();
+                Some(path.clone())
+            }).unwrap();
+            assert_eq!(scanned.len(), 1);
+            assert!(token.truncated());
+
+            token.reset();
+            let control = ScanControl::new(None, 100, Some(token.clone()), Some(1));
+            assert_eq!(control.run(py, &files, |path| Some(path.clone())).unwrap().len(), files.len());
+            assert!(!token.truncated());
+        });
+    }
+
+    #[test]
+    fn max_depth_stops_the_walk() {
+        let dir = tree(&["top.py", "one/mid.py", "one/two/deep.py"]);
+        let options = WalkOptions { max_depth: Some(2), sort: true, ..WalkOptions::default() };
+        assert_eq!(discovered(dir.path(), &[""], options), vec!["one/mid.py", "top.py"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_symlinks_stay_inside_the_root_and_out_of_loops() {
+        let dir = tree(&["root/a.py", "outside/b.py"]);
+        let root = dir.path().join("root");
+        std::os::unix::fs::symlink(&root, root.join("loop")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("outside"), root.join("out")).unwrap();
+        let skipped = SkipLog::default();
+        let options = WalkOptions { follow_symlinks: true, sort: true, ..WalkOptions::default() };
+        let roots = vec![root.to_string_lossy().to_string()];
+        let files: Vec<String> = options.entries_under(&roots, &skipped).unwrap()
+            .filter_map(|entry| discovered_file(&entry, size_limit_bytes(None)))
+            .map(|(path, _, _)| path)
+            .collect();
+        assert_eq!(files, vec![root.join("a.py").to_string_lossy().to_string()]);
+        let reasons: Vec<String> = skipped.lock().unwrap().iter().map(|(_, reason)| reason.clone()).collect();
+        assert!(reasons.contains(&"symlink_outside_root".to_string()));
+        assert_eq!(discovered(dir.path(), &["root"], WalkOptions::default()), vec!["root/a.py"]);
+    }
+
+    #[test]
+    fn include_and_exclude_globs_pick_the_files() {
+        let dir = tree(&["src/app.py", "src/app.js", "build/out.py", "src/build.py"]);
+        let options = WalkOptions {
+            include: vec!["*.py".to_string()],
+            exclude: vec!["build/".to_string()],
+            sort: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(discovered(dir.path(), &[""], options), vec!["src/app.py", "src/build.py"]);
+    }
+
+    #[test]
+    fn roots_inside_another_root_are_walked_once() {
+        let dir = tree(&["a.py", "sub/b.py"]);
+        let files = discovered(dir.path(), &["", "sub"], WalkOptions { sort: true, ..WalkOptions::default() });
+        assert_eq!(files, vec!["a.py", "sub/b.py"]);
+    }
+
+    #[test]
+    fn hidden_entries_follow_the_policy() {
+        let dir = tree(&[".env", ".github/ci.yml", ".cache/blob.txt", "src/app.py"]);
+        let with = |hidden| discovered(dir.path(), &[""], WalkOptions { hidden, sort: true, ..WalkOptions::default() });
+        assert_eq!(with(Hidden::Include), vec![".cache/blob.txt", ".env", ".github/ci.yml", "src/app.py"]);
+        assert_eq!(with(Hidden::Exclude), vec!["src/app.py"]);
+        assert_eq!(with(Hidden::Allow(vec![".github".to_string()])), vec![".github/ci.yml", "src/app.py"]);
+    }
+
+    #[test]
+    fn sorted_walks_list_every_root_in_string_order() {
+        let dir = tree(&["r1/a.py", "r1/a/x.py", "r1/a-b/y.py", "r1/a-b.py", "r1/B/q.py", "r1/ab.py", "r2/A.py", "r2/z/1.py"]);
+        let files = discovered(dir.path(), &["r2", "r1"], WalkOptions { sort: true, ..WalkOptions::default() });
+        let mut sorted = files.clone();
+        sorted.sort();
+        assert_eq!(files, sorted);
+        assert_eq!(files.len(), 8);
+    }
+
+    #[test]
+    fn wardenignore_files_apply_without_gitignore_and_below_their_directory() {
+        let dir = tree(&["app.py", "secret.py", "local.py", "sub/local.py", "sub/kept.py"]);
+        std::fs::write(dir.path().join(".wardenignore"), "secret.py\n").unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "app.py\n").unwrap();
+        std::fs::write(dir.path().join("sub/.wardenignore"), "local.py\n").unwrap();
+        let options = WalkOptions { hidden: Hidden::Exclude, sort: true, ..WalkOptions::default() };
+        assert_eq!(discovered(dir.path(), &[""], options), vec!["app.py", "local.py", "sub/kept.py"]);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::loc::LineCounter;
+use crate::scanner::{split_lines, ValidationResult};
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::path::Path;
+
+// Lines allowed above a header: a shebang, a PHP open tag or an XML
+// declaration (a Python encoding line is a comment, so part of the header)
+fn is_preamble(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("#!") || line.starts_with("<?php") || line.starts_with("<?xml")
+}
+
+// A comment without its delimiters
+fn comment_text(comment: &str) -> &str {
+    let comment = comment.trim();
+    let comment = ["*/", "-->", "\"\"\"", "'''"].iter().find_map(|close| comment.strip_suffix(close)).unwrap_or(comment);
+    comment.trim_start_matches(['#', '/', '*', '-', '<', '!', ';', '"', '\'']).trim()
+}
+
+// The comments opening the file, as (line of the first, text with one line
+// per comment line), up to the first line of code or `max_lines`
+fn header(language: &str, bytes: &[u8], max_lines: usize) -> Option<(usize, String)> {
+    let mut counter = LineCounter::new(language);
+    let mut first = None;
+    let mut text = Vec::new();
+    for (ln, line) in split_lines(bytes).into_iter().take(max_lines).enumerate() {
+        let line = String::from_utf8_lossy(line);
+        if line.trim().is_empty() || (ln == 0 && is_preamble(&line)) {
+            continue;
+        }
+        let spans = counter.comment_spans(&line);
+        let mut outside = line.to_string();
+        for &(start, end) in spans.iter().rev() {
+            outside.replace_range(start..end, "");
+        }
+        if !outside.trim().is_empty() {
+            break;
+        }
+        first.get_or_insert(ln + 1);
+        text.extend(spans.iter().map(|&(start, end)| comment_text(&line[start..end]).to_string()));
+    }
+    first.map(|first| (first, text.join("\n")))
+}
+
+fn file_license(file_path: &str, patterns: &[Regex], max_lines: usize) -> Vec<ValidationResult> {
+    let Ok(bytes) = std::fs::read(file_path) else { return Vec::new() };
+    let language = detect_language(Path::new(file_path), &bytes);
+    // Formats with no comments cannot carry a header
+    if matches!(language.as_str(), "markdown" | "json" | "unknown") {
+        return Vec::new();
+    }
+    let result = match header(&language, &bytes, max_lines) {
+        None => ValidationResult::finding(
+            "missing_license_header",
+            file_path,
+            format!("No license header comment in the first {} lines", max_lines),
+            1,
+            String::new(),
+        ),
+        Some((_, text)) if patterns.iter().any(|pattern| pattern.is_match(&text)) => return Vec::new(),
+        Some((line, text)) => ValidationResult::finding(
+            "license_header_mismatch",
+            file_path,
+            "The header comment matches none of the required license headers".to_string(),
+            line,
+            text.lines().find(|line| !line.is_empty()).unwrap_or_default().to_string(),
+        ),
+    };
+    vec![result]
+}
+
+/// License header policy: each of `files` must open, within its first
+/// `max_lines` lines, with a comment matching one of
+/// `required_header_patterns` (regexes, searched in the header's text with
+/// comment delimiters stripped and one line per comment line, so
+/// `Copyright \(c\) \d{4} Acme` works whichever comment style the language
+/// uses). A shebang, PHP open tag or XML declaration may come first.
+///
+/// Reports "missing_license_header" for a file whose first code comes
+/// before any comment, and "license_header_mismatch" on the first line of
+/// a header that matches no pattern, in file order. Markdown, JSON and
+/// unknown file types are skipped. ValueError names an invalid pattern.
+#[pyfunction]
+#[pyo3(signature = (files, required_header_patterns, max_lines=20, progress=None, progress_every=100, cancel_token=None, threads=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn check_license_headers(
+    py: Python<'_>,
+    files: Vec<String>,
+    required_header_patterns: Vec<String>,
+    max_lines: usize,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<ValidationResult>> {
+    let patterns = required_header_patterns
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(|e| PyValueError::new_err(format!("License header pattern '{}': {}", pattern, e))))
+        .collect::<PyResult<Vec<Regex>>>()?;
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_license(path, &patterns, max_lines))
+}
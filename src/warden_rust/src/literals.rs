@@ -0,0 +1,288 @@
+use crate::ast::{get_language_parser, parse};
+use crate::encoding;
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+use tree_sitter::Node;
+
+/// A string literal in source code. Lines and columns are 1-based, columns
+/// count bytes, and the end is exclusive.
+#[pyclass]
+#[derive(Clone)]
+pub struct StringLiteral {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub end_line: usize,
+    #[pyo3(get)]
+    pub end_column: usize,
+    // The text between the quotes with escapes decoded; interpolations
+    // (`{name}` in an f-string, `${name}` in a template) are kept as written
+    #[pyo3(get)]
+    pub value: String,
+    // Whether `value` was cut to `max_len` characters
+    #[pyo3(get)]
+    pub truncated: bool,
+}
+
+#[pymethods]
+impl StringLiteral {
+    fn __repr__(&self) -> String {
+        format!("StringLiteral({}:{}:{} {:?})", self.file_path, self.line, self.column, self.value)
+    }
+}
+
+/// An absolute filesystem path written in a string literal. The position
+/// is the literal's, as in `StringLiteral`.
+#[pyclass]
+#[derive(Clone)]
+pub struct AbsolutePath {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub path: String,
+    // "unix" or "windows" (drive letter or UNC share)
+    #[pyo3(get)]
+    pub os: String,
+    // "home" (a user's directory), "temp", "system", "network" (a UNC
+    // share) or "other"
+    #[pyo3(get)]
+    pub classification: String,
+    // The literal's whole value
+    #[pyo3(get)]
+    pub value: String,
+}
+
+#[pymethods]
+impl AbsolutePath {
+    fn __repr__(&self) -> String {
+        format!("AbsolutePath({}:{}:{} {} {})", self.file_path, self.line, self.column, self.os, self.path)
+    }
+}
+
+pub(crate) fn is_string_literal(kind: &str) -> bool {
+    kind.ends_with("string_literal")
+        || matches!(kind, "string" | "template_string" | "encapsed_string" | "interpolated_string_expression" | "text_block")
+}
+
+// Decodes the escapes shared by the C family: `\n`, `\t`, `\r`, `\0`,
+// `\xHH`, `\uHHHH`, `\u{H...}` and an escaped quote or backslash. Others
+// are kept with their backslash, and an escaped newline is dropped.
+fn unescape(text: &str) -> String {
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            value.push(c);
+            break;
+        };
+        let mut hex = |digits: usize| {
+            let mut code = String::new();
+            let braced = chars.next_if_eq(&'{').is_some();
+            while let Some(digit) = chars.next_if(|d| d.is_ascii_hexdigit() && (braced || code.len() < digits)) {
+                code.push(digit);
+            }
+            if braced {
+                chars.next_if_eq(&'}');
+            }
+            u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+        };
+        let decoded = match escape {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' | '\'' | '"' | '`' | '$' => Some(escape),
+            'x' => hex(2),
+            'u' => hex(4),
+            '\n' => continue,
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => value.push(decoded),
+            None => {
+                value.push(c);
+                value.push(escape);
+            }
+        }
+    }
+    value
+}
+
+// The value of a literal written as `text`: prefixes such as `r`, `b`,
+// `f`, `@` and `$` and matching quotes (tripled, or with Rust's `#`s)
+// stripped, and escapes decoded unless the literal is raw
+pub(crate) fn literal_value(text: &str, kind: &str) -> String {
+    let quote = text.find(['"', '\'', '`']).unwrap_or(0);
+    let (prefix, rest) = text.split_at(quote);
+    let hashes = prefix.len() - prefix.trim_end_matches('#').len();
+    let rest = rest.strip_suffix(&"#".repeat(hashes)).unwrap_or(rest);
+    let delimiter = ["\"\"\"", "'''", "\"", "'", "`"].into_iter().find(|delimiter| rest.starts_with(delimiter));
+    let inner = match delimiter {
+        Some(delimiter) if rest.len() >= 2 * delimiter.len() && rest.ends_with(delimiter) => {
+            &rest[delimiter.len()..rest.len() - delimiter.len()]
+        }
+        Some(delimiter) => &rest[delimiter.len()..],
+        None => rest,
+    };
+    let raw = kind.contains("raw") || kind.contains("verbatim") || prefix.contains(['r', 'R', '@']);
+    if raw {
+        inner.to_string()
+    } else {
+        unescape(inner)
+    }
+}
+
+fn file_literals(path: &str, max_len: Option<usize>) -> Vec<StringLiteral> {
+    let Ok(content) = encoding::read_to_string(path) else { return vec![] };
+    let language = detect_language(Path::new(path), content.as_bytes());
+    let Some(grammar) = get_language_parser(&language) else { return vec![] };
+    let Some(tree) = parse(&content, &language, grammar, None) else { return vec![] };
+    let source = content.as_bytes();
+    let mut literals = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if !(node.is_named() && is_string_literal(node.kind())) {
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+            continue;
+        }
+        let mut value = literal_value(node.utf8_text(source).unwrap_or_default(), node.kind());
+        let cut = max_len.and_then(|max_len| value.char_indices().nth(max_len)).map(|(end, _)| end);
+        if let Some(end) = cut {
+            value.truncate(end);
+        }
+        let (start, end) = (node.start_position(), node.end_position());
+        literals.push(StringLiteral {
+            file_path: path.to_string(),
+            line: start.row + 1,
+            column: start.column + 1,
+            end_line: end.row + 1,
+            end_column: end.column + 1,
+            value,
+            truncated: cut.is_some(),
+        });
+    }
+    literals
+}
+
+// Unix paths under the usual top-level directories only, since API routes
+// such as "/users/1" look the same; each starts the value or follows a
+// separator, so the path of a URL is not taken for one
+fn unix_path_regex() -> &'static Regex {
+    static UNIX: OnceLock<Regex> = OnceLock::new();
+    UNIX.get_or_init(|| {
+        let roots = "home|Users|root|tmp|var|etc|opt|usr|srv|mnt|media|private|Volumes|Library|Applications|System|dev|proc|sys|bin|sbin|lib|lib64|run|snap|nix";
+        Regex::new(&format!(r#"(?:^|[\s=:'"(,;])(/(?:{})/[^\s'"`,;:()*?<>|]*)"#, roots)).unwrap()
+    })
+}
+
+fn windows_path_regex() -> &'static Regex {
+    static WINDOWS: OnceLock<Regex> = OnceLock::new();
+    WINDOWS.get_or_init(|| Regex::new(r#"(?:^|[^A-Za-z0-9])([A-Za-z]:[\\/][^\s'"`,;*?<>|]*|\\\\[A-Za-z0-9._$\-]+\\[^\s'"`,;*?<>|]+)"#).unwrap())
+}
+
+fn classify_unix(path: &str) -> &'static str {
+    let starts = |prefixes: &[&str]| prefixes.iter().any(|prefix| path.starts_with(prefix));
+    if starts(&["/home/", "/Users/", "/root/"]) {
+        "home"
+    } else if starts(&["/tmp/", "/var/tmp/", "/private/tmp/", "/dev/shm/"]) {
+        "temp"
+    } else {
+        "system"
+    }
+}
+
+fn classify_windows(path: &str) -> &'static str {
+    let path = path.replace('/', "\\").to_ascii_lowercase();
+    let rest = path.get(2..).unwrap_or_default();
+    if path.starts_with("\\\\") {
+        "network"
+    } else if rest.contains("\\temp\\") || rest.starts_with("\\tmp\\") {
+        "temp"
+    } else if rest.starts_with("\\users\\") || rest.starts_with("\\documents and settings\\") {
+        "home"
+    } else if ["\\windows", "\\program files", "\\programdata"].iter().any(|dir| rest.starts_with(dir)) {
+        "system"
+    } else {
+        "other"
+    }
+}
+
+fn file_absolute_paths(path: &str) -> Vec<AbsolutePath> {
+    let mut found = Vec::new();
+    for literal in file_literals(path, None) {
+        let unix = unix_path_regex().captures_iter(&literal.value).map(|c| ("unix", c[1].to_string()));
+        let windows = windows_path_regex().captures_iter(&literal.value).map(|c| ("windows", c[1].to_string()));
+        for (os, absolute) in unix.chain(windows).collect::<Vec<_>>() {
+            let classification = if os == "unix" { classify_unix(&absolute) } else { classify_windows(&absolute) };
+            found.push(AbsolutePath {
+                file_path: literal.file_path.clone(),
+                line: literal.line,
+                column: literal.column,
+                path: absolute,
+                os: os.to_string(),
+                classification: classification.to_string(),
+                value: literal.value.clone(),
+            });
+        }
+    }
+    found
+}
+
+/// Every string literal in `files`, in file and then position order, for
+/// checks that should look at strings only and not at comments or code.
+/// The language is detected from the extension; files with no grammar are
+/// skipped. A literal made of parts, such as Python's implicit
+/// concatenation, is reported part by part, and strings nested in an
+/// interpolation are part of the enclosing literal's value. `max_len`
+/// caps each value at that many characters.
+#[pyfunction]
+#[pyo3(signature = (files, max_len=None, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn extract_strings(
+    py: Python<'_>,
+    files: Vec<String>,
+    max_len: Option<usize>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<StringLiteral>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_literals(path, max_len))
+}
+
+/// Absolute filesystem paths hardcoded in string literals of `files`, in
+/// file and then position order: Unix paths under a standard top-level
+/// directory (`/home/...`, `/tmp/...`, `/etc/...`), Windows drive paths
+/// (`C:\Users\...`, `D:/data`) and UNC shares. Only literals are looked
+/// at, so comments and the paths of URLs do not count; a literal holding
+/// several paths, such as a `PATH` value, yields each of them.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_absolute_paths(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<AbsolutePath>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_absolute_paths(path))
+}
@@ -0,0 +1,241 @@
+// Classifies lines as code, comment or blank the way tokei does: a line with
+// any code on it is code, a line holding nothing but comments is a comment,
+// and a whitespace-only line is blank, even inside a block comment.
+
+// How a language writes comments
+struct CommentSyntax {
+    line: &'static [&'static str],
+    block: &'static [(&'static str, &'static str)],
+    // Blocks that are only comments when they open a line: Python
+    // docstrings, Ruby's `=begin`/`=end`
+    leading_block: &'static [(&'static str, &'static str)],
+    quotes: &'static [char],
+}
+
+const C_LIKE: CommentSyntax = CommentSyntax { line: &["//"], block: &[("/*", "*/")], leading_block: &[], quotes: &['"', '\''] };
+
+fn comment_syntax(language: &str) -> CommentSyntax {
+    match language {
+        "python" => CommentSyntax {
+            line: &["#"],
+            block: &[],
+            leading_block: &[("\"\"\"", "\"\"\""), ("'''", "'''")],
+            quotes: &['"', '\''],
+        },
+        // `'` starts lifetimes as well as character literals
+        "rust" => CommentSyntax { quotes: &['"'], ..C_LIKE },
+        "php" => CommentSyntax { line: &["//", "#"], ..C_LIKE },
+        "ruby" => CommentSyntax { line: &["#"], block: &[], leading_block: &[("=begin", "=end")], quotes: &['"', '\''] },
+        "shell" | "yaml" | "dockerfile" | "makefile" => CommentSyntax { line: &["#"], block: &[], leading_block: &[], quotes: &['"', '\''] },
+        "sql" => CommentSyntax { line: &["--"], ..C_LIKE },
+        "markdown" | "json" | "unknown" => CommentSyntax { line: &[], block: &[], leading_block: &[], quotes: &[] },
+        _ => C_LIKE,
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct LineCounts {
+    pub lines: usize,
+    pub code: usize,
+    pub comment: usize,
+    pub blank: usize,
+}
+
+// How the lines of some text end: `\r\n` or a bare `\n`
+#[derive(Clone, Copy, Default)]
+pub(crate) struct LineEndings {
+    pub lf: usize,
+    pub crlf: usize,
+}
+
+impl LineEndings {
+    pub fn count(text: &[u8]) -> Self {
+        let lines = text.iter().filter(|&&b| b == b'\n').count();
+        let crlf = text.windows(2).filter(|pair| pair == b"\r\n").count();
+        LineEndings { lf: lines - crlf, crlf }
+    }
+
+    // "lf", "crlf" or "mixed", None for text without a line ending
+    pub fn kind(&self) -> Option<&'static str> {
+        match (self.lf, self.crlf) {
+            (0, 0) => None,
+            (_, 0) => Some("lf"),
+            (0, _) => Some("crlf"),
+            _ => Some("mixed"),
+        }
+    }
+}
+
+// How a line is indented: `Some('\t')` or `Some(' ')` by its first
+// character, None when it is not indented or blank
+pub(crate) fn indentation(line: &str) -> Option<char> {
+    line.chars().next().filter(|c| matches!(c, '\t' | ' ')).filter(|_| !line.trim().is_empty())
+}
+
+// Whether a line, its ending already stripped, ends in spaces or tabs
+pub(crate) fn has_trailing_whitespace(line: &str) -> bool {
+    line.ends_with([' ', '\t'])
+}
+
+// Whitespace style of some lines: trailing whitespace, tab and space indents
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Whitespace {
+    pub trailing: usize,
+    pub tabs: usize,
+    pub spaces: usize,
+}
+
+impl Whitespace {
+    pub fn count(&mut self, line: &str) {
+        self.trailing += usize::from(has_trailing_whitespace(line));
+        match indentation(line) {
+            Some('\t') => self.tabs += 1,
+            Some(_) => self.spaces += 1,
+            None => {}
+        }
+    }
+
+    // "tabs", "spaces" or "mixed", None when no line is indented
+    pub fn indent_style(&self) -> Option<&'static str> {
+        match (self.tabs, self.spaces) {
+            (0, 0) => None,
+            (_, 0) => Some("tabs"),
+            (0, _) => Some("spaces"),
+            _ => Some("mixed"),
+        }
+    }
+}
+
+// The next comment delimiter or quote in a line
+enum Token {
+    Line,
+    Block(&'static str),
+    Quote(char),
+}
+
+pub(crate) struct LineCounter {
+    syntax: CommentSyntax,
+    // The delimiter closing the block comment the last line left open
+    open_block: Option<&'static str>,
+    pub counts: LineCounts,
+}
+
+impl LineCounter {
+    pub fn new(language: &str) -> Self {
+        LineCounter { syntax: comment_syntax(language), open_block: None, counts: LineCounts::default() }
+    }
+
+    // The first token in `text`, as (byte position, token, length)
+    fn next_token(&self, text: &str, line_start: bool) -> Option<(usize, Token, usize)> {
+        let mut best: Option<(usize, Token, usize)> = None;
+        let mut consider = |position: Option<usize>, token: Token, len: usize| {
+            if let Some(position) = position {
+                if best.as_ref().is_none_or(|(b, _, _)| position < *b) {
+                    best = Some((position, token, len));
+                }
+            }
+        };
+        for (open, close) in self.syntax.block {
+            consider(text.find(open), Token::Block(close), open.len());
+        }
+        if line_start {
+            for (open, close) in self.syntax.leading_block {
+                consider(text.starts_with(open).then_some(0), Token::Block(close), open.len());
+            }
+        }
+        for marker in self.syntax.line {
+            consider(text.find(marker), Token::Line, marker.len());
+        }
+        for &quote in self.syntax.quotes {
+            consider(text.find(quote), Token::Quote(quote), quote.len_utf8());
+        }
+        best
+    }
+
+    pub fn count(&mut self, line: &str) {
+        self.counts.lines += 1;
+        if line.trim().is_empty() {
+            self.counts.blank += 1;
+            return;
+        }
+        let (code, comments) = self.scan(line);
+        if code {
+            self.counts.code += 1;
+        } else if !comments.is_empty() {
+            self.counts.comment += 1;
+        } else {
+            self.counts.blank += 1;
+        }
+    }
+
+    /// The byte ranges of `line` inside comments, delimiters included, for
+    /// languages read without a grammar. Lines must come in order, as for
+    /// `count`, which this does not update.
+    pub fn comment_spans(&mut self, line: &str) -> Vec<(usize, usize)> {
+        self.scan(line).1
+    }
+
+    // Whether `line` holds code, and its comments' byte ranges
+    fn scan(&mut self, line: &str) -> (bool, Vec<(usize, usize)>) {
+        let line = line.trim_end();
+        let mut rest = line.trim_start();
+        let offset = |rest: &str| line.len() - rest.len();
+        let line_start = self.open_block.is_none();
+        let mut code = false;
+        let mut comments = Vec::new();
+        // Where the block comment opened on this line starts, its opening
+        // delimiter included
+        let mut opened_at = None;
+        loop {
+            if let Some(close) = self.open_block {
+                let start = opened_at.take().unwrap_or(offset(rest));
+                match rest.find(close) {
+                    Some(end) => {
+                        rest = &rest[end + close.len()..];
+                        self.open_block = None;
+                        comments.push((start, offset(rest)));
+                    }
+                    None => {
+                        comments.push((start, line.len()));
+                        break;
+                    }
+                }
+                continue;
+            }
+            let at_start = line_start && !code && comments.is_empty();
+            let Some((position, token, len)) = self.next_token(rest, at_start) else {
+                code |= !rest.trim().is_empty();
+                break;
+            };
+            code |= !rest[..position].trim().is_empty();
+            let start = offset(rest) + position;
+            rest = &rest[position + len..];
+            match token {
+                Token::Line => {
+                    comments.push((start, line.len()));
+                    break;
+                }
+                Token::Block(close) => {
+                    self.open_block = Some(close);
+                    opened_at = Some(start);
+                }
+                // Skips the string so delimiters inside it do not count; an
+                // unterminated one runs to the end of the line
+                Token::Quote(quote) => {
+                    code = true;
+                    let mut escaped = false;
+                    let end = rest.char_indices().find(|&(_, c)| {
+                        let closes = c == quote && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        closes
+                    });
+                    match end {
+                        Some((end, _)) => rest = &rest[end + quote.len_utf8()..],
+                        None => break,
+                    }
+                }
+            }
+        }
+        (code, comments)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::ast::{get_language_parser, parse};
+use crate::encoding;
+use crate::loc::LineCounter;
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::path::Path;
+
+/// A marker such as `TODO` or `FIXME` in a comment. `line_number` and
+/// `column` are 1-based, the column counting bytes from the start of the
+/// line.
+#[pyclass]
+#[derive(Clone)]
+pub struct Marker {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line_number: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    // The marker as given to `extract_markers`
+    #[pyo3(get)]
+    pub marker: String,
+    // The rest of the comment line, without the separator after the marker
+    // and any closing delimiter
+    #[pyo3(get)]
+    pub text: String,
+    // `alice` in `TODO(alice)` or `TODO @alice`
+    #[pyo3(get)]
+    pub author: Option<String>,
+}
+
+#[pymethods]
+impl Marker {
+    fn __repr__(&self) -> String {
+        format!("Marker({}:{} {} {:?})", self.file_path, self.line_number, self.marker, self.text)
+    }
+}
+
+const DEFAULT_MARKERS: &[&str] = &["TODO", "FIXME", "HACK", "XXX"];
+
+// A marker as a whole word, then an optional author and separator
+fn marker_regex(markers: &[String]) -> Result<Regex, regex::Error> {
+    let alternatives: Vec<String> = markers.iter().map(|marker| regex::escape(marker)).collect();
+    Regex::new(&format!(r"\b({})\b(?:\(([^)]*)\)|\s*@([\w.\-]+))?[:\s\-]*(.*)", alternatives.join("|")))
+}
+
+// `*/`, `-->` and the like left at the end of a comment's text
+fn strip_closing(text: &str) -> &str {
+    let text = text.trim_end();
+    ["*/", "-->", "#}", "%>"].iter().find_map(|close| text.strip_suffix(close)).unwrap_or(text).trim()
+}
+
+fn comment_markers(file_path: &str, regex: &Regex, line_number: usize, column: usize, comment: &str, out: &mut Vec<Marker>) {
+    for c in regex.captures_iter(comment) {
+        let whole = c.get(0).unwrap();
+        let author = c.get(2).or(c.get(3)).map(|m| m.as_str().trim().to_string()).filter(|author| !author.is_empty());
+        out.push(Marker {
+            file_path: file_path.to_string(),
+            line_number,
+            column: column + whole.start() + 1,
+            marker: c[1].to_string(),
+            text: strip_closing(&c[4]).to_string(),
+            author,
+        });
+    }
+}
+
+fn file_markers(file_path: &str, regex: &Regex) -> Vec<Marker> {
+    let Ok(content) = encoding::read_to_string(file_path) else { return Vec::new() };
+    let language = detect_language(Path::new(file_path), content.as_bytes());
+    let mut out = Vec::new();
+    let Some(tree) = get_language_parser(&language).and_then(|grammar| parse(&content, &language, grammar, None)) else {
+        // No grammar: comments as the line counter finds them
+        let mut counter = LineCounter::new(&language);
+        for (ln, line) in content.lines().enumerate() {
+            for (start, end) in counter.comment_spans(line) {
+                comment_markers(file_path, regex, ln + 1, start, &line[start..end], &mut out);
+            }
+        }
+        return out;
+    };
+    let source = content.as_bytes();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if !node.kind().contains("comment") {
+            let mut cursor = node.walk();
+            let children: Vec<_> = node.children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+            continue;
+        }
+        let start = node.start_position();
+        let text = node.utf8_text(source).unwrap_or_default();
+        for (i, line) in text.lines().enumerate() {
+            let column = if i == 0 { start.column } else { 0 };
+            comment_markers(file_path, regex, start.row + i + 1, column, line, &mut out);
+        }
+    }
+    out
+}
+
+/// Markers in the comments of `files`, in file and then line order:
+/// `markers` (default TODO, FIXME, HACK and XXX) as whole, case-sensitive
+/// words, so a marker in a string literal or an identifier is not
+/// reported. Comments are found with the language's grammar, or for
+/// languages without one (shell, YAML, SQL) by their comment syntax as in
+/// `get_file_stats` line counts.
+///
+/// The author is taken from `TODO(alice)` or `TODO @alice`; the text is the
+/// rest of the line, so a marker's note continuing on the next comment line
+/// is cut there.
+#[pyfunction]
+#[pyo3(signature = (files, markers=None, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn extract_markers(
+    py: Python<'_>,
+    files: Vec<String>,
+    markers: Option<Vec<String>>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<Marker>> {
+    let markers = markers.unwrap_or_else(|| DEFAULT_MARKERS.iter().map(|marker| marker.to_string()).collect());
+    if markers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let regex = marker_regex(&markers).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_markers(path, &regex))
+}
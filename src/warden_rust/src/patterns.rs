@@ -0,0 +1,279 @@
+use crate::ast::{get_language_parser, parse};
+use crate::encoding;
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+use tree_sitter::{Node, Query, QueryCursor};
+
+/// A structural rule for `match_ast_patterns`: a code snippet in
+/// `language` that matches code with the same syntax tree.
+///
+/// `$NAME` (upper case) is a metavariable matching any one expression,
+/// identifier or statement, reported in the hit's `bindings`; using the same
+/// metavariable twice requires the same code in both places. `...` matches
+/// any run of arguments, elements or statements, including none. Otherwise
+/// the match is exact: `subprocess.run($CMD, shell=True)` does not match a
+/// call with a third argument, while `subprocess.run(..., shell=True)` does.
+#[pyclass]
+#[derive(Clone)]
+pub struct AstPattern {
+    #[pyo3(get, set)]
+    pub id: String,
+    #[pyo3(get, set)]
+    pub language: String,
+    #[pyo3(get, set)]
+    pub pattern: String,
+    // Copied verbatim into every hit of the pattern
+    #[pyo3(get, set)]
+    pub severity: Option<String>,
+    #[pyo3(get, set)]
+    pub category: Option<String>,
+}
+
+#[pymethods]
+impl AstPattern {
+    #[new]
+    #[pyo3(signature = (id, language, pattern, severity=None, category=None))]
+    fn new(id: String, language: String, pattern: String, severity: Option<String>, category: Option<String>) -> Self {
+        AstPattern { id, language, pattern, severity, category }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("AstPattern({} [{}] {})", self.id, self.language, self.pattern)
+    }
+}
+
+/// Code matching an `AstPattern`. Lines and columns are 1-based, columns
+/// count bytes, and the end is exclusive.
+#[pyclass]
+#[derive(Clone)]
+pub struct AstPatternHit {
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub end_line: usize,
+    #[pyo3(get)]
+    pub end_column: usize,
+    // First line of the matched code
+    #[pyo3(get)]
+    pub snippet: String,
+    // Metavariable name (without `$`) to the code it matched
+    #[pyo3(get)]
+    pub bindings: HashMap<String, String>,
+    #[pyo3(get)]
+    pub severity: Option<String>,
+    #[pyo3(get)]
+    pub category: Option<String>,
+}
+
+#[pymethods]
+impl AstPatternHit {
+    fn __repr__(&self) -> String {
+        format!("AstPatternHit({} {}:{}:{})", self.rule_id, self.file_path, self.line, self.column)
+    }
+}
+
+// Identifiers standing in for metavariables and `...` while the pattern is
+// parsed, valid in every grammar
+const METAVARIABLE_PREFIX: &str = "__warden_mv_";
+const ELLIPSIS: &str = "__warden_ellipsis";
+
+// Separators that vary with the number of elements, and so with `...`
+const PUNCTUATION: &[&str] = &["(", ")", "[", "]", "{", "}", ",", ";"];
+
+// Text around a snippet that makes it a whole file, for the grammars that
+// do not take expressions at the top level
+fn wrappers(language: &str) -> &'static [(&'static str, &'static str)] {
+    match language {
+        "go" => &[("package p\nfunc f() {\n", "\n}"), ("package p\n", "")],
+        "java" | "csharp" => &[("class C { void f() {\n", ";\n} }"), ("class C { void f() {\n", "\n} }"), ("class C {\n", "\n}")],
+        "rust" => &[("fn f() {\n", ";\n}"), ("fn f() {\n", "\n}")],
+        "c" | "cpp" => &[("void f() {\n", ";\n}"), ("void f() {\n", "\n}")],
+        "php" => &[("<?php\n", ";"), ("<?php\n", "")],
+        "kotlin" => &[("fun f() {\n", "\n}")],
+        _ => &[],
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Turns a pattern's syntax tree into an anchored tree-sitter query
+struct Compiler<'s> {
+    source: &'s [u8],
+    predicates: Vec<String>,
+    captures: usize,
+}
+
+impl Compiler<'_> {
+    fn node(&mut self, node: Node) -> String {
+        let text = node.utf8_text(self.source).unwrap_or_default();
+        let metavariable = text.strip_prefix(METAVARIABLE_PREFIX)
+            .filter(|name| name.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_'));
+        if let Some(name) = metavariable {
+            return format!("(_) @{}", name);
+        }
+        if node.named_child_count() == 0 {
+            let capture = format!("_{}", self.captures);
+            self.captures += 1;
+            self.predicates.push(format!("(#eq? @{} \"{}\")", capture, escape(text)));
+            return format!("({}) @{}", node.kind(), capture);
+        }
+        // Every named child is anchored to the one before it (or to the
+        // start and end) unless a `...` comes between
+        let mut parts = Vec::new();
+        let mut anchored = true;
+        let mut cursor = node.walk();
+        let mut more = cursor.goto_first_child();
+        while more {
+            let child = cursor.node();
+            let field = cursor.field_name();
+            more = cursor.goto_next_sibling();
+            if child.is_extra() || (!child.is_named() && PUNCTUATION.contains(&child.kind())) {
+                continue;
+            }
+            if !child.is_named() {
+                parts.push(format!("\"{}\"", escape(child.kind())));
+                continue;
+            }
+            if child.utf8_text(self.source) == Ok(ELLIPSIS) {
+                anchored = false;
+                continue;
+            }
+            if anchored {
+                parts.push(".".to_string());
+            }
+            let pattern = self.node(child);
+            parts.push(match field {
+                Some(field) => format!("{}: {}", field, pattern),
+                None => pattern,
+            });
+            anchored = true;
+        }
+        if anchored {
+            parts.push(".".to_string());
+        }
+        format!("({} {})", node.kind(), parts.join(" "))
+    }
+}
+
+// A pattern ready to run against files of its language
+struct Compiled {
+    pattern: AstPattern,
+    query: Query,
+}
+
+fn compile(pattern: &AstPattern) -> PyResult<Compiled> {
+    let fail = |reason: &str| PyValueError::new_err(format!("AST pattern '{}': {}", pattern.id, reason));
+    let grammar = get_language_parser(&pattern.language)
+        .ok_or_else(|| fail(&format!("no grammar for language '{}'", pattern.language)))?;
+    static METAVARIABLE: OnceLock<Regex> = OnceLock::new();
+    let metavariable = METAVARIABLE.get_or_init(|| Regex::new(r"\$([A-Z_][A-Z0-9_]*)").unwrap());
+    let snippet = metavariable.replace_all(pattern.pattern.trim(), format!("{}$1", METAVARIABLE_PREFIX).as_str())
+        .replace("...", ELLIPSIS);
+    for &(prefix, suffix) in [("", "")].iter().chain(wrappers(&pattern.language)) {
+        let source = format!("{}{}{}", prefix, snippet, suffix);
+        let Some(tree) = parse(&source, &pattern.language, grammar, None) else { continue };
+        if tree.root_node().has_error() {
+            continue;
+        }
+        let (start, end) = (prefix.len(), prefix.len() + snippet.len());
+        let Some(root) = tree.root_node().named_descendant_for_byte_range(start, end) else { continue };
+        if (root.start_byte(), root.end_byte()) != (start, end) {
+            continue;
+        }
+        let mut compiler = Compiler { source: source.as_bytes(), predicates: Vec::new(), captures: 0 };
+        let root = compiler.node(root);
+        let query_source = format!("({} @_match {})", root, compiler.predicates.join(" "));
+        let query = Query::new(grammar, &query_source).map_err(|e| fail(&e.message))?;
+        return Ok(Compiled { pattern: pattern.clone(), query });
+    }
+    Err(fail(&format!("does not parse as a single {} expression, statement or definition", pattern.language)))
+}
+
+fn file_hits(path: &str, patterns: &[&Compiled]) -> Vec<AstPatternHit> {
+    let Ok(content) = encoding::read_to_string(path) else { return vec![] };
+    let language = detect_language(Path::new(path), content.as_bytes());
+    let Some(grammar) = get_language_parser(&language) else { return vec![] };
+    let Some(tree) = parse(&content, &language, grammar, None) else { return vec![] };
+    let source = content.as_bytes();
+    let mut hits = Vec::new();
+    for compiled in patterns.iter().filter(|compiled| compiled.pattern.language == language) {
+        let query = &compiled.query;
+        let mut seen = HashSet::new();
+        let mut cursor = QueryCursor::new();
+        for m in cursor.matches(query, tree.root_node(), source) {
+            let mut matched = None;
+            let mut bindings: HashMap<String, String> = HashMap::new();
+            let mut consistent = true;
+            for capture in m.captures {
+                let name = &query.capture_names()[capture.index as usize];
+                let text = capture.node.utf8_text(source).unwrap_or_default();
+                if name == "_match" {
+                    matched = Some(capture.node);
+                } else if !name.starts_with('_') {
+                    let bound = bindings.entry(name.clone()).or_insert_with(|| text.to_string());
+                    consistent &= bound == text;
+                }
+            }
+            let Some(node) = matched.filter(|node| consistent && seen.insert(node.id())) else { continue };
+            let (start, end) = (node.start_position(), node.end_position());
+            let snippet = node.utf8_text(source).unwrap_or_default().lines().next().unwrap_or_default();
+            let snippet = match snippet.char_indices().nth(200) {
+                Some((end, _)) => format!("{}...", &snippet[..end]),
+                None => snippet.to_string(),
+            };
+            hits.push(AstPatternHit {
+                rule_id: compiled.pattern.id.clone(),
+                file_path: path.to_string(),
+                line: start.row + 1,
+                column: start.column + 1,
+                end_line: end.row + 1,
+                end_column: end.column + 1,
+                snippet,
+                bindings,
+                severity: compiled.pattern.severity.clone(),
+                category: compiled.pattern.category.clone(),
+            });
+        }
+    }
+    hits.sort_by_key(|hit| (hit.line, hit.column));
+    hits
+}
+
+/// Runs structural `patterns` over `files`, each pattern over the files of
+/// its language as detected from the extension, in parallel with the GIL
+/// released. Returns the hits in file and then position order; a node
+/// matching a pattern in several ways is reported once, with the first
+/// bindings found.
+///
+/// Every pattern is compiled up front: ValueError names the first one whose
+/// language has no grammar or whose snippet does not parse as one piece of
+/// code in it.
+#[pyfunction]
+#[pyo3(signature = (files, patterns, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn match_ast_patterns(
+    py: Python<'_>,
+    files: Vec<String>,
+    patterns: Vec<AstPattern>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<AstPatternHit>> {
+    let compiled = patterns.iter().map(compile).collect::<PyResult<Vec<Compiled>>>()?;
+    let compiled: Vec<&Compiled> = compiled.iter().collect();
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_hits(path, &compiled))
+}
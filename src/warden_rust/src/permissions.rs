@@ -0,0 +1,79 @@
+// Permission hygiene from POSIX mode bits: files any user may rewrite,
+// files that run with their owner's or group's rights, and executable text
+// the kernel cannot run for want of an interpreter line
+
+use crate::encoding;
+use crate::scanner::{split_lines, ValidationResult};
+use crate::{CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use std::fs::{File, Metadata};
+use std::io::Read;
+
+/// The permission bits of `metadata`, setuid, setgid and sticky included
+/// (0o4755 and the like); None off Unix, where there are none.
+pub(crate) fn mode_bits(metadata: &Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+fn file_permissions(file_path: &str) -> Vec<ValidationResult> {
+    let Ok(metadata) = std::fs::metadata(file_path) else { return Vec::new() };
+    let Some(mode) = mode_bits(&metadata).filter(|_| metadata.is_file()) else { return Vec::new() };
+    let mut findings = Vec::new();
+    let mut report = |rule_id: &str, message: &str, line: usize, snippet: String| {
+        findings.push(ValidationResult::finding(rule_id, file_path, format!("{} (mode {:04o})", message, mode), line, snippet));
+    };
+    if mode & 0o002 != 0 {
+        report("world_writable_file", "File is writable by every user", 0, String::new());
+    }
+    if mode & 0o4000 != 0 {
+        report("setuid_file", "File runs with its owner's rights (setuid)", 0, String::new());
+    }
+    if mode & 0o2000 != 0 {
+        report("setgid_file", "File runs with its group's rights (setgid)", 0, String::new());
+    }
+    if mode & 0o111 != 0 && metadata.len() > 0 {
+        let mut head = Vec::with_capacity(1024);
+        if let Ok(file) = File::open(file_path) {
+            let _ = file.take(1024).read_to_end(&mut head);
+        }
+        // Compiled executables need no interpreter line
+        if !head.is_empty() && !encoding::is_binary(&head) {
+            let (text, _) = encoding::decode(&head);
+            if !text.starts_with(b"#!") {
+                let first = split_lines(&text).first().map(|line| String::from_utf8_lossy(line).into_owned());
+                report("executable_without_shebang", "Executable text file has no #! interpreter line", 1, first.unwrap_or_default());
+            }
+        }
+    }
+    findings
+}
+
+/// Permission hygiene of `files`, from their POSIX mode bits, in file
+/// order: "world_writable_file" when any user may write it,
+/// "setuid_file" and "setgid_file" for the set-ID bits, and
+/// "executable_without_shebang" on line 1 of a non-empty text file with an
+/// execute bit set that does not open with `#!` (binaries are fine). The
+/// message ends in the octal mode. Symlinks are judged by their target;
+/// directories, missing paths and every file off Unix yield nothing.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn check_file_permissions(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<ValidationResult>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_permissions(path))
+}
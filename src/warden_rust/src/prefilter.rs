@@ -0,0 +1,84 @@
+use aho_corasick::AhoCorasick;
+use regex::RegexSet;
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+
+/// Cheap "which rules could possibly match this line" check.
+///
+/// Most rules contain literal text that every match must start or end with
+/// (`api_key`, `BEGIN RSA`, `eval(`). Those literals go into one Aho-Corasick
+/// automaton so a line that contains none of them never reaches the regex
+/// engine. Rules without a usable literal are checked with a RegexSet instead.
+pub(crate) struct LiteralPrefilter {
+    automaton: Option<AhoCorasick>,
+    // Automaton pattern id -> rule index
+    literal_rules: Vec<usize>,
+    // Rules with no extractable literal, and a set over exactly those
+    unfiltered: Vec<usize>,
+    unfiltered_set: Option<RegexSet>,
+}
+
+impl LiteralPrefilter {
+    pub(crate) fn new(patterns: &[String]) -> Self {
+        let mut literals = Vec::new();
+        let mut literal_rules = Vec::new();
+        let mut unfiltered = Vec::new();
+
+        for (idx, pattern) in patterns.iter().enumerate() {
+            match required_literals(pattern) {
+                Some(lits) => {
+                    literal_rules.extend(std::iter::repeat_n(idx, lits.len()));
+                    literals.extend(lits);
+                }
+                None => unfiltered.push(idx),
+            }
+        }
+
+        let automaton = if literals.is_empty() {
+            None
+        } else {
+            AhoCorasick::new(&literals).ok()
+        };
+        // Without an automaton every rule has to go through the regex engine
+        if automaton.is_none() && !literal_rules.is_empty() {
+            unfiltered = (0..patterns.len()).collect();
+            literal_rules.clear();
+        }
+        let unfiltered_set = RegexSet::new(unfiltered.iter().map(|&idx| &patterns[idx])).ok();
+
+        LiteralPrefilter { automaton, literal_rules, unfiltered, unfiltered_set }
+    }
+
+    /// Rule indices that may match `line`, sorted and deduplicated. Rules not
+    /// listed are guaranteed not to match.
+    pub(crate) fn candidates(&self, line: &str) -> Vec<usize> {
+        let mut out = Vec::new();
+        if let Some(automaton) = &self.automaton {
+            out.extend(
+                automaton.find_overlapping_iter(line)
+                    .map(|m| self.literal_rules[m.pattern().as_usize()]),
+            );
+        }
+        match &self.unfiltered_set {
+            Some(set) => out.extend(set.matches(line).into_iter().map(|i| self.unfiltered[i])),
+            None => out.extend_from_slice(&self.unfiltered),
+        }
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+}
+
+// Literals of which at least one occurs in every match of `pattern`, taken
+// from the match prefixes or, failing that, the suffixes. None when no such
+// finite, non-empty set exists (e.g. the pattern starts and ends with `\w+`).
+fn required_literals(pattern: &str) -> Option<Vec<Vec<u8>>> {
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    [ExtractKind::Prefix, ExtractKind::Suffix].into_iter().find_map(|kind| {
+        let seq = Extractor::new().kind(kind).extract(&hir);
+        let lits = seq.literals()?;
+        if lits.is_empty() || lits.iter().any(|lit| lit.as_bytes().is_empty()) {
+            return None;
+        }
+        Some(lits.iter().map(|lit| lit.as_bytes().to_vec()).collect())
+    })
+}
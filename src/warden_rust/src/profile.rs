@@ -0,0 +1,209 @@
+use crate::classify::{is_vendored, HEAD_BYTES};
+use crate::{build_walker, discovered_file, size_limit_bytes, stop_here, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::Path;
+
+/// What a project is built with, as found by `detect_project_profile`.
+#[pyclass]
+#[derive(Clone)]
+pub struct ProjectProfile {
+    #[pyo3(get)]
+    pub root_path: String,
+    // Number of files per detected language, "unknown" left out
+    #[pyo3(get)]
+    pub languages: HashMap<String, usize>,
+    // The language with the most files
+    #[pyo3(get)]
+    pub primary_language: Option<String>,
+    // Sorted framework names, such as "django", "react" or "spring"
+    #[pyo3(get)]
+    pub frameworks: Vec<String>,
+    // Framework to the first manifest or source file it was found in
+    #[pyo3(get)]
+    pub evidence: HashMap<String, String>,
+    // Sorted: "pip", "poetry", "pipenv", "uv", "npm", "yarn", "pnpm",
+    // "maven", "gradle", "go", "cargo", "bundler", "composer", "nuget"
+    #[pyo3(get)]
+    pub package_managers: Vec<String>,
+    // Manifest and lock files, relative to the root, sorted
+    #[pyo3(get)]
+    pub manifests: Vec<String>,
+}
+
+#[pymethods]
+impl ProjectProfile {
+    fn __repr__(&self) -> String {
+        format!("ProjectProfile({} {:?} {:?})", self.root_path, self.primary_language, self.frameworks)
+    }
+}
+
+// A framework, the names it goes by in manifests and the imports that use it
+struct Framework {
+    name: &'static str,
+    dependencies: &'static [&'static str],
+    imports: &'static [&'static str],
+}
+
+const FRAMEWORKS: &[Framework] = &[
+    Framework { name: "django", dependencies: &["django"], imports: &["from django", "import django"] },
+    Framework { name: "flask", dependencies: &["flask"], imports: &["from flask", "import flask"] },
+    Framework { name: "fastapi", dependencies: &["fastapi"], imports: &["from fastapi", "import fastapi"] },
+    Framework { name: "react", dependencies: &["\"react\""], imports: &["from 'react'", "from \"react\"", "require('react')"] },
+    Framework { name: "next", dependencies: &["\"next\""], imports: &["from 'next/", "from \"next/"] },
+    Framework { name: "vue", dependencies: &["\"vue\""], imports: &["from 'vue'", "from \"vue\""] },
+    Framework { name: "nuxt", dependencies: &["\"nuxt\""], imports: &["from 'nuxt", "from \"nuxt"] },
+    Framework { name: "angular", dependencies: &["\"@angular/core\""], imports: &["from '@angular/core'", "from \"@angular/core\""] },
+    Framework { name: "svelte", dependencies: &["\"svelte\""], imports: &["from 'svelte", "from \"svelte"] },
+    Framework { name: "express", dependencies: &["\"express\""], imports: &["require('express')", "require(\"express\")", "from 'express'", "from \"express\""] },
+    Framework { name: "nestjs", dependencies: &["\"@nestjs/core\""], imports: &["from '@nestjs/", "from \"@nestjs/"] },
+    Framework { name: "spring", dependencies: &["org.springframework"], imports: &["import org.springframework"] },
+    Framework { name: "gin", dependencies: &["github.com/gin-gonic/gin"], imports: &["\"github.com/gin-gonic/gin\""] },
+    Framework { name: "echo", dependencies: &["github.com/labstack/echo"], imports: &["\"github.com/labstack/echo"] },
+    Framework { name: "fiber", dependencies: &["github.com/gofiber/fiber"], imports: &["\"github.com/gofiber/fiber"] },
+    Framework { name: "actix", dependencies: &["actix-web"], imports: &["use actix_web"] },
+    Framework { name: "axum", dependencies: &["axum"], imports: &["use axum"] },
+    Framework { name: "rocket", dependencies: &["rocket"], imports: &["use rocket"] },
+    Framework { name: "rails", dependencies: &["rails"], imports: &["Rails.application", "< ApplicationController", "< ActiveRecord::Base"] },
+    Framework { name: "sinatra", dependencies: &["sinatra"], imports: &["require 'sinatra'", "require \"sinatra\""] },
+    Framework { name: "laravel", dependencies: &["laravel/framework"], imports: &["use Illuminate\\"] },
+    Framework { name: "symfony", dependencies: &["symfony/framework-bundle"], imports: &["use Symfony\\"] },
+    Framework { name: "aspnetcore", dependencies: &["microsoft.aspnetcore", "microsoft.net.sdk.web"], imports: &["using Microsoft.AspNetCore"] },
+];
+
+// The package manager a manifest or lock file belongs to, by its name
+fn package_manager(name: &str, content: &str) -> Option<&'static str> {
+    Some(match name {
+        "pyproject.toml" if content.contains("[tool.poetry") => "poetry",
+        "requirements.txt" | "setup.py" | "setup.cfg" | "pyproject.toml" => "pip",
+        "poetry.lock" => "poetry",
+        "Pipfile" | "Pipfile.lock" => "pipenv",
+        "uv.lock" => "uv",
+        "package.json" | "package-lock.json" => "npm",
+        "yarn.lock" => "yarn",
+        "pnpm-lock.yaml" => "pnpm",
+        "pom.xml" => "maven",
+        "build.gradle" | "build.gradle.kts" => "gradle",
+        "go.mod" => "go",
+        "Cargo.toml" => "cargo",
+        "Gemfile" | "Gemfile.lock" => "bundler",
+        "composer.json" => "composer",
+        _ if name.starts_with("requirements") && name.ends_with(".txt") => "pip",
+        _ if name.ends_with(".csproj") => "nuget",
+        _ => return None,
+    })
+}
+
+// Whether `needle` occurs in `text` as a whole name, not inside a longer one
+// such as "flask" in "flasket"
+fn mentions(text: &str, needle: &str) -> bool {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(needle).any(|(at, _)| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + needle.len()..].chars().next();
+        !before.is_some_and(is_name) && !after.is_some_and(is_name)
+    })
+}
+
+// What one file says about the project
+#[derive(Default)]
+struct FileFindings {
+    manifest: Option<&'static str>,
+    frameworks: Vec<&'static str>,
+}
+
+fn file_findings(path: &str) -> FileFindings {
+    let name = Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let Ok(file) = std::fs::File::open(path) else { return FileFindings::default() };
+    let mut bytes = Vec::new();
+    let is_manifest = package_manager(&name, "").is_some();
+    // Manifests are read whole, sources only as far as their imports go
+    let limit = if is_manifest { 1024 * 1024 } else { HEAD_BYTES as u64 };
+    if file.take(limit).read_to_end(&mut bytes).is_err() {
+        return FileFindings::default();
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    // Lock files list every transitive dependency, frameworks included
+    let is_lock = name.ends_with(".lock") || name.contains("-lock.");
+    if is_manifest {
+        if is_lock {
+            return FileFindings { manifest: package_manager(&name, &text), frameworks: Vec::new() };
+        }
+        let lowered = text.to_lowercase();
+        let frameworks = FRAMEWORKS.iter()
+            .filter(|framework| framework.dependencies.iter().any(|dependency| mentions(&lowered, dependency)))
+            .map(|framework| framework.name)
+            .collect();
+        return FileFindings { manifest: package_manager(&name, &text), frameworks };
+    }
+    let frameworks = FRAMEWORKS.iter()
+        .filter(|framework| framework.imports.iter().any(|import| text.contains(import)))
+        .map(|framework| framework.name)
+        .collect();
+    FileFindings { manifest: None, frameworks }
+}
+
+/// Profiles the project under `root_path` in one parallel pass: languages
+/// by file count, package managers and frameworks from the manifests
+/// (`requirements.txt`, `pyproject.toml`, `package.json`, `pom.xml`,
+/// `go.mod`, `Cargo.toml`, `Gemfile`, `composer.json`, `*.csproj`, ...)
+/// and from the imports near the top of each source file. Files are
+/// discovered as by `discover_files`; vendored ones are left out so a
+/// framework bundled in `node_modules/` does not count.
+#[pyfunction]
+#[pyo3(signature = (root_path, use_gitignore=true, max_size_mb=None, progress=None, progress_every=100, cancel_token=None, threads=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn detect_project_profile(
+    py: Python<'_>,
+    root_path: String,
+    use_gitignore: bool,
+    max_size_mb: Option<u64>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<ProjectProfile> {
+    let size_limit = size_limit_bytes(max_size_mb);
+    let files: Vec<(String, String)> = py.allow_threads(|| {
+        build_walker(&root_path, use_gitignore)
+            .take_while(|_| !stop_here(&cancel_token))
+            .flatten()
+            .filter_map(|entry| discovered_file(&entry, size_limit))
+            .filter(|(path, _, _)| !Path::new(path).strip_prefix(&root_path).is_ok_and(|relative| is_vendored(relative, &[])))
+            .map(|(path, _, language)| (path, language))
+            .collect()
+    });
+
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    let findings = control.run(py, &files, |(path, _)| Some((path.clone(), file_findings(path))))?;
+
+    let mut languages: HashMap<String, usize> = HashMap::new();
+    for (_, language) in files.iter().filter(|(_, language)| language != "unknown") {
+        *languages.entry(language.clone()).or_insert(0) += 1;
+    }
+    let primary_language = languages.iter().max_by_key(|&(language, count)| (count, std::cmp::Reverse(language))).map(|(language, _)| language.clone());
+
+    // Sorted by path, so the evidence kept for a framework does not depend
+    // on the order the workers finished in
+    let findings: BTreeMap<String, FileFindings> = findings.into_iter().collect();
+    let relative = |path: &str| Path::new(path).strip_prefix(&root_path).map_or(path.to_string(), |path| path.to_string_lossy().into_owned());
+    let mut evidence: HashMap<String, String> = HashMap::new();
+    let mut package_managers = Vec::new();
+    let mut manifests = Vec::new();
+    for (path, found) in &findings {
+        if let Some(manager) = found.manifest {
+            manifests.push(relative(path));
+            package_managers.push(manager.to_string());
+        }
+        for framework in &found.frameworks {
+            evidence.entry(framework.to_string()).or_insert_with(|| relative(path));
+        }
+    }
+    package_managers.sort();
+    package_managers.dedup();
+    manifests.sort();
+    let mut frameworks: Vec<String> = evidence.keys().cloned().collect();
+    frameworks.sort();
+    Ok(ProjectProfile { root_path, languages, primary_language, frameworks, evidence, package_managers, manifests })
+}
@@ -0,0 +1,185 @@
+use crate::read_file;
+use crate::scanner::split_lines;
+use pyo3::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// A file that moved between two scan snapshots.
+///
+/// `exact` renames kept their content hash and have `similarity` 1.0; the
+/// others are paired by how many lines they share.
+#[pyclass]
+#[derive(Clone)]
+pub struct Rename {
+    #[pyo3(get)]
+    pub old_path: String,
+    #[pyo3(get)]
+    pub new_path: String,
+    #[pyo3(get)]
+    pub similarity: f64,
+    #[pyo3(get)]
+    pub exact: bool,
+}
+
+#[pymethods]
+impl Rename {
+    fn __repr__(&self) -> String {
+        format!("Rename({} -> {} {:.2})", self.old_path, self.new_path, self.similarity)
+    }
+}
+
+// One `(path, hash, size)` listing entry
+type Entry = (String, String, u64);
+
+// How often each non-blank line occurs, by the hash of its trimmed text
+struct Lines {
+    counts: HashMap<u64, usize>,
+    total: usize,
+}
+
+impl Lines {
+    fn of(content: &[u8]) -> Self {
+        let mut counts = HashMap::new();
+        let mut total = 0;
+        for line in split_lines(content) {
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            let mut hasher = DefaultHasher::new();
+            line.hash(&mut hasher);
+            *counts.entry(hasher.finish()).or_insert(0) += 1;
+            total += 1;
+        }
+        Lines { counts, total }
+    }
+
+    // The share of lines the two have in common, each line matched once:
+    // 1.0 for the same lines in any order, 0 for none alike
+    fn similarity(&self, other: &Lines) -> f64 {
+        if self.total + other.total == 0 {
+            return 0.0;
+        }
+        let (small, large) = if self.counts.len() <= other.counts.len() { (self, other) } else { (other, self) };
+        let common: usize = small.counts.iter()
+            .map(|(line, count)| (*count).min(large.counts.get(line).copied().unwrap_or(0)))
+            .sum();
+        2.0 * common as f64 / (self.total + other.total) as f64
+    }
+
+    // No pair can share more than this: all of the shorter file's lines
+    fn bound(&self, other: &Lines) -> f64 {
+        match self.total + other.total {
+            0 => 0.0,
+            sum => 2.0 * self.total.min(other.total) as f64 / sum as f64,
+        }
+    }
+}
+
+// The lines of `path` from `contents`, else from disk; None when neither has it
+fn lines_of(path: &str, contents: &HashMap<String, String>) -> Option<Lines> {
+    match contents.get(path) {
+        Some(content) => Some(Lines::of(content.as_bytes())),
+        None => read_file(path).ok().map(|bytes| Lines::of(&bytes)),
+    }
+}
+
+// 1.0 for a move (same file name), 0.75 for a rename in place (same directory
+// and extension), 0 otherwise
+fn path_similarity(old: &str, new: &str) -> f64 {
+    let (old, new) = (Path::new(old), Path::new(new));
+    if old.file_name() == new.file_name() {
+        1.0
+    } else if old.parent() == new.parent() && old.extension() == new.extension() {
+        0.75
+    } else {
+        0.0
+    }
+}
+
+// Pairs off the candidates with the best scores first, each path used once;
+// between equal scores, the pair with the more alike paths wins
+fn pair_greedily(candidates: Vec<Rename>, used_old: &mut HashSet<String>, used_new: &mut HashSet<String>) -> Vec<Rename> {
+    let mut candidates: Vec<(f64, Rename)> = candidates.into_iter()
+        .map(|candidate| (path_similarity(&candidate.old_path, &candidate.new_path), candidate))
+        .collect();
+    candidates.sort_by(|(a_path, a), (b_path, b)| {
+        b.similarity.total_cmp(&a.similarity)
+            .then_with(|| b_path.total_cmp(a_path))
+            .then_with(|| (&a.old_path, &a.new_path).cmp(&(&b.old_path, &b.new_path)))
+    });
+    let mut renames = Vec::new();
+    for (_, candidate) in candidates {
+        if used_old.contains(&candidate.old_path) || used_new.contains(&candidate.new_path) {
+            continue;
+        }
+        used_old.insert(candidate.old_path.clone());
+        used_new.insert(candidate.new_path.clone());
+        renames.push(candidate);
+    }
+    renames
+}
+
+/// Renames and moves between two `(path, hash, size)` listings, so an
+/// incremental scan can carry a file's results over instead of treating it
+/// as deleted and added. Only paths missing from `new` are rename sources and
+/// only paths missing from `old` are targets.
+///
+/// Same-hash pairs are exact renames. The remaining pairs score the share
+/// of non-blank lines they have in common, ignoring indentation, and pairs
+/// scoring at least `min_similarity` are reported; a pair's paths (same file
+/// name, or same directory and extension) only settle equal scores. The
+/// content of each path comes from `contents`, which maps paths to their
+/// text, else from the file on disk, so a deleted file needs its previous
+/// text in `contents`; a pair missing either side's content is only ever
+/// an exact rename. An empty hash never counts as a match.
+#[pyfunction]
+#[pyo3(signature = (old, new, min_similarity=0.5, contents=None))]
+pub fn detect_renames(old: Vec<Entry>, new: Vec<Entry>, min_similarity: f64, contents: Option<HashMap<String, String>>) -> Vec<Rename> {
+    let old_paths: HashSet<&str> = old.iter().map(|(path, _, _)| path.as_str()).collect();
+    let new_paths: HashSet<&str> = new.iter().map(|(path, _, _)| path.as_str()).collect();
+    let deleted: Vec<&Entry> = old.iter().filter(|(path, _, _)| !new_paths.contains(path.as_str())).collect();
+    let added: Vec<&Entry> = new.iter().filter(|(path, _, _)| !old_paths.contains(path.as_str())).collect();
+
+    let mut by_hash: HashMap<&str, Vec<&Entry>> = HashMap::new();
+    for entry in deleted.iter().filter(|(_, hash, _)| !hash.is_empty()) {
+        by_hash.entry(entry.1.as_str()).or_default().push(entry);
+    }
+    let exact_candidates = added.iter()
+        .flat_map(|(new_path, hash, _)| {
+            by_hash.get(hash.as_str()).into_iter().flatten().map(move |(old_path, _, _)| Rename {
+                old_path: old_path.clone(),
+                new_path: new_path.clone(),
+                similarity: 1.0,
+                exact: true,
+            })
+        })
+        .collect();
+    let (mut used_old, mut used_new) = (HashSet::new(), HashSet::new());
+    let mut renames = pair_greedily(exact_candidates, &mut used_old, &mut used_new);
+
+    let contents = contents.unwrap_or_default();
+    let lines = |entries: &[&Entry], used: &HashSet<String>| -> Vec<(String, Lines)> {
+        entries.iter()
+            .filter(|(path, _, _)| !used.contains(path))
+            .filter_map(|(path, _, _)| Some((path.clone(), lines_of(path, &contents)?)))
+            .collect()
+    };
+    let (deleted, added) = (lines(&deleted, &used_old), lines(&added, &used_new));
+    let mut similar_candidates = Vec::new();
+    for (old_path, old_lines) in &deleted {
+        for (new_path, new_lines) in &added {
+            if old_lines.bound(new_lines) < min_similarity {
+                continue;
+            }
+            let similarity = old_lines.similarity(new_lines);
+            if similarity > 0.0 && similarity >= min_similarity {
+                similar_candidates.push(Rename { old_path: old_path.clone(), new_path: new_path.clone(), similarity, exact: false });
+            }
+        }
+    }
+    renames.extend(pair_greedily(similar_candidates, &mut used_old, &mut used_new));
+    renames
+}
@@ -0,0 +1,2235 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use regex::{Captures, Match, Regex, RegexBuilder};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tree_sitter::{Point, Tree};
+
+use crate::budget::{
+    warn_over_budget, BudgetReport, RegexLimits, RuleClock, DEFAULT_FILE_BUDGET_MS, DEFAULT_LINE_BUDGET_MS,
+    DEFAULT_REGEX_SIZE_LIMIT,
+};
+use crate::blobs::decoded_blobs;
+use crate::cache::{CacheKey, ScanCache};
+use crate::encoding;
+use crate::ast::{get_language_parser, parse};
+use crate::complexity::{function_metrics, max_nesting_depth};
+use crate::git::{staged_blobs, GitHistory, GitHistoryHit};
+use crate::loc::{has_trailing_whitespace, indentation, LineEndings, Whitespace};
+use crate::prefilter::LiteralPrefilter;
+use crate::strings::{printable_runs, BinaryStringHit};
+use crate::validators::{self, Validator};
+use crate::hashing::HashAlgo;
+use crate::permissions::mode_bits;
+use crate::{
+    build_walker, content_stats, scan_root, detect_language, is_large_file, read_file, size_limit_bytes, stop_here,
+    CancellationToken, FileBytes, FileStats, ScanControl, ScanItem,
+};
+
+// Metric types measured on the content itself, which validating a file then
+// has to hold in memory rather than stream
+const CONTENT_METRICS: &[&str] = &[
+    "max_line_length",
+    "max_nesting_depth",
+    "function_line_count",
+    "mixed_line_endings",
+    "trailing_whitespace",
+    "mixed_indentation",
+    "missing_trailing_newline",
+];
+
+// How much of an overlong line a max_line_length violation quotes
+const LONG_LINE_SNIPPET_CHARS: usize = 200;
+
+#[pyclass]
+#[derive(Clone)]
+pub struct RustRule {
+    #[pyo3(get, set)]
+    pub id: String,
+    #[pyo3(get, set)]
+    pub pattern: String,
+    // Match against the whole file instead of line by line, for things like
+    // PEM blocks or triple-quoted SQL. `^`/`$` still anchor at line boundaries.
+    #[pyo3(get, set)]
+    pub multiline: bool,
+    // Regex flags, any of "i" (case-insensitive), "s" (`.` matches newline),
+    // "m" (`^`/`$` at line boundaries), "x" (verbose), "u" (Unicode classes,
+    // the default) and "U" (swap greediness). Equivalent to leading `(?flags)`.
+    #[pyo3(get, set)]
+    pub flags: String,
+    // Restrict the rule to some files. A glob without `/` is matched against
+    // the file name, otherwise against the path as passed to the scan. No
+    // include globs means every file that is not excluded.
+    #[pyo3(get, set)]
+    pub include_globs: Vec<String>,
+    #[pyo3(get, set)]
+    pub exclude_globs: Vec<String>,
+    // Copied verbatim into every MatchHit / ValidationResult of the rule
+    #[pyo3(get, set)]
+    pub severity: Option<String>,
+    #[pyo3(get, set)]
+    pub category: Option<String>,
+    pub metadata: Metadata,
+    // Offline format check run on every hit: "aws_access_key",
+    // "github_token", "slack_token" or "jwt". Checks the named group
+    // `secret` when the pattern has one, else the whole match.
+    #[pyo3(get, set)]
+    pub validator: Option<String>,
+    // Mask the secret in `snippet`, `matched_text` and `groups` so it never
+    // leaves the scanner; the secret is the named group `secret` when the
+    // pattern has one, else the whole match. Validators still see it.
+    #[pyo3(get, set)]
+    pub redact: bool,
+    // Only report matches lying wholly inside a syntax node of one of these
+    // kinds, such as ["string"] or ["comment"]. A kind also takes in the
+    // tree-sitter kinds having it as a word, so "string" covers
+    // `string_literal` and `template_string`, and "comment" `line_comment`.
+    // Files whose language has no grammar get no hits from such a rule.
+    #[pyo3(get, set)]
+    pub node_kinds: Vec<String>,
+}
+
+// A caller-supplied dict shared, not copied, by the rule and all of its hits.
+// Behind an Arc so workers can hand it out without taking the GIL.
+pub type Metadata = Option<Arc<Py<PyDict>>>;
+
+pub(crate) fn metadata_ref(py: Python<'_>, metadata: &Metadata) -> Option<Py<PyDict>> {
+    metadata.as_ref().map(|dict| dict.clone_ref(py))
+}
+
+#[pymethods]
+impl RustRule {
+    #[new]
+    #[pyo3(signature = (id, pattern, multiline=false, flags=String::new(), include_globs=Vec::new(), exclude_globs=Vec::new(), severity=None, category=None, metadata=None, validator=None, redact=false, node_kinds=Vec::new()))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        id: String,
+        pattern: String,
+        multiline: bool,
+        flags: String,
+        include_globs: Vec<String>,
+        exclude_globs: Vec<String>,
+        severity: Option<String>,
+        category: Option<String>,
+        metadata: Option<Py<PyDict>>,
+        validator: Option<String>,
+        redact: bool,
+        node_kinds: Vec<String>,
+    ) -> Self {
+        RustRule {
+            id,
+            pattern,
+            multiline,
+            flags,
+            include_globs,
+            exclude_globs,
+            severity,
+            category,
+            metadata: metadata.map(Arc::new),
+            validator,
+            redact,
+            node_kinds,
+        }
+    }
+
+    #[getter(metadata)]
+    fn get_metadata(&self, py: Python<'_>) -> Option<Py<PyDict>> {
+        metadata_ref(py, &self.metadata)
+    }
+
+    #[setter(metadata)]
+    fn set_metadata(&mut self, metadata: Option<Py<PyDict>>) {
+        self.metadata = metadata.map(Arc::new);
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct MatchHit {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line_number: usize,
+    // 1-based, in bytes of the line as UTF-8: of `snippet`'s text for a file
+    // transcoded from UTF-16 or Latin-1, not of the bytes on disk
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub snippet: String,
+    #[pyo3(get)]
+    pub matched_text: String,
+    // Where the match ends: 1-based, exclusive, so for single-line matches
+    // `line[column - 1:match_end_column - 1] == matched_text`. Only multiline
+    // rules can end on a later line than they start.
+    #[pyo3(get)]
+    pub end_line_number: usize,
+    #[pyo3(get)]
+    pub match_end_column: usize,
+    // Named capture groups that took part in the match
+    #[pyo3(get)]
+    pub groups: HashMap<String, String>,
+    // Up to `context_lines` lines around the match, empty unless requested
+    #[pyo3(get)]
+    pub before: Vec<String>,
+    #[pyo3(get)]
+    pub after: Vec<String>,
+    // Set on the last hit kept when `max_hits_per_file`/`max_hits_per_rule`
+    // dropped further hits of this file or rule
+    #[pyo3(get)]
+    pub truncated: bool,
+    // The file was not valid UTF-8 and was matched as decoded lossily, with
+    // U+FFFD for each bad sequence (so columns after one may be shifted)
+    #[pyo3(get)]
+    pub had_encoding_errors: bool,
+    // From the rule
+    #[pyo3(get)]
+    pub severity: Option<String>,
+    #[pyo3(get)]
+    pub category: Option<String>,
+    pub metadata: Metadata,
+    // The rule's validator and whether the hit passed it; validated is
+    // always false for rules without one
+    #[pyo3(get)]
+    pub validator: Option<String>,
+    #[pyo3(get)]
+    pub validated: bool,
+    // The rule has `redact` set and the secret is masked
+    #[pyo3(get)]
+    pub redacted: bool,
+    // "base64" or "hex" when the match is in decoded content (`decode_blobs`);
+    // the position is then that of the encoded literal
+    #[pyo3(get)]
+    pub decoded_from: Option<String>,
+}
+
+#[pymethods]
+impl MatchHit {
+    #[getter]
+    fn metadata(&self, py: Python<'_>) -> Option<Py<PyDict>> {
+        metadata_ref(py, &self.metadata)
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct MetricRule {
+    #[pyo3(get, set)]
+    pub id: String,
+    #[pyo3(get, set)]
+    // "line_count", "size_bytes", "max_line_length", "max_nesting_depth",
+    // "function_line_count", "mixed_line_endings" (lines ending the less
+    // common of `\n` and `\r\n` way, so threshold 0 allows no mixing),
+    // "trailing_whitespace" (lines ending in spaces or tabs),
+    // "mixed_indentation" (lines indented the less common of tab and space
+    // way), "missing_trailing_newline" (1 without a final newline, so with
+    // threshold 0)
+    pub metric_type: String,
+    #[pyo3(get, set)]
+    pub threshold: u64,
+}
+
+#[pymethods]
+impl MetricRule {
+    #[new]
+    fn new(id: String, metric_type: String, threshold: u64) -> Self {
+        MetricRule { id, metric_type, threshold }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct ValidationResult {
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub message: String,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub snippet: String,
+    // See `MatchHit.truncated` and `MatchHit.had_encoding_errors`
+    #[pyo3(get)]
+    pub truncated: bool,
+    #[pyo3(get)]
+    pub had_encoding_errors: bool,
+    // From the regex rule; metric rules carry none
+    #[pyo3(get)]
+    pub severity: Option<String>,
+    #[pyo3(get)]
+    pub category: Option<String>,
+    pub metadata: Metadata,
+    // See `MatchHit.validator` and `MatchHit.decoded_from`
+    #[pyo3(get)]
+    pub validator: Option<String>,
+    #[pyo3(get)]
+    pub validated: bool,
+    #[pyo3(get)]
+    pub decoded_from: Option<String>,
+}
+
+#[pymethods]
+impl ValidationResult {
+    #[getter]
+    fn metadata(&self, py: Python<'_>) -> Option<Py<PyDict>> {
+        metadata_ref(py, &self.metadata)
+    }
+}
+
+impl ValidationResult {
+    fn metric(rule: &MetricRule, file_path: &str, message: String) -> Self {
+        ValidationResult::finding(&rule.id, file_path, message, 0, String::new())
+    }
+
+    // A result of one of the built-in checks, which carry no rule metadata
+    pub(crate) fn finding(rule_id: &str, file_path: &str, message: String, line: usize, snippet: String) -> Self {
+        ValidationResult {
+            rule_id: rule_id.to_string(),
+            file_path: file_path.to_string(),
+            message,
+            line,
+            snippet,
+            truncated: false,
+            had_encoding_errors: false,
+            severity: None,
+            category: None,
+            metadata: None,
+            validator: None,
+            validated: false,
+            decoded_from: None,
+        }
+    }
+}
+
+impl From<MatchHit> for ValidationResult {
+    fn from(hit: MatchHit) -> Self {
+        ValidationResult {
+            rule_id: hit.rule_id,
+            file_path: hit.file_path,
+            message: "Pattern match found".to_string(),
+            line: hit.line_number,
+            snippet: hit.snippet,
+            truncated: hit.truncated,
+            had_encoding_errors: hit.had_encoding_errors,
+            severity: hit.severity,
+            category: hit.category,
+            metadata: hit.metadata,
+            validator: hit.validator,
+            validated: hit.validated,
+            decoded_from: hit.decoded_from,
+        }
+    }
+}
+
+/// Why a regex rule could not be used. `offset` is the byte offset into
+/// `pattern` of a syntax error, when there is one.
+#[pyclass]
+#[derive(Clone)]
+pub struct RuleCompileReport {
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub pattern: String,
+    #[pyo3(get)]
+    pub message: String,
+    #[pyo3(get)]
+    pub offset: Option<usize>,
+}
+
+#[pymethods]
+impl RuleCompileReport {
+    fn __repr__(&self) -> String {
+        match self.offset {
+            Some(offset) => format!("RuleCompileReport({}: {} at offset {})", self.rule_id, self.message, offset),
+            None => format!("RuleCompileReport({}: {})", self.rule_id, self.message),
+        }
+    }
+}
+
+// Makes rejected rules visible: a UserWarning by default, a ValueError
+// listing every bad rule when the caller asked for `strict`
+fn report_rule_errors(py: Python<'_>, errors: &[RuleCompileReport], strict: bool) -> PyResult<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let details: Vec<String> = errors.iter().map(|e| format!("{}: {}", e.rule_id, e.message)).collect();
+    let message = format!("{} rule(s) failed to compile: {}", errors.len(), details.join("; "));
+    if strict {
+        return Err(PyValueError::new_err(message));
+    }
+    crate::warn_user(py, &message)
+}
+
+/// Compiles `rules` without scanning anything and returns the ones that
+/// would be skipped, so configuration can be validated up front.
+#[pyfunction]
+#[pyo3(signature = (rules, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT))]
+pub fn check_rules(rules: Vec<RustRule>, regex_size_limit: usize) -> Vec<RuleCompileReport> {
+    rules.into_iter().filter_map(|rule| CompiledRule::compile(rule, regex_size_limit).err()).collect()
+}
+
+/// Per-call knobs of the pattern scans.
+#[derive(Clone, Default)]
+pub(crate) struct MatchOptions {
+    pub context_lines: usize,
+    pub max_hits_per_file: Option<usize>,
+    // Across the whole scan, counted by rule id
+    pub max_hits_per_rule: Option<usize>,
+    // Also run the line rules over decoded base64/hex literals
+    pub decode_blobs: bool,
+    // Only hits touching these lines are kept; a file without an entry has
+    // none
+    pub changed_ranges: Option<Arc<ChangedRanges>>,
+}
+
+/// Per-file line ranges, 1-based and inclusive, such as the hunks of a diff.
+pub(crate) type ChangedRanges = HashMap<String, Vec<(usize, usize)>>;
+
+fn touches_changed_lines(ranges: &ChangedRanges, hit: &MatchHit) -> bool {
+    ranges.get(&hit.file_path).is_some_and(|ranges| {
+        ranges.iter().any(|&(start, end)| hit.line_number <= end && hit.end_line_number >= start)
+    })
+}
+
+// What a single read of a file produced
+struct FileScan {
+    line_count: usize,
+    hits: Vec<MatchHit>,
+    over_budget: Vec<BudgetReport>,
+    had_encoding_errors: bool,
+}
+
+impl FileScan {
+    fn new() -> Self {
+        FileScan { line_count: 0, hits: Vec::new(), over_budget: Vec::new(), had_encoding_errors: false }
+    }
+
+    // Decodes one line, noting whether it needed replacement characters
+    fn decode<'a>(&mut self, line: &'a [u8]) -> Cow<'a, str> {
+        let text = String::from_utf8_lossy(line);
+        if let Cow::Owned(_) = text {
+            self.had_encoding_errors = true;
+        }
+        text
+    }
+
+    fn finish(mut self, clock: RuleClock<'_>) -> Self {
+        self.over_budget = clock.reports;
+        if self.had_encoding_errors {
+            for hit in &mut self.hits {
+                hit.had_encoding_errors = true;
+            }
+        }
+        self
+    }
+}
+
+// Everything found in one file, metric violations kept apart from pattern
+// hits so hit limits never drop a metric violation
+pub(crate) struct FileFindings {
+    violations: Vec<ValidationResult>,
+    hits: Vec<MatchHit>,
+    over_budget: Vec<BudgetReport>,
+}
+
+impl FileFindings {
+    fn empty() -> Self {
+        FileFindings { violations: Vec::new(), hits: Vec::new(), over_budget: Vec::new() }
+    }
+
+    fn from_scan(scan: FileScan) -> Self {
+        FileFindings { violations: Vec::new(), hits: scan.hits, over_budget: scan.over_budget }
+    }
+
+    fn into_results(self) -> impl Iterator<Item = ValidationResult> {
+        self.violations.into_iter().chain(self.hits.into_iter().map(ValidationResult::from))
+    }
+}
+
+// Keeps the first `max` hits of every rule id, in order; the last hit kept of
+// a rule that had more is flagged as truncated
+fn cap_hits_per_rule(files: &mut [FileFindings], max: usize) {
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    for hit in files.iter().flat_map(|f| &f.hits) {
+        *totals.entry(hit.rule_id.clone()).or_default() += 1;
+    }
+    if totals.values().all(|&total| total <= max) {
+        return;
+    }
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for file in files.iter_mut() {
+        file.hits.retain_mut(|hit| {
+            let ordinal = seen.entry(hit.rule_id.clone()).or_default();
+            *ordinal += 1;
+            if *ordinal == max && totals[&hit.rule_id] > max {
+                hit.truncated = true;
+            }
+            *ordinal <= max
+        });
+    }
+}
+
+fn cap_hits_per_file(file: &mut FileFindings, max: usize) {
+    if file.hits.len() > max {
+        file.hits.truncate(max);
+        if let Some(last) = file.hits.last_mut() {
+            last.truncated = true;
+        }
+    }
+}
+
+// 1-based start and (exclusive) end position of a match
+struct Span {
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+// A list of globs split by what they are matched against
+struct GlobList {
+    on_path: GlobSet,
+    on_name: GlobSet,
+}
+
+impl GlobList {
+    fn new(patterns: &[String]) -> Result<Self, globset::Error> {
+        let mut on_path = GlobSetBuilder::new();
+        let mut on_name = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = Glob::new(pattern)?;
+            if pattern.contains('/') {
+                on_path.add(glob);
+            } else {
+                on_name.add(glob);
+            }
+        }
+        Ok(GlobList { on_path: on_path.build()?, on_name: on_name.build()? })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.on_path.is_empty() && self.on_name.is_empty()
+    }
+
+    fn is_match(&self, path: &Path) -> bool {
+        self.on_path.is_match(path)
+            || path.file_name().is_some_and(|name| self.on_name.is_match(name))
+    }
+}
+
+// The files a rule applies to, from its include/exclude globs
+struct PathScope {
+    include: GlobList,
+    exclude: GlobList,
+}
+
+impl PathScope {
+    fn new(rule: &RustRule) -> Result<Self, globset::Error> {
+        Ok(PathScope {
+            include: GlobList::new(&rule.include_globs)?,
+            exclude: GlobList::new(&rule.exclude_globs)?,
+        })
+    }
+
+    fn applies_to(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+// One match of a rule: the whole match, its named groups, and the `secret`
+// group that validators and redaction look at when the pattern has one
+struct Found<'t> {
+    whole: Match<'t>,
+    groups: HashMap<String, String>,
+    secret: Option<Match<'t>>,
+}
+
+impl<'t> Found<'t> {
+    fn plain(whole: Match<'t>) -> Self {
+        Found { whole, groups: HashMap::new(), secret: None }
+    }
+}
+
+struct CompiledRule {
+    rule: RustRule,
+    regex: Regex,
+    scope: PathScope,
+    validator: Option<Validator>,
+    // Capture groups are only worth the slower `captures` search if named
+    has_named_groups: bool,
+}
+
+// A rule's `flags` string (plus `multiline`), parsed
+#[derive(Clone, Copy, Default)]
+struct RegexFlags {
+    case_insensitive: bool,
+    dot_matches_new_line: bool,
+    multi_line: bool,
+    ignore_whitespace: bool,
+    swap_greed: bool,
+}
+
+impl RegexFlags {
+    // Err carries the first unknown flag
+    fn parse(rule: &RustRule) -> Result<Self, char> {
+        let mut flags = RegexFlags { multi_line: rule.multiline, ..Default::default() };
+        for flag in rule.flags.chars() {
+            match flag {
+                'i' => flags.case_insensitive = true,
+                's' => flags.dot_matches_new_line = true,
+                'm' => flags.multi_line = true,
+                'x' => flags.ignore_whitespace = true,
+                'u' => {} // Unicode is on by default
+                'U' => flags.swap_greed = true,
+                other => return Err(other),
+            }
+        }
+        Ok(flags)
+    }
+
+    fn build(&self, pattern: &str, size_limit: usize) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(pattern)
+            .size_limit(size_limit)
+            .case_insensitive(self.case_insensitive)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .multi_line(self.multi_line)
+            .ignore_whitespace(self.ignore_whitespace)
+            .swap_greed(self.swap_greed)
+            .build()
+    }
+
+    // `regex::Error` only has a multi-line rendered message; re-parse to get
+    // the bare error kind and where it is
+    fn syntax_error(&self, pattern: &str) -> Option<(String, usize)> {
+        let err = regex_syntax::ParserBuilder::new()
+            .case_insensitive(self.case_insensitive)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .multi_line(self.multi_line)
+            .ignore_whitespace(self.ignore_whitespace)
+            .swap_greed(self.swap_greed)
+            .build()
+            .parse(pattern)
+            .err()?;
+        match err {
+            regex_syntax::Error::Parse(e) => Some((e.kind().to_string(), e.span().start.offset)),
+            regex_syntax::Error::Translate(e) => Some((e.kind().to_string(), e.span().start.offset)),
+            _ => None,
+        }
+    }
+}
+
+impl CompiledRule {
+    fn compile(rule: RustRule, size_limit: usize) -> Result<Self, RuleCompileReport> {
+        let report = |message: String, offset: Option<usize>| RuleCompileReport {
+            rule_id: rule.id.clone(),
+            pattern: rule.pattern.clone(),
+            message,
+            offset,
+        };
+        let flags = RegexFlags::parse(&rule)
+            .map_err(|flag| report(format!("unknown regex flag '{}'", flag), None))?;
+        let regex = flags.build(&rule.pattern, size_limit).map_err(|e| match flags.syntax_error(&rule.pattern) {
+            Some((message, offset)) => report(message, Some(offset)),
+            None => report(e.to_string(), None),
+        })?;
+        let scope = PathScope::new(&rule)
+            .map_err(|e| report(format!("invalid glob: {}", e), None))?;
+        let validator = match &rule.validator {
+            Some(name) => Some(Validator::from_name(name).ok_or_else(|| report(format!("unknown validator '{}'", name), None))?),
+            None => None,
+        };
+        let has_named_groups = regex.capture_names().any(|name| name.is_some());
+        Ok(CompiledRule { rule, regex, scope, validator, has_named_groups })
+    }
+
+    // The pattern with its flags inlined, for consumers that only take a
+    // pattern string (the prefilter's literal extraction and RegexSet)
+    fn inline_pattern(&self) -> String {
+        if self.rule.flags.is_empty() {
+            return self.rule.pattern.clone();
+        }
+        // In verbose mode a trailing `#` comment would swallow the closing paren
+        let end = if self.rule.flags.contains('x') { "\n)" } else { ")" };
+        format!("(?{}:{}{}", self.rule.flags, self.rule.pattern, end)
+    }
+
+    fn found<'t>(&self, caps: Captures<'t>) -> Option<Found<'t>> {
+        let groups = self.regex.capture_names()
+            .flatten()
+            .filter_map(|name| caps.name(name).map(|g| (name.to_string(), g.as_str().to_string())))
+            .collect();
+        Some(Found { whole: caps.get(0)?, groups, secret: caps.name("secret") })
+    }
+
+    // First match in `text` with its named groups
+    fn find<'t>(&self, text: &'t str) -> Option<Found<'t>> {
+        if !self.has_named_groups {
+            return self.regex.find(text).map(Found::plain);
+        }
+        self.found(self.regex.captures(text)?)
+    }
+
+    // Every match in `text` with its named groups, searched for lazily so the
+    // caller can stop part-way
+    fn find_iter<'r, 't>(&'r self, text: &'t str) -> Box<dyn Iterator<Item = Found<'t>> + 'r>
+    where
+        't: 'r,
+    {
+        if !self.has_named_groups {
+            return Box::new(self.regex.find_iter(text).map(Found::plain));
+        }
+        Box::new(self.regex.captures_iter(text).filter_map(|caps| self.found(caps)))
+    }
+
+    fn hit(&self, file_path: &str, found: Found<'_>, span: Span, line: &str) -> MatchHit {
+        let Found { whole: m, mut groups, secret } = found;
+        let verdict = self.validator
+            .and_then(|validator| validators::check(secret.unwrap_or(m).as_str(), Some(validator)));
+        let (snippet, matched_text) = if self.rule.redact {
+            // The secret group, or else the whole match, relative to the match
+            let masked = secret.map_or(0..m.len(), |s| s.start() - m.start()..s.end() - m.start());
+            // Only the part of a multiline match on its first line is in `line`
+            let line_end = if span.end_line == span.line { span.end_column - 1 } else { line.len() };
+            let (start, end) = (span.column - 1 + masked.start, (span.column - 1 + masked.end).min(line_end));
+            for (name, value) in groups.iter_mut() {
+                if secret.is_none() || name == "secret" {
+                    *value = mask_secret(value);
+                }
+            }
+            (redact_span(line, start, end).trim().to_string(), redact_span(m.as_str(), masked.start, masked.end))
+        } else {
+            (line.trim().to_string(), m.as_str().to_string())
+        };
+        MatchHit {
+            file_path: file_path.to_string(),
+            line_number: span.line,
+            column: span.column,
+            rule_id: self.rule.id.clone(),
+            snippet,
+            matched_text,
+            end_line_number: span.end_line,
+            match_end_column: span.end_column,
+            groups,
+            before: Vec::new(),
+            after: Vec::new(),
+            truncated: false,
+            had_encoding_errors: false,
+            severity: self.rule.severity.clone(),
+            category: self.rule.category.clone(),
+            metadata: self.rule.metadata.clone(),
+            validator: verdict.map(|(name, _)| name.to_string()),
+            validated: verdict.is_some_and(|(_, ok)| ok),
+            redacted: self.rule.redact,
+            decoded_from: None,
+        }
+    }
+}
+
+// Whether `span` lies wholly inside a node of one of `kinds`, or of a kind
+// having one of them as a word
+fn inside_node_kind(tree: &Tree, span: &Span, kinds: &[String]) -> bool {
+    let start = Point::new(span.line - 1, span.column - 1);
+    let end = Point::new(span.end_line - 1, span.end_column - 1);
+    let mut node = tree.root_node().descendant_for_point_range(start, end);
+    while let Some(current) = node {
+        let kind = current.kind();
+        if kinds.iter().any(|wanted| kind == wanted || kind.split('_').any(|word| word == wanted)) {
+            return true;
+        }
+        node = current.parent();
+    }
+    false
+}
+
+type Content<'b> = Box<dyn FnOnce() -> Option<Cow<'b, [u8]>> + 'b>;
+
+// The syntax tree of the file being scanned, for the rules with
+// `node_kinds`; `content` is the whole file, only read and parsed once one
+// of those rules matches
+struct SyntaxTree<'b> {
+    file_path: &'b str,
+    content: Option<Content<'b>>,
+    tree: Option<Tree>,
+}
+
+impl<'b> SyntaxTree<'b> {
+    fn new(file_path: &'b str, content: impl FnOnce() -> Option<Cow<'b, [u8]>> + 'b) -> Self {
+        SyntaxTree { file_path, content: Some(Box::new(content)), tree: None }
+    }
+
+    // Whether a match at `span` may be reported for `rule`
+    fn admits(&mut self, rule: &CompiledRule, span: &Span) -> bool {
+        if rule.rule.node_kinds.is_empty() {
+            return true;
+        }
+        if let Some(content) = self.content.take() {
+            let content = content().map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+            self.tree = content.as_deref().and_then(|content| {
+                let language = detect_language(Path::new(self.file_path), content.as_bytes());
+                parse(content, &language, get_language_parser(&language)?, None)
+            });
+        }
+        self.tree.as_ref().is_some_and(|tree| inside_node_kind(tree, span, &rule.rule.node_kinds))
+    }
+}
+
+// Keeps a short prefix (enough to tell an AKIA key from an ASIA one) of
+// longer secrets and stars out the rest, one `*` per character
+pub(crate) fn mask_secret(secret: &str) -> String {
+    let count = secret.chars().count();
+    let keep = if count > 8 { 4 } else { 0 };
+    secret.chars().take(keep).chain(std::iter::repeat_n('*', count - keep)).collect()
+}
+
+// `line` with the bytes start..end masked; the range is clamped to the line
+fn redact_span(line: &str, start: usize, end: usize) -> String {
+    let end = end.min(line.len());
+    let start = start.min(end);
+    format!("{}{}{}", &line[..start], mask_secret(&line[start..end]), &line[end..])
+}
+
+// Byte offsets of line starts, to map whole-file match offsets to line/column
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { starts }
+    }
+
+    // 1-based (line, column) of a byte offset
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        (line, offset - self.starts[line - 1] + 1)
+    }
+
+    fn line<'a>(&self, text: &'a str, line_number: usize) -> &'a str {
+        let start = self.starts[line_number - 1];
+        let end = self.starts.get(line_number).map_or(text.len(), |&next| next - 1);
+        text[start..end].trim_end_matches('\r')
+    }
+}
+
+// Same line splitting as `BufRead::lines`, over an in-memory buffer:
+// `\n` or `\r\n` terminated, no empty trailing line, and an unterminated
+// last line kept whole
+pub(crate) fn split_lines(bytes: &[u8]) -> Vec<&[u8]> {
+    if bytes.is_empty() {
+        return Vec::new();
+    }
+    let (body, terminated) = match bytes.strip_suffix(b"\n") {
+        Some(body) => (body, true),
+        None => (bytes, false),
+    };
+    let mut lines: Vec<&[u8]> = body.split(|&b| b == b'\n').collect();
+    let last = lines.len() - 1;
+    for (idx, line) in lines.iter_mut().enumerate() {
+        if idx < last || terminated {
+            *line = line.strip_suffix(b"\r").unwrap_or(line);
+        }
+    }
+    lines
+}
+
+// A line from `read_until(b'\n')` without its "\n" or "\r\n"
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    match line.strip_suffix(b"\n") {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => line,
+    }
+}
+
+// Fills `before`/`after` of each hit from the file's lines
+fn attach_context(hits: &mut [MatchHit], lines: &[&[u8]], context_lines: usize) {
+    let text = |range: std::ops::Range<usize>| -> Vec<String> {
+        lines[range].iter().map(|line| String::from_utf8_lossy(line).into_owned()).collect()
+    };
+    for hit in hits {
+        let start = hit.line_number - 1;
+        let end = hit.end_line_number.min(lines.len());
+        hit.before = text(start.saturating_sub(context_lines)..start);
+        hit.after = text(end..(end + context_lines).min(lines.len()));
+    }
+}
+
+// Which line/block rules apply to the file being scanned
+struct ActiveRules {
+    line: Vec<bool>,
+    block: Vec<bool>,
+}
+
+impl ActiveRules {
+    fn any(&self) -> bool {
+        self.line.contains(&true) || self.block.contains(&true)
+    }
+}
+
+/// Compiled form of a regex + metric rule set, shared read-only by the workers.
+pub(crate) struct RuleSet {
+    line_rules: Vec<CompiledRule>,
+    // Narrows each line down to the few line rules worth running
+    prefilter: LiteralPrefilter,
+    // `multiline` rules, run once over the whole file content
+    block_rules: Vec<CompiledRule>,
+    metric_rules: Vec<MetricRule>,
+    // Regex rules that were rejected at compile time
+    errors: Vec<RuleCompileReport>,
+    limits: RegexLimits,
+    // Hash of the compiled regex rules, identifying them in a `ScanCache`
+    fingerprint: String,
+}
+
+// Every rule field that can change a hit, in rule order
+fn rules_fingerprint<'a>(rules: impl Iterator<Item = &'a RustRule>) -> String {
+    let mut hasher = Sha256::new();
+    for rule in rules {
+        let fields = [
+            rule.id.as_str(),
+            rule.pattern.as_str(),
+            if rule.multiline { "multiline" } else { "" },
+            rule.flags.as_str(),
+            &rule.include_globs.join("\0"),
+            &rule.exclude_globs.join("\0"),
+            rule.severity.as_deref().unwrap_or_default(),
+            rule.category.as_deref().unwrap_or_default(),
+            rule.validator.as_deref().unwrap_or_default(),
+            if rule.redact { "redact" } else { "" },
+            &rule.node_kinds.join("\0"),
+        ];
+        for field in fields {
+            hasher.update(field.as_bytes());
+            hasher.update(b"\x1f");
+        }
+        hasher.update(b"\x1e");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+impl RuleSet {
+    pub(crate) fn compile(regex_rules: Vec<RustRule>, metric_rules: Vec<MetricRule>, limits: RegexLimits) -> Self {
+        let mut errors = Vec::new();
+        let (block_rules, line_rules): (Vec<_>, Vec<_>) = regex_rules.into_iter()
+            .filter_map(|rule| CompiledRule::compile(rule, limits.size_limit).map_err(|e| errors.push(e)).ok())
+            .partition(|r| r.rule.multiline);
+        let patterns: Vec<String> = line_rules.iter().map(CompiledRule::inline_pattern).collect();
+        let prefilter = LiteralPrefilter::new(&patterns);
+        let fingerprint = rules_fingerprint(line_rules.iter().chain(&block_rules).map(|r| &r.rule));
+        RuleSet { line_rules, prefilter, block_rules, metric_rules, errors, limits, fingerprint }
+    }
+
+    // Line rules take clock slots 0..n, block rules the ones after
+    fn clock<'a>(&'a self, file_path: &'a str) -> RuleClock<'a> {
+        RuleClock::new(&self.limits, file_path, self.line_rules.len() + self.block_rules.len())
+    }
+
+    fn has_regex_rules(&self) -> bool {
+        !self.line_rules.is_empty() || !self.block_rules.is_empty()
+    }
+
+    fn active_rules(&self, file_path: &str) -> ActiveRules {
+        let path = Path::new(file_path);
+        let applies = |rules: &[CompiledRule]| rules.iter().map(|r| r.scope.applies_to(path)).collect();
+        ActiveRules { line: applies(&self.line_rules), block: applies(&self.block_rules) }
+    }
+
+    fn has_metric(&self, metric_type: &str) -> bool {
+        self.metric_rules.iter().any(|r| r.metric_type == metric_type)
+    }
+
+    fn has_content_metrics(&self) -> bool {
+        CONTENT_METRICS.iter().any(|metric_type| self.has_metric(metric_type))
+    }
+
+    // Runs the line rules over `text`, counted as line `line_number` of the
+    // file for the budgets; one hit per rule is enough for reporting, the
+    // first `admits` takes, so the rest of the line is still searched after
+    // a match outside a rule's `node_kinds`
+    fn each_line_hit<'t>(
+        &self,
+        line_number: usize,
+        text: &'t str,
+        active: &mut ActiveRules,
+        clock: &mut RuleClock<'_>,
+        mut admits: impl FnMut(&CompiledRule, &Span) -> bool,
+        mut on_hit: impl FnMut(&CompiledRule, Found<'t>, Span),
+    ) {
+        let span = |found: &Found| Span {
+            line: line_number,
+            column: found.whole.start() + 1,
+            end_line: line_number,
+            end_column: found.whole.end() + 1,
+        };
+        for idx in self.prefilter.candidates(text) {
+            if !active.line[idx] {
+                continue;
+            }
+            let rule = &self.line_rules[idx];
+            // The syntax tree is left out of the rule's time budget
+            let (found, within_budget) = if rule.rule.node_kinds.is_empty() {
+                clock.time(idx, &rule.rule.id, Some(line_number), || rule.find(text).into_iter().collect())
+            } else {
+                clock.time(idx, &rule.rule.id, Some(line_number), || rule.find_iter(text).collect::<Vec<_>>())
+            };
+            if !within_budget {
+                active.line[idx] = false;
+            }
+            if let Some(found) = found.into_iter().find(|found| admits(rule, &span(found))) {
+                let span = span(&found);
+                on_hit(rule, found, span);
+            }
+        }
+    }
+
+    fn match_line(&self, line_number: usize, line: &str, active: &mut ActiveRules, clock: &mut RuleClock<'_>, tree: &mut SyntaxTree, hits: &mut Vec<MatchHit>) {
+        let file_path = clock.file_path;
+        self.each_line_hit(line_number, line, active, clock, |rule, span| tree.admits(rule, span), |rule, found, span| {
+            hits.push(rule.hit(file_path, found, span, line));
+        });
+    }
+
+    // Second pass over the text hidden in the line's base64/hex literals. The
+    // hits point at the literal in the line; matched_text is decoded text.
+    fn match_decoded(&self, line_number: usize, line: &str, active: &mut ActiveRules, clock: &mut RuleClock<'_>, tree: &mut SyntaxTree, hits: &mut Vec<MatchHit>) {
+        let file_path = clock.file_path;
+        for blob in decoded_blobs(line) {
+            // Where the literal is in the file, for `node_kinds`
+            let literal = Span { line: line_number, column: blob.start + 1, end_line: line_number, end_column: blob.end + 1 };
+            for decoded in blob.text.lines() {
+                self.each_line_hit(line_number, decoded, active, clock, |rule, _| tree.admits(rule, &literal), |rule, found, span| {
+                    let mut hit = rule.hit(file_path, found, span, decoded);
+                    hit.column = blob.start + 1;
+                    hit.match_end_column = blob.end + 1;
+                    hit.snippet = if rule.rule.redact {
+                        redact_span(line, blob.start, blob.end).trim().to_string()
+                    } else {
+                        line.trim().to_string()
+                    };
+                    hit.decoded_from = Some(blob.encoding.to_string());
+                    hits.push(hit);
+                });
+            }
+        }
+    }
+
+    // Counts lines and runs every regex rule over them in one pass
+    fn scan_lines(&self, file_path: &str, options: &MatchOptions, mut active: ActiveRules) -> Option<FileScan> {
+        let has_block_rules = active.block.contains(&true);
+
+        if !has_block_rules && options.context_lines == 0 && !is_large_file(Path::new(file_path)) {
+            // Stream, no need to hold the file in memory; other encodings
+            // than UTF-8, and UTF-8 found invalid on the way, are read
+            // whole below
+            let mut reader = BufReader::new(File::open(file_path).ok()?);
+            if reader.fill_buf().is_ok_and(|head| encoding::detect(head) == "utf-8") {
+                let mut scan = FileScan::new();
+                let mut clock = self.clock(file_path);
+                let mut tree = SyntaxTree::new(file_path, || read_file(file_path).ok().map(|bytes| Cow::Owned(bytes.to_vec())));
+                let mut buf = Vec::new();
+                while matches!(reader.read_until(b'\n', &mut buf), Ok(n) if n > 0) {
+                    scan.line_count += 1;
+                    let line = scan.decode(trim_line_ending(&buf));
+                    if scan.had_encoding_errors {
+                        break;
+                    }
+                    self.match_line(scan.line_count, &line, &mut active, &mut clock, &mut tree, &mut scan.hits);
+                    if options.decode_blobs {
+                        self.match_decoded(scan.line_count, &line, &mut active, &mut clock, &mut tree, &mut scan.hits);
+                    }
+                    buf.clear();
+                }
+                // Past the head the file may not be UTF-8 after all, and
+                // `encoding::detect` over all of it decides how to read it
+                if !scan.had_encoding_errors {
+                    return Some(scan.finish(clock));
+                }
+            }
+        }
+
+        // Large files are mapped, so their UTF-8 lines are matched in place
+        let bytes = read_file(file_path).ok()?;
+        Some(self.scan_bytes(file_path, &bytes, options, active))
+    }
+
+    // `scan_lines` over content already in memory, transcoded to UTF-8 first
+    fn scan_bytes(&self, file_path: &str, bytes: &[u8], options: &MatchOptions, mut active: ActiveRules) -> FileScan {
+        let (text, _) = encoding::decode(bytes);
+        let bytes = &*text;
+        let mut scan = FileScan::new();
+        let mut clock = self.clock(file_path);
+        let mut tree = SyntaxTree::new(file_path, || Some(Cow::Borrowed(bytes)));
+        let lines = split_lines(bytes);
+        scan.line_count = lines.len();
+        for (ln, line) in lines.iter().enumerate() {
+            let line = scan.decode(line);
+            self.match_line(ln + 1, &line, &mut active, &mut clock, &mut tree, &mut scan.hits);
+            if options.decode_blobs {
+                self.match_decoded(ln + 1, &line, &mut active, &mut clock, &mut tree, &mut scan.hits);
+            }
+        }
+
+        // Block rules report every match, located by where it starts
+        if active.block.contains(&true) {
+            self.match_blocks(file_path, bytes, &active, &mut clock, &mut tree, &mut scan);
+        }
+
+        if options.context_lines > 0 {
+            attach_context(&mut scan.hits, &lines, options.context_lines);
+        }
+        scan.finish(clock)
+    }
+
+    // The block rules' part of `scan_bytes`, run over the whole content
+    fn match_blocks(&self, file_path: &str, bytes: &[u8], active: &ActiveRules, clock: &mut RuleClock<'_>, tree: &mut SyntaxTree, scan: &mut FileScan) {
+        let content = scan.decode(bytes);
+        let index = LineIndex::new(&content);
+        let slots = self.line_rules.len()..;
+        let block_rules = self.block_rules.iter().zip(slots).zip(&active.block).filter(|(_, &on)| on);
+        for ((rule, slot), _) in block_rules {
+            let mut matches = rule.find_iter(&content);
+            loop {
+                let (found, within_budget) = clock.time(slot, &rule.rule.id, None, || matches.next());
+                let Some(found) = found else { break };
+                let m = found.whole;
+                let (line, column) = index.locate(m.start());
+                // The end offset is exclusive; locate its last byte instead so
+                // a match ending in a newline is not pushed to the next line
+                let (end_line, end_column) = match m.end().checked_sub(1) {
+                    Some(last) if m.end() > m.start() => {
+                        let (end_line, last_column) = index.locate(last);
+                        (end_line, last_column + 1)
+                    }
+                    _ => (line, column),
+                };
+                let span = Span { line, column, end_line, end_column };
+                if tree.admits(rule, &span) {
+                    scan.hits.push(rule.hit(file_path, found, span, index.line(&content, line)));
+                }
+                if !within_budget {
+                    break;
+                }
+            }
+        }
+        scan.hits.sort_by_key(|hit| hit.line_number);
+    }
+
+    // Drops hits outside `changed_ranges`, then applies the per-file stage of
+    // the hit limits; a file cannot contribute more than `max_hits_per_rule`
+    // hits of one rule to the final result
+    fn limit_hits(&self, mut findings: FileFindings, options: &MatchOptions) -> FileFindings {
+        if let Some(ranges) = &options.changed_ranges {
+            findings.hits.retain(|hit| touches_changed_lines(ranges, hit));
+        }
+        if let Some(max) = options.max_hits_per_rule {
+            cap_hits_per_rule(std::slice::from_mut(&mut findings), max);
+        }
+        if let Some(max) = options.max_hits_per_file {
+            cap_hits_per_file(&mut findings, max);
+        }
+        findings
+    }
+
+    pub(crate) fn match_file(&self, file_path: &str, options: &MatchOptions) -> FileFindings {
+        if options.changed_ranges.as_ref().is_some_and(|ranges| !ranges.contains_key(file_path)) {
+            return FileFindings::empty();
+        }
+        let active = self.active_rules(file_path);
+        if !active.any() {
+            return FileFindings::empty();
+        }
+        let Some(scan) = self.scan_lines(file_path, options, active) else { return FileFindings::empty() };
+        self.limit_hits(FileFindings::from_scan(scan), options)
+    }
+
+    // `match_file` over a buffer supplied by the caller; `file_path` is only
+    // used for reporting and for the rules' path globs
+    pub(crate) fn match_content(&self, file_path: &str, content: &str, options: &MatchOptions) -> FileFindings {
+        let active = self.active_rules(file_path);
+        if !active.any() {
+            return FileFindings::empty();
+        }
+        let scan = self.scan_bytes(file_path, content.as_bytes(), options, active);
+        self.limit_hits(FileFindings::from_scan(scan), options)
+    }
+
+    // Runs the line rules over every printable string in the file's raw bytes;
+    // each string counts as one line for the time budgets
+    pub(crate) fn match_strings(&self, file_path: &str, min_len: usize) -> (Vec<BinaryStringHit>, Vec<BudgetReport>) {
+        let mut active = self.active_rules(file_path);
+        // A string pulled out of raw bytes has no syntax node to lie in
+        for (on, rule) in active.line.iter_mut().zip(&self.line_rules) {
+            *on &= rule.rule.node_kinds.is_empty();
+        }
+        if !active.line.contains(&true) {
+            return (Vec::new(), Vec::new());
+        }
+        let Ok(bytes) = read_file(file_path) else { return (Vec::new(), Vec::new()) };
+        let mut clock = self.clock(file_path);
+        let mut tree = SyntaxTree::new(file_path, || None);
+        let mut found = Vec::new();
+        for (idx, run) in printable_runs(&bytes, min_len).iter().enumerate() {
+            let mut hits = Vec::new();
+            self.match_line(idx + 1, &run.text, &mut active, &mut clock, &mut tree, &mut hits);
+            found.extend(hits.into_iter().map(|hit| BinaryStringHit::new(hit, run)));
+        }
+        (found, clock.reports)
+    }
+
+    // Runs the line rules over some of a file's lines, such as the ones a
+    // commit added; `lines` are `(line_number, bytes)` pairs in file order,
+    // and `content` the whole file they are from, for `node_kinds`
+    fn match_lines<'b>(&self, file_path: &str, lines: &[(usize, Vec<u8>)], content: impl FnOnce() -> Option<Vec<u8>> + 'b, options: &MatchOptions) -> FileFindings {
+        let mut active = self.active_rules(file_path);
+        if !active.line.contains(&true) {
+            return FileFindings::empty();
+        }
+        let mut scan = FileScan::new();
+        let mut clock = self.clock(file_path);
+        let mut tree = SyntaxTree::new(file_path, || content().map(Cow::Owned));
+        for (line_number, line) in lines {
+            let line = scan.decode(line);
+            self.match_line(*line_number, &line, &mut active, &mut clock, &mut tree, &mut scan.hits);
+            if options.decode_blobs {
+                self.match_decoded(*line_number, &line, &mut active, &mut clock, &mut tree, &mut scan.hits);
+            }
+        }
+        FileFindings::from_scan(scan.finish(clock))
+    }
+
+    fn rule_metadata(&self, rule_id: &str) -> Metadata {
+        self.line_rules.iter().chain(&self.block_rules).find(|r| r.rule.id == rule_id).and_then(|r| r.rule.metadata.clone())
+    }
+
+    // Reads the file once and takes its hits from `cache` when it holds a
+    // scan of the same content, rules and options; otherwise scans it and
+    // caches the result. The content comes along for the metric rules.
+    fn cached_scan(&self, file_path: &str, options: &MatchOptions, cache: &ScanCache) -> Option<(FileScan, FileBytes)> {
+        let bytes = read_file(file_path).ok()?;
+        let content_hash = format!("{:x}", Sha256::digest(&*bytes));
+        let key = CacheKey {
+            ruleset: self.fingerprint.clone(),
+            context_lines: options.context_lines,
+            decode_blobs: options.decode_blobs,
+            path: file_path.to_string(),
+        };
+        if let Some(cached) = cache.lookup(&key, &content_hash, |rule_id| self.rule_metadata(rule_id)) {
+            let scan = FileScan { line_count: cached.line_count, hits: cached.hits, ..FileScan::new() };
+            return Some((scan, bytes));
+        }
+        let scan = self.scan_bytes(file_path, &bytes, options, self.active_rules(file_path));
+        if scan.over_budget.is_empty() {
+            cache.store(key, content_hash, scan.line_count, &scan.hits);
+        }
+        Some((scan, bytes))
+    }
+
+    // `match_file` through a `ScanCache`
+    pub(crate) fn match_file_cached(&self, file_path: &str, options: &MatchOptions, cache: &ScanCache) -> FileFindings {
+        if options.changed_ranges.as_ref().is_some_and(|ranges| !ranges.contains_key(file_path)) {
+            return FileFindings::empty();
+        }
+        if !self.active_rules(file_path).any() {
+            return FileFindings::empty();
+        }
+        let Some((scan, _)) = self.cached_scan(file_path, options, cache) else { return FileFindings::empty() };
+        self.limit_hits(FileFindings::from_scan(scan), options)
+    }
+
+    // `validate_file` through a `ScanCache`
+    pub(crate) fn validate_file_cached(&self, path_str: &str, options: &MatchOptions, cache: &ScanCache) -> FileFindings {
+        let scan_options = MatchOptions { context_lines: 0, ..options.clone() };
+        let Some((scan, bytes)) = self.cached_scan(path_str, &scan_options, cache) else { return FileFindings::empty() };
+        let mut findings = FileFindings::empty();
+        self.check_metric(path_str, "size_bytes", "File size", bytes.len() as u64, &mut findings.violations);
+        self.check_metric(path_str, "line_count", "Line count", scan.line_count as u64, &mut findings.violations);
+        self.check_content_metrics(path_str, &bytes, &mut findings.violations);
+        findings.hits = scan.hits;
+        findings.over_budget = scan.over_budget;
+        self.limit_hits(findings, options)
+    }
+
+    // A violation for every `metric_type` rule whose threshold `value` exceeds
+    fn check_metric(&self, path_str: &str, metric_type: &str, label: &str, value: u64, violations: &mut Vec<ValidationResult>) {
+        self.check_metric_at(path_str, metric_type, label, value, (0, ""), violations);
+    }
+
+    // `check_metric` for a value measured at a `(line, snippet)` of the file
+    fn check_metric_at(
+        &self,
+        path_str: &str,
+        metric_type: &str,
+        label: &str,
+        value: u64,
+        (line, snippet): (usize, &str),
+        violations: &mut Vec<ValidationResult>,
+    ) {
+        for rule in &self.metric_rules {
+            if rule.metric_type == metric_type && value > rule.threshold {
+                let message = format!("{} {} exceeds limit {}", label, value, rule.threshold);
+                let snippet = snippet.trim().to_string();
+                violations.push(ValidationResult { line, snippet, ..ValidationResult::metric(rule, path_str, message) });
+            }
+        }
+    }
+
+    // The metrics of `CONTENT_METRICS`, which need the whole content
+    fn check_content_metrics(&self, path_str: &str, bytes: &[u8], violations: &mut Vec<ValidationResult>) {
+        if !self.has_content_metrics() {
+            return;
+        }
+        let (text, _) = encoding::decode(bytes);
+        let content = String::from_utf8_lossy(&text);
+        let lines: Vec<&str> = content.lines().collect();
+        let line_text = |line: usize| lines.get(line.wrapping_sub(1)).copied().unwrap_or_default();
+        if self.has_metric("mixed_line_endings") {
+            let endings = LineEndings::count(&text);
+            // Ties go to the ending of the first line
+            let first_crlf = text.split(|&b| b == b'\n').next().is_some_and(|line| line.ends_with(b"\r"));
+            let minority_crlf = endings.crlf < endings.lf || (endings.crlf == endings.lf && !first_crlf);
+            let value = endings.lf.min(endings.crlf);
+            let line = text.split_inclusive(|&b| b == b'\n').position(|line| line.ends_with(b"\n") && line.ends_with(b"\r\n") == minority_crlf);
+            if let Some(index) = line {
+                self.check_metric_at(path_str, "mixed_line_endings", "Mixed line endings", value as u64, (index + 1, line_text(index + 1)), violations);
+            }
+        }
+        if self.has_metric("trailing_whitespace") {
+            let count = lines.iter().filter(|line| has_trailing_whitespace(line)).count();
+            if let Some(index) = lines.iter().position(|line| has_trailing_whitespace(line)) {
+                self.check_metric_at(path_str, "trailing_whitespace", "Lines with trailing whitespace", count as u64, (index + 1, lines[index]), violations);
+            }
+        }
+        if self.has_metric("mixed_indentation") {
+            let mut whitespace = Whitespace::default();
+            lines.iter().for_each(|line| whitespace.count(line));
+            // Ties go to the indent of the first indented line
+            let first_tab = lines.iter().find_map(|line| indentation(line)) == Some('\t');
+            let minority = if whitespace.tabs < whitespace.spaces || (whitespace.tabs == whitespace.spaces && !first_tab) { '\t' } else { ' ' };
+            let value = whitespace.tabs.min(whitespace.spaces);
+            if let Some(index) = lines.iter().position(|line| indentation(line) == Some(minority)) {
+                self.check_metric_at(path_str, "mixed_indentation", "Mixed indentation", value as u64, (index + 1, lines[index]), violations);
+            }
+        }
+        if self.has_metric("missing_trailing_newline") && !text.is_empty() && !text.ends_with(b"\n") {
+            let last = lines.len().max(1);
+            self.check_metric_at(path_str, "missing_trailing_newline", "Missing final newline", 1, (last, line_text(last)), violations);
+        }
+        if self.has_metric("max_line_length") {
+            // One violation per line, its length in characters
+            for (index, line) in lines.iter().enumerate() {
+                let length = line.chars().count();
+                if self.metric_rules.iter().any(|r| r.metric_type == "max_line_length" && length as u64 > r.threshold) {
+                    let snippet = match line.char_indices().nth(LONG_LINE_SNIPPET_CHARS) {
+                        Some((end, _)) => format!("{}...", &line[..end]),
+                        None => line.to_string(),
+                    };
+                    self.check_metric_at(path_str, "max_line_length", "Line length", length as u64, (index + 1, &snippet), violations);
+                }
+            }
+        }
+        // The rest come from the syntax tree
+        let language = detect_language(Path::new(path_str), content.as_bytes());
+        let Some(grammar) = get_language_parser(&language) else { return };
+        let Some(tree) = parse(&content, &language, grammar, None) else { return };
+        if self.has_metric("max_nesting_depth") {
+            let (depth, line) = max_nesting_depth(&tree);
+            self.check_metric_at(path_str, "max_nesting_depth", "Nesting depth", depth as u64, (line, line_text(line)), violations);
+        }
+        if self.has_metric("function_line_count") {
+            for function in function_metrics(&tree, &content, &language, grammar) {
+                let lines = function.end_line - function.start_line + 1;
+                let label = format!("Function {} line count", function.qualified_name);
+                let at = (function.start_line, line_text(function.start_line));
+                self.check_metric_at(path_str, "function_line_count", &label, lines as u64, at, violations);
+            }
+        }
+    }
+
+    pub(crate) fn validate_file(&self, path_str: &str, options: &MatchOptions) -> FileFindings {
+        let path = Path::new(path_str);
+        let mut findings = FileFindings::empty();
+
+        // Content metrics need the whole file in memory anyway
+        if self.has_content_metrics() {
+            let Ok(bytes) = read_file(path_str) else { return findings };
+            return self.validate_content(path_str, &bytes, options);
+        }
+
+        // 1. Check Metadata Metrics (Fastest)
+        let metadata = if self.metric_rules.is_empty() { None } else { path.metadata().ok() };
+        if let Some(metadata) = &metadata {
+            self.check_metric(path_str, "size_bytes", "File size", metadata.len(), &mut findings.violations);
+        }
+
+        // 2. Line metrics and regex patterns share a single read of the file
+        let check_lines = metadata.is_some() && self.has_metric("line_count");
+        let active = self.active_rules(path_str);
+        if !check_lines && !active.any() {
+            return findings;
+        }
+        let scan_options = MatchOptions { context_lines: 0, ..options.clone() };
+        let Some(scan) = self.scan_lines(path_str, &scan_options, active) else { return findings };
+
+        if check_lines {
+            self.check_metric(path_str, "line_count", "Line count", scan.line_count as u64, &mut findings.violations);
+        }
+
+        findings.hits = scan.hits;
+        findings.over_budget = scan.over_budget;
+        self.limit_hits(findings, options)
+    }
+
+    // `validate_file` over content supplied by the caller, e.g. a staged blob
+    pub(crate) fn validate_content(&self, path_str: &str, bytes: &[u8], options: &MatchOptions) -> FileFindings {
+        let scan_options = MatchOptions { context_lines: 0, ..options.clone() };
+        self.scan_content(path_str, bytes, &scan_options)
+    }
+
+    // Metric violations and pattern hits of content already in memory, the
+    // hits carrying `options.context_lines` of context
+    fn scan_content(&self, path_str: &str, bytes: &[u8], options: &MatchOptions) -> FileFindings {
+        let mut findings = FileFindings::empty();
+        self.check_metric(path_str, "size_bytes", "File size", bytes.len() as u64, &mut findings.violations);
+        let active = self.active_rules(path_str);
+        let scan = self.scan_bytes(path_str, bytes, options, active);
+        self.check_metric(path_str, "line_count", "Line count", scan.line_count as u64, &mut findings.violations);
+        self.check_content_metrics(path_str, bytes, &mut findings.violations);
+        findings.hits = scan.hits;
+        findings.over_budget = scan.over_budget;
+        self.limit_hits(findings, options)
+    }
+}
+
+/// Everything `scan_all` found: what `get_file_stats`, `match_patterns` and
+/// `validate_files` would each have returned for the same files.
+#[pyclass]
+#[derive(Clone)]
+pub struct ScanAllResult {
+    #[pyo3(get)]
+    pub stats: Vec<FileStats>,
+    #[pyo3(get)]
+    pub hits: Vec<MatchHit>,
+    // Metric violations, then the hits as validation results
+    #[pyo3(get)]
+    pub results: Vec<ValidationResult>,
+}
+
+#[pymethods]
+impl ScanAllResult {
+    fn __repr__(&self) -> String {
+        format!("ScanAllResult(files={}, hits={}, results={})", self.stats.len(), self.hits.len(), self.results.len())
+    }
+}
+
+/// A directory to walk, or the files to scan.
+#[derive(FromPyObject)]
+pub enum ScanTarget {
+    Root(String),
+    Files(Vec<String>),
+}
+
+impl ScanTarget {
+    // The files under a root are found like `discover_files`, except that
+    // binaries are kept for their stats; they come with the root they are
+    // below, the working directory for a list of files
+    fn files(self, py: Python<'_>, use_gitignore: bool, max_size_mb: Option<u64>, cancel_token: &Option<CancellationToken>) -> (PathBuf, Vec<String>) {
+        let root = match self {
+            ScanTarget::Files(files) => return (scan_root(None), files),
+            ScanTarget::Root(root) => root,
+        };
+        let size_limit = size_limit_bytes(max_size_mb);
+        let files = py.allow_threads(|| {
+            build_walker(&root, use_gitignore)
+                .take_while(|_| !stop_here(cancel_token))
+                .flatten()
+                .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_file()))
+                .filter(|entry| entry.metadata().is_ok_and(|m| m.len() <= size_limit))
+                .map(|entry| entry.path().to_string_lossy().to_string())
+                .collect()
+        });
+        (PathBuf::from(root), files)
+    }
+}
+
+/// Rule set compiled once and reused across scans.
+///
+/// Watch mode and per-frame scans call `scan`/`match_files` repeatedly with the
+/// same rules; holding a `Scanner` skips regex compilation on every call.
+#[pyclass]
+pub struct Scanner {
+    rules: RuleSet,
+    // Rules that overran their time budget during the latest scan
+    budget_reports: Mutex<Vec<BudgetReport>>,
+}
+
+impl Scanner {
+    // Runs `scan_one` over every file, then the cross-file hit limits
+    fn findings_with<S, F>(&self, py: Python<'_>, files: &[S], control: ScanControl, options: &MatchOptions, scan_one: F) -> PyResult<Vec<FileFindings>>
+    where
+        S: ScanItem,
+        F: Fn(&S) -> FileFindings + Sync + Send,
+    {
+        let mut findings = control.run(py, files, |path| Some(scan_one(path)))?;
+        self.finish_findings(py, &mut findings, options)?;
+        Ok(findings)
+    }
+
+    fn finish_findings(&self, py: Python<'_>, findings: &mut [FileFindings], options: &MatchOptions) -> PyResult<()> {
+        if let Some(max) = options.max_hits_per_rule {
+            cap_hits_per_rule(findings, max);
+        }
+        let reports: Vec<BudgetReport> = findings.iter_mut().flat_map(|f| std::mem::take(&mut f.over_budget)).collect();
+        self.record_budget_reports(py, reports)
+    }
+
+    fn record_budget_reports(&self, py: Python<'_>, reports: Vec<BudgetReport>) -> PyResult<()> {
+        warn_over_budget(py, &reports)?;
+        *self.budget_reports.lock().unwrap() = reports;
+        Ok(())
+    }
+
+    fn strings_with(&self, py: Python<'_>, files: Vec<String>, control: ScanControl, min_len: usize) -> PyResult<Vec<BinaryStringHit>> {
+        if self.rules.line_rules.is_empty() {
+            return Ok(Vec::new());
+        }
+        let per_file = control.run(py, &files, |path| Some(self.rules.match_strings(path, min_len)))?;
+        let (hits, reports): (Vec<_>, Vec<_>) = per_file.into_iter().unzip();
+        self.record_budget_reports(py, reports.into_iter().flatten().collect())?;
+        Ok(hits.into_iter().flatten().collect())
+    }
+
+    fn match_with(&self, py: Python<'_>, files: Vec<String>, control: ScanControl, options: &MatchOptions, cache: Option<&ScanCache>) -> PyResult<Vec<MatchHit>> {
+        if !self.rules.has_regex_rules() {
+            return Ok(Vec::new());
+        }
+        let findings = self.findings_with(py, &files, control, options, |path| match cache {
+            Some(cache) => self.rules.match_file_cached(path, options, cache),
+            None => self.rules.match_file(path, options),
+        })?;
+        Ok(findings.into_iter().flat_map(|f| f.hits).collect())
+    }
+
+    fn match_content_with(&self, py: Python<'_>, items: Vec<(String, String)>, control: ScanControl, options: &MatchOptions) -> PyResult<Vec<MatchHit>> {
+        if !self.rules.has_regex_rules() {
+            return Ok(Vec::new());
+        }
+        let findings = self.findings_with(py, &items, control, options, |(path, content)| {
+            self.rules.match_content(path, content, options)
+        })?;
+        Ok(findings.into_iter().flat_map(|f| f.hits).collect())
+    }
+
+    fn staged_with(&self, py: Python<'_>, repo: &str, paths: Vec<String>, control: ScanControl, options: &MatchOptions) -> PyResult<Vec<ValidationResult>> {
+        let blobs = py.allow_threads(|| staged_blobs(repo, &paths))?;
+        let findings = self.findings_with(py, &blobs, control, options, |(path, bytes)| {
+            self.rules.validate_content(path, bytes, options)
+        })?;
+        Ok(findings.into_iter().flat_map(FileFindings::into_results).collect())
+    }
+
+    fn git_history_with(&self, py: Python<'_>, repo: &str, max_commits: Option<usize>, control: ScanControl, options: &MatchOptions) -> PyResult<Vec<GitHistoryHit>> {
+        if self.rules.line_rules.is_empty() {
+            return Ok(Vec::new());
+        }
+        let history = py.allow_threads(|| GitHistory::open(repo))?;
+        let per_change = history.scan_changes(py, repo, max_commits, control, |change| {
+            let lines = history.added_lines(change);
+            let findings = self.rules.match_lines(&change.path, &lines, || history.blob(change), options);
+            let hits: Vec<GitHistoryHit> = findings.hits.into_iter()
+                .map(|hit| GitHistoryHit { commit: (*change.commit).clone(), hit })
+                .collect();
+            Some((hits, findings.over_budget))
+        })?;
+        let (hits, reports): (Vec<_>, Vec<_>) = per_change.into_iter().unzip();
+        self.record_budget_reports(py, reports.into_iter().flatten().collect())?;
+        Ok(hits.into_iter().flatten().collect())
+    }
+
+    // Reads each file once for its stats, hits and metric violations;
+    // binaries are matched too, as `match_files` and `scan` would
+    #[allow(clippy::too_many_arguments)]
+    fn all_with(&self, py: Python<'_>, root: &Path, files: Vec<String>, control: ScanControl, options: &MatchOptions, algo: HashAlgo, exact_hash: bool) -> PyResult<ScanAllResult> {
+        let per_file = control.run(py, &files, |path| {
+            let bytes = read_file(path).ok()?;
+            let mut stats = content_stats(path, root, &bytes, algo, exact_hash);
+            stats.mode = std::fs::metadata(path).ok().and_then(|metadata| mode_bits(&metadata));
+            let findings = self.rules.scan_content(path, &bytes, options);
+            Some((stats, findings))
+        })?;
+        let (stats, mut findings): (Vec<_>, Vec<_>) = per_file.into_iter().unzip();
+        self.finish_findings(py, &mut findings, options)?;
+        let mut result = ScanAllResult { stats, hits: Vec::new(), results: Vec::new() };
+        for file in findings {
+            result.results.extend(file.violations);
+            result.results.extend(file.hits.iter().cloned().map(ValidationResult::from));
+            result.hits.extend(file.hits);
+        }
+        Ok(result)
+    }
+
+    fn validate_with(&self, py: Python<'_>, files: Vec<String>, control: ScanControl, options: &MatchOptions, cache: Option<&ScanCache>) -> PyResult<Vec<ValidationResult>> {
+        let findings = self.findings_with(py, &files, control, options, |path| match cache {
+            Some(cache) => self.rules.validate_file_cached(path, options, cache),
+            None => self.rules.validate_file(path, options),
+        })?;
+        Ok(findings.into_iter().flat_map(FileFindings::into_results).collect())
+    }
+}
+
+#[pymethods]
+impl Scanner {
+    /// Rules that fail to compile are skipped with a UserWarning, or raise
+    /// ValueError with `strict=True`; either way they end up in `rule_errors`.
+    ///
+    /// `regex_size_limit` caps a rule's compiled size in bytes. A rule whose
+    /// single search takes longer than `line_budget_ms`, or whose searches on
+    /// one file add up to more than `file_budget_ms`, is dropped for the rest
+    /// of that file and listed in `budget_reports`; None disables a budget.
+    #[new]
+    #[pyo3(signature = (regex_rules=Vec::new(), metric_rules=Vec::new(), strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS)))]
+    fn new(
+        py: Python<'_>,
+        regex_rules: Vec<RustRule>,
+        metric_rules: Vec<MetricRule>,
+        strict: bool,
+        regex_size_limit: usize,
+        line_budget_ms: Option<u64>,
+        file_budget_ms: Option<u64>,
+    ) -> PyResult<Self> {
+        let limits = RegexLimits::new(regex_size_limit, line_budget_ms, file_budget_ms);
+        let rules = RuleSet::compile(regex_rules, metric_rules, limits);
+        report_rule_errors(py, &rules.errors, strict)?;
+        Ok(Scanner { rules, budget_reports: Mutex::new(Vec::new()) })
+    }
+
+    #[getter]
+    fn rule_errors(&self) -> Vec<RuleCompileReport> {
+        self.rules.errors.clone()
+    }
+
+    #[getter]
+    fn budget_reports(&self) -> Vec<BudgetReport> {
+        self.budget_reports.lock().unwrap().clone()
+    }
+
+    // Identifies this scanner's regex rules in a `ScanCache`
+    #[getter]
+    fn ruleset_hash(&self) -> String {
+        self.rules.fingerprint.clone()
+    }
+
+    /// Same results as `validate_files` with this scanner's rules. With a
+    /// `ScanCache`, files whose content was already scanned with these rules
+    /// are not matched again.
+    #[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, changed_ranges=None, cache=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn scan(
+        &self,
+        py: Python<'_>,
+        files: Vec<String>,
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+        max_hits_per_file: Option<usize>,
+        max_hits_per_rule: Option<usize>,
+        decode_blobs: bool,
+        changed_ranges: Option<ChangedRanges>,
+        cache: Option<PyRef<'_, ScanCache>>,
+    ) -> PyResult<Vec<ValidationResult>> {
+        let changed_ranges = changed_ranges.map(Arc::new);
+        let options = MatchOptions { context_lines: 0, max_hits_per_file, max_hits_per_rule, decode_blobs, changed_ranges };
+        let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+        self.validate_with(py, files, control, &options, cache.as_deref())
+    }
+
+    /// `scan` over the staged version of each path in the git repository at
+    /// `repo`, exactly as it would be committed, for pre-commit checks that
+    /// must ignore unstaged edits. Paths are relative to the work tree or
+    /// absolute inside it and are reported as given; paths with nothing
+    /// staged are skipped.
+    #[pyo3(signature = (repo, paths, progress=None, progress_every=100, cancel_token=None, threads=None, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn scan_staged(
+        &self,
+        py: Python<'_>,
+        repo: String,
+        paths: Vec<String>,
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+        max_hits_per_file: Option<usize>,
+        max_hits_per_rule: Option<usize>,
+        decode_blobs: bool,
+    ) -> PyResult<Vec<ValidationResult>> {
+        let options = MatchOptions { max_hits_per_file, max_hits_per_rule, decode_blobs, ..Default::default() };
+        let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+        self.staged_with(py, &repo, paths, control, &options)
+    }
+
+    /// Same results as `match_patterns` with this scanner's regex rules;
+    /// `cache` as in `scan`.
+    #[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None, context_lines=0, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, changed_ranges=None, cache=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn match_files(
+        &self,
+        py: Python<'_>,
+        files: Vec<String>,
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+        context_lines: usize,
+        max_hits_per_file: Option<usize>,
+        max_hits_per_rule: Option<usize>,
+        decode_blobs: bool,
+        changed_ranges: Option<ChangedRanges>,
+        cache: Option<PyRef<'_, ScanCache>>,
+    ) -> PyResult<Vec<MatchHit>> {
+        let changed_ranges = changed_ranges.map(Arc::new);
+        let options = MatchOptions { context_lines, max_hits_per_file, max_hits_per_rule, decode_blobs, changed_ranges };
+        let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+        self.match_with(py, files, control, &options, cache.as_deref())
+    }
+
+    /// `match_files` over `(path, content)` pairs, e.g. unsaved editor
+    /// buffers. Nothing is read from disk; `path` is used for reporting and
+    /// for the rules' path globs.
+    #[pyo3(signature = (items, progress=None, progress_every=100, cancel_token=None, threads=None, context_lines=0, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn match_content(
+        &self,
+        py: Python<'_>,
+        items: Vec<(String, String)>,
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+        context_lines: usize,
+        max_hits_per_file: Option<usize>,
+        max_hits_per_rule: Option<usize>,
+        decode_blobs: bool,
+    ) -> PyResult<Vec<MatchHit>> {
+        let options = MatchOptions { context_lines, max_hits_per_file, max_hits_per_rule, decode_blobs, changed_ranges: None };
+        let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+        self.match_content_with(py, items, control, &options)
+    }
+
+    /// Runs the regex rules over the printable ASCII and UTF-16LE strings of
+    /// at least `min_len` characters in each file's raw bytes, for binaries
+    /// (.pyc, compiled artifacts) that the text scans skip. `multiline` rules
+    /// do not apply, as no string spans a line break.
+    #[pyo3(signature = (files, min_len=4, progress=None, progress_every=100, cancel_token=None, threads=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn scan_binary_strings(
+        &self,
+        py: Python<'_>,
+        files: Vec<String>,
+        min_len: usize,
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+    ) -> PyResult<Vec<BinaryStringHit>> {
+        let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+        self.strings_with(py, files, control, min_len)
+    }
+
+    /// Runs the regex rules over the lines each commit reachable from HEAD
+    /// added, so a secret that was committed and later removed is still
+    /// found. Only the newest `max_commits` commits are walked when set.
+    /// `multiline` rules do not apply, as a diff has no whole-file content.
+    ///
+    /// Merge commits are skipped, though they count towards `max_commits`:
+    /// the lines they bring in were added by the commits being merged. The
+    /// changes are scanned while the walk is still finding them, so
+    /// `progress` gets 0 for `files_total`, and setting `cancel_token` stops
+    /// the walk too.
+    #[pyo3(signature = (repo, max_commits=None, progress=None, progress_every=100, cancel_token=None, threads=None, decode_blobs=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn scan_git_history(
+        &self,
+        py: Python<'_>,
+        repo: String,
+        max_commits: Option<usize>,
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+        decode_blobs: bool,
+    ) -> PyResult<Vec<GitHistoryHit>> {
+        let options = MatchOptions { decode_blobs, ..Default::default() };
+        let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+        self.git_history_with(py, &repo, max_commits, control, &options)
+    }
+
+    /// `get_file_stats`, `match_files` and `scan` in one pass that reads
+    /// each file once, for pipelines that need all three. `root_or_files` is
+    /// either a directory, walked like `discover_files` but keeping binaries,
+    /// or a list of files. Binaries are matched like any other file, as in
+    /// `match_files`; their stats have `is_binary` set. The stats classify
+    /// files by the directories below the root, or below the working
+    /// directory for a list of files.
+    #[pyo3(signature = (root_or_files, progress=None, progress_every=100, cancel_token=None, threads=None, context_lines=0, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, hash_algo="sha256", exact_hash=false, use_gitignore=true, max_size_mb=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn scan_all(
+        &self,
+        py: Python<'_>,
+        root_or_files: ScanTarget,
+        progress: Option<PyObject>,
+        progress_every: usize,
+        cancel_token: Option<CancellationToken>,
+        threads: Option<usize>,
+        context_lines: usize,
+        max_hits_per_file: Option<usize>,
+        max_hits_per_rule: Option<usize>,
+        decode_blobs: bool,
+        hash_algo: &str,
+        exact_hash: bool,
+        use_gitignore: bool,
+        max_size_mb: Option<u64>,
+    ) -> PyResult<ScanAllResult> {
+        let algo = HashAlgo::parse(hash_algo)?;
+        let (root, files) = root_or_files.files(py, use_gitignore, max_size_mb, &cancel_token);
+        let options = MatchOptions { context_lines, max_hits_per_file, max_hits_per_rule, decode_blobs, changed_ranges: None };
+        let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+        self.all_with(py, &root, files, control, &options, algo, exact_hash)
+    }
+}
+
+/// With `changed_ranges` (path to 1-based, inclusive `(start, end)` line
+/// ranges, e.g. the hunks of a pull request) only hits touching those lines
+/// are returned; files without an entry are not scanned.
+#[pyfunction]
+#[pyo3(signature = (files, rules, progress=None, progress_every=100, cancel_token=None, threads=None, context_lines=0, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS), changed_ranges=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn match_patterns(
+    py: Python<'_>,
+    files: Vec<String>,
+    rules: Vec<RustRule>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    context_lines: usize,
+    max_hits_per_file: Option<usize>,
+    max_hits_per_rule: Option<usize>,
+    decode_blobs: bool,
+    strict: bool,
+    regex_size_limit: usize,
+    line_budget_ms: Option<u64>,
+    file_budget_ms: Option<u64>,
+    changed_ranges: Option<ChangedRanges>,
+) -> PyResult<Vec<MatchHit>> {
+    let changed_ranges = changed_ranges.map(Arc::new);
+    let options = MatchOptions { context_lines, max_hits_per_file, max_hits_per_rule, decode_blobs, changed_ranges };
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    Scanner::new(py, rules, Vec::new(), strict, regex_size_limit, line_budget_ms, file_budget_ms)?
+        .match_with(py, files, control, &options, None)
+}
+
+/// `match_patterns` over in-memory `(path, content)` pairs instead of files.
+#[pyfunction]
+#[pyo3(signature = (items, rules, progress=None, progress_every=100, cancel_token=None, threads=None, context_lines=0, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS)))]
+#[allow(clippy::too_many_arguments)]
+pub fn match_patterns_content(
+    py: Python<'_>,
+    items: Vec<(String, String)>,
+    rules: Vec<RustRule>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    context_lines: usize,
+    max_hits_per_file: Option<usize>,
+    max_hits_per_rule: Option<usize>,
+    decode_blobs: bool,
+    strict: bool,
+    regex_size_limit: usize,
+    line_budget_ms: Option<u64>,
+    file_budget_ms: Option<u64>,
+) -> PyResult<Vec<MatchHit>> {
+    let options = MatchOptions { context_lines, max_hits_per_file, max_hits_per_rule, decode_blobs, changed_ranges: None };
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    Scanner::new(py, rules, Vec::new(), strict, regex_size_limit, line_budget_ms, file_budget_ms)?
+        .match_content_with(py, items, control, &options)
+}
+
+/// See `Scanner.scan_binary_strings`.
+#[pyfunction]
+#[pyo3(signature = (files, rules, min_len=4, progress=None, progress_every=100, cancel_token=None, threads=None, strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS)))]
+#[allow(clippy::too_many_arguments)]
+pub fn scan_binary_strings(
+    py: Python<'_>,
+    files: Vec<String>,
+    rules: Vec<RustRule>,
+    min_len: usize,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    strict: bool,
+    regex_size_limit: usize,
+    line_budget_ms: Option<u64>,
+    file_budget_ms: Option<u64>,
+) -> PyResult<Vec<BinaryStringHit>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    Scanner::new(py, rules, Vec::new(), strict, regex_size_limit, line_budget_ms, file_budget_ms)?
+        .strings_with(py, files, control, min_len)
+}
+
+/// See `Scanner.scan_git_history`.
+#[pyfunction]
+#[pyo3(signature = (repo, rules, max_commits=None, progress=None, progress_every=100, cancel_token=None, threads=None, decode_blobs=false, strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS)))]
+#[allow(clippy::too_many_arguments)]
+pub fn scan_git_history(
+    py: Python<'_>,
+    repo: String,
+    rules: Vec<RustRule>,
+    max_commits: Option<usize>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    decode_blobs: bool,
+    strict: bool,
+    regex_size_limit: usize,
+    line_budget_ms: Option<u64>,
+    file_budget_ms: Option<u64>,
+) -> PyResult<Vec<GitHistoryHit>> {
+    let options = MatchOptions { decode_blobs, ..Default::default() };
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    Scanner::new(py, rules, Vec::new(), strict, regex_size_limit, line_budget_ms, file_budget_ms)?
+        .git_history_with(py, &repo, max_commits, control, &options)
+}
+
+/// `changed_ranges` filters pattern hits as in `match_patterns`; metric
+/// violations concern the whole file and are always reported.
+#[pyfunction]
+#[pyo3(signature = (files, regex_rules, metric_rules, progress=None, progress_every=100, cancel_token=None, threads=None, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS), changed_ranges=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn validate_files(
+    py: Python<'_>,
+    files: Vec<String>,
+    regex_rules: Vec<RustRule>,
+    metric_rules: Vec<MetricRule>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    max_hits_per_file: Option<usize>,
+    max_hits_per_rule: Option<usize>,
+    decode_blobs: bool,
+    strict: bool,
+    regex_size_limit: usize,
+    line_budget_ms: Option<u64>,
+    file_budget_ms: Option<u64>,
+    changed_ranges: Option<ChangedRanges>,
+) -> PyResult<Vec<ValidationResult>> {
+    let changed_ranges = changed_ranges.map(Arc::new);
+    let options = MatchOptions { context_lines: 0, max_hits_per_file, max_hits_per_rule, decode_blobs, changed_ranges };
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    Scanner::new(py, regex_rules, metric_rules, strict, regex_size_limit, line_budget_ms, file_budget_ms)?
+        .validate_with(py, files, control, &options, None)
+}
+
+/// See `Scanner.scan_staged`.
+#[pyfunction]
+#[pyo3(signature = (repo, paths, regex_rules, metric_rules, progress=None, progress_every=100, cancel_token=None, threads=None, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS)))]
+#[allow(clippy::too_many_arguments)]
+pub fn validate_staged(
+    py: Python<'_>,
+    repo: String,
+    paths: Vec<String>,
+    regex_rules: Vec<RustRule>,
+    metric_rules: Vec<MetricRule>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    max_hits_per_file: Option<usize>,
+    max_hits_per_rule: Option<usize>,
+    decode_blobs: bool,
+    strict: bool,
+    regex_size_limit: usize,
+    line_budget_ms: Option<u64>,
+    file_budget_ms: Option<u64>,
+) -> PyResult<Vec<ValidationResult>> {
+    let options = MatchOptions { max_hits_per_file, max_hits_per_rule, decode_blobs, ..Default::default() };
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    Scanner::new(py, regex_rules, metric_rules, strict, regex_size_limit, line_budget_ms, file_budget_ms)?
+        .staged_with(py, &repo, paths, control, &options)
+}
+
+/// See `Scanner.scan_all`.
+#[pyfunction]
+#[pyo3(signature = (root_or_files, regex_rules, metric_rules, progress=None, progress_every=100, cancel_token=None, threads=None, context_lines=0, max_hits_per_file=None, max_hits_per_rule=None, decode_blobs=false, hash_algo="sha256", exact_hash=false, use_gitignore=true, max_size_mb=None, strict=false, regex_size_limit=DEFAULT_REGEX_SIZE_LIMIT, line_budget_ms=Some(DEFAULT_LINE_BUDGET_MS), file_budget_ms=Some(DEFAULT_FILE_BUDGET_MS)))]
+#[allow(clippy::too_many_arguments)]
+pub fn scan_all(
+    py: Python<'_>,
+    root_or_files: ScanTarget,
+    regex_rules: Vec<RustRule>,
+    metric_rules: Vec<MetricRule>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+    context_lines: usize,
+    max_hits_per_file: Option<usize>,
+    max_hits_per_rule: Option<usize>,
+    decode_blobs: bool,
+    hash_algo: &str,
+    exact_hash: bool,
+    use_gitignore: bool,
+    max_size_mb: Option<u64>,
+    strict: bool,
+    regex_size_limit: usize,
+    line_budget_ms: Option<u64>,
+    file_budget_ms: Option<u64>,
+) -> PyResult<ScanAllResult> {
+    Scanner::new(py, regex_rules, metric_rules, strict, regex_size_limit, line_budget_ms, file_budget_ms)?.scan_all(
+        py, root_or_files, progress, progress_every, cancel_token, threads, context_lines, max_hits_per_file,
+        max_hits_per_rule, decode_blobs, hash_algo, exact_hash, use_gitignore, max_size_mb,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, multiline: bool, node_kinds: &[&str]) -> RustRule {
+        let node_kinds = node_kinds.iter().map(|kind| kind.to_string()).collect();
+        RustRule::new(
+            "secret".to_string(), pattern.to_string(), multiline, String::new(), Vec::new(), Vec::new(),
+            None, None, None, None, false, node_kinds,
+        )
+    }
+
+    fn scanner(py: Python<'_>, rules: Vec<RustRule>, strict: bool) -> PyResult<Scanner> {
+        Scanner::new(py, rules, Vec::new(), strict, DEFAULT_REGEX_SIZE_LIMIT, None, None)
+    }
+
+    fn content_hits(py: Python<'_>, scanner: &Scanner, file_path: &str, content: &str) -> Vec<(String, usize)> {
+        let items = vec![(file_path.to_string(), content.to_string())];
+        let hits = scanner.match_content(py, items, None, 100, None, None, 0, None, None, false).unwrap();
+        hits.into_iter().map(|hit| (hit.rule_id, hit.line_number)).collect()
+    }
+
+    #[test]
+    fn a_scanner_is_reused_across_scans() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let scanner = scanner(py, vec![rule("secret", false, &[])], false).unwrap();
+            assert_eq!(content_hits(py, &scanner, "a.py", "secret = 1\n"), vec![("secret".to_string(), 1)]);
+            assert_eq!(content_hits(py, &scanner, "b.py", "x = 1\nsecret = 2\n"), vec![("secret".to_string(), 2)]);
+        });
+    }
+
+    #[test]
+    fn rules_that_fail_to_compile_are_reported_or_raise() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let rules = || vec![rule("secret", false, &[]), RustRule { id: "broken".to_string(), ..rule("(unclosed", false, &[]) }];
+            let lenient = scanner(py, rules(), false).unwrap();
+            assert_eq!(lenient.rule_errors().iter().map(|error| error.rule_id.as_str()).collect::<Vec<_>>(), vec!["broken"]);
+            assert_eq!(content_hits(py, &lenient, "a.py", "secret\n").len(), 1);
+            let strict = scanner(py, rules(), true);
+            assert!(strict.is_err_and(|e| e.is_instance_of::<pyo3::exceptions::PyValueError>(py)));
+        });
+    }
+
+    #[test]
+    fn the_ruleset_hash_follows_the_rules() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let hash = |pattern: &str| scanner(py, vec![rule(pattern, false, &[])], false).unwrap().ruleset_hash();
+            assert_eq!(hash("secret"), hash("secret"));
+            assert_ne!(hash("secret"), hash("token"));
+        });
+    }
+
+    fn hit_lines(rule: RustRule, file_path: &str, content: &str) -> Vec<usize> {
+        let limits = RegexLimits::new(DEFAULT_REGEX_SIZE_LIMIT, None, None);
+        let rules = RuleSet::compile(vec![rule], Vec::new(), limits);
+        let findings = rules.scan_content(file_path, content.as_bytes(), &MatchOptions::default());
+        findings.hits.iter().map(|hit| hit.line_number).collect()
+    }
+
+    const CONTENT: &str = "secret = load()\ntoken = \"secret\"\n# the secret is above\n";
+
+    #[test]
+    fn a_rule_over_its_budget_keeps_its_earlier_hits_and_stops_for_the_file() {
+        // No search is ever this fast, so the rule overruns on its first line
+        let limits = RegexLimits::new(DEFAULT_REGEX_SIZE_LIMIT, Some(0), None);
+        let rules = RuleSet::compile(vec![rule("secret", false, &[])], Vec::new(), limits);
+        let findings = rules.scan_content("app.py", CONTENT.as_bytes(), &MatchOptions::default());
+        assert_eq!(findings.hits.iter().map(|hit| hit.line_number).collect::<Vec<_>>(), vec![1]);
+        let reports: Vec<_> = findings.over_budget.iter().map(|r| (r.rule_id.as_str(), r.line_number, r.budget.as_str())).collect();
+        assert_eq!(reports, vec![("secret", Some(1), "line")]);
+    }
+
+    #[test]
+    fn node_kinds_keep_only_matches_inside_those_nodes() {
+        assert_eq!(hit_lines(rule("secret", false, &[]), "app.py", CONTENT), vec![1, 2, 3]);
+        assert_eq!(hit_lines(rule("secret", false, &["string"]), "app.py", CONTENT), vec![2]);
+        assert_eq!(hit_lines(rule("secret", false, &["comment"]), "app.py", CONTENT), vec![3]);
+    }
+
+    #[test]
+    fn node_kinds_take_the_first_admitted_match_on_a_line() {
+        let content = "secret = \"secret\"\n";
+        let limits = RegexLimits::new(DEFAULT_REGEX_SIZE_LIMIT, None, None);
+        let rules = RuleSet::compile(vec![rule("secret", false, &["string"])], Vec::new(), limits);
+        let findings = rules.scan_content("app.py", content.as_bytes(), &MatchOptions::default());
+        assert_eq!(findings.hits.iter().map(|hit| hit.column).collect::<Vec<_>>(), vec![11]);
+    }
+
+    #[test]
+    fn node_kinds_apply_to_multiline_rules() {
+        let content = "x = \"\"\"begin\nend\"\"\"\n# begin\n# end\n";
+        assert_eq!(hit_lines(rule(r"begin\s+end", true, &["string"]), "app.py", content), vec![1]);
+    }
+
+    #[test]
+    fn node_kinds_never_match_without_a_grammar() {
+        assert!(hit_lines(rule("secret", false, &["string"]), "notes.txt", CONTENT).is_empty());
+    }
+
+    #[test]
+    fn a_file_reads_the_same_streamed_or_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.py");
+        // ASCII for longer than the streamed head, then Latin-1
+        let content = [b"x = 1\n".repeat(2000), b"caf\xe9 password=1\n".to_vec()].concat();
+        std::fs::write(&path, content).unwrap();
+        let path = path.to_str().unwrap();
+        let rules = RuleSet::compile(vec![rule(r"password=\d", false, &[])], Vec::new(), RegexLimits::new(DEFAULT_REGEX_SIZE_LIMIT, None, None));
+        let hits = |context_lines| {
+            let options = MatchOptions { context_lines, ..MatchOptions::default() };
+            let scan = rules.scan_lines(path, &options, rules.active_rules(path)).unwrap();
+            scan.hits.into_iter().map(|hit| (hit.line_number, hit.column, hit.snippet, hit.had_encoding_errors)).collect::<Vec<_>>()
+        };
+        let expected = vec![(2001, 7, "caf\u{e9} password=1".to_string(), false)];
+        assert_eq!(hits(0), expected);
+        assert_eq!(hits(1), expected);
+    }
+
+    #[test]
+    fn scan_all_stats_match_get_file_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.sh");
+        std::fs::write(&path, "#!/bin/sh\necho password=1\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+        }
+        let path = path.to_str().unwrap().to_string();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let scanner = scanner(py, vec![rule(r"password=\d", false, &[])], false).unwrap();
+            let control = ScanControl::new(None, 100, None, None);
+            let result = scanner
+                .all_with(py, dir.path(), vec![path.clone()], control, &MatchOptions::default(), HashAlgo::Sha256, false)
+                .unwrap();
+            let alone = crate::file_stats(&path, dir.path(), HashAlgo::Sha256, false);
+            let stats = &result.stats[0];
+            assert_eq!((stats.mode, &stats.hash, &stats.interpreter), (alone.mode, &alone.hash, &alone.interpreter));
+            #[cfg(unix)]
+            assert_eq!(stats.mode, Some(0o750));
+            assert_eq!(result.hits.len(), 1);
+        });
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Dev", "-c", "user.email=dev@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    fn history_hits(py: Python<'_>, repo: &Path, cancel_token: Option<CancellationToken>) -> Vec<(String, usize)> {
+        let scanner = scanner(py, vec![rule(r"password=\d", false, &[])], false).unwrap();
+        let control = ScanControl::new(None, 100, cancel_token, None);
+        let hits = scanner.git_history_with(py, repo.to_str().unwrap(), None, control, &MatchOptions::default()).unwrap();
+        hits.into_iter().map(|hit| (hit.hit.matched_text, hit.hit.line_number)).collect()
+    }
+
+    #[test]
+    fn git_history_finds_removed_lines_newest_first_and_stops_when_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "-q"]);
+        std::fs::write(dir.path().join("a.py"), "password=1\n").unwrap();
+        git(dir.path(), &["add", "a.py"]);
+        git(dir.path(), &["commit", "-qm", "first"]);
+        std::fs::write(dir.path().join("a.py"), "x = 1\npassword=2\n").unwrap();
+        git(dir.path(), &["commit", "-qam", "second"]);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let found = history_hits(py, dir.path(), None);
+            assert_eq!(found, vec![("password=2".to_string(), 2), ("password=1".to_string(), 1)]);
+
+            let token = CancellationToken::default();
+            token.cancel();
+            assert!(history_hits(py, dir.path(), Some(token.clone())).is_empty());
+            assert!(token.truncated());
+        });
+    }
+
+    #[test]
+    fn the_cache_answers_unchanged_files_and_rescans_changed_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.py");
+        let files = vec![file.to_str().unwrap().to_string()];
+        let cache_path = dir.path().join("scan.cache").to_str().unwrap().to_string();
+        std::fs::write(&file, "password=1\n").unwrap();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let open = || py.get_type::<ScanCache>().call1((cache_path.clone(),)).unwrap();
+            let counts = |cache: &Bound<'_, PyAny>| -> (usize, usize) {
+                (cache.getattr("hits").unwrap().extract().unwrap(), cache.getattr("misses").unwrap().extract().unwrap())
+            };
+            let lines = |scanner: &Scanner, cache: &Bound<'_, PyAny>| -> Vec<usize> {
+                let cache = cache.extract::<PyRef<'_, ScanCache>>().unwrap();
+                let hits = scanner.match_files(py, files.clone(), None, 100, None, None, 0, None, None, false, None, Some(cache)).unwrap();
+                hits.into_iter().map(|hit| hit.line_number).collect()
+            };
+            let secrets = scanner(py, vec![rule(r"password=\d", false, &[])], false).unwrap();
+            let cache = open();
+            assert_eq!(lines(&secrets, &cache), vec![1]);
+            assert_eq!(lines(&secrets, &cache), vec![1]);
+            assert_eq!(counts(&cache), (1, 1));
+
+            std::fs::write(&file, "x = 1\npassword=2\n").unwrap();
+            assert_eq!(lines(&secrets, &cache), vec![2]);
+            assert_eq!(counts(&cache), (1, 2));
+
+            let other_rules = scanner(py, vec![rule(r"x = \d", false, &[])], false).unwrap();
+            assert_eq!(lines(&other_rules, &cache), vec![1]);
+            assert_eq!(counts(&cache), (1, 3));
+
+            cache.call_method0("save").unwrap();
+            let reloaded = open();
+            assert_eq!(reloaded.len().unwrap(), 2);
+            assert_eq!(lines(&secrets, &reloaded), vec![2]);
+            assert_eq!(counts(&reloaded), (1, 0));
+        });
+    }
+}
@@ -0,0 +1,144 @@
+use crate::ast::{get_language_parser, parse, tree_metadata, AstMetadata};
+use crate::detect_language;
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use tree_sitter::{InputEdit, Point, Tree};
+
+// One `(start_byte, old_end_byte, new_text)` edit: the bytes in
+// `start_byte..old_end_byte` of the current source are replaced by `new_text`
+type Edit = (usize, usize, String);
+
+// What a session remembers about one file
+struct SessionFile {
+    language: String,
+    source: String,
+    // None when the language has no grammar or the parser gave up
+    tree: Option<Tree>,
+}
+
+impl SessionFile {
+    fn metadata(&self) -> AstMetadata {
+        match (&self.tree, get_language_parser(&self.language)) {
+            (Some(tree), Some(grammar)) => tree_metadata(tree, &self.source, &self.language, grammar),
+            (None, Some(_)) => AstMetadata::unparsed(),
+            _ => AstMetadata::empty(),
+        }
+    }
+}
+
+// Row and byte column of `offset` in `text`, as tree-sitter counts them
+fn point_at(text: &str, offset: usize) -> Point {
+    let before = &text.as_bytes()[..offset];
+    let row = before.iter().filter(|&&b| b == b'\n').count();
+    let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    Point { row, column: offset - line_start }
+}
+
+fn check_edit(source: &str, (start, old_end, _): &Edit) -> PyResult<()> {
+    if start > old_end || *old_end > source.len() {
+        return Err(PyValueError::new_err(format!(
+            "Edit {}..{} is outside the {}-byte source", start, old_end, source.len()
+        )));
+    }
+    if !source.is_char_boundary(*start) || !source.is_char_boundary(*old_end) {
+        return Err(PyValueError::new_err(format!("Edit {}..{} splits a UTF-8 character", start, old_end)));
+    }
+    Ok(())
+}
+
+/// Keeps the parse tree of every file it was given so that, after small
+/// edits, re-parsing reuses the unchanged parts of the old tree instead of
+/// starting over — for watch mode and editor integrations.
+///
+/// Files are keyed by path. `parse` (re)loads a file's whole source; `edit`
+/// applies `(start_byte, old_end_byte, new_text)` replacements, in order and
+/// with byte offsets into the source as it stands after the previous ones,
+/// like an LSP content change. Both return the file's `AstMetadata`.
+#[pyclass]
+pub struct ParseSession {
+    files: HashMap<String, SessionFile>,
+}
+
+#[pymethods]
+impl ParseSession {
+    #[new]
+    fn new() -> Self {
+        ParseSession { files: HashMap::new() }
+    }
+
+    /// Parses `content` from scratch and remembers it under `path`. The
+    /// language comes from the path's extension unless given.
+    #[pyo3(signature = (path, content, language=None))]
+    fn parse(&mut self, py: Python<'_>, path: String, content: String, language: Option<String>) -> AstMetadata {
+        let language = language.unwrap_or_else(|| detect_language(Path::new(&path), content.as_bytes()));
+        let (file, metadata) = py.allow_threads(|| {
+            let tree = get_language_parser(&language).and_then(|grammar| parse(&content, &language, grammar, None));
+            let file = SessionFile { language, source: content, tree };
+            let metadata = file.metadata();
+            (file, metadata)
+        });
+        self.files.insert(path, file);
+        metadata
+    }
+
+    /// Applies `edits` to the source remembered for `path` and re-parses it
+    /// incrementally. Raises KeyError for a path never parsed, and
+    /// ValueError, leaving the file as it was, for an edit outside the
+    /// source or splitting a character.
+    fn edit(&mut self, py: Python<'_>, path: &str, edits: Vec<Edit>) -> PyResult<AstMetadata> {
+        let Some(file) = self.files.get_mut(path) else {
+            return Err(PyKeyError::new_err(path.to_string()));
+        };
+        let mut source = file.source.clone();
+        let mut tree = file.tree.clone();
+        for edit in &edits {
+            check_edit(&source, edit)?;
+            let (start, old_end, new_text) = edit;
+            let start_position = point_at(&source, *start);
+            let old_end_position = point_at(&source, *old_end);
+            source.replace_range(*start..*old_end, new_text);
+            let new_end = start + new_text.len();
+            let input_edit = InputEdit {
+                start_byte: *start,
+                old_end_byte: *old_end,
+                new_end_byte: new_end,
+                start_position,
+                old_end_position,
+                new_end_position: point_at(&source, new_end),
+            };
+            if let Some(tree) = &mut tree {
+                tree.edit(&input_edit);
+            }
+        }
+        py.allow_threads(|| {
+            let grammar = get_language_parser(&file.language);
+            file.tree = grammar.and_then(|grammar| parse(&source, &file.language, grammar, tree.as_ref()));
+            file.source = source;
+            Ok(file.metadata())
+        })
+    }
+
+    /// The source remembered for `path`, with every edit applied.
+    fn source(&self, path: &str) -> Option<String> {
+        self.files.get(path).map(|file| file.source.clone())
+    }
+
+    /// Forgets `path`; returns whether it was known.
+    fn remove(&mut self, path: &str) -> bool {
+        self.files.remove(path).is_some()
+    }
+
+    fn __len__(&self) -> usize {
+        self.files.len()
+    }
+
+    fn __contains__(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ParseSession({} files)", self.files.len())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::scanner::{metadata_ref, MatchHit, Metadata};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// A rule match inside a printable string extracted from a binary file.
+///
+/// Offsets are bytes into the file: `string_offset` is where the extracted
+/// string starts, `offset` where the match starts. For "utf-16le" strings
+/// every character takes two bytes.
+#[pyclass]
+#[derive(Clone)]
+pub struct BinaryStringHit {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub offset: usize,
+    #[pyo3(get)]
+    pub string_offset: usize,
+    // "ascii" or "utf-16le"
+    #[pyo3(get)]
+    pub encoding: String,
+    #[pyo3(get)]
+    pub string: String,
+    #[pyo3(get)]
+    pub matched_text: String,
+    #[pyo3(get)]
+    pub severity: Option<String>,
+    #[pyo3(get)]
+    pub category: Option<String>,
+    metadata: Metadata,
+    // See `MatchHit.validator`
+    #[pyo3(get)]
+    pub validator: Option<String>,
+    #[pyo3(get)]
+    pub validated: bool,
+}
+
+#[pymethods]
+impl BinaryStringHit {
+    #[getter]
+    fn metadata(&self, py: Python<'_>) -> Option<Py<PyDict>> {
+        metadata_ref(py, &self.metadata)
+    }
+}
+
+impl BinaryStringHit {
+    // `hit` was found in `run.text`; its column is 1-based into that text
+    pub(crate) fn new(hit: MatchHit, run: &PrintableRun) -> Self {
+        let char_width = if run.encoding == "utf-16le" { 2 } else { 1 };
+        // A redacted hit's matched_text is already masked; splice it in
+        let string = if hit.redacted {
+            let (start, end) = (hit.column - 1, hit.match_end_column - 1);
+            format!("{}{}{}", &run.text[..start], hit.matched_text, &run.text[end..])
+        } else {
+            run.text.clone()
+        };
+        BinaryStringHit {
+            file_path: hit.file_path,
+            rule_id: hit.rule_id,
+            offset: run.offset + (hit.column - 1) * char_width,
+            string_offset: run.offset,
+            encoding: run.encoding.to_string(),
+            string,
+            matched_text: hit.matched_text,
+            severity: hit.severity,
+            category: hit.category,
+            metadata: hit.metadata,
+            validator: hit.validator,
+            validated: hit.validated,
+        }
+    }
+}
+
+/// A run of printable characters found in raw bytes.
+pub(crate) struct PrintableRun {
+    pub offset: usize,
+    pub encoding: &'static str,
+    pub text: String,
+}
+
+// Like `strings(1)`: tab plus the printable ASCII range
+fn is_printable(b: u8) -> bool {
+    b == b'\t' || (0x20..=0x7e).contains(&b)
+}
+
+/// Printable ASCII runs and UTF-16LE runs (printable ASCII byte, then 0x00)
+/// of at least `min_len` characters, ordered by offset.
+pub(crate) fn printable_runs(bytes: &[u8], min_len: usize) -> Vec<PrintableRun> {
+    let min_len = min_len.max(1);
+    let mut runs = Vec::new();
+
+    let mut start = 0;
+    for (i, &b) in bytes.iter().chain(std::iter::once(&0)).enumerate() {
+        if is_printable(b) {
+            continue;
+        }
+        if i - start >= min_len {
+            let text = String::from_utf8_lossy(&bytes[start..i]).into_owned();
+            runs.push(PrintableRun { offset: start, encoding: "ascii", text });
+        }
+        start = i + 1;
+    }
+
+    // Both byte alignments, so strings at odd offsets are found too
+    for parity in 0..2 {
+        let units = bytes.get(parity..).unwrap_or_default().chunks_exact(2);
+        let mut text = String::new();
+        let mut run_start = parity;
+        for (i, unit) in units.map(Some).chain(std::iter::once(None)).enumerate() {
+            match unit {
+                Some(&[lo, 0]) if is_printable(lo) => text.push(lo as char),
+                _ => {
+                    if text.len() >= min_len {
+                        runs.push(PrintableRun { offset: run_start, encoding: "utf-16le", text: std::mem::take(&mut text) });
+                    }
+                    text.clear();
+                    run_start = parity + (i + 1) * 2;
+                }
+            }
+        }
+    }
+
+    runs.sort_by_key(|run| run.offset);
+    runs
+}
@@ -0,0 +1,145 @@
+use crate::loc::LineCounter;
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// An inline `warden:ignore` directive. Lines are 1-based.
+#[pyclass]
+#[derive(Clone)]
+pub struct Suppression {
+    #[pyo3(get)]
+    pub file_path: String,
+    // The line of the comment
+    #[pyo3(get)]
+    pub line_number: usize,
+    // The line it silences: its own when it shares it with code, otherwise
+    // the next line holding code, None when no code follows
+    #[pyo3(get)]
+    pub target_line: Option<usize>,
+    // Rule ids to silence; empty means every rule
+    #[pyo3(get)]
+    pub rules: Vec<String>,
+    #[pyo3(get)]
+    pub reason: Option<String>,
+    // The directive as written, without the comment delimiters
+    #[pyo3(get)]
+    pub text: String,
+}
+
+#[pymethods]
+impl Suppression {
+    fn __repr__(&self) -> String {
+        format!("Suppression({}:{} -> {:?} {:?})", self.file_path, self.line_number, self.target_line, self.rules)
+    }
+}
+
+// The directive right after a comment delimiter, in either spelling:
+// `warden:ignore` or the older `warden-ignore`
+fn directive_regex() -> &'static Regex {
+    static DIRECTIVE: OnceLock<Regex> = OnceLock::new();
+    DIRECTIVE.get_or_init(|| Regex::new(r"(?:^|#|//|/\*|--|<!--)\s*(warden[:\-]ignore\b.*)").unwrap())
+}
+
+// Rule ids from `[A, B]` or `: A, B`, then `reason=...`, quoted or running
+// to the end of the comment
+fn parse_directive(directive: &str) -> (Vec<String>, Option<String>) {
+    let rest = directive.trim_start_matches("warden:ignore").trim_start_matches("warden-ignore");
+    let (rules, rest) = if let Some(list) = rest.strip_prefix('[') {
+        list.split_once(']').unwrap_or((list, ""))
+    } else if let Some(list) = rest.trim_start().strip_prefix(':') {
+        match list.find("reason") {
+            Some(at) => list.split_at(at),
+            None => (list, ""),
+        }
+    } else {
+        ("", rest)
+    };
+    let rules = rules.split([',', ' ']).map(str::trim).filter(|rule| !rule.is_empty()).map(str::to_string).collect();
+    let reason = rest.trim_start().strip_prefix("reason").and_then(|reason| reason.trim_start().strip_prefix('=')).map(|reason| {
+        let reason = reason.trim();
+        ['"', '\''].iter().find_map(|&quote| reason.strip_prefix(quote).and_then(|r| r.split(quote).next())).unwrap_or(reason).to_string()
+    });
+    (rules, reason.filter(|reason| !reason.is_empty()))
+}
+
+fn content_suppressions(file_path: &str, content: &str) -> Vec<Suppression> {
+    let language = detect_language(Path::new(file_path), content.as_bytes());
+    let mut counter = LineCounter::new(&language);
+    // Languages without comments of their own (JSON, Markdown and unknown
+    // ones) take a directive anywhere on a line, for that line
+    let whole_lines = matches!(language.as_str(), "markdown" | "json" | "unknown");
+    let mut found: Vec<Suppression> = Vec::new();
+    // Directives alone on their line, waiting for the next line of code
+    let mut pending: Vec<usize> = Vec::new();
+    for (ln, line) in content.lines().enumerate() {
+        let spans = if whole_lines {
+            line.find("warden:ignore").or_else(|| line.find("warden-ignore")).map(|start| vec![(start, line.len())]).unwrap_or_default()
+        } else {
+            counter.comment_spans(line)
+        };
+        let mut outside = line.to_string();
+        for &(start, end) in spans.iter().rev() {
+            outside.replace_range(start..end, "");
+        }
+        let has_code = whole_lines || !outside.trim().is_empty();
+        if has_code {
+            for index in pending.drain(..) {
+                found[index].target_line = Some(ln + 1);
+            }
+        }
+        for (start, end) in spans {
+            let comment = line[start..end].trim_end();
+            let comment = ["*/", "-->"].iter().find_map(|close| comment.strip_suffix(close)).unwrap_or(comment).trim_end();
+            for c in directive_regex().captures_iter(comment) {
+                let text = c[1].to_string();
+                let (rules, reason) = parse_directive(&text);
+                if !has_code {
+                    pending.push(found.len());
+                }
+                found.push(Suppression {
+                    file_path: file_path.to_string(),
+                    line_number: ln + 1,
+                    target_line: has_code.then_some(ln + 1),
+                    rules,
+                    reason,
+                    text,
+                });
+            }
+        }
+    }
+    found
+}
+
+fn file_suppressions(file_path: &str) -> Vec<Suppression> {
+    let Ok(bytes) = std::fs::read(file_path) else { return Vec::new() };
+    content_suppressions(file_path, &String::from_utf8_lossy(&bytes))
+}
+
+/// Inline suppression directives in the comments of `files`, in file and
+/// then line order:
+///
+/// ```text
+/// x = eval(data)  # warden:ignore[PY-EVAL, PY-EXEC] reason="trusted input"
+/// // warden:ignore
+/// /* warden:ignore[JS-001] reason=legacy */
+/// ```
+///
+/// Rules go in brackets, or after a colon as in `# warden-ignore: A, B`,
+/// the older spelling also accepted; none means every rule. Comments are
+/// found by each language's syntax, so a directive inside a string literal
+/// does not count.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn parse_suppressions(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<Suppression>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_suppressions(path))
+}
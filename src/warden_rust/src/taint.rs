@@ -0,0 +1,463 @@
+use crate::ast::{function_definitions, get_language_parser, parse, qualified_name};
+use crate::encoding;
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tree_sitter::Node;
+
+/// Sources, sinks and sanitizers for `find_taint_flows`, all written as
+/// the code names them.
+///
+/// A source is an expression such as `request.args` or `$_GET`, or a
+/// function such as `request.getParameter`, found wherever it appears in
+/// an assigned value, so `request.args.get("id")` and `request.args["id"]`
+/// are both tainted. A sink or sanitizer is a function called by that
+/// name, either alone or as the last part of the callee: `execute` covers
+/// `cursor.execute(...)` and `db.cursor().execute(...)`, `cursor.execute`
+/// only the former. A value passed through a sanitizer is clean.
+#[pyclass]
+#[derive(Clone)]
+pub struct TaintRule {
+    #[pyo3(get, set)]
+    pub id: String,
+    #[pyo3(get, set)]
+    pub sources: Vec<String>,
+    #[pyo3(get, set)]
+    pub sinks: Vec<String>,
+    #[pyo3(get, set)]
+    pub sanitizers: Vec<String>,
+    // Languages the rule applies to, as detected from the extension; none
+    // means every language
+    #[pyo3(get, set)]
+    pub languages: Vec<String>,
+    // Copied verbatim into every flow of the rule
+    #[pyo3(get, set)]
+    pub severity: Option<String>,
+    #[pyo3(get, set)]
+    pub category: Option<String>,
+}
+
+#[pymethods]
+impl TaintRule {
+    #[new]
+    #[pyo3(signature = (id, sources, sinks, sanitizers=Vec::new(), languages=Vec::new(), severity=None, category=None))]
+    fn new(
+        id: String,
+        sources: Vec<String>,
+        sinks: Vec<String>,
+        sanitizers: Vec<String>,
+        languages: Vec<String>,
+        severity: Option<String>,
+        category: Option<String>,
+    ) -> Self {
+        TaintRule { id, sources, sinks, sanitizers, languages, severity, category }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TaintRule({} {:?} -> {:?})", self.id, self.sources, self.sinks)
+    }
+}
+
+/// Tainted data reaching a sink within one function.
+#[pyclass]
+#[derive(Clone)]
+pub struct TaintFlow {
+    #[pyo3(get)]
+    pub rule_id: String,
+    #[pyo3(get)]
+    pub file_path: String,
+    // Qualified name of the function, `<module>` for top-level code
+    #[pyo3(get)]
+    pub function: String,
+    // The variable passed to the sink, None when the source is passed to it
+    // directly
+    #[pyo3(get)]
+    pub variable: Option<String>,
+    // The source the data came from, as written, and its 1-based line
+    #[pyo3(get)]
+    pub source: String,
+    #[pyo3(get)]
+    pub source_line: usize,
+    // The sink's callee as written, and where the call starts (1-based)
+    #[pyo3(get)]
+    pub sink: String,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub snippet: String,
+    #[pyo3(get)]
+    pub severity: Option<String>,
+    #[pyo3(get)]
+    pub category: Option<String>,
+}
+
+#[pymethods]
+impl TaintFlow {
+    fn __repr__(&self) -> String {
+        let via = self.variable.as_deref().map(|variable| format!(" via {}", variable)).unwrap_or_default();
+        format!("TaintFlow({} {}:{} {}{} -> {})", self.rule_id, self.file_path, self.line, self.source, via, self.sink)
+    }
+}
+
+// Where a tainted value came from
+#[derive(Clone)]
+struct Origin {
+    source: String,
+    line: usize,
+    variable: Option<String>,
+}
+
+// The node's tokens without the whitespace between them, strings left
+// empty, so it can be compared with a name as written in a rule
+pub(crate) fn code_text(node: Node, source: &[u8]) -> String {
+    let mut text = String::new();
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        if node.is_named() && node.kind().contains("string") {
+            text.push_str("\"\"");
+        } else if node.child_count() == 0 {
+            text.push_str(node.utf8_text(source).unwrap_or_default());
+        } else {
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    text
+}
+
+// The length of `code_text` for every node under `node`, by id, found in
+// one pass, so that nodes too long to be a name need not have their text
+// built
+fn code_text_lengths(node: Node, source: &[u8]) -> HashMap<usize, usize> {
+    let mut lengths = HashMap::new();
+    let mut stack = vec![(node, false)];
+    while let Some((node, done)) = stack.pop() {
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        if !done && !children.is_empty() {
+            stack.push((node, true));
+            stack.extend(children.into_iter().map(|child| (child, false)));
+            continue;
+        }
+        let length = if node.is_named() && node.kind().contains("string") {
+            2
+        } else if children.is_empty() {
+            node.utf8_text(source).unwrap_or_default().len()
+        } else {
+            children.iter().map(|child| lengths[&child.id()]).sum()
+        };
+        lengths.insert(node.id(), length);
+    }
+    lengths
+}
+
+// Whether `callee` calls `name`, alone or as its last member
+fn calls(callee: &str, name: &str) -> bool {
+    callee == name || [".", "->", "::"].iter().any(|sep| callee.strip_suffix(name).is_some_and(|head| head.ends_with(sep)))
+}
+
+// The callee and arguments of a call node
+fn call_parts<'t>(node: Node<'t>, source: &[u8]) -> Option<(String, Node<'t>)> {
+    if !(node.kind().contains("call") || node.kind().contains("invocation")) {
+        return None;
+    }
+    let arguments = node.child_by_field_name("arguments")?;
+    let mut cursor = node.walk();
+    let callee = node.children(&mut cursor)
+        .take_while(|child| child.id() != arguments.id())
+        .map(|child| code_text(child, source))
+        .collect();
+    Some((callee, arguments))
+}
+
+pub(crate) fn is_name_kind(kind: &str) -> bool {
+    kind.contains("identifier") || kind == "variable_name"
+}
+
+pub(crate) fn is_member_kind(kind: &str) -> bool {
+    ["attribute", "member", "field_expression", "field_access", "selector", "scoped_identifier"].iter().any(|part| kind.contains(part))
+}
+
+// The (target, value) sides of an assignment, initialised declaration or
+// for-each loop, and whether it is compound (`+=`), which adds to the
+// target's taint rather than replacing it
+pub(crate) fn assignment_parts<'t>(node: Node<'t>, source: &[u8]) -> Option<(Node<'t>, Node<'t>, bool)> {
+    // So do binary operators, and keyword arguments and defaults a name and
+    // a value
+    if ["binary", "comparison", "boolean", "argument", "parameter"].iter().any(|part| node.kind().contains(part)) {
+        return None;
+    }
+    let (target, value) = [("left", "right"), ("name", "value"), ("pattern", "value"), ("declarator", "value")]
+        .iter()
+        .find_map(|&(target, value)| Some((node.child_by_field_name(target)?, node.child_by_field_name(value)?)))?;
+    let mut cursor = node.walk();
+    let operator = node.children(&mut cursor)
+        .filter(|child| !child.is_named() && child.start_byte() >= target.end_byte() && child.end_byte() <= value.start_byte())
+        .find_map(|child| child.utf8_text(source).ok())?;
+    let compound = operator.len() > 1 && operator.ends_with('=') && !matches!(operator, ":=" | "==" | "!=" | "<=" | ">=" | "===" | "!==");
+    (compound || matches!(operator, "=" | ":=" | "in" | "of" | ":")).then_some((target, value, compound))
+}
+
+// The names an assignment to `target` taints: itself for a name or member
+// access, else every name in a destructuring pattern
+pub(crate) fn target_names(target: Node, source: &[u8]) -> Vec<String> {
+    if is_name_kind(target.kind()) || is_member_kind(target.kind()) {
+        return vec![code_text(target, source)];
+    }
+    let mut names = Vec::new();
+    let mut stack = vec![target];
+    while let Some(node) = stack.pop() {
+        if is_name_kind(node.kind()) {
+            names.push(code_text(node, source));
+            continue;
+        }
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.named_children(&mut cursor).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    names
+}
+
+// One rule run over the code of one function, in source order
+struct Scope<'a> {
+    rule: &'a TaintRule,
+    source: &'a [u8],
+    // Nested functions, each its own scope
+    skip: &'a HashSet<usize>,
+    tainted: HashMap<String, Origin>,
+    flows: Vec<(Node<'a>, String, Origin)>,
+}
+
+// The steps of `Scope::visit`, run from a stack rather than by recursion,
+// since expressions nest deeper than the thread's stack
+enum Step<'a> {
+    Visit(Node<'a>),
+    Assign(Node<'a>),
+    Sink(Node<'a>),
+}
+
+impl<'a> Scope<'a> {
+    // Where `node`'s value came from, if anywhere tainted: the first source
+    // or tainted variable in it, in source order, outside sanitizer calls
+    fn taint(&self, node: Node) -> Option<Origin> {
+        let longest = self.rule.sources.iter().chain(self.tainted.keys()).map(String::len).max().unwrap_or(0);
+        let lengths = code_text_lengths(node, self.source);
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if let Some((callee, _)) = call_parts(node, self.source) {
+                if self.rule.sanitizers.iter().any(|sanitizer| calls(&callee, sanitizer)) {
+                    continue;
+                }
+                // Some grammars have no node for the callee of a method call
+                if self.rule.sources.contains(&callee) {
+                    return Some(Origin { source: callee, line: node.start_position().row + 1, variable: None });
+                }
+            }
+            if node.is_named() && lengths[&node.id()] <= longest {
+                let text = code_text(node, self.source);
+                if self.rule.sources.contains(&text) {
+                    return Some(Origin { source: text, line: node.start_position().row + 1, variable: None });
+                }
+                if let Some(origin) = self.tainted.get(&text) {
+                    return Some(Origin { variable: Some(text), ..origin.clone() });
+                }
+            }
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.named_children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+        }
+        None
+    }
+
+    // Runs the code under `node` in source order: an assignment's value
+    // before it is assigned and before the rest, such as a loop's body, and
+    // a call's arguments before the call is checked against the sinks
+    fn visit(&mut self, node: Node<'a>) {
+        let mut stack = vec![Step::Visit(node)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Visit(node) => {
+                    let mut cursor = node.walk();
+                    let mut children: Vec<Node<'a>> =
+                        node.children(&mut cursor).filter(|child| !self.skip.contains(&child.id())).collect();
+                    let value = assignment_parts(node, self.source).map(|(_, value, _)| value);
+                    if let Some(value) = value {
+                        children.retain(|child| child.id() != value.id());
+                    }
+                    stack.push(Step::Sink(node));
+                    stack.extend(children.into_iter().rev().map(Step::Visit));
+                    if let Some(value) = value {
+                        stack.push(Step::Assign(node));
+                        stack.push(Step::Visit(value));
+                    }
+                }
+                Step::Assign(node) => self.assign(node),
+                Step::Sink(node) => self.check_sink(node),
+            }
+        }
+    }
+
+    fn assign(&mut self, node: Node<'a>) {
+        let Some((target, value, compound)) = assignment_parts(node, self.source) else { return };
+        let origin = self.taint(value);
+        for name in target_names(target, self.source) {
+            match &origin {
+                Some(origin) => {
+                    self.tainted.insert(name, Origin { variable: None, ..origin.clone() });
+                }
+                None if !compound => {
+                    self.tainted.remove(&name);
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn check_sink(&mut self, node: Node<'a>) {
+        if let Some((callee, arguments)) = call_parts(node, self.source) {
+            if self.rule.sinks.iter().any(|sink| calls(&callee, sink)) {
+                if let Some(origin) = self.taint(arguments) {
+                    self.flows.push((node, callee, origin));
+                }
+            }
+        }
+    }
+}
+
+fn file_flows(path: &str, rules: &[TaintRule]) -> Vec<TaintFlow> {
+    let Ok(content) = encoding::read_to_string(path) else { return vec![] };
+    let language = detect_language(Path::new(path), content.as_bytes());
+    let rules: Vec<&TaintRule> = rules.iter().filter(|rule| rule.languages.is_empty() || rule.languages.contains(&language)).collect();
+    if rules.is_empty() {
+        return vec![];
+    }
+    let Some(grammar) = get_language_parser(&language) else { return vec![] };
+    let Some(tree) = parse(&content, &language, grammar, None) else { return vec![] };
+    let source = content.as_bytes();
+    let functions = function_definitions(&tree, &content, &language, grammar);
+    let skip: HashSet<usize> = functions.iter().map(|(_, definition)| definition.id()).collect();
+    let scopes = functions.iter()
+        .map(|&(name, definition)| {
+            let name = name.utf8_text(source).unwrap_or_default();
+            (qualified_name(definition, name, &language, source), definition)
+        })
+        .chain(std::iter::once(("<module>".to_string(), tree.root_node())));
+
+    let lines: Vec<&str> = content.lines().collect();
+    let mut flows = Vec::new();
+    for (function, root) in scopes {
+        for &rule in &rules {
+            let mut scope = Scope { rule, source, skip: &skip, tainted: HashMap::new(), flows: Vec::new() };
+            let mut cursor = root.walk();
+            let children: Vec<Node> = root.children(&mut cursor).filter(|child| !skip.contains(&child.id())).collect();
+            for child in children {
+                scope.visit(child);
+            }
+            for (node, sink, origin) in scope.flows {
+                let start = node.start_position();
+                flows.push(TaintFlow {
+                    rule_id: rule.id.clone(),
+                    file_path: path.to_string(),
+                    function: function.clone(),
+                    variable: origin.variable,
+                    source: origin.source,
+                    source_line: origin.line,
+                    sink,
+                    line: start.row + 1,
+                    column: start.column + 1,
+                    snippet: lines.get(start.row).map(|line| line.trim().to_string()).unwrap_or_default(),
+                    severity: rule.severity.clone(),
+                    category: rule.category.clone(),
+                });
+            }
+        }
+    }
+    flows.sort_by_key(|flow| (flow.line, flow.column));
+    flows
+}
+
+/// Follows data from the `rules`' sources to their sinks within each
+/// function of `files`, in parallel with the GIL released. A variable is
+/// tainted by an assignment, initialised declaration or for-each loop whose
+/// value mentions a source or a tainted variable, unless through a
+/// sanitizer, and clean again once assigned an untainted value; a flow is
+/// a sink call with a tainted argument. Statements are taken in source
+/// order, so taint does not flow back around loops, and calls to other
+/// functions are not followed. Top-level code counts as one more function,
+/// `<module>`.
+///
+/// Returns the flows in file and then position order.
+#[pyfunction]
+#[pyo3(signature = (files, rules, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_taint_flows(
+    py: Python<'_>,
+    files: Vec<String>,
+    rules: Vec<TaintRule>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<TaintFlow>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_flows(path, &rules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(sanitizers: &[&str]) -> TaintRule {
+        TaintRule {
+            id: "command_injection".to_string(),
+            sources: vec!["request.args".to_string()],
+            sinks: vec!["os.system".to_string()],
+            sanitizers: sanitizers.iter().map(|name| name.to_string()).collect(),
+            languages: Vec::new(),
+            severity: None,
+            category: None,
+        }
+    }
+
+    fn flows(content: &str, rule: TaintRule) -> Vec<TaintFlow> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.py");
+        std::fs::write(&path, content).unwrap();
+        file_flows(path.to_str().unwrap(), &[rule])
+    }
+
+    #[test]
+    fn follows_a_source_through_variables_to_a_sink() {
+        let found = flows("def handler(request):\n    name = request.args\n    command = name\n    os.system(command)\n", rule(&[]));
+        assert_eq!(found.len(), 1);
+        let flow = &found[0];
+        assert_eq!((flow.function.as_str(), flow.sink.as_str(), flow.line), ("handler", "os.system", 4));
+        assert_eq!((flow.source.as_str(), flow.source_line, flow.variable.as_deref()), ("request.args", 2, Some("command")));
+    }
+
+    #[test]
+    fn sanitizers_and_reassignment_clear_the_taint() {
+        let sanitized = "def handler(request):\n    name = shlex.quote(request.args)\n    os.system(name)\n";
+        assert!(flows(sanitized, rule(&["shlex.quote"])).is_empty());
+        let reassigned = "def handler(request):\n    name = request.args\n    name = \"ls\"\n    os.system(name)\n";
+        assert!(flows(reassigned, rule(&[])).is_empty());
+    }
+
+    #[test]
+    fn taint_is_not_followed_into_other_functions() {
+        let content = "name = request.args\ndef run():\n    os.system(name)\n";
+        assert!(flows(content, rule(&[])).is_empty());
+    }
+
+    #[test]
+    fn deeply_nested_expressions_do_not_overflow_the_stack() {
+        let terms = vec!["name"; 10_000].join(" + ");
+        let content = format!("name = request.args\ncommand = {}\nos.system(command)\n", terms);
+        let found = flows(&content, rule(&[]));
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].function.as_str(), found[0].line), ("<module>", 3));
+    }
+}
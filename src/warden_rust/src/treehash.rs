@@ -0,0 +1,135 @@
+use crate::{build_walker, is_truncated, stop_here, warn_user, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Content hashes of a directory tree, rolled up Merkle-style: a directory's
+/// hash covers the names and hashes of everything below it, so comparing
+/// `directories["src"]` across runs tells whether anything under `src/`
+/// changed without looking at its files.
+///
+/// Keys are paths relative to the root with `/` separators; the root itself
+/// is `""` in `directories` and also `root_hash`.
+#[pyclass]
+#[derive(Clone)]
+pub struct TreeHash {
+    // None when cancelled, as are `directories` left empty: a hash of part
+    // of the tree would pass for the whole. `files` keeps what was hashed
+    #[pyo3(get)]
+    pub root_hash: Option<String>,
+    #[pyo3(get)]
+    pub directories: HashMap<String, String>,
+    #[pyo3(get)]
+    pub files: HashMap<String, String>,
+}
+
+#[pymethods]
+impl TreeHash {
+    fn __repr__(&self) -> String {
+        let root = self.root_hash.as_ref().map_or("None", |hash| &hash[..12]);
+        format!("TreeHash({} files, {} directories, root={})", self.files.len(), self.directories.len(), root)
+    }
+}
+
+// A directory's children by name; BTreeMap keeps them in byte order, which
+// makes the hash independent of walk order
+#[derive(Default)]
+struct Node {
+    files: BTreeMap<String, String>,
+    dirs: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn insert(&mut self, relative: &str, hash: String) {
+        match relative.split_once('/') {
+            Some((dir, rest)) => self.dirs.entry(dir.to_string()).or_default().insert(rest, hash),
+            None => {
+                self.files.insert(relative.to_string(), hash);
+            }
+        }
+    }
+
+    // One "<kind> <hash> <name>\n" line per child, like a git tree
+    fn hash(&self, path: &str, directories: &mut HashMap<String, String>) -> String {
+        let mut children: BTreeMap<&str, (&str, String)> = BTreeMap::new();
+        for (name, node) in &self.dirs {
+            let child = if path.is_empty() { name.clone() } else { format!("{}/{}", path, name) };
+            children.insert(name, ("dir", node.hash(&child, directories)));
+        }
+        for (name, hash) in &self.files {
+            children.insert(name, ("file", hash.clone()));
+        }
+        let mut hasher = Sha256::new();
+        for (name, (kind, hash)) in children {
+            hasher.update(format!("{} {} {}\n", kind, hash, name));
+        }
+        let hash = format!("{:x}", hasher.finalize());
+        directories.insert(path.to_string(), hash.clone());
+        hash
+    }
+}
+
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+    Some(parts.join("/"))
+}
+
+/// Hashes every file under `root_path` (SHA-256 of its raw bytes, binaries
+/// included) and rolls the results up into one hash per directory and a
+/// root hash. The same contents at the same relative paths always give the
+/// same hashes, wherever the tree lives, so `root_hash` can stand for the
+/// whole tree in a scan manifest. Empty directories do not count.
+///
+/// Files that cannot be read are left out with a UserWarning. Once
+/// cut short by cancellation (see `CancellationToken.truncated`), only
+/// `files` is filled in.
+#[pyfunction]
+#[pyo3(signature = (root_path, use_gitignore=true, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn hash_tree(
+    py: Python<'_>,
+    root_path: String,
+    use_gitignore: bool,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<TreeHash> {
+    let root = Path::new(&root_path);
+    let files: Vec<(String, String)> = py.allow_threads(|| {
+        build_walker(&root_path, use_gitignore)
+            .take_while(|_| !stop_here(&cancel_token))
+            .flatten()
+            .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_file()))
+            .filter_map(|entry| {
+                let relative = relative_path(root, entry.path())?;
+                Some((entry.path().to_string_lossy().to_string(), relative))
+            })
+            .collect()
+    });
+
+    let control = ScanControl::new(progress, progress_every, cancel_token.clone(), threads);
+    let hashed = control.run(py, &files, |(path, relative)| {
+        let hash = std::fs::read(path).map(|bytes| format!("{:x}", Sha256::digest(&bytes)));
+        Some((path.clone(), relative.clone(), hash))
+    })?;
+
+    let mut tree = Node::default();
+    let mut file_hashes = HashMap::new();
+    for (path, relative, hash) in hashed {
+        match hash {
+            Ok(hash) => {
+                tree.insert(&relative, hash.clone());
+                file_hashes.insert(relative, hash);
+            }
+            Err(e) => warn_user(py, &format!("Leaving unreadable file {} out of the tree hash: {}", path, e))?,
+        }
+    }
+    if is_truncated(&cancel_token) {
+        return Ok(TreeHash { root_hash: None, directories: HashMap::new(), files: file_hashes });
+    }
+    let mut directories = HashMap::new();
+    let root_hash = tree.hash("", &mut directories);
+    Ok(TreeHash { root_hash: Some(root_hash), directories, files: file_hashes })
+}
@@ -0,0 +1,445 @@
+use crate::ast::{get_language_parser, identifiers, parse};
+use crate::encoding;
+use crate::literals::{is_string_literal, literal_value};
+use crate::scanner::split_lines;
+use crate::{detect_language, CancellationToken, ScanControl};
+use pyo3::prelude::*;
+use std::borrow::Cow;
+use std::path::Path;
+use tree_sitter::{Node, Tree};
+
+/// A Unicode character, or an identifier holding some, that makes code
+/// read differently from how it runs.
+///
+/// `column`/`end_column` are 1-based byte offsets into the line, as
+/// transcoded to UTF-8, end exclusive.
+#[pyclass]
+#[derive(Clone)]
+pub struct UnicodeHit {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line_number: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub end_column: usize,
+    // "bidi" for a bidirectional control character; "zero_width" or
+    // "confusable" for an identifier or string key holding invisible
+    // characters or letters that pass for Latin ones
+    #[pyo3(get)]
+    pub kind: String,
+    // The suspicious characters, as "U+202E"
+    #[pyo3(get)]
+    pub codepoints: Vec<String>,
+    // Their Unicode names, in the same order
+    #[pyo3(get)]
+    pub names: Vec<String>,
+    // The identifier or string key, None for "bidi"
+    #[pyo3(get)]
+    pub text: Option<String>,
+    // What `text` reads as: the invisible characters dropped, or each
+    // lookalike replaced by the ASCII letter it passes for
+    #[pyo3(get)]
+    pub looks_like: Option<String>,
+    // The line, with every invisible character spelled out as `<U+202E>`
+    #[pyo3(get)]
+    pub snippet: String,
+}
+
+#[pymethods]
+impl UnicodeHit {
+    fn __repr__(&self) -> String {
+        format!("UnicodeHit({}:{}:{} {} {:?})", self.file_path, self.line_number, self.column, self.kind, self.codepoints)
+    }
+}
+
+/// An identifier with characters outside ASCII, where it occurs. Columns
+/// are as in `UnicodeHit`.
+#[pyclass]
+#[derive(Clone)]
+pub struct NonAsciiIdentifier {
+    #[pyo3(get)]
+    pub file_path: String,
+    #[pyo3(get)]
+    pub line_number: usize,
+    #[pyo3(get)]
+    pub column: usize,
+    #[pyo3(get)]
+    pub end_column: usize,
+    #[pyo3(get)]
+    pub name: String,
+    // Its non-ASCII characters, each once in order of appearance, as "U+00E9"
+    #[pyo3(get)]
+    pub codepoints: Vec<String>,
+}
+
+#[pymethods]
+impl NonAsciiIdentifier {
+    fn __repr__(&self) -> String {
+        format!("NonAsciiIdentifier({}:{}:{} {})", self.file_path, self.line_number, self.column, self.name)
+    }
+}
+
+// The embeddings, overrides and isolates that reorder how text displays,
+// the Trojan Source characters
+const BIDI_CONTROLS: &[(char, &str)] = &[
+    ('\u{202A}', "LEFT-TO-RIGHT EMBEDDING"),
+    ('\u{202B}', "RIGHT-TO-LEFT EMBEDDING"),
+    ('\u{202C}', "POP DIRECTIONAL FORMATTING"),
+    ('\u{202D}', "LEFT-TO-RIGHT OVERRIDE"),
+    ('\u{202E}', "RIGHT-TO-LEFT OVERRIDE"),
+    ('\u{2066}', "LEFT-TO-RIGHT ISOLATE"),
+    ('\u{2067}', "RIGHT-TO-LEFT ISOLATE"),
+    ('\u{2068}', "FIRST STRONG ISOLATE"),
+    ('\u{2069}', "POP DIRECTIONAL ISOLATE"),
+];
+
+fn bidi_name(c: char) -> Option<&'static str> {
+    BIDI_CONTROLS.iter().find(|&&(control, _)| control == c).map(|&(_, name)| name)
+}
+
+// Characters that take no space, so two identifiers can look alike while
+// differing
+const ZERO_WIDTH: &[(char, &str)] = &[
+    ('\u{00AD}', "SOFT HYPHEN"),
+    ('\u{180E}', "MONGOLIAN VOWEL SEPARATOR"),
+    ('\u{200B}', "ZERO WIDTH SPACE"),
+    ('\u{200C}', "ZERO WIDTH NON-JOINER"),
+    ('\u{200D}', "ZERO WIDTH JOINER"),
+    ('\u{2060}', "WORD JOINER"),
+    ('\u{2061}', "FUNCTION APPLICATION"),
+    ('\u{2062}', "INVISIBLE TIMES"),
+    ('\u{2063}', "INVISIBLE SEPARATOR"),
+    ('\u{2064}', "INVISIBLE PLUS"),
+    ('\u{FEFF}', "ZERO WIDTH NO-BREAK SPACE"),
+];
+
+// Greek, Cyrillic and Armenian letters that look like a Latin one, with
+// that letter
+const LOOKALIKES: &[(char, char, &str)] = &[
+    ('\u{0391}', 'A', "GREEK CAPITAL LETTER ALPHA"),
+    ('\u{0392}', 'B', "GREEK CAPITAL LETTER BETA"),
+    ('\u{0395}', 'E', "GREEK CAPITAL LETTER EPSILON"),
+    ('\u{0396}', 'Z', "GREEK CAPITAL LETTER ZETA"),
+    ('\u{0397}', 'H', "GREEK CAPITAL LETTER ETA"),
+    ('\u{0399}', 'I', "GREEK CAPITAL LETTER IOTA"),
+    ('\u{039A}', 'K', "GREEK CAPITAL LETTER KAPPA"),
+    ('\u{039C}', 'M', "GREEK CAPITAL LETTER MU"),
+    ('\u{039D}', 'N', "GREEK CAPITAL LETTER NU"),
+    ('\u{039F}', 'O', "GREEK CAPITAL LETTER OMICRON"),
+    ('\u{03A1}', 'P', "GREEK CAPITAL LETTER RHO"),
+    ('\u{03A4}', 'T', "GREEK CAPITAL LETTER TAU"),
+    ('\u{03A5}', 'Y', "GREEK CAPITAL LETTER UPSILON"),
+    ('\u{03A7}', 'X', "GREEK CAPITAL LETTER CHI"),
+    ('\u{03B1}', 'a', "GREEK SMALL LETTER ALPHA"),
+    ('\u{03B9}', 'i', "GREEK SMALL LETTER IOTA"),
+    ('\u{03BA}', 'k', "GREEK SMALL LETTER KAPPA"),
+    ('\u{03BD}', 'v', "GREEK SMALL LETTER NU"),
+    ('\u{03BF}', 'o', "GREEK SMALL LETTER OMICRON"),
+    ('\u{03C1}', 'p', "GREEK SMALL LETTER RHO"),
+    ('\u{03C5}', 'u', "GREEK SMALL LETTER UPSILON"),
+    ('\u{0405}', 'S', "CYRILLIC CAPITAL LETTER DZE"),
+    ('\u{0406}', 'I', "CYRILLIC CAPITAL LETTER BYELORUSSIAN-UKRAINIAN I"),
+    ('\u{0408}', 'J', "CYRILLIC CAPITAL LETTER JE"),
+    ('\u{0410}', 'A', "CYRILLIC CAPITAL LETTER A"),
+    ('\u{0412}', 'B', "CYRILLIC CAPITAL LETTER VE"),
+    ('\u{0415}', 'E', "CYRILLIC CAPITAL LETTER IE"),
+    ('\u{041A}', 'K', "CYRILLIC CAPITAL LETTER KA"),
+    ('\u{041C}', 'M', "CYRILLIC CAPITAL LETTER EM"),
+    ('\u{041D}', 'H', "CYRILLIC CAPITAL LETTER EN"),
+    ('\u{041E}', 'O', "CYRILLIC CAPITAL LETTER O"),
+    ('\u{0420}', 'P', "CYRILLIC CAPITAL LETTER ER"),
+    ('\u{0421}', 'C', "CYRILLIC CAPITAL LETTER ES"),
+    ('\u{0422}', 'T', "CYRILLIC CAPITAL LETTER TE"),
+    ('\u{0425}', 'X', "CYRILLIC CAPITAL LETTER HA"),
+    ('\u{0430}', 'a', "CYRILLIC SMALL LETTER A"),
+    ('\u{0435}', 'e', "CYRILLIC SMALL LETTER IE"),
+    ('\u{043E}', 'o', "CYRILLIC SMALL LETTER O"),
+    ('\u{0440}', 'p', "CYRILLIC SMALL LETTER ER"),
+    ('\u{0441}', 'c', "CYRILLIC SMALL LETTER ES"),
+    ('\u{0443}', 'y', "CYRILLIC SMALL LETTER U"),
+    ('\u{0445}', 'x', "CYRILLIC SMALL LETTER HA"),
+    ('\u{0455}', 's', "CYRILLIC SMALL LETTER DZE"),
+    ('\u{0456}', 'i', "CYRILLIC SMALL LETTER BYELORUSSIAN-UKRAINIAN I"),
+    ('\u{0458}', 'j', "CYRILLIC SMALL LETTER JE"),
+    ('\u{04BB}', 'h', "CYRILLIC SMALL LETTER SHHA"),
+    ('\u{04CF}', 'l', "CYRILLIC SMALL LETTER PALOCHKA"),
+    ('\u{0501}', 'd', "CYRILLIC SMALL LETTER KOMI DE"),
+    ('\u{051A}', 'Q', "CYRILLIC CAPITAL LETTER QA"),
+    ('\u{051B}', 'q', "CYRILLIC SMALL LETTER QA"),
+    ('\u{051C}', 'W', "CYRILLIC CAPITAL LETTER WE"),
+    ('\u{051D}', 'w', "CYRILLIC SMALL LETTER WE"),
+    ('\u{0570}', 'h', "ARMENIAN SMALL LETTER HO"),
+    ('\u{0578}', 'n', "ARMENIAN SMALL LETTER VO"),
+    ('\u{057D}', 'u', "ARMENIAN SMALL LETTER SEH"),
+    ('\u{0581}', 'g', "ARMENIAN SMALL LETTER CO"),
+    ('\u{0585}', 'o', "ARMENIAN SMALL LETTER OH"),
+];
+
+fn zero_width_name(c: char) -> Option<&'static str> {
+    ZERO_WIDTH.iter().find(|&&(invisible, _)| invisible == c).map(|&(_, name)| name)
+}
+
+// The ASCII letter `c` passes for and the name of `c`: a Greek, Cyrillic
+// or Armenian lookalike, or a fullwidth Latin letter
+fn lookalike(c: char) -> Option<(char, String)> {
+    if let Some(&(_, latin, name)) = LOOKALIKES.iter().find(|&&(lookalike, _, _)| lookalike == c) {
+        return Some((latin, name.to_string()));
+    }
+    let (case, first, base) = match c {
+        '\u{FF21}'..='\u{FF3A}' => ("CAPITAL", 0xFF21, b'A'),
+        '\u{FF41}'..='\u{FF5A}' => ("SMALL", 0xFF41, b'a'),
+        _ => return None,
+    };
+    let latin = char::from(base + (c as u32 - first) as u8);
+    Some((latin, format!("FULLWIDTH LATIN {} LETTER {}", case, latin.to_ascii_uppercase())))
+}
+
+fn is_latin(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}') && c != '\u{00D7}' && c != '\u{00F7}'
+}
+
+// A name written from byte `start` to `end` of its line `line_number`
+struct Name<'a> {
+    line_number: usize,
+    start: usize,
+    end: usize,
+    text: Cow<'a, str>,
+}
+
+// The "zero_width" and "confusable" hits for an identifier or key. A
+// lookalike counts beside Latin letters, or when every letter is one, as
+// in a Cyrillic name spelt with "o" and "p" lookalikes.
+fn suspicious(file_path: &str, name: &Name, line: &str) -> Vec<UnicodeHit> {
+    let text = name.text.as_ref();
+    let hit = |kind: &str, found: Vec<(char, String)>, looks_like: String| UnicodeHit {
+        file_path: file_path.to_string(),
+        line_number: name.line_number,
+        column: name.start + 1,
+        end_column: name.end + 1,
+        kind: kind.to_string(),
+        codepoints: found.iter().map(|&(c, _)| codepoint(c)).collect(),
+        names: found.into_iter().map(|(_, name)| name).collect(),
+        text: Some(text.to_string()),
+        looks_like: Some(looks_like),
+        snippet: spelled_out(line, |c| zero_width_name(c).is_some() || bidi_name(c).is_some()),
+    };
+    let mut hits = Vec::new();
+    let invisible: Vec<(char, String)> = text.chars().filter_map(|c| Some((c, zero_width_name(c)?.to_string()))).collect();
+    if !invisible.is_empty() {
+        hits.push(hit("zero_width", invisible, text.chars().filter(|&c| zero_width_name(c).is_none()).collect()));
+    }
+    let lookalikes: Vec<(char, String)> = text.chars().filter_map(|c| Some((c, lookalike(c)?.1))).collect();
+    let letters = || text.chars().filter(|c| c.is_alphabetic());
+    let beside_latin = letters().any(is_latin);
+    let all_lookalikes = letters().all(|c| lookalike(c).is_some());
+    if !lookalikes.is_empty() && (beside_latin || all_lookalikes) {
+        let looks_like = text.chars().map(|c| lookalike(c).map_or(c, |(latin, _)| latin)).collect();
+        hits.push(hit("confusable", lookalikes, looks_like));
+    }
+    hits
+}
+
+// The file's lines, its content rejoined from them and its syntax tree;
+// None for a binary file or one without a grammar
+fn parsed_text(file_path: &str) -> Option<(Vec<String>, String, Tree)> {
+    let lines = text_lines(file_path)?;
+    let content = lines.join("\n");
+    let language = detect_language(Path::new(file_path), content.as_bytes());
+    let tree = parse(&content, &language, get_language_parser(&language)?, None)?;
+    Some((lines, content, tree))
+}
+
+fn file_suspicious_identifiers(file_path: &str) -> Vec<UnicodeHit> {
+    let Some((lines, content, tree)) = parsed_text(file_path) else { return Vec::new() };
+    let source = content.as_bytes();
+    // String literals used as keys, as in `{"name": value}`
+    let mut keys = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.is_named() && is_string_literal(node.kind()) {
+            if node.parent().and_then(|parent| parent.child_by_field_name("key")) == Some(node) {
+                keys.push(node);
+            }
+            continue;
+        }
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    let mut nodes = identifiers(&tree);
+    nodes.extend(keys);
+    let covered: Vec<(usize, usize)> = nodes.iter().map(|node| (node.start_byte(), node.end_byte())).collect();
+    let mut names: Vec<Name> = nodes.into_iter().filter_map(|node| {
+        let written = node.utf8_text(source).ok().filter(|written| !written.is_ascii())?;
+        let (start, end) = (node.start_position(), node.end_position());
+        let line_end = if end.row == start.row { end.column } else { lines[start.row].len() };
+        let text = if is_string_literal(node.kind()) { Cow::Owned(literal_value(written, node.kind())) } else { Cow::Borrowed(written) };
+        Some(Name { line_number: start.row + 1, start: start.column, end: line_end, text })
+    }).collect();
+    // A zero-width character most grammars reject splits the name into
+    // tokens around an error, so it is found in the text instead
+    // The offset of each line in `content`, which joins them with "\n"
+    let mut line_start = 0;
+    for (ln, line) in lines.iter().enumerate() {
+        if ln > 0 {
+            line_start += lines[ln - 1].len() + 1;
+        }
+        for (at, _) in line.char_indices().filter(|&(_, c)| zero_width_name(c).is_some()) {
+            let offset = line_start + at;
+            let Some(node) = tree.root_node().descendant_for_byte_range(offset, offset) else { continue };
+            let in_comment_or_string = std::iter::successors(Some(node), |node| node.parent())
+                .any(|node| node.kind().contains("comment") || is_string_literal(node.kind()));
+            if in_comment_or_string || covered.iter().any(|&(start, end)| start <= offset && offset < end) {
+                continue;
+            }
+            let part = |c: char| c.is_alphanumeric() || c == '_' || zero_width_name(c).is_some();
+            let start = line[..at].rfind(|c: char| !part(c)).map_or(0, |before| before + line[before..].chars().next().map_or(1, char::len_utf8));
+            let end = line[at..].find(|c: char| !part(c)).map_or(line.len(), |after| at + after);
+            let word = &line[start..end];
+            if word.chars().any(char::is_alphanumeric) && !names.iter().any(|name| name.line_number == ln + 1 && name.start == start) {
+                names.push(Name { line_number: ln + 1, start, end, text: Cow::Borrowed(word) });
+            }
+        }
+    }
+    names.sort_by_key(|name| (name.line_number, name.start));
+    names.iter().flat_map(|name| suspicious(file_path, name, &lines[name.line_number - 1])).collect()
+}
+
+fn codepoint(c: char) -> String {
+    format!("U+{:04X}", c as u32)
+}
+
+// `line` with the characters `hidden` picks out written as `<U+XXXX>`
+fn spelled_out(line: &str, hidden: impl Fn(char) -> bool) -> String {
+    line.trim()
+        .chars()
+        .map(|c| if hidden(c) { format!("<{}>", codepoint(c)) } else { c.to_string() })
+        .collect()
+}
+
+// The file's lines as UTF-8, None for a binary or unreadable file
+fn text_lines(file_path: &str) -> Option<Vec<String>> {
+    let bytes = std::fs::read(file_path).ok()?;
+    if encoding::is_binary(&bytes[..bytes.len().min(1024)]) {
+        return None;
+    }
+    let (text, _) = encoding::decode(&bytes);
+    Some(split_lines(&text).into_iter().map(|line| String::from_utf8_lossy(line).into_owned()).collect())
+}
+
+fn file_bidi_controls(file_path: &str) -> Vec<UnicodeHit> {
+    let Some(lines) = text_lines(file_path) else { return Vec::new() };
+    let mut out = Vec::new();
+    for (ln, line) in lines.iter().enumerate() {
+        for (at, c) in line.char_indices() {
+            let Some(name) = bidi_name(c) else { continue };
+            out.push(UnicodeHit {
+                file_path: file_path.to_string(),
+                line_number: ln + 1,
+                column: at + 1,
+                end_column: at + c.len_utf8() + 1,
+                kind: "bidi".to_string(),
+                codepoints: vec![codepoint(c)],
+                names: vec![name.to_string()],
+                text: None,
+                looks_like: None,
+                snippet: spelled_out(line, |c| bidi_name(c).is_some()),
+            });
+        }
+    }
+    out
+}
+
+/// Identifiers and string keys (as in `{"name": value}`) in each file that
+/// hold zero-width characters or letters passing for Latin ones, in source
+/// order, found in the syntax tree so comments and other strings are left
+/// alone:
+///
+/// - "zero_width": zero-width spaces and joiners, word joiners, soft
+///   hyphens and the like, which make two names that look the same differ
+/// - "confusable": Greek, Cyrillic or Armenian lookalikes beside Latin
+///   letters, as in a `password` spelt with a Cyrillic "a", or making up
+///   the whole name; fullwidth Latin letters
+///
+/// `looks_like` is the name as a reader sees it. Names written wholly in
+/// another script, Greek or Cyrillic words included, are not reported. Files without
+/// a grammar are skipped.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_suspicious_identifiers(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<UnicodeHit>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_suspicious_identifiers(path))
+}
+
+fn file_non_ascii_identifiers(file_path: &str) -> Vec<NonAsciiIdentifier> {
+    let Some((lines, content, tree)) = parsed_text(file_path) else { return Vec::new() };
+    let source = content.as_bytes();
+    identifiers(&tree).into_iter().filter_map(|node| {
+        let name = node.utf8_text(source).ok().filter(|name| !name.is_ascii())?;
+        let (start, end) = (node.start_position(), node.end_position());
+        let end_column = if end.row == start.row { end.column } else { lines[start.row].len() };
+        let mut codepoints: Vec<String> = Vec::new();
+        for c in name.chars().filter(|c| !c.is_ascii()).map(codepoint) {
+            if !codepoints.contains(&c) {
+                codepoints.push(c);
+            }
+        }
+        Some(NonAsciiIdentifier {
+            file_path: file_path.to_string(),
+            line_number: start.row + 1,
+            column: start.column + 1,
+            end_column: end_column + 1,
+            name: name.to_string(),
+            codepoints,
+        })
+    }).collect()
+}
+
+/// Every occurrence of an identifier holding non-ASCII characters in each
+/// file, in source order, as some compliance profiles require flagging.
+/// Identifiers come from the syntax tree, so comments and strings are left
+/// out; accented Latin, other scripts and invisible characters all count.
+/// Files without a grammar are skipped.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_non_ascii_identifiers(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<NonAsciiIdentifier>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_non_ascii_identifiers(path))
+}
+
+/// Every Unicode bidirectional control character (U+202A to U+202E and
+/// U+2066 to U+2069) in each file, in line and then column order. These
+/// reorder how a line displays without changing how it compiles, which
+/// lets a reviewer read code other than what runs (CVE-2021-42574, "Trojan
+/// Source"). Each is reported wherever it is, comments and strings
+/// included; the snippet spells the controls out. Binary files are
+/// skipped.
+#[pyfunction]
+#[pyo3(signature = (files, progress=None, progress_every=100, cancel_token=None, threads=None))]
+pub fn find_bidi_controls(
+    py: Python<'_>,
+    files: Vec<String>,
+    progress: Option<PyObject>,
+    progress_every: usize,
+    cancel_token: Option<CancellationToken>,
+    threads: Option<usize>,
+) -> PyResult<Vec<UnicodeHit>> {
+    let control = ScanControl::new(progress, progress_every, cancel_token, threads);
+    control.run(py, &files, |path| file_bidi_controls(path))
+}